@@ -0,0 +1,79 @@
+//! Prints a source file with the coverage of each line from a JSON report, a
+//! quick way to check a single file from the terminal without opening the
+//! HTML report.
+use crate::config::Color;
+use crate::errors::RunError;
+use crate::report::{annotate_source, json, source_cache::SourceCache, Annotation};
+use crate::traces::TraceMap;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Prints the file with `+` before covered lines, `-` before uncovered lines
+/// and a space before lines which can't be covered. Relative paths in the
+/// report are resolved against the root.
+pub fn print_file_coverage(
+    report: &Path,
+    file: &Path,
+    root: &Path,
+    color: Color,
+) -> Result<(), RunError> {
+    let traces = json::import(report)?;
+    let path = find_file(&traces, file, root).ok_or_else(|| {
+        RunError::CovReport(format!(
+            "{} has no coverage for {}",
+            report.display(),
+            file.display()
+        ))
+    })?;
+    let mut cache = SourceCache::new(true);
+    let source = cache.get(file)?;
+    let color = color.use_color_on(atty::Stream::Stdout);
+    let annotated = annotate_source(
+        source,
+        &traces.get_child_traces(path),
+        Annotation::Markers { color },
+    );
+    // Output piped to a pager or head can be closed early
+    match io::stdout().lock().write_all(annotated.as_bytes()) {
+        Err(e) if e.kind() != ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Path of the file in the report, the file is looked up relative to the root
+/// first and otherwise by a report path it ends with
+fn find_file<'a>(traces: &'a TraceMap, file: &Path, root: &Path) -> Option<&'a PathBuf> {
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    let file = canonical(file);
+    let files = traces.files();
+    files
+        .iter()
+        .find(|p| canonical(&root.join(p)) == file)
+        .or_else(|| files.iter().find(|p| file.ends_with(p)))
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{CoverageStat, Trace};
+
+    #[test]
+    fn files_found_in_report() {
+        let mut traces = TraceMap::new();
+        traces.add_trace(
+            Path::new("src/lib.rs"),
+            Trace::new_stub(1, CoverageStat::Line(1)),
+        );
+        let root = Path::new("/home/rust/project");
+        assert_eq!(
+            find_file(&traces, Path::new("/home/rust/project/src/lib.rs"), root),
+            Some(&PathBuf::from("src/lib.rs"))
+        );
+        assert_eq!(
+            find_file(&traces, Path::new("/mnt/project/src/lib.rs"), root),
+            Some(&PathBuf::from("src/lib.rs"))
+        );
+        assert_eq!(find_file(&traces, Path::new("src/main.rs"), root), None);
+    }
+}
@@ -0,0 +1,245 @@
+//! Caches the coverage results of a config so a run where no source file has
+//! changed can reuse them instead of building and running the tests. The
+//! coverage of a file depends on every test which runs it, so the results are
+//! only reused if no file changed at all. Each file in the cache is stored
+//! with a hash of its contents and the cache is invalidated by any change.
+use crate::config::Config;
+use crate::traces::{Trace, TraceMap};
+use crate::TestType;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct CachedFile {
+    /// Hash of the file contents when the coverage was collected
+    hash: u64,
+    traces: Vec<Trace>,
+    test_lines: Vec<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CoverageCache {
+    /// Version of tarpaulin which wrote the cache
+    version: String,
+    /// Hash of the options which affect the coverage results
+    options: u64,
+    files: BTreeMap<PathBuf, CachedFile>,
+    by_type: BTreeMap<TestType, BTreeMap<PathBuf, Vec<Trace>>>,
+}
+
+fn cache_path(config: &Config, dir: &Path) -> PathBuf {
+    let name = if config.name.is_empty() {
+        "coverage"
+    } else {
+        &config.name
+    };
+    dir.join(format!("{}.bin", name))
+}
+
+fn hash_file(path: &Path) -> Option<u64> {
+    let contents = fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Hash of the config ignoring the options which only affect how the results
+/// are reported
+fn hash_options(config: &Config) -> u64 {
+    let mut options = config.clone();
+    options.generate.clear();
+    options.output_directory = PathBuf::new();
+    options.output_file = None;
+    options.verbose = false;
+    options.debug = false;
+    options.cache_dir = None;
+    options.clear_cache = false;
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(&options)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Source files and manifests of the project, a new file invalidates the
+/// cache even if no test covers it yet
+fn project_files(config: &Config) -> BTreeSet<PathBuf> {
+    let root = match config.manifest.parent() {
+        Some(root) => root,
+        None => return BTreeSet::new(),
+    };
+    WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name().to_string_lossy();
+            e.depth() == 0 || !(name == "target" || name.starts_with('.'))
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|p| {
+            p.extension().map(|e| e == "rs").unwrap_or(false)
+                || p.file_name()
+                    .map(|n| n == "Cargo.toml" || n == "Cargo.lock")
+                    .unwrap_or(false)
+        })
+        .collect()
+}
+
+fn to_traces(files: BTreeMap<PathBuf, Vec<Trace>>) -> TraceMap {
+    let mut result = TraceMap::new();
+    for (path, traces) in files {
+        for trace in traces {
+            result.add_trace(&path, trace);
+        }
+    }
+    result
+}
+
+/// Loads the cached coverage results if no file has changed since they were
+/// collected
+pub(crate) fn load(config: &Config) -> Option<(TraceMap, BTreeMap<TestType, TraceMap>)> {
+    let path = cache_path(config, config.cache_dir.as_ref()?);
+    if config.clear_cache {
+        info!("Clearing coverage cache {}", path.display());
+        let _ = fs::remove_file(&path);
+        return None;
+    }
+    let cache: CoverageCache = bincode::deserialize(&fs::read(&path).ok()?).ok()?;
+    if cache.version != env!("CARGO_PKG_VERSION") || cache.options != hash_options(config) {
+        debug!("Coverage cache was written with different options");
+        return None;
+    }
+    let unchanged = cache
+        .files
+        .iter()
+        .all(|(file, cached)| hash_file(file) == Some(cached.hash));
+    let added = project_files(config)
+        .iter()
+        .any(|file| !cache.files.contains_key(file));
+    if !unchanged || added {
+        debug!("Source files changed since the coverage was cached");
+        return None;
+    }
+    info!("No source files changed, using cached coverage results");
+    let mut result = TraceMap::new();
+    for (file, cached) in cache.files {
+        for line in cached.test_lines {
+            result.mark_test_line(&file, line);
+        }
+        for trace in cached.traces {
+            result.add_trace(&file, trace);
+        }
+    }
+    let by_type = cache
+        .by_type
+        .into_iter()
+        .map(|(test_type, files)| (test_type, to_traces(files)))
+        .collect();
+    Some((result, by_type))
+}
+
+/// Stores the coverage results with the hash of each project file
+pub(crate) fn store(config: &Config, traces: &TraceMap, by_type: &BTreeMap<TestType, TraceMap>) {
+    let dir = match config.cache_dir {
+        Some(ref dir) => dir,
+        None => return,
+    };
+    let mut files = BTreeMap::new();
+    let traced = traces.files().into_iter().cloned();
+    for file in project_files(config).into_iter().chain(traced) {
+        if let Some(hash) = hash_file(&file) {
+            let file_traces = traces.get_child_traces(&file);
+            let test_lines = file_traces
+                .iter()
+                .map(|t| t.line)
+                .filter(|line| traces.is_test_line(&file, *line))
+                .collect();
+            let cached = CachedFile {
+                hash,
+                traces: file_traces.into_iter().cloned().collect(),
+                test_lines,
+            };
+            files.insert(file, cached);
+        }
+    }
+    let by_type = by_type
+        .iter()
+        .map(|(test_type, traces)| {
+            let files = traces
+                .iter()
+                .map(|(path, t)| (path.clone(), t.clone()))
+                .collect();
+            (*test_type, files)
+        })
+        .collect();
+    let cache = CoverageCache {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        options: hash_options(config),
+        files,
+        by_type,
+    };
+    let written = fs::create_dir_all(dir)
+        .ok()
+        .and_then(|_| bincode::serialize(&cache).ok())
+        .and_then(|bytes| fs::write(cache_path(config, dir), bytes).ok());
+    if written.is_none() {
+        warn!("Failed to write coverage cache to {}", dir.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::CoverageStat;
+    use std::env;
+
+    #[test]
+    fn cached_results_reused_until_change() {
+        let project = env::temp_dir().join("tarpaulin-cache-project");
+        let _ = fs::remove_dir_all(&project);
+        fs::create_dir_all(project.join("src")).unwrap();
+        fs::write(project.join("Cargo.toml"), "[package]\nname = \"cached\"\n").unwrap();
+        let lib = project.join("src/lib.rs");
+        fs::write(&lib, "pub fn foo() {}\n").unwrap();
+
+        let mut config = Config::default();
+        config.manifest = project.join("Cargo.toml");
+        config.cache_dir = Some(project.join("target/cache"));
+        let mut traces = TraceMap::new();
+        traces.add_trace(&lib, Trace::new_stub(1, CoverageStat::Line(2)));
+        traces.mark_test_line(&lib, 1);
+        assert!(load(&config).is_none());
+        store(&config, &traces, &BTreeMap::new());
+
+        let (cached, _) = load(&config).unwrap();
+        assert_eq!(cached.total_covered(), 1);
+        assert!(cached.is_test_line(&lib, 1));
+
+        // Changing options which affect coverage invalidates the cache
+        config.run_ignored = true;
+        assert!(load(&config).is_none());
+        config.run_ignored = false;
+
+        fs::write(project.join("src/new.rs"), "").unwrap();
+        assert!(load(&config).is_none());
+        fs::remove_file(project.join("src/new.rs")).unwrap();
+        assert!(load(&config).is_some());
+
+        fs::write(&lib, "pub fn foo() { }\n").unwrap();
+        assert!(load(&config).is_none());
+
+        store(&config, &traces, &BTreeMap::new());
+        config.clear_cache = true;
+        assert!(load(&config).is_none());
+        config.clear_cache = false;
+        assert!(load(&config).is_none());
+        let _ = fs::remove_dir_all(&project);
+    }
+}
@@ -0,0 +1,143 @@
+//! Compares the coverage with the coverage of a git branch. The branch is
+//! checked out in a temporary git worktree so the working directory and any
+//! uncommitted changes in it are left alone while its coverage is collected.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::launch_tarpaulin;
+use crate::traces::TraceMap;
+use log::{info, warn};
+use std::path::Path;
+use std::process::Command;
+
+/// Runs git in the given directory returning its trimmed stdout
+pub(crate) fn git_output(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String, RunError> {
+    git_output(dir, args).map_err(RunError::CompareBranch)
+}
+
+/// Collects the coverage of the branch, returning the coverage percentage
+/// ranging from 0.0-1.0
+pub(crate) fn branch_coverage(config: &Config, branch: &str) -> Result<f64, RunError> {
+    let project_dir = config
+        .manifest
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    // Where the project is in the repository so the same project is used in
+    // the worktree
+    let prefix = git(&project_dir, &["rev-parse", "--show-prefix"])?;
+    let tarpaulin_dir = project_dir.join("target").join("tarpaulin");
+    let worktree = tarpaulin_dir.join("compare-branch");
+    if worktree.exists() {
+        let _ = git(
+            &project_dir,
+            &["worktree", "remove", "--force", &path_str(&worktree)],
+        );
+    }
+    info!("Checking out {} to compare coverage", branch);
+    git(
+        &project_dir,
+        &["worktree", "add", "--detach", &path_str(&worktree), branch],
+    )?;
+
+    let branch_dir = worktree.join(prefix);
+    let mut branch_config = config.clone();
+    branch_config.manifest = branch_dir.join("Cargo.toml");
+    branch_config.root = Some(path_str(&branch_dir));
+    // The worktree is recreated for each comparison, building in a target
+    // directory outside of it only rebuilds what changed since the last one
+    branch_config.target_dir = Some(tarpaulin_dir.join("compare-target"));
+    branch_config.compare_branch = None;
+    let result = launch_tarpaulin(&branch_config);
+
+    if let Err(e) = git(
+        &project_dir,
+        &["worktree", "remove", "--force", &path_str(&worktree)],
+    ) {
+        warn!("Failed to remove worktree {}: {}", worktree.display(), e);
+    }
+    let (mut traces, ret) = result?;
+    if ret != 0 {
+        warn!("Tests failed on {}, its coverage may be lower", branch);
+    }
+    traces.dedup();
+    Ok(traces.coverage_percentage())
+}
+
+fn path_str(path: &Path) -> String {
+    path.display().to_string()
+}
+
+/// Describes the change in coverage, both percentages range from 0.0-100.0
+fn describe_change(base: f64, current: f64) -> String {
+    format!(
+        "coverage changed from {:.2}% to {:.2}%, Δ{:+.2}%",
+        base,
+        current,
+        current - base
+    )
+}
+
+/// Prints the change in coverage compared to the branch, returning a
+/// description of the regression if coverage dropped by more than the maximum
+pub(crate) fn compare_with_branch(
+    config: &Config,
+    result: &TraceMap,
+    branch: &str,
+) -> Result<Option<String>, RunError> {
+    let base = branch_coverage(config, branch)? * 100.0f64;
+    let current = result.coverage_percentage() * 100.0f64;
+    let change = describe_change(base, current);
+    println!("|| Compared to {}: {}", branch, change);
+    if is_regression(base, current, config.max_regression) {
+        Ok(Some(format!("{} compared to {}", change, branch)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Whether coverage dropped by more than the maximum regression
+fn is_regression(base: f64, current: f64, max_regression: f64) -> bool {
+    base - current > max_regression
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coverage_change() {
+        assert_eq!(
+            describe_change(73.2, 74.8),
+            "coverage changed from 73.20% to 74.80%, Δ+1.60%"
+        );
+        assert_eq!(
+            describe_change(74.8, 73.2),
+            "coverage changed from 74.80% to 73.20%, Δ-1.60%"
+        );
+    }
+
+    #[test]
+    fn regression_threshold() {
+        assert!(!is_regression(70.0, 75.0, 0.0));
+        assert!(!is_regression(70.0, 70.0, 0.0));
+        assert!(!is_regression(70.0, 69.5, 1.0));
+        assert!(is_regression(70.0, 68.0, 1.0));
+    }
+}
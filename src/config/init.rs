@@ -0,0 +1,258 @@
+//! Writes a starter `tarpaulin.toml` for a project. The options which are
+//! turned on come from a few questions about the project, every other option
+//! is written commented out with its description and default value so the
+//! file documents what can be configured.
+use crate::config::{config_schema, Config, OutputFile, RunType};
+use dialoguer::Confirmation;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// What the workspace manifest says about the project
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProjectInfo {
+    /// The manifest has a `[workspace]` table
+    pub workspace: bool,
+    /// The manifest declares features
+    pub features: bool,
+    /// The project has examples
+    pub examples: bool,
+    /// The project is a library so doctests can run
+    pub library: bool,
+}
+
+impl ProjectInfo {
+    /// Inspects the manifest and the layout of the project around it
+    pub fn from_manifest(manifest: &Path) -> ProjectInfo {
+        let root = manifest.parent().unwrap_or_else(|| Path::new("."));
+        let toml = fs::read_to_string(manifest)
+            .ok()
+            .and_then(|s| s.parse::<toml::Value>().ok())
+            .unwrap_or_else(|| toml::Value::Table(Default::default()));
+        let has = |key: &str| toml.get(key).is_some();
+        ProjectInfo {
+            workspace: has("workspace"),
+            features: has("features"),
+            examples: has("example") || root.join("examples").is_dir(),
+            library: has("lib") || root.join("src").join("lib.rs").is_file(),
+        }
+    }
+}
+
+/// Answers to the questions asked when generating the config
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InitAnswers {
+    pub doctests: bool,
+    pub examples: bool,
+    pub branch: bool,
+    pub workspace: bool,
+    pub all_features: bool,
+    pub html: bool,
+    pub ignore_tests: bool,
+}
+
+fn ask(question: &str, default: bool) -> io::Result<bool> {
+    Confirmation::new()
+        .with_text(question)
+        .default(default)
+        .interact()
+}
+
+impl InitAnswers {
+    /// Asks the questions, defaults are picked from the project
+    pub fn prompt(info: &ProjectInfo) -> io::Result<InitAnswers> {
+        Ok(InitAnswers {
+            doctests: ask("Does your project use doctests?", info.library)?,
+            examples: if info.examples {
+                ask("Collect coverage of the examples?", false)?
+            } else {
+                false
+            },
+            branch: ask("Do you need branch coverage?", false)?,
+            workspace: if info.workspace {
+                ask("Collect coverage of every package in the workspace?", true)?
+            } else {
+                false
+            },
+            all_features: if info.features {
+                ask("Build with all features enabled?", false)?
+            } else {
+                false
+            },
+            html: ask("Generate an HTML report?", true)?,
+            ignore_tests: ask("Exclude the test code from coverage?", true)?,
+        })
+    }
+
+    fn to_config(&self) -> Config {
+        let mut config = Config::default();
+        config.run_types = vec![RunType::Tests];
+        if self.doctests {
+            config.run_types.push(RunType::Doctests);
+        }
+        if self.examples {
+            config.run_types.push(RunType::Examples);
+        }
+        config.branch_coverage = self.branch;
+        config.all = self.workspace;
+        config.all_features = self.all_features;
+        config.ignore_tests = self.ignore_tests;
+        if self.html {
+            config.generate = vec![OutputFile::Html];
+        }
+        config
+    }
+}
+
+/// Descriptions of the options in the config file taken from the schema
+fn option_descriptions() -> Vec<(String, String)> {
+    let schema = serde_json::to_value(config_schema()).unwrap_or_default();
+    match schema["$defs"]["Config"]["properties"].as_object() {
+        Some(properties) => properties
+            .iter()
+            .map(|(key, property)| {
+                let description = property["description"].as_str().unwrap_or_default();
+                (key.clone(), description.to_string())
+            })
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Writes the value as it would appear after the key, tables are written
+/// inline
+fn toml_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::Table(table) if table.is_empty() => "{}".to_string(),
+        toml::Value::Table(table) => {
+            let entries = table
+                .iter()
+                .map(|(k, v)| format!("{} = {}", toml::Value::from(k.as_str()), toml_value(v)))
+                .collect::<Vec<_>>();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        toml::Value::Array(array) => {
+            let values = array.iter().map(toml_value).collect::<Vec<_>>();
+            format!("[{}]", values.join(", "))
+        }
+        value => value.to_string(),
+    }
+}
+
+/// Serializes the config leaving out the options which depend on where
+/// tarpaulin runs
+fn to_table(config: &Config) -> toml::value::Table {
+    let mut table = match toml::Value::try_from(config) {
+        Ok(toml::Value::Table(table)) => table,
+        _ => toml::value::Table::new(),
+    };
+    for key in &["name", "config", "manifest-path"] {
+        table.remove(*key);
+    }
+    table
+}
+
+/// The config file for the answers, options set by the answers are written
+/// out and the rest are left commented out with their defaults
+pub fn generate_config(answers: &InitAnswers) -> String {
+    let chosen = to_table(&answers.to_config());
+    let defaults = to_table(&Config::default());
+    let mut result = String::from(
+        "# Generated by cargo tarpaulin config init, uncomment options to change them.\n\
+         # See cargo tarpaulin --help for more information.\n[default]\n",
+    );
+    for (key, description) in option_descriptions() {
+        if key == "name" || key == "config" {
+            continue;
+        }
+        result.push('\n');
+        for line in description.lines() {
+            result.push_str(&format!("# {}\n", line));
+        }
+        match chosen.get(&key) {
+            Some(value) if Some(value) != defaults.get(&key) => {
+                result.push_str(&format!("{} = {}\n", key, toml_value(value)));
+            }
+            Some(value) => result.push_str(&format!("# {} = {}\n", key, toml_value(value))),
+            None => result.push_str(&format!("# {} =\n", key)),
+        }
+    }
+    result
+}
+
+/// Asks about the project and writes `tarpaulin.toml` next to the manifest,
+/// an existing config is only replaced if forced
+pub fn init_config(manifest: &Path, force: bool) -> io::Result<PathBuf> {
+    let root = manifest.parent().unwrap_or_else(|| Path::new("."));
+    let path = root.join("tarpaulin.toml");
+    if path.exists() && !force {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!(
+                "{} already exists, use --force to replace it",
+                path.display()
+            ),
+        ));
+    }
+    let answers = InitAnswers::prompt(&ProjectInfo::from_manifest(manifest))?;
+    fs::write(&path, generate_config(&answers))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn inspect_project() {
+        let root = env::temp_dir().join("tarpaulin-init-project");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/lib.rs"), "").unwrap();
+        let manifest = root.join("Cargo.toml");
+        fs::write(
+            &manifest,
+            "[workspace]\nmembers = [\"a\"]\n[features]\nfoo = []\n",
+        )
+        .unwrap();
+        let info = ProjectInfo::from_manifest(&manifest);
+        assert_eq!(
+            info,
+            ProjectInfo {
+                workspace: true,
+                features: true,
+                examples: false,
+                library: true,
+            }
+        );
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn generated_config_loads() {
+        let answers = InitAnswers {
+            doctests: true,
+            branch: true,
+            html: true,
+            ..Default::default()
+        };
+        let generated = generate_config(&answers);
+        assert!(generated.contains("\nbranch = true\n"));
+        assert!(generated.contains("\n# Duration to wait before a timeout occurs\n"));
+        assert!(generated.contains("\n# count = false\n"));
+
+        let path = env::temp_dir().join("tarpaulin-init.toml");
+        fs::write(&path, &generated).unwrap();
+        let configs = Config::load_config_file(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(configs.len(), 1);
+        assert!(configs[0].branch_coverage);
+        assert_eq!(
+            configs[0].run_types,
+            vec![RunType::Tests, RunType::Doctests]
+        );
+        assert_eq!(configs[0].generate, vec![OutputFile::Html]);
+        assert!(!configs[0].count);
+    }
+}
@@ -1,3 +1,6 @@
+pub use self::init::{generate_config, init_config, InitAnswers, ProjectInfo};
+pub use self::parse::{anchored_wildcard_regex, parse_path_mapping, parse_signal};
+pub use self::schema::config_schema;
 pub use self::types::*;
 
 use self::parse::*;
@@ -5,23 +8,120 @@ use clap::ArgMatches;
 use coveralls_api::CiService;
 use humantime_serde::deserialize as humantime_serde;
 use log::{error, info, warn};
+use nix::sys::signal::Signal;
 use regex::Regex;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::fs::File;
 use std::io::{Error, ErrorKind, Read};
+use std::ops::Index;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+mod init;
 mod parse;
+mod schema;
 pub mod types;
 
-pub struct ConfigWrapper(pub Vec<Config>);
+/// Config files looked for in the project directory, in order of precedence
+const CONFIG_FILE_NAMES: [&str; 6] = [
+    "tarpaulin.toml",
+    ".tarpaulin.toml",
+    "tarpaulin.yml",
+    ".tarpaulin.yml",
+    "tarpaulin.yaml",
+    ".tarpaulin.yaml",
+];
+
+pub struct ConfigWrapper(
+    pub Vec<Config>,
+    /// Number of errors found validating the configs
+    usize,
+);
+
+impl ConfigWrapper {
+    /// Number of configs in the wrapper
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if the wrapper contains no configs
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns an error if validating the configs found any errors, they've
+    /// already been logged when the configs were created
+    pub fn check_valid(&self) -> Result<(), String> {
+        if self.1 == 0 {
+            Ok(())
+        } else {
+            Err(format!("Found {} errors in the configs", self.1))
+        }
+    }
+
+    /// The config to generate reports of an earlier run with, this is the
+    /// config named `report` or the only config. Picking one of several other
+    /// configs would be arbitrary so it's an error.
+    pub fn report_config(&self) -> Result<&Config, String> {
+        if let Some(config) = self.0.iter().find(|c| c.name == "report") {
+            return Ok(config);
+        }
+        match self.0.as_slice() {
+            [config] => Ok(config),
+            [] => Err("No configs to generate the report with".to_string()),
+            _ => Err(format!(
+                "Found {} configs, name the config to generate the report with `report`",
+                self.0.len()
+            )),
+        }
+    }
+
+    /// Serializes the configs as a TOML config file with a table for each.
+    /// A config which didn't come from a config file is named `default`.
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        let mut tables = toml::value::Table::new();
+        for config in &self.0 {
+            let mut table = toml::Value::try_from(config)?;
+            if let Some(table) = table.as_table_mut() {
+                table.remove("name");
+                table.remove("config");
+            }
+            let name = if config.name.is_empty() {
+                "default".to_string()
+            } else {
+                config.name.clone()
+            };
+            tables.insert(name, table);
+        }
+        toml::to_string(&tables)
+    }
+}
+
+impl IntoIterator for ConfigWrapper {
+    type Item = Config;
+    type IntoIter = std::vec::IntoIter<Config>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl Index<usize> for ConfigWrapper {
+    type Output = Config;
+
+    fn index(&self, index: usize) -> &Config {
+        &self.0[index]
+    }
+}
 
 /// Specifies the current configuration tarpaulin is using.
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(default)]
 pub struct Config {
     pub name: String,
@@ -41,6 +141,25 @@ pub struct Config {
     /// Ignore panic macros in code.
     #[serde(rename = "ignore-panics")]
     pub ignore_panics: bool,
+    /// Hide panic messages printed by the test executables
+    #[serde(rename = "suppress-panics-output")]
+    pub suppress_panic_output: bool,
+    /// Exclude source files detected as generated code
+    #[serde(rename = "exclude-generated")]
+    pub exclude_generated: bool,
+    /// Markers searched for in the header of a file to detect generated code
+    #[serde(rename = "generated-file-markers")]
+    pub generated_file_markers: Vec<String>,
+    /// Regexes of source lines to ignore in coverage. Unlike excluded files
+    /// the lines are still shown in the HTML report
+    #[serde(rename = "ignore-lines-matching")]
+    pub ignore_lines_matching: Vec<String>,
+    /// Markers in a comment which exclude the line from coverage
+    #[serde(rename = "exclusion-comment")]
+    pub exclusion_line_comment: Vec<String>,
+    /// Exclude the code between `coverage:off` and `coverage:on` comments
+    #[serde(rename = "block-exclusions")]
+    pub block_exclusions: bool,
     /// Flag to add a clean step when preparing the target project
     #[serde(rename = "force-clean")]
     pub force_clean: bool,
@@ -62,7 +181,12 @@ pub struct Config {
     /// Key relating to coveralls service or repo
     pub coveralls: Option<String>,
     /// Enum representing CI tool used.
-    #[serde(rename = "ciserver", deserialize_with = "deserialize_ci_server")]
+    #[serde(
+        rename = "ciserver",
+        deserialize_with = "deserialize_ci_server",
+        serialize_with = "serialize_ci_server"
+    )]
+    #[schemars(with = "Option<String>")]
     pub ci_tool: Option<CiService>,
     /// Only valid if coveralls option is set. If coveralls option is set,
     /// as well as report_uri, then the report will be sent to this endpoint
@@ -83,8 +207,46 @@ pub struct Config {
     #[serde(alias = "workspace")]
     pub all: bool,
     /// Duration to wait before a timeout occurs
-    #[serde(deserialize_with = "humantime_serde", rename = "timeout")]
+    #[serde(
+        deserialize_with = "humantime_serde",
+        serialize_with = "::humantime_serde::serialize",
+        rename = "timeout"
+    )]
+    #[schemars(with = "schema::DurationString")]
     pub test_timeout: Duration,
+    /// Timeout of the doctests, the test timeout is used if unset
+    #[serde(
+        deserialize_with = "humantime_serde",
+        serialize_with = "::humantime_serde::serialize",
+        rename = "doc-test-timeout"
+    )]
+    #[schemars(with = "Option<schema::DurationString>")]
+    pub doctest_timeout: Option<Duration>,
+    /// Signal sent to a test which timed out, like SIGTERM to let it clean up
+    #[serde(
+        deserialize_with = "deserialize_signal",
+        serialize_with = "serialize_signal",
+        rename = "timeout-signal"
+    )]
+    #[schemars(with = "String")]
+    pub timeout_signal: Signal,
+    /// How long a timed out test has to exit after the timeout signal before
+    /// it's killed
+    #[serde(
+        deserialize_with = "humantime_serde",
+        serialize_with = "::humantime_serde::serialize",
+        rename = "timeout-signal-grace"
+    )]
+    #[schemars(with = "schema::DurationString")]
+    pub timeout_signal_grace: Duration,
+    /// Timeouts for specific test binaries overriding the test timeout
+    #[serde(
+        deserialize_with = "deserialize_timeouts",
+        serialize_with = "serialize_timeouts",
+        rename = "timeout-per-binary"
+    )]
+    #[schemars(with = "HashMap<String, schema::DurationString>")]
+    pub timeout_per_binary: HashMap<String, Duration>,
     /// Build in release mode
     pub release: bool,
     /// Build the tests only don't run coverage
@@ -97,8 +259,31 @@ pub struct Config {
     /// Directory for generated artifacts
     #[serde(rename = "target-dir")]
     pub target_dir: Option<PathBuf>,
+    /// Target triple to build for, the tests still have to run on the host
+    pub target: Option<String>,
+    /// Sysroot of a cross compiled build, it's removed from the start of the
+    /// source paths in the debug info
+    pub sysroot: Option<PathBuf>,
+    /// Where the sources are when they aren't at the paths in the debug info,
+    /// such as tests built in a container
+    #[serde(rename = "source-dir")]
+    pub source_dir: Option<PathBuf>,
+    /// Search and replace pairs applied to source paths in the reports, the
+    /// first pair found in a path is used
+    #[serde(rename = "path-mapping")]
+    pub path_mappings: Vec<(String, String)>,
+    /// Prefixes removed from the start of source paths in the reports before
+    /// the path mappings are applied
+    #[serde(rename = "strip-prefix")]
+    pub strip_prefixes: Vec<String>,
     /// Run tarpaulin on project without accessing the network
     pub offline: bool,
+    /// CPU to generate code for, passed to rustc as `-C target-cpu`
+    #[serde(rename = "target-cpu")]
+    pub target_cpu: Option<String>,
+    /// Extra flags passed to rustc after the instrumentation flags and
+    /// `RUSTFLAGS`
+    pub rustflags: Vec<String>,
     /// Types of tests for tarpaulin to collect coverage on
     #[serde(rename = "run-types")]
     pub run_types: Vec<RunType>,
@@ -108,10 +293,17 @@ pub struct Config {
     pub exclude: Vec<String>,
     /// Files to exclude from testing in their compiled form
     #[serde(skip_deserializing, skip_serializing)]
-    excluded_files: RefCell<Vec<Regex>>,
+    excluded_files: RefCell<Vec<(bool, Regex)>>,
     /// Files to exclude from testing in uncompiled form (for serde)
     #[serde(rename = "exclude-files")]
     excluded_files_raw: Vec<String>,
+    /// Files to include in the results in their compiled form, when set only
+    /// files matching them are reported
+    #[serde(skip_deserializing, skip_serializing)]
+    included_files: RefCell<Vec<Regex>>,
+    /// Files to include in the results in uncompiled form (for serde)
+    #[serde(rename = "include-files")]
+    included_files_raw: Vec<String>,
     /// Varargs to be forwarded to the test executables.
     #[serde(rename = "args")]
     pub varargs: Vec<String>,
@@ -123,6 +315,216 @@ pub struct Config {
     /// Output files to generate
     #[serde(rename = "out")]
     pub generate: Vec<OutputFile>,
+    /// Coverage percentage below which files start expanded in the HTML report
+    #[serde(rename = "html-expand-uncovered")]
+    pub html_expand_uncovered: Option<f64>,
+    /// Cache source files in memory while generating reports
+    #[serde(rename = "source-cache")]
+    pub source_cache: bool,
+    /// Use demangled symbol paths as function names in reports
+    #[serde(rename = "demangled-names")]
+    pub demangled_names: bool,
+    /// Executable to run once all reports have been generated
+    #[serde(rename = "coverage-report-hook")]
+    pub coverage_report_hook: Option<PathBuf>,
+    /// Fail the run if the coverage report hook fails
+    #[serde(rename = "hook-fails-build")]
+    pub hook_fails_build: bool,
+    /// Deduplicate coverage of code shared between test binaries
+    #[serde(rename = "dedup-symbols")]
+    pub dedup_symbols: bool,
+    /// How hit counts for the same line in different test binaries combine
+    #[serde(rename = "count-aggregation")]
+    pub count_aggregation: CountAggregation,
+    /// When to colour the output
+    pub color: Color,
+    /// Count a passing doctest as covering the doc comment it came from
+    #[serde(rename = "include-doctests-in-line-coverage")]
+    pub include_doctests_in_line_coverage: bool,
+    /// Run the doctests without counting the lines they hit in the coverage
+    #[serde(rename = "exclude-doc-tests-from-coverage")]
+    pub exclude_doc_tests_from_coverage: bool,
+    /// Ignore coverage attributed to source files outside the project
+    #[serde(rename = "ignore-extern-crate-coverage")]
+    pub ignore_extern_crate_coverage: bool,
+    /// Report coverage for each type of test separately
+    #[serde(rename = "report-by-test-type")]
+    pub report_by_test_type: bool,
+    /// Minimum coverage percentage of unit tests
+    #[serde(rename = "min-unit-coverage")]
+    pub min_unit_coverage: Option<f64>,
+    /// Minimum coverage percentage of integration tests
+    #[serde(rename = "min-integration-coverage")]
+    pub min_integration_coverage: Option<f64>,
+    /// Minimum coverage percentage of doctests
+    #[serde(rename = "min-doctest-coverage")]
+    pub min_doctest_coverage: Option<f64>,
+    /// Exclude source of crates only used as build dependencies
+    #[serde(rename = "ignore-build-dependencies")]
+    pub ignore_build_dependencies: bool,
+    /// Only report which functions were called instead of line coverage
+    #[serde(rename = "function-coverage-only")]
+    pub function_coverage_only: bool,
+    /// PGO profile to compare the coverage results against
+    #[serde(rename = "pgo-profile")]
+    pub pgo_profile: Option<PathBuf>,
+    /// Write the reports of each config to a subdirectory of the output
+    /// directory named after the config
+    #[serde(rename = "output-per-config", alias = "output-dir-per-config")]
+    pub output_per_config: bool,
+    /// Environment variables not passed to the test executables, `*` matches
+    /// any characters
+    #[serde(rename = "test-binary-env-filter")]
+    pub test_binary_env_filter: Vec<String>,
+    /// Source file to print annotated with its coverage after the run
+    #[serde(rename = "print-coverage-for")]
+    pub print_coverage_for: Option<PathBuf>,
+    /// Print the covered and coverable lines of each file, worst coverage
+    /// first
+    #[serde(rename = "report-line-count")]
+    pub report_line_count: bool,
+    /// Only print the line counts of this many of the worst covered files
+    #[serde(rename = "top-n")]
+    pub top_n: Option<usize>,
+    /// Fail if the coverage percentage is below this value
+    #[serde(rename = "minimum-coverage")]
+    pub minimum_coverage: Option<f64>,
+    /// Minimum coverage of files matching each pattern, patterns use the
+    /// same `*` wildcards as excluded files and match the whole path relative
+    /// to the root
+    #[serde(rename = "file-coverage-threshold")]
+    pub file_thresholds: HashMap<String, f64>,
+    /// Write the JSON report without whitespace
+    #[serde(rename = "compact-json")]
+    pub compact_json: bool,
+    /// Whether line numbers in the JSON report start at 1 or 0
+    #[serde(rename = "line-numbering")]
+    pub line_numbering: LineNumbering,
+    /// Config keys of the options explicitly set on the command line
+    #[serde(skip)]
+    args_set: HashSet<String>,
+    /// Exact path to write the report to, requires a single output format
+    #[serde(rename = "output-file")]
+    pub output_file: Option<PathBuf>,
+    /// Never clean the project before building, reusing existing build artefacts
+    #[serde(rename = "skip-clean")]
+    pub skip_clean: bool,
+    /// Number of parallel jobs used to build the project, defaults to the number of CPUs
+    pub jobs: Option<usize>,
+    /// Number of threads the test executables run tests on, passed as
+    /// `--test-threads` unless it's already in the test arguments
+    #[serde(rename = "implicit-test-threads")]
+    pub implicit_test_threads: Option<usize>,
+    /// Number of times a failing test executable is run again
+    #[serde(rename = "retry-count")]
+    pub retry_count: usize,
+    /// Names of the test targets to run, all tests are run if empty
+    #[serde(rename = "test")]
+    pub test_names: Vec<String>,
+    /// Names of the bench targets to run, all benches are run if empty
+    #[serde(rename = "bench")]
+    pub bench_names: Vec<String>,
+    /// Names of the binary targets to run, implies the `Bins` run type. Binaries
+    /// must exit normally for their coverage to be collected
+    #[serde(rename = "bin")]
+    pub bins: Vec<String>,
+    /// Test executables built in an earlier step, when set the project isn't
+    /// built and only these are run
+    #[serde(rename = "binary-path")]
+    pub binary_paths: Vec<PathBuf>,
+    /// Report the percentage of functions called alongside line coverage
+    #[serde(rename = "function-coverage")]
+    pub function_coverage: bool,
+    /// Report the coverage of test code separately from the code under test
+    #[serde(rename = "include-tests")]
+    pub include_tests: bool,
+    /// Coverage percentages where the badge turns from red to yellow and
+    /// from yellow to green
+    #[serde(rename = "badge-thresholds")]
+    pub badge_thresholds: (f64, f64),
+    /// Codecov upload token, when empty the `CODECOV_TOKEN` environment
+    /// variable is used if it's set
+    pub codecov: Option<String>,
+    /// URL of a self-hosted Codecov instance to upload to instead of codecov.io
+    #[serde(rename = "codecov-url")]
+    pub codecov_url: Option<String>,
+    /// Write a coverage summary to `$GITHUB_STEP_SUMMARY` when running in
+    /// GitHub Actions
+    #[serde(rename = "github-summary")]
+    pub github_summary: bool,
+    /// Fail if the line coverage percentage is below this value
+    #[serde(rename = "fail-under-line")]
+    pub minimum_line_coverage: Option<f64>,
+    /// Fail if the branch coverage percentage is below this value, this isn't
+    /// checked until branch coverage is collected
+    #[serde(rename = "fail-under-branch")]
+    pub minimum_branch_coverage: Option<f64>,
+    /// Git branch to compare the coverage against
+    #[serde(rename = "compare-with-branch")]
+    pub compare_branch: Option<String>,
+    /// Percentage coverage can drop by compared to the branch before failing
+    #[serde(rename = "max-regression")]
+    pub max_regression: f64,
+    /// Report the coverage of the lines changed in the git diff
+    #[serde(rename = "diff-coverage")]
+    pub diff_coverage: bool,
+    /// Git reference the diff is taken against, defaults to HEAD
+    #[serde(rename = "diff-from")]
+    pub diff_from: Option<String>,
+    /// Fail if the coverage of the changed lines is below this value
+    #[serde(rename = "diff-coverage-minimum")]
+    pub diff_coverage_minimum: Option<f64>,
+    /// Title of the HTML report, defaults to one naming the package
+    #[serde(rename = "report-title")]
+    pub report_title: Option<String>,
+    /// Directories of workspace members to build, relative to the workspace
+    /// root, as an alternative to their package names
+    #[serde(rename = "workspace-members")]
+    pub workspace_members: Vec<PathBuf>,
+    /// Keep running the remaining test binaries when collecting the coverage
+    /// of one fails, the coverage collected before the failure is kept
+    #[serde(rename = "no-fail-fast")]
+    pub no_fail_fast: bool,
+    /// Report every failed config once all configs have run instead of only
+    /// the first failure. Applies to the whole run if any config sets it
+    #[serde(rename = "keep-going")]
+    pub keep_going: bool,
+    /// Directory to cache coverage results in, a run with no changed source
+    /// files reuses the cached results instead of running the tests
+    #[serde(rename = "cache-dir")]
+    pub cache_dir: Option<PathBuf>,
+    /// Remove the cached coverage results before running
+    #[serde(rename = "clear-cache")]
+    pub clear_cache: bool,
+    /// Metadata written to the JSON, HTML and LCOV reports to label them
+    #[serde(rename = "report-tags")]
+    pub report_tags: HashMap<String, String>,
+    /// Environment variables set for cargo and the test executables,
+    /// overriding the inherited environment
+    #[serde(rename = "cargo-env")]
+    pub cargo_env: HashMap<String, String>,
+    /// Environment variables only set for the test executables so they
+    /// aren't visible to the build
+    #[serde(rename = "test-env")]
+    pub test_env: HashMap<String, String>,
+    /// Leave out lines the linker removed or that have no machine code, as
+    /// they can never be covered
+    #[serde(rename = "strip-dead-code")]
+    pub strip_dead_code: bool,
+    /// Cargo profile to build with instead of `dev`, or `release` with `--release`
+    #[serde(rename = "profile")]
+    pub profile: Option<String>,
+    /// Collect coverage from project binaries the tests exec, such as
+    /// `CARGO_BIN_EXE_<name>` binaries run by integration tests
+    #[serde(rename = "follow-exec")]
+    pub follow_exec: bool,
+    /// Build with the nightly only rustc flags which improve the accuracy of
+    /// the coverage, requires a nightly toolchain
+    #[serde(rename = "nightly")]
+    pub nightly: bool,
+    /// How coverage is collected, llvm-cov requires a nightly toolchain
+    #[serde(rename = "engine")]
+    pub engine: CoverageEngine,
 }
 
 impl Default for Config {
@@ -136,6 +538,12 @@ impl Default for Config {
             run_ignored: false,
             ignore_tests: false,
             ignore_panics: false,
+            suppress_panic_output: false,
+            exclude_generated: false,
+            generated_file_markers: default_generated_markers(),
+            ignore_lines_matching: vec![],
+            exclusion_line_comment: default_exclusion_comments(),
+            block_exclusions: true,
             force_clean: false,
             verbose: false,
             debug: false,
@@ -156,15 +564,92 @@ impl Default for Config {
             exclude: vec![],
             excluded_files: RefCell::new(vec![]),
             excluded_files_raw: vec![],
+            included_files: RefCell::new(vec![]),
+            included_files_raw: vec![],
             varargs: vec![],
             test_timeout: Duration::from_secs(60),
+            doctest_timeout: None,
+            timeout_signal: Signal::SIGKILL,
+            timeout_signal_grace: Duration::from_secs(5),
+            timeout_per_binary: HashMap::new(),
             release: false,
             all_features: false,
             no_run: false,
             locked: false,
             frozen: false,
             target_dir: None,
+            target: None,
+            sysroot: None,
+            source_dir: None,
+            path_mappings: vec![],
+            strip_prefixes: vec![],
             offline: false,
+            target_cpu: None,
+            rustflags: vec![],
+            html_expand_uncovered: None,
+            source_cache: true,
+            demangled_names: false,
+            coverage_report_hook: None,
+            hook_fails_build: false,
+            dedup_symbols: true,
+            count_aggregation: CountAggregation::Max,
+            color: Color::Auto,
+            include_doctests_in_line_coverage: false,
+            exclude_doc_tests_from_coverage: false,
+            ignore_extern_crate_coverage: true,
+            report_by_test_type: false,
+            min_unit_coverage: None,
+            min_integration_coverage: None,
+            min_doctest_coverage: None,
+            ignore_build_dependencies: true,
+            function_coverage_only: false,
+            pgo_profile: None,
+            output_per_config: false,
+            test_binary_env_filter: vec![],
+            print_coverage_for: None,
+            report_line_count: false,
+            top_n: None,
+            minimum_coverage: None,
+            file_thresholds: HashMap::new(),
+            compact_json: false,
+            line_numbering: LineNumbering::OneBased,
+            args_set: HashSet::new(),
+            output_file: None,
+            skip_clean: false,
+            jobs: None,
+            implicit_test_threads: None,
+            retry_count: 0,
+            test_names: vec![],
+            bench_names: vec![],
+            bins: vec![],
+            binary_paths: vec![],
+            function_coverage: false,
+            include_tests: false,
+            badge_thresholds: DEFAULT_BADGE_THRESHOLDS,
+            codecov: None,
+            codecov_url: None,
+            github_summary: true,
+            minimum_line_coverage: None,
+            minimum_branch_coverage: None,
+            compare_branch: None,
+            max_regression: 0.0,
+            diff_coverage: false,
+            diff_from: None,
+            diff_coverage_minimum: None,
+            report_title: None,
+            workspace_members: vec![],
+            no_fail_fast: false,
+            keep_going: false,
+            cache_dir: None,
+            clear_cache: false,
+            report_tags: HashMap::new(),
+            cargo_env: HashMap::new(),
+            test_env: HashMap::new(),
+            strip_dead_code: false,
+            profile: None,
+            follow_exec: false,
+            nightly: false,
+            engine: CoverageEngine::Ptrace,
         }
     }
 }
@@ -176,8 +661,10 @@ impl<'a> From<&'a ArgMatches<'a>> for ConfigWrapper {
         let verbose = args.is_present("verbose") || debug;
         let excluded_files = get_excluded(args);
         let excluded_files_raw = get_list(args, "exclude-files");
+        let included_files = get_included(args);
+        let included_files_raw = get_list(args, "include-files");
 
-        let args_config = Config {
+        let mut args_config = Config {
             name: String::new(),
             manifest: get_manifest(args),
             config: None,
@@ -186,6 +673,12 @@ impl<'a> From<&'a ArgMatches<'a>> for ConfigWrapper {
             run_ignored: args.is_present("ignored"),
             ignore_tests: args.is_present("ignore-tests"),
             ignore_panics: args.is_present("ignore-panics"),
+            suppress_panic_output: args.is_present("suppress-panics-output"),
+            exclude_generated: args.is_present("exclude-generated"),
+            generated_file_markers: get_generated_markers(args),
+            ignore_lines_matching: get_list(args, "ignore-lines-matching"),
+            exclusion_line_comment: get_exclusion_comments(args),
+            block_exclusions: !args.is_present("no-block-exclusions"),
             force_clean: args.is_present("force-clean"),
             verbose,
             debug,
@@ -207,17 +700,104 @@ impl<'a> From<&'a ArgMatches<'a>> for ConfigWrapper {
             exclude: get_list(args, "exclude"),
             excluded_files: RefCell::new(excluded_files.clone()),
             excluded_files_raw: excluded_files_raw.clone(),
+            included_files: RefCell::new(included_files),
+            included_files_raw,
             varargs: get_list(args, "args"),
             test_timeout: get_timeout(args),
+            doctest_timeout: get_doctest_timeout(args),
+            timeout_signal: get_timeout_signal(args),
+            timeout_signal_grace: get_timeout_signal_grace(args),
+            timeout_per_binary: HashMap::new(),
             release: args.is_present("release"),
             no_run: args.is_present("no-run"),
             locked: args.is_present("locked"),
             frozen: args.is_present("frozen"),
             target_dir: get_target_dir(args),
+            target: args.value_of("target").map(ToString::to_string),
+            sysroot: args.value_of("sysroot").map(PathBuf::from),
+            source_dir: args.value_of("source-dir").map(PathBuf::from),
+            path_mappings: get_path_mappings(args),
+            strip_prefixes: get_list(args, "strip-prefix"),
             offline: args.is_present("offline"),
+            target_cpu: get_target_cpu(args),
+            rustflags: get_list(args, "rustflags"),
+            html_expand_uncovered: get_html_expand_uncovered(args),
+            source_cache: !args.is_present("no-source-cache"),
+            demangled_names: args.is_present("demangled-names"),
+            coverage_report_hook: get_coverage_report_hook(args),
+            hook_fails_build: args.is_present("hook-fails-build"),
+            dedup_symbols: !args.is_present("no-dedup-symbols"),
+            count_aggregation: get_count_aggregation(args),
+            color: get_color(args),
+            include_doctests_in_line_coverage: args.is_present("include-doctests-in-line-coverage"),
+            exclude_doc_tests_from_coverage: args.is_present("exclude-doc-tests-from-coverage"),
+            ignore_extern_crate_coverage: !args.is_present("include-extern-crate-coverage"),
+            report_by_test_type: args.is_present("report-by-test-type"),
+            min_unit_coverage: get_percentage(args, "min-unit-coverage"),
+            min_integration_coverage: get_percentage(args, "min-integration-coverage"),
+            min_doctest_coverage: get_percentage(args, "min-doctest-coverage"),
+            ignore_build_dependencies: !args.is_present("include-build-dependencies"),
+            function_coverage_only: args.is_present("function-coverage-only"),
+            pgo_profile: get_pgo_profile(args),
+            output_per_config: args.is_present("output-per-config")
+                || args.is_present("output-dir-per-config"),
+            test_binary_env_filter: get_list(args, "test-binary-env-filter"),
+            print_coverage_for: get_print_coverage_for(args),
+            report_line_count: args.is_present("report-line-count"),
+            top_n: get_top_n(args),
+            minimum_coverage: get_percentage(args, "minimum-coverage"),
+            file_thresholds: HashMap::new(),
+            compact_json: args.is_present("compact-json"),
+            line_numbering: get_line_numbering(args),
+            args_set: HashSet::new(),
+            output_file: get_output_file(args),
+            skip_clean: args.is_present("skip-clean"),
+            jobs: get_jobs(args),
+            implicit_test_threads: get_implicit_test_threads(args),
+            retry_count: get_retry_count(args),
+            test_names: get_list(args, "test"),
+            bench_names: get_list(args, "bench"),
+            bins: get_list(args, "bin"),
+            binary_paths: get_binary_paths(args),
+            function_coverage: args.is_present("function"),
+            include_tests: args.is_present("include-tests"),
+            badge_thresholds: get_badge_thresholds(args),
+            codecov: get_codecov(args),
+            codecov_url: get_codecov_url(args),
+            github_summary: !args.is_present("no-github-summary"),
+            minimum_line_coverage: get_percentage(args, "fail-under-line"),
+            minimum_branch_coverage: get_percentage(args, "fail-under-branch"),
+            compare_branch: get_compare_branch(args),
+            max_regression: get_percentage(args, "max-regression").unwrap_or(0.0),
+            diff_coverage: args.is_present("diff-coverage"),
+            diff_from: get_diff_from(args),
+            diff_coverage_minimum: get_percentage(args, "diff-coverage-minimum"),
+            report_title: get_report_title(args),
+            workspace_members: get_workspace_members(args),
+            no_fail_fast: args.is_present("no-fail-fast"),
+            keep_going: args.is_present("keep-going"),
+            cache_dir: get_cache_dir(args),
+            clear_cache: args.is_present("clear-cache"),
+            report_tags: get_report_tags(args),
+            cargo_env: get_env_vars(args, "cargo-env"),
+            test_env: get_env_vars(args, "test-env"),
+            strip_dead_code: args.is_present("strip-dead-code"),
+            profile: get_profile(args),
+            follow_exec: args.is_present("follow-exec"),
+            nightly: args.is_present("nightly"),
+            engine: get_engine(args),
         };
-        if args.is_present("ignore-config") {
-            Self(vec![args_config])
+        args_config.args_set = MERGEABLE_KEYS
+            .iter()
+            .filter(|key| {
+                cli_arg_names(key)
+                    .iter()
+                    .any(|name| args.occurrences_of(name) > 0)
+            })
+            .map(ToString::to_string)
+            .collect();
+        let mut wrapper = if args.is_present("ignore-config") {
+            Self(vec![args_config], 0)
         } else if args.is_present("config") {
             let mut path = PathBuf::from(args.value_of("config").unwrap());
             if path.is_relative() {
@@ -230,66 +810,361 @@ impl<'a> From<&'a ArgMatches<'a>> for ConfigWrapper {
             let confs = Config::load_config_file(&path);
             Config::get_config_vec(confs, args_config)
         } else {
-            if let Some(cfg) = args_config.check_for_configs() {
+            let name = args.value_of("config-file-name");
+            if let Some(cfg) = args_config.check_for_config_named(name) {
                 let confs = Config::load_config_file(&cfg);
                 Config::get_config_vec(confs, args_config)
             } else {
-                Self(vec![args_config])
+                Self(vec![args_config], 0)
+            }
+        };
+        let selected = get_config_names(args, "config-names");
+        let excluded = get_config_names(args, "exclude-config-names");
+        wrapper
+            .0
+            .retain(|config| is_config_selected(&config.name, &selected, &excluded));
+        // Environment variables override the config file but not arguments
+        // given on the command line
+        let overrides = get_env_overrides()
+            .into_iter()
+            .filter(|(key, _)| {
+                cli_arg_names(key)
+                    .iter()
+                    .all(|name| args.occurrences_of(name) == 0)
+            })
+            .collect::<Vec<_>>();
+        for config in wrapper.0.iter_mut() {
+            config.apply_env_overrides(&overrides);
+        }
+        wrapper.1 += log_validation(&wrapper.0);
+        wrapper
+    }
+}
+
+/// Logs the problems found validating the configs, returning the number of
+/// errors
+pub fn log_validation(configs: &[Config]) -> usize {
+    let mut errors = 0;
+    for config in configs {
+        let validation = config.validate();
+        let prefix = if config.name.is_empty() {
+            String::new()
+        } else {
+            format!("[{}] ", config.name)
+        };
+        for warning in &validation.warnings {
+            warn!("{}{}", prefix, warning);
+        }
+        for e in &validation.errors {
+            error!("{}{}", prefix, e);
+        }
+        errors += validation.errors.len();
+    }
+    errors
+}
+
+/// Generates consuming builder methods on `Config`, each one setting a field
+/// from the given argument.
+macro_rules! builder_methods {
+    ($($(#[$meta:meta])* $name:ident($arg:ident: $ty:ty) => $field:ident = $value:expr;)*) => {
+        impl Config {
+            $(
+                $(#[$meta])*
+                pub fn $name(mut self, $arg: $ty) -> Self {
+                    self.$field = $value;
+                    self
+                }
+            )*
+        }
+    };
+}
+
+builder_methods! {
+    /// Sets the root directory used to calculate relative paths
+    with_root(root: impl AsRef<str>) => root = Some(root.as_ref().to_string());
+    /// Sets the path to the projects cargo manifest
+    with_manifest(manifest: impl Into<PathBuf>) => manifest = manifest.into();
+    /// Sets the types of tests to collect coverage on
+    with_run_types(run_types: impl IntoIterator<Item = RunType>) =>
+        run_types = run_types.into_iter().collect();
+    /// Sets the packages to build
+    with_packages(packages: impl IntoIterator<Item = impl ToString>) =>
+        packages = packages.into_iter().map(|x| x.to_string()).collect();
+    /// Sets the features to include in the build
+    with_features(features: impl IntoIterator<Item = impl ToString>) =>
+        features = features.into_iter().map(|x| x.to_string()).collect();
+    /// Sets the duration to wait before a test times out
+    with_timeout(timeout: Duration) => test_timeout = timeout;
+    /// Sets the output files to generate
+    with_generate(generate: impl IntoIterator<Item = OutputFile>) =>
+        generate = generate.into_iter().collect();
+    /// Sets the directory to write output files to
+    with_output_directory(dir: impl Into<PathBuf>) => output_directory = dir.into();
+    /// Sets the exact path to write the report to
+    with_output_file(file: impl Into<PathBuf>) => output_file = Some(file.into());
+}
+
+/// Generates `Config::copy_option` which copies an option from another config
+/// by its config key, and the list of keys it accepts.
+macro_rules! mergeable_options {
+    ($($key:literal => $field:ident,)*) => {
+        /// Config keys of the options arguments override when merging
+        const MERGEABLE_KEYS: &[&str] = &[$($key),*];
+
+        impl Config {
+            /// Copies the option with the given config key from another config
+            fn copy_option(&mut self, other: &Config, key: &str) {
+                match key {
+                    $($key => self.$field = other.$field.clone(),)*
+                    _ => {}
+                }
             }
         }
+    };
+}
+
+mergeable_options! {
+    "ignored" => run_ignored,
+    "ignore-tests" => ignore_tests,
+    "ignore-panics" => ignore_panics,
+    "suppress-panics-output" => suppress_panic_output,
+    "exclude-generated" => exclude_generated,
+    "generated-file-markers" => generated_file_markers,
+    "ignore-lines-matching" => ignore_lines_matching,
+    "exclusion-comment" => exclusion_line_comment,
+    "block-exclusions" => block_exclusions,
+    "force-clean" => force_clean,
+    "count" => count,
+    "line" => line_coverage,
+    "branch" => branch_coverage,
+    "output-dir" => output_directory,
+    "coveralls" => coveralls,
+    "ciserver" => ci_tool,
+    "report-uri" => report_uri,
+    "forward" => forward_signals,
+    "all-features" => all_features,
+    "no-default-features" => no_default_features,
+    "all" => all,
+    "timeout" => test_timeout,
+    "doc-test-timeout" => doctest_timeout,
+    "timeout-signal" => timeout_signal,
+    "timeout-signal-grace" => timeout_signal_grace,
+    "release" => release,
+    "no-run" => no_run,
+    "locked" => locked,
+    "frozen" => frozen,
+    "target-dir" => target_dir,
+    "target" => target,
+    "sysroot" => sysroot,
+    "source-dir" => source_dir,
+    "path-mapping" => path_mappings,
+    "strip-prefix" => strip_prefixes,
+    "offline" => offline,
+    "target-cpu" => target_cpu,
+    "rustflags" => rustflags,
+    "run-types" => run_types,
+    "packages" => packages,
+    "exclude" => exclude,
+    "args" => varargs,
+    "features" => features,
+    "Z" => unstable_features,
+    "out" => generate,
+    "html-expand-uncovered" => html_expand_uncovered,
+    "source-cache" => source_cache,
+    "demangled-names" => demangled_names,
+    "coverage-report-hook" => coverage_report_hook,
+    "hook-fails-build" => hook_fails_build,
+    "dedup-symbols" => dedup_symbols,
+    "count-aggregation" => count_aggregation,
+    "color" => color,
+    "include-doctests-in-line-coverage" => include_doctests_in_line_coverage,
+    "exclude-doc-tests-from-coverage" => exclude_doc_tests_from_coverage,
+    "ignore-extern-crate-coverage" => ignore_extern_crate_coverage,
+    "report-by-test-type" => report_by_test_type,
+    "min-unit-coverage" => min_unit_coverage,
+    "min-integration-coverage" => min_integration_coverage,
+    "min-doctest-coverage" => min_doctest_coverage,
+    "ignore-build-dependencies" => ignore_build_dependencies,
+    "function-coverage-only" => function_coverage_only,
+    "pgo-profile" => pgo_profile,
+    "output-per-config" => output_per_config,
+    "test-binary-env-filter" => test_binary_env_filter,
+    "print-coverage-for" => print_coverage_for,
+    "report-line-count" => report_line_count,
+    "top-n" => top_n,
+    "minimum-coverage" => minimum_coverage,
+    "compact-json" => compact_json,
+    "line-numbering" => line_numbering,
+    "output-file" => output_file,
+    "skip-clean" => skip_clean,
+    "jobs" => jobs,
+    "implicit-test-threads" => implicit_test_threads,
+    "retry-count" => retry_count,
+    "test" => test_names,
+    "bench" => bench_names,
+    "bin" => bins,
+    "binary-path" => binary_paths,
+    "function-coverage" => function_coverage,
+    "include-tests" => include_tests,
+    "badge-thresholds" => badge_thresholds,
+    "codecov" => codecov,
+    "codecov-url" => codecov_url,
+    "github-summary" => github_summary,
+    "fail-under-line" => minimum_line_coverage,
+    "fail-under-branch" => minimum_branch_coverage,
+    "compare-with-branch" => compare_branch,
+    "max-regression" => max_regression,
+    "diff-coverage" => diff_coverage,
+    "diff-from" => diff_from,
+    "diff-coverage-minimum" => diff_coverage_minimum,
+    "report-title" => report_title,
+    "workspace-members" => workspace_members,
+    "no-fail-fast" => no_fail_fast,
+    "keep-going" => keep_going,
+    "cache-dir" => cache_dir,
+    "clear-cache" => clear_cache,
+    "report-tags" => report_tags,
+    "cargo-env" => cargo_env,
+    "test-env" => test_env,
+    "strip-dead-code" => strip_dead_code,
+    "profile" => profile,
+    "follow-exec" => follow_exec,
+    "nightly" => nightly,
+    "engine" => engine,
+}
+
+/// Whether the name is allowed as a cargo profile name
+pub(crate) fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Problems found when validating a config
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Validation {
+    /// Problems which make the config unusable
+    pub errors: Vec<String>,
+    /// Options which are ignored or likely to be a mistake
+    pub warnings: Vec<String>,
+}
+
+impl Validation {
+    /// Returns true if no errors were found
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
     }
 }
 
 impl Config {
+    /// Merges the arguments into the configs from a config file. A config file
+    /// which can't be loaded counts as an error so tarpaulin doesn't run
+    /// without the configs the project asked for.
     pub fn get_config_vec(file_configs: std::io::Result<Vec<Self>>, backup: Self) -> ConfigWrapper {
-        if file_configs.is_err() {
-            warn!("Failed to deserialize config file falling back to provided args");
-            ConfigWrapper(vec![backup])
-        } else {
-            let mut confs = file_configs.unwrap();
-            for c in confs.iter_mut() {
-                c.merge(&backup);
+        match file_configs {
+            Err(e) => {
+                error!("Failed to load config file: {}", e);
+                ConfigWrapper(vec![backup], 1)
             }
-            if confs.is_empty() {
-                ConfigWrapper(vec![backup])
-            } else {
-                ConfigWrapper(confs)
+            Ok(mut confs) => {
+                for c in confs.iter_mut() {
+                    c.merge(&backup);
+                }
+                if confs.is_empty() {
+                    ConfigWrapper(vec![backup], 0)
+                } else {
+                    ConfigWrapper(confs, 0)
+                }
             }
         }
     }
 
     /// Taking an existing config look for any relevant config files
     pub fn check_for_configs(&self) -> Option<PathBuf> {
+        self.check_for_config_named(None)
+    }
+
+    /// Looks for a config file with the given name instead of the default
+    /// names, without a name this is the same as `check_for_configs`
+    pub fn check_for_config_named(&self, name: Option<&str>) -> Option<PathBuf> {
         if let Some(root) = &self.root {
-            Self::check_path_for_configs(&root)
+            Self::check_path_for_configs(&root, name)
         } else {
             if let Some(root) = self.manifest.clone().parent() {
-                Self::check_path_for_configs(&root)
+                Self::check_path_for_configs(&root, name)
             } else {
                 None
             }
         }
     }
 
-    fn check_path_for_configs<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
-        let mut path_1 = PathBuf::from(path.as_ref());
-        let mut path_2 = path_1.clone();
-        path_1.push("tarpaulin.toml");
-        path_2.push(".tarpaulin.toml");
-        if path_1.exists() {
-            Some(path_1)
-        } else if path_2.exists() {
-            Some(path_2)
-        } else {
-            None
+    fn check_path_for_configs<P: AsRef<Path>>(path: P, name: Option<&str>) -> Option<PathBuf> {
+        if let Some(name) = name {
+            let file = path.as_ref().join(name);
+            if file.exists() {
+                return Some(file);
+            }
+            warn!(
+                "No config file named {} in {}",
+                name,
+                path.as_ref().display()
+            );
+            return None;
+        }
+        let manifest = path.as_ref().join("Cargo.toml");
+        let in_manifest = has_manifest_config(&manifest);
+        let config_file = CONFIG_FILE_NAMES
+            .iter()
+            .map(|name| path.as_ref().join(name))
+            .find(|file| file.exists());
+        match config_file {
+            Some(file) => {
+                if in_manifest {
+                    warn!(
+                        "Using {} and ignoring [package.metadata.tarpaulin] in {}",
+                        file.display(),
+                        manifest.display()
+                    );
+                }
+                Some(file)
+            }
+            None if in_manifest => Some(manifest),
+            None => None,
         }
     }
 
+    /// Loads the configs from a tarpaulin config file, or from the
+    /// `[package.metadata.tarpaulin]` table if the file is a cargo manifest
     pub fn load_config_file<P: AsRef<Path>>(file: P) -> std::io::Result<Vec<Self>> {
         let mut f = File::open(file.as_ref())?;
         let mut buffer = Vec::new();
         f.read_to_end(&mut buffer)?;
-        let mut res = Self::parse_config_toml(&buffer);
+        let is_yaml = match file.as_ref().extension() {
+            Some(ext) => ext == "yml" || ext == "yaml",
+            None => false,
+        };
+        let is_manifest = file.as_ref().file_name() == Some("Cargo.toml".as_ref());
+        if is_yaml {
+            if let Some(name) = duplicate_yaml_config(&buffer) {
+                let file_name = file.as_ref().file_name().unwrap_or_default();
+                let msg = format!(
+                    "Duplicate config table '{}' in {}",
+                    name,
+                    Path::new(file_name).display()
+                );
+                error!("{}", msg);
+                return Err(Error::new(ErrorKind::InvalidData, msg));
+            }
+        }
+        let mut res = if is_manifest {
+            Self::parse_manifest_config(&buffer)
+        } else if is_yaml {
+            Self::parse_config_yaml(&buffer)
+        } else {
+            Self::parse_config_toml(&buffer)
+        }
+        .map_err(|e| Error::new(e.kind(), format!("{}: {}", file.as_ref().display(), e)));
         if let Ok(cfs) = res.as_mut() {
             for mut c in cfs.iter_mut() {
                 c.config = Some(file.as_ref().to_path_buf());
@@ -299,13 +1174,130 @@ impl Config {
     }
 
     pub fn parse_config_toml(buffer: &[u8]) -> std::io::Result<Vec<Self>> {
-        let mut map: HashMap<String, Self> = toml::from_slice(&buffer).map_err(|e| {
+        if let Ok(value) = toml::from_slice::<toml::Value>(&buffer) {
+            if is_workspace_config(&value) {
+                return Self::parse_workspace_config_toml(value);
+            }
+        }
+        let map: HashMap<String, Self> = toml::from_slice(&buffer).map_err(|e| {
+            let message = toml_error_message(&e);
+            error!("Invalid config file {}", message);
+            Error::new(ErrorKind::InvalidData, message)
+        })?;
+        if let Ok(tables) = toml::from_slice(&buffer) {
+            Self::check_unknown_keys(&tables)?;
+        }
+        Self::named_configs(map)
+    }
+
+    /// Parses a workspace config, the options at the top of the file are the
+    /// defaults of every member and each `[members.<crate>]` table overrides
+    /// them for one member. A config is made for each member which only
+    /// covers that package unless it sets `packages` itself.
+    pub fn parse_workspace_config_toml(value: toml::Value) -> std::io::Result<Vec<Self>> {
+        let mut defaults = match value {
+            toml::Value::Table(table) => table,
+            _ => toml::value::Table::new(),
+        };
+        let members = match defaults.remove("members") {
+            Some(toml::Value::Table(members)) => members,
+            _ => toml::value::Table::new(),
+        };
+        let mut tables = BTreeMap::new();
+        for (member, overrides) in members {
+            let mut table = defaults.clone();
+            if let toml::Value::Table(overrides) = overrides {
+                table.extend(overrides);
+            }
+            table
+                .entry("packages")
+                .or_insert_with(|| toml::Value::Array(vec![member.clone().into()]));
+            tables.insert(member, toml::Value::Table(table));
+        }
+        if let Ok(tables) = serde_json::to_value(&tables).and_then(serde_json::from_value) {
+            Self::check_unknown_keys(&tables)?;
+        }
+        let map: HashMap<String, Self> = toml::Value::Table(tables.into_iter().collect())
+            .try_into()
+            .map_err(|e| {
+                error!("Invalid config file {}", e);
+                Error::new(ErrorKind::InvalidData, format!("{}", e))
+            })?;
+        Self::named_configs(map)
+    }
+
+    pub fn parse_config_yaml(buffer: &[u8]) -> std::io::Result<Vec<Self>> {
+        let map: HashMap<String, Self> = serde_yaml::from_slice(&buffer).map_err(|e| {
             error!("Invalid config file {}", e);
             Error::new(ErrorKind::InvalidData, format!("{}", e))
         })?;
+        if let Ok(tables) = serde_yaml::from_slice(&buffer) {
+            Self::check_unknown_keys(&tables)?;
+        }
+        Self::named_configs(map)
+    }
+
+    /// Parses the `[package.metadata.tarpaulin]` table of a cargo manifest,
+    /// its tables are the same as the ones in a tarpaulin config file
+    pub fn parse_manifest_config(buffer: &[u8]) -> std::io::Result<Vec<Self>> {
+        let section = manifest_config_section(buffer).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                "No [package.metadata.tarpaulin] table in manifest",
+            )
+        })?;
+        if let Ok(tables) = section.clone().try_into() {
+            Self::check_unknown_keys(&tables)?;
+        }
+        let map: HashMap<String, Self> = section.try_into().map_err(|e| {
+            error!("Invalid config in manifest {}", e);
+            Error::new(ErrorKind::InvalidData, format!("{}", e))
+        })?;
+        Self::named_configs(map)
+    }
+
+    /// Returns an error listing every key in the config tables which isn't a
+    /// config option, suggesting the option it's most likely a typo of
+    fn check_unknown_keys(tables: &BTreeMap<String, serde_json::Value>) -> std::io::Result<()> {
+        let defaults = serde_json::to_value(Config::default()).unwrap_or_default();
+        let mut known = defaults
+            .as_object()
+            .map(|o| o.keys().map(String::as_str).collect::<Vec<_>>())
+            .unwrap_or_default();
+        known.push("workspace");
+        known.push("output-dir-per-config");
+
+        let mut unknown = vec![];
+        for (name, table) in tables {
+            let keys = table.as_object().into_iter().flat_map(|o| o.keys());
+            for key in keys.filter(|k| !known.contains(&k.as_str())) {
+                let message = match closest_key(key, &known) {
+                    Some(suggestion) => format!(
+                        "Unknown key `{}` in [{}], did you mean `{}`?",
+                        key, name, suggestion
+                    ),
+                    None => format!("Unknown key `{}` in [{}]", key, name),
+                };
+                error!("{}", message);
+                unknown.push(message);
+            }
+        }
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::InvalidData, unknown.join("\n")))
+        }
+    }
 
+    fn named_configs(mut map: HashMap<String, Self>) -> std::io::Result<Vec<Self>> {
         let mut result = Vec::new();
         for (name, mut conf) in map.iter_mut() {
+            if !is_valid_config_name(name) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Config name '{}' can't be used in file names", name),
+                ));
+            }
             conf.name = name.to_string();
             result.push(conf.clone());
         }
@@ -317,7 +1309,8 @@ impl Config {
     }
 
     /// Given a config made from args ignoring the config file take the
-    /// relevant settings that should be carried across and move them
+    /// relevant settings that should be carried across and move them. Every
+    /// option explicitly set on the command line overrides the config file.
     pub fn merge(&mut self, other: &Config) {
         if other.debug {
             self.debug = other.debug;
@@ -335,6 +1328,466 @@ impl Config {
             let mut excluded_files = self.excluded_files.borrow_mut();
             excluded_files.clear();
         }
+        if !other.included_files_raw.is_empty() {
+            self.included_files_raw
+                .extend_from_slice(&other.included_files_raw);
+            self.included_files.borrow_mut().clear();
+        }
+        for key in &other.args_set {
+            self.copy_option(other, key);
+        }
+    }
+
+    /// Sets the options named by `TARPAULIN_` environment variables, invalid
+    /// values and unknown options are ignored with a warning
+    pub fn apply_env_overrides(&mut self, overrides: &[(String, String)]) {
+        for (key, value) in overrides {
+            let var = format!("{}{}", ENV_PREFIX, key.to_uppercase().replace('-', "_"));
+            match self.set_from_env(key, value) {
+                Ok(true) => info!("Using {}={} from the environment", var, value),
+                Ok(false) => warn!("{} doesn't match a config option", var),
+                Err(e) => warn!("Ignoring {}: {}", var, e),
+            }
+        }
+    }
+
+    /// Sets the option with the given config key from an environment variable
+    /// value, returns false if there is no such option
+    fn set_from_env(&mut self, key: &str, value: &str) -> Result<bool, String> {
+        match key {
+            "manifest-path" => self.manifest = PathBuf::from(value),
+            "root" => self.root = Some(value.to_string()),
+            "ignored" => self.run_ignored = env_flag(value)?,
+            "ignore-tests" => self.ignore_tests = env_flag(value)?,
+            "ignore-panics" => self.ignore_panics = env_flag(value)?,
+            "suppress-panics-output" => self.suppress_panic_output = env_flag(value)?,
+            "exclude-generated" => self.exclude_generated = env_flag(value)?,
+            "generated-file-markers" => self.generated_file_markers = env_list(value),
+            "ignore-lines-matching" => self.ignore_lines_matching = env_list(value),
+            "exclusion-comment" => self.exclusion_line_comment = env_list(value),
+            "block-exclusions" => self.block_exclusions = env_flag(value)?,
+            "force-clean" => self.force_clean = env_flag(value)?,
+            "verbose" => self.verbose = env_flag(value)?,
+            "debug" => self.debug = env_flag(value)?,
+            "count" => self.count = env_flag(value)?,
+            "line" => self.line_coverage = env_flag(value)?,
+            "branch" => self.branch_coverage = env_flag(value)?,
+            "output-dir" => self.output_directory = PathBuf::from(value),
+            "coveralls" => self.coveralls = Some(value.to_string()),
+            "ciserver" => self.ci_tool = value.parse::<Ci>().map(|ci| ci.0).ok(),
+            "report-uri" => self.report_uri = Some(value.to_string()),
+            "forward" => self.forward_signals = env_flag(value)?,
+            "all-features" => self.all_features = env_flag(value)?,
+            "no-default-features" => self.no_default_features = env_flag(value)?,
+            "all" | "workspace" => self.all = env_flag(value)?,
+            "timeout" => self.test_timeout = Duration::from_secs(env_number(value)?),
+            "doc-test-timeout" => {
+                self.doctest_timeout = Some(Duration::from_secs(env_number(value)?))
+            }
+            "timeout-signal" => self.timeout_signal = parse_signal(value)?,
+            "timeout-signal-grace" => {
+                self.timeout_signal_grace = Duration::from_secs(env_number(value)?)
+            }
+            "release" => self.release = env_flag(value)?,
+            "no-run" => self.no_run = env_flag(value)?,
+            "locked" => self.locked = env_flag(value)?,
+            "frozen" => self.frozen = env_flag(value)?,
+            "target-dir" => self.target_dir = Some(PathBuf::from(value)),
+            "target" => self.target = Some(value.to_string()),
+            "sysroot" => self.sysroot = Some(PathBuf::from(value)),
+            "source-dir" => self.source_dir = Some(PathBuf::from(value)),
+            "path-mapping" => {
+                self.path_mappings = env_list(value)
+                    .iter()
+                    .map(|m| parse_path_mapping(m))
+                    .collect::<Result<_, _>>()?
+            }
+            "strip-prefix" => self.strip_prefixes = env_list(value),
+            "offline" => self.offline = env_flag(value)?,
+            "target-cpu" => self.target_cpu = Some(value.to_string()),
+            "rustflags" => self.rustflags = env_list(value),
+            "run-types" => self.run_types = env_parse_list(value)?,
+            "packages" => self.packages = env_list(value),
+            "exclude" => self.exclude = env_list(value),
+            "exclude-files" => {
+                self.excluded_files_raw = env_list(value);
+                self.excluded_files.borrow_mut().clear();
+            }
+            "include-files" => {
+                self.included_files_raw = env_list(value);
+                self.included_files.borrow_mut().clear();
+            }
+            "args" => self.varargs = env_list(value),
+            "features" => self.features = env_list(value),
+            "z" => self.unstable_features = env_list(value),
+            "out" => self.generate = env_parse_list(value)?,
+            "html-expand-uncovered" => self.html_expand_uncovered = Some(env_number(value)?),
+            "source-cache" => self.source_cache = env_flag(value)?,
+            "demangled-names" => self.demangled_names = env_flag(value)?,
+            "coverage-report-hook" => self.coverage_report_hook = Some(PathBuf::from(value)),
+            "hook-fails-build" => self.hook_fails_build = env_flag(value)?,
+            "dedup-symbols" => self.dedup_symbols = env_flag(value)?,
+            "count-aggregation" => self.count_aggregation = value.parse()?,
+            "color" => self.color = value.parse()?,
+            "include-doctests-in-line-coverage" => {
+                self.include_doctests_in_line_coverage = env_flag(value)?
+            }
+            "exclude-doc-tests-from-coverage" => {
+                self.exclude_doc_tests_from_coverage = env_flag(value)?
+            }
+            "ignore-extern-crate-coverage" => self.ignore_extern_crate_coverage = env_flag(value)?,
+            "report-by-test-type" => self.report_by_test_type = env_flag(value)?,
+            "min-unit-coverage" => self.min_unit_coverage = Some(env_number(value)?),
+            "min-integration-coverage" => self.min_integration_coverage = Some(env_number(value)?),
+            "min-doctest-coverage" => self.min_doctest_coverage = Some(env_number(value)?),
+            "ignore-build-dependencies" => self.ignore_build_dependencies = env_flag(value)?,
+            "function-coverage-only" => self.function_coverage_only = env_flag(value)?,
+            "pgo-profile" => self.pgo_profile = Some(PathBuf::from(value)),
+            "output-per-config" | "output-dir-per-config" => {
+                self.output_per_config = env_flag(value)?
+            }
+            "test-binary-env-filter" => self.test_binary_env_filter = env_list(value),
+            "print-coverage-for" => self.print_coverage_for = Some(PathBuf::from(value)),
+            "report-line-count" => self.report_line_count = env_flag(value)?,
+            "top-n" => self.top_n = Some(env_number(value)?),
+            "minimum-coverage" => self.minimum_coverage = Some(env_number(value)?),
+            "compact-json" => self.compact_json = env_flag(value)?,
+            "line-numbering" => self.line_numbering = value.parse()?,
+            "output-file" => self.output_file = Some(PathBuf::from(value)),
+            "skip-clean" => self.skip_clean = env_flag(value)?,
+            "jobs" => self.jobs = Some(env_number(value)?),
+            "implicit-test-threads" => self.implicit_test_threads = Some(env_number(value)?),
+            "retry-count" => self.retry_count = env_number(value)?,
+            "test" => self.test_names = env_list(value),
+            "bench" => self.bench_names = env_list(value),
+            "bin" => self.bins = env_list(value),
+            "binary-path" => {
+                self.binary_paths = env_list(value).into_iter().map(PathBuf::from).collect()
+            }
+            "function-coverage" => self.function_coverage = env_flag(value)?,
+            "include-tests" => self.include_tests = env_flag(value)?,
+            "badge-thresholds" => self.badge_thresholds = env_badge_thresholds(value)?,
+            "codecov" => self.codecov = Some(value.to_string()),
+            "codecov-url" => self.codecov_url = Some(value.to_string()),
+            "github-summary" => self.github_summary = env_flag(value)?,
+            "fail-under-line" => self.minimum_line_coverage = Some(env_number(value)?),
+            "fail-under-branch" => self.minimum_branch_coverage = Some(env_number(value)?),
+            "compare-with-branch" => self.compare_branch = Some(value.to_string()),
+            "max-regression" => self.max_regression = env_number(value)?,
+            "diff-coverage" => self.diff_coverage = env_flag(value)?,
+            "diff-from" => self.diff_from = Some(value.to_string()),
+            "diff-coverage-minimum" => self.diff_coverage_minimum = Some(env_number(value)?),
+            "report-title" => self.report_title = Some(value.to_string()),
+            "workspace-members" => {
+                self.workspace_members = env_list(value).iter().map(PathBuf::from).collect()
+            }
+            "no-fail-fast" => self.no_fail_fast = env_flag(value)?,
+            "keep-going" => self.keep_going = env_flag(value)?,
+            "cache-dir" => self.cache_dir = Some(PathBuf::from(value)),
+            "clear-cache" => self.clear_cache = env_flag(value)?,
+            "report-tags" => self.report_tags = env_key_values(value)?,
+            "cargo-env" => self.cargo_env = env_key_values(value)?,
+            "test-env" => self.test_env = env_key_values(value)?,
+            "strip-dead-code" => self.strip_dead_code = env_flag(value)?,
+            "profile" => self.profile = Some(value.to_string()),
+            "follow-exec" => self.follow_exec = env_flag(value)?,
+            "nightly" => self.nightly = env_flag(value)?,
+            "engine" => self.engine = value.parse()?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Returns the timeout for the given test binary. Binaries are matched by
+    /// their file name with or without the hash cargo appends to it.
+    pub fn binary_timeout(&self, binary: &Path) -> Duration {
+        let name = binary
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let stem = name.rsplitn(2, '-').last().unwrap_or(name);
+        self.timeout_per_binary
+            .get(name)
+            .or_else(|| self.timeout_per_binary.get(stem))
+            .cloned()
+            .unwrap_or(self.test_timeout)
+    }
+
+    /// The `--test-threads` argument for the test executables, none if the
+    /// number of threads isn't set or the test arguments already set it
+    pub fn test_threads_arg(&self) -> Option<String> {
+        let threads = self.implicit_test_threads?;
+        if self.varargs.iter().any(|a| a.starts_with("--test-threads")) {
+            None
+        } else {
+            Some(format!("--test-threads={}", threads))
+        }
+    }
+
+    /// Compiles the patterns of lines to ignore, invalid patterns are skipped
+    pub fn ignored_line_patterns(&self) -> Vec<Regex> {
+        self.ignore_lines_matching
+            .iter()
+            .filter_map(|pattern| Regex::new(pattern).ok())
+            .collect()
+    }
+
+    /// Returns true if the environment variable shouldn't be passed to the
+    /// test executables. Filters can use `*` to match any characters.
+    pub fn is_env_filtered(&self, name: &str) -> bool {
+        self.test_binary_env_filter
+            .iter()
+            .any(|filter| glob_match(filter, name))
+    }
+
+    /// The `-Z` flags passed to rustc with `--nightly`. The set follows what
+    /// currently gives the most accurate coverage and may change between
+    /// versions of tarpaulin as nightly features are stabilised or removed.
+    pub fn nightly_defaults() -> Vec<String> {
+        vec![
+            // MIR optimisations still run at opt-level 0 and can inline or
+            // remove statements so their lines are never hit
+            "mir-opt-level=0".to_string(),
+            // Without shared generics the instances of the project's generic
+            // functions are built into each test executable
+            "share-generics=no".to_string(),
+        ]
+    }
+
+    /// Output formats to generate reports in, a format given more than once by
+    /// the config file, arguments or environment is only generated once
+    pub fn all_output_formats(&self) -> Vec<OutputFile> {
+        let mut outputs = self.generate.clone();
+        dedup_outputs(&mut outputs);
+        outputs
+    }
+
+    /// Checks the config for conflicting or out of range options
+    pub fn validate(&self) -> Validation {
+        let mut result = Validation::default();
+        if self.all_features && self.no_default_features {
+            result
+                .errors
+                .push("all-features and no-default-features can't both be set".to_string());
+        }
+        if self.jobs == Some(0) {
+            result.errors.push("jobs must be at least 1".to_string());
+        }
+        if self.output_per_config && self.name.is_empty() {
+            result.errors.push(
+                "output-per-config writes to a subdirectory named after the config, it can't be \
+                 used without a named config"
+                    .to_string(),
+            );
+        }
+        if self.top_n == Some(0) {
+            result.errors.push("top-n must be at least 1".to_string());
+        }
+        if self.top_n.is_some() && !self.report_line_count {
+            result
+                .warnings
+                .push("top-n has no effect without report-line-count".to_string());
+        }
+        if self.implicit_test_threads == Some(0) {
+            result
+                .errors
+                .push("implicit-test-threads must be at least 1".to_string());
+        }
+        if self.force_clean && self.skip_clean {
+            result
+                .errors
+                .push("force-clean and skip-clean can't both be set".to_string());
+        }
+        if self.exclude_doc_tests_from_coverage && self.include_doctests_in_line_coverage {
+            result.errors.push(
+                "exclude-doc-tests-from-coverage and include-doctests-in-line-coverage can't both \
+                 be set"
+                    .to_string(),
+            );
+        }
+        if let Some(ref profile) = self.profile {
+            if !is_valid_profile_name(profile) {
+                result.errors.push(format!(
+                    "profile `{}` may only contain letters, numbers, `-` and `_`",
+                    profile
+                ));
+            }
+            if self.release {
+                result
+                    .errors
+                    .push("release and profile can't both be set".to_string());
+            }
+        }
+        if let Some(problem) = manifest_problem(&self.manifest) {
+            result.errors.push(problem);
+        }
+        for flag in instrumentation_conflicts(&self.rustflags) {
+            result.warnings.push(format!(
+                "rustflags `{}` conflicts with the flags tarpaulin builds with, coverage may be \
+                 missing",
+                flag
+            ));
+        }
+        if self.follow_exec && self.engine == CoverageEngine::LlvmCov {
+            result
+                .warnings
+                .push("follow-exec only applies to the ptrace engine".to_string());
+        }
+        if self.frozen && (self.locked || self.offline) {
+            result
+                .warnings
+                .push("frozen already implies locked and offline".to_string());
+        }
+        if self.ignore_tests && self.include_tests {
+            result
+                .errors
+                .push("ignore-tests and include-tests can't both be set".to_string());
+        }
+        let percentages = [
+            ("html-expand-uncovered", self.html_expand_uncovered),
+            ("minimum-coverage", self.minimum_coverage),
+            ("fail-under-line", self.minimum_line_coverage),
+            ("fail-under-branch", self.minimum_branch_coverage),
+            ("min-unit-coverage", self.min_unit_coverage),
+            ("min-integration-coverage", self.min_integration_coverage),
+            ("min-doctest-coverage", self.min_doctest_coverage),
+            ("diff-coverage-minimum", self.diff_coverage_minimum),
+        ];
+        if !self.diff_coverage && (self.diff_from.is_some() || self.diff_coverage_minimum.is_some())
+        {
+            result.warnings.push(
+                "diff-from and diff-coverage-minimum have no effect without diff-coverage"
+                    .to_string(),
+            );
+        }
+        if self.max_regression < 0.0 || self.max_regression > 100.0 {
+            result
+                .errors
+                .push("max-regression must be between 0 and 100".to_string());
+        }
+        for (name, value) in percentages.iter() {
+            if let Some(value) = value {
+                if *value < 0.0 || *value > 100.0 {
+                    result
+                        .errors
+                        .push(format!("{} must be between 0 and 100", name));
+                }
+            }
+        }
+        let (low, high) = self.badge_thresholds;
+        if low < 0.0 || high > 100.0 || low > high {
+            result.errors.push(
+                "badge-thresholds must be two increasing percentages between 0 and 100".to_string(),
+            );
+        }
+        for (pattern, threshold) in &self.file_thresholds {
+            if *threshold < 0.0 || *threshold > 100.0 {
+                result.errors.push(format!(
+                    "file-coverage-threshold for {} must be between 0 and 100",
+                    pattern
+                ));
+            }
+        }
+        // File patterns use `*` wildcards, only the ignored lines are regexes
+        for pattern in self
+            .excluded_files_raw
+            .iter()
+            .chain(self.included_files_raw.iter())
+        {
+            if let Err(e) = check_wildcard_pattern(pattern) {
+                result
+                    .errors
+                    .push(format!("Invalid pattern {}: {}", pattern, e));
+            }
+        }
+        for pattern in self.file_thresholds.keys() {
+            if let Err(e) = anchored_wildcard_regex(pattern) {
+                result.errors.push(format!(
+                    "Invalid file-coverage-threshold pattern {}: {}",
+                    pattern, e
+                ));
+            }
+        }
+        for pattern in &self.ignore_lines_matching {
+            if let Err(e) = Regex::new(pattern) {
+                result
+                    .errors
+                    .push(format!("Invalid regex {}: {}", pattern, e));
+            }
+        }
+        if let Err(e) = self.check_output_file() {
+            result.errors.push(e);
+        }
+        let outputs = self.all_output_formats();
+        if outputs.len() != self.generate.len() {
+            result
+                .warnings
+                .push("Output formats are listed more than once".to_string());
+        }
+        for output in &outputs {
+            match output {
+                OutputFile::Html
+                | OutputFile::Xml
+                | OutputFile::Lcov
+                | OutputFile::Json
+                | OutputFile::SonarQube
+                | OutputFile::Badges => {}
+                _ => result
+                    .errors
+                    .push(format!("Output format {} is not supported", output)),
+            }
+        }
+        let codecov_uri = self
+            .report_uri
+            .as_ref()
+            .map(|uri| uri.contains("codecov"))
+            .unwrap_or(false);
+        if self.coveralls.is_some() && codecov_uri {
+            result
+                .warnings
+                .push("report-uri points to codecov but a coveralls report is sent".to_string());
+        }
+        let has_minimum = self.min_unit_coverage.is_some()
+            || self.min_integration_coverage.is_some()
+            || self.min_doctest_coverage.is_some();
+        if has_minimum && !self.report_by_test_type {
+            result
+                .warnings
+                .push("Minimum coverage per test type requires report-by-test-type".to_string());
+        }
+        if self.coveralls.is_some() && self.ci_tool.is_none() {
+            result.warnings.push(
+                "coveralls is set without ciserver, the CI service is inferred from the environment"
+                    .to_string(),
+            );
+        }
+        if self.report_uri.is_some() && self.coveralls.is_none() {
+            result
+                .warnings
+                .push("report-uri is only used with coveralls".to_string());
+        }
+        if self.minimum_branch_coverage.is_some() {
+            result.warnings.push(
+                "fail-under-branch isn't checked as branch coverage isn't collected yet"
+                    .to_string(),
+            );
+        }
+        if self.codecov_url.is_some() && self.codecov.is_none() {
+            result
+                .warnings
+                .push("codecov-url is only used with codecov".to_string());
+        }
+        if self.hook_fails_build && self.coverage_report_hook.is_none() {
+            result
+                .warnings
+                .push("hook-fails-build is set without a coverage-report-hook".to_string());
+        }
+        if self.suppress_panic_output && !self.ignore_panics {
+            result
+                .warnings
+                .push("suppress-panics-output is set without ignore-panics".to_string());
+        }
+        result
     }
 
     #[inline]
@@ -342,6 +1795,22 @@ impl Config {
         self.coveralls.is_some()
     }
 
+    #[inline]
+    pub fn is_codecov(&self) -> bool {
+        self.codecov.is_some()
+    }
+
+    /// Token to upload to codecov with, the `CODECOV_TOKEN` environment
+    /// variable is used when no token is given. Public repositories on some
+    /// CI services can upload without a token.
+    pub fn codecov_token(&self) -> Option<String> {
+        match self.codecov {
+            Some(ref token) if !token.is_empty() => Some(token.clone()),
+            Some(_) => env::var("CODECOV_TOKEN").ok().filter(|t| !t.is_empty()),
+            None => None,
+        }
+    }
+
     #[inline]
     pub fn exclude_path(&self, path: &Path) -> bool {
         if self.excluded_files.borrow().len() != self.excluded_files_raw.len() {
@@ -350,12 +1819,27 @@ impl Config {
             excluded_files.clear();
             excluded_files.append(&mut compiled);
         }
+        if self.included_files.borrow().len() != self.included_files_raw.len() {
+            let mut included_files = self.included_files.borrow_mut();
+            let mut compiled = regexes_from_patterns(&self.included_files_raw);
+            included_files.clear();
+            included_files.append(&mut compiled);
+        }
         let project = self.strip_base_dir(path);
+        let project = project.to_str().unwrap_or("");
 
+        let included = self.included_files.borrow();
+        if !included.is_empty() && !included.iter().any(|x| x.is_match(project)) {
+            return true;
+        }
+        // Later patterns take precedence so negations can re-include files
         self.excluded_files
             .borrow()
             .iter()
-            .any(|x| x.is_match(project.to_str().unwrap_or("")))
+            .rev()
+            .find(|(_, x)| x.is_match(project))
+            .map(|(negated, _)| !negated)
+            .unwrap_or(false)
     }
 
     ///
@@ -383,12 +1867,119 @@ impl Config {
         path_relative_from(path, &self.get_base_dir()).unwrap_or_else(|| path.to_path_buf())
     }
 
+    /// Removes the first matching strip prefix from the path and then applies
+    /// the first path mapping whose search string is in the path
+    pub fn map_path(&self, path: &Path) -> PathBuf {
+        let path = self.strip_path_prefix(path);
+        let path_str = path.to_string_lossy();
+        self.path_mappings
+            .iter()
+            .find(|(from, _)| path_str.contains(from.as_str()))
+            .map(|(from, to)| PathBuf::from(path_str.replacen(from.as_str(), to, 1)))
+            .unwrap_or_else(|| path.clone())
+    }
+
+    /// Removes the first strip prefix the path starts with. Prefixes match
+    /// whole directories, `*` matches any characters in a directory name and
+    /// a leading `~` is the home directory.
+    fn strip_path_prefix(&self, path: &Path) -> PathBuf {
+        for prefix in &self.strip_prefixes {
+            let prefix = match (prefix.strip_prefix('~'), env::var_os("HOME")) {
+                (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+                    format!("{}{}", home.to_string_lossy(), rest)
+                }
+                _ => prefix.clone(),
+            };
+            let mut components = path.components();
+            let matched = Path::new(&prefix).components().all(|expected| {
+                components
+                    .next()
+                    .map(|c| {
+                        glob_match(
+                            &expected.as_os_str().to_string_lossy(),
+                            &c.as_os_str().to_string_lossy(),
+                        )
+                    })
+                    .unwrap_or(false)
+            });
+            let rest = components.as_path();
+            if matched && rest != Path::new("") {
+                return rest.to_path_buf();
+            }
+        }
+        path.to_path_buf()
+    }
+
+    /// Directory to write report files to. When writing output per config
+    /// this is a subdirectory of the output directory named after the config.
+    pub fn report_directory(&self) -> PathBuf {
+        if self.output_per_config && !self.name.is_empty() {
+            self.output_directory.join(&self.name)
+        } else {
+            self.output_directory.clone()
+        }
+    }
+
+    /// Path the report in the given format is written to, this is the output
+    /// file if one is set or the format's default name in the report directory
+    pub fn output_path(&self, format: &OutputFile) -> PathBuf {
+        match self.output_file {
+            Some(ref file) => file.clone(),
+            None => self.report_directory().join(format.default_filename()),
+        }
+    }
+
+    /// An output file can only hold one report so it requires exactly one
+    /// output format which writes to a file
+    pub fn check_output_file(&self) -> Result<(), String> {
+        let file_formats = self
+            .all_output_formats()
+            .iter()
+            .filter(|f| !f.default_filename().is_empty())
+            .count();
+        if self.output_file.is_some() && file_formats != 1 {
+            Err(format!(
+                "--output-file only applies when exactly one output format is selected, {} were given",
+                file_formats
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Run types to build and run, naming binaries with `--bin` runs them even
+    /// if `Bins` isn't one of the run types
+    pub fn build_run_types(&self) -> Vec<RunType> {
+        let mut run_types = self.run_types.clone();
+        if !self.bins.is_empty() && !run_types.contains(&RunType::Bins) {
+            run_types.push(RunType::Bins);
+        }
+        run_types
+    }
+
     #[inline]
     pub fn is_default_output_dir(&self) -> bool {
         self.output_directory == env::current_dir().unwrap()
     }
 }
 
+/// Returns the `[package.metadata.tarpaulin]` table of a cargo manifest
+fn manifest_config_section(buffer: &[u8]) -> Option<toml::Value> {
+    let manifest: toml::Value = toml::from_slice(buffer).ok()?;
+    manifest
+        .get("package")?
+        .get("metadata")?
+        .get("tarpaulin")
+        .cloned()
+}
+
+/// Checks if the cargo manifest at the path contains tarpaulin configs
+fn has_manifest_config(manifest: &Path) -> bool {
+    std::fs::read(manifest)
+        .map(|buffer| manifest_config_section(&buffer).is_some())
+        .unwrap_or(false)
+}
+
 /// Gets the relative path from one directory to another, if it exists.
 /// Credit to brson from this commit from 2015
 /// https://github.com/rust-lang/rust/pull/23283/files
@@ -438,6 +2029,7 @@ fn path_relative_from(path: &Path, base: &Path) -> Option<PathBuf> {
 mod tests {
     use super::*;
     use clap::App;
+    use std::fs;
 
     #[test]
     fn exclude_paths() {
@@ -445,7 +2037,7 @@ mod tests {
             .args_from_usage("--exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'")
             .get_matches_from_safe(vec!["tarpaulin", "--exclude-files", "*module*"])
             .unwrap();
-        let conf = ConfigWrapper::from(&matches).0;
+        let conf = ConfigWrapper::from(&matches);
         assert_eq!(conf.len(), 1);
         assert!(conf[0].exclude_path(Path::new("src/module/file.rs")));
         assert!(!conf[0].exclude_path(Path::new("src/mod.rs")));
@@ -453,13 +2045,51 @@ mod tests {
         assert!(conf[0].exclude_path(Path::new("module.rs")));
     }
 
+    #[test]
+    fn include_paths() {
+        let matches = App::new("tarpaulin")
+            .args_from_usage(
+                "--exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'
+                 --include-files [FILE]... 'Only include given files in coverage results has * wildcard'",
+            )
+            .get_matches_from_safe(vec![
+                "tarpaulin",
+                "--include-files",
+                "src/*",
+                "--exclude-files",
+                "*/generated/*",
+            ])
+            .unwrap();
+        let conf = ConfigWrapper::from(&matches);
+        assert_eq!(conf.len(), 1);
+        assert!(!conf[0].exclude_path(Path::new("src/lib.rs")));
+        assert!(!conf[0].exclude_path(Path::new("src/module/file.rs")));
+        assert!(conf[0].exclude_path(Path::new("src/generated/file.rs")));
+        assert!(conf[0].exclude_path(Path::new("vendor/lib.rs")));
+        assert!(conf[0].exclude_path(Path::new("build.rs")));
+    }
+
+    #[test]
+    fn negated_exclusions() {
+        let mut config = Config::default();
+        config.excluded_files_raw = vec![
+            "generated/*".to_string(),
+            "!generated/important.rs".to_string(),
+            "generated/important.rs.bak".to_string(),
+        ];
+        assert!(config.exclude_path(Path::new("generated/schema.rs")));
+        assert!(!config.exclude_path(Path::new("generated/important.rs")));
+        assert!(config.exclude_path(Path::new("generated/important.rs.bak")));
+        assert!(!config.exclude_path(Path::new("src/lib.rs")));
+    }
+
     #[test]
     fn no_exclusions() {
         let matches = App::new("tarpaulin")
             .args_from_usage("--exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'")
             .get_matches_from_safe(vec!["tarpaulin"])
             .unwrap();
-        let conf = ConfigWrapper::from(&matches).0;
+        let conf = ConfigWrapper::from(&matches);
         assert_eq!(conf.len(), 1);
         assert!(!conf[0].exclude_path(Path::new("src/module/file.rs")));
         assert!(!conf[0].exclude_path(Path::new("src/mod.rs")));
@@ -473,7 +2103,7 @@ mod tests {
             .args_from_usage("--exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'")
             .get_matches_from_safe(vec!["tarpaulin", "--exclude-files", "*/lib.rs"])
             .unwrap();
-        let conf = ConfigWrapper::from(&matches).0;
+        let conf = ConfigWrapper::from(&matches);
         assert_eq!(conf.len(), 1);
         assert!(conf[0].exclude_path(Path::new("src/lib.rs")));
         assert!(!conf[0].exclude_path(Path::new("src/mod.rs")));
@@ -482,29 +2112,713 @@ mod tests {
     }
 
     #[test]
-    fn relative_path_test() {
-        let path_a = Path::new("/this/should/form/a/rel/path/");
-        let path_b = Path::new("/this/should/form/b/rel/path/");
+    fn builder_config() {
+        let config = Config::default()
+            .with_root("/home/rust")
+            .with_manifest("/home/rust/foo/Cargo.toml")
+            .with_run_types(vec![RunType::Tests, RunType::Doctests])
+            .with_packages(&["pack_1"])
+            .with_features(&["async", "full"])
+            .with_timeout(Duration::from_secs(120))
+            .with_generate(vec![OutputFile::Html])
+            .with_output_directory("/tmp");
 
-        let rel_path = path_relative_from(path_b, path_a);
-        assert!(rel_path.is_some());
+        assert_eq!(config.root, Some("/home/rust".to_string()));
+        assert_eq!(config.manifest, PathBuf::from("/home/rust/foo/Cargo.toml"));
+        assert_eq!(config.run_types, vec![RunType::Tests, RunType::Doctests]);
+        assert_eq!(config.packages, vec!["pack_1".to_string()]);
         assert_eq!(
-            rel_path.unwrap().to_str().unwrap(),
-            "../../../b/rel/path",
-            "Wrong relative path"
+            config.features,
+            vec!["async".to_string(), "full".to_string()]
         );
+        assert_eq!(config.test_timeout, Duration::from_secs(120));
+        assert_eq!(config.generate, vec![OutputFile::Html]);
+        assert_eq!(config.output_directory, PathBuf::from("/tmp"));
+    }
 
-        let path_a = Path::new("/this/should/not/form/a/rel/path/");
-        let path_b = Path::new("./this/should/not/form/a/rel/path/");
-
-        let rel_path = path_relative_from(path_b, path_a);
-        assert_eq!(rel_path, None, "Did not expect relative path");
+    #[test]
+    fn per_binary_timeout() {
+        let toml = r#"[global]
+        timeout = "30s"
 
-        let path_a = Path::new("./this/should/form/a/rel/path/");
-        let path_b = Path::new("./this/should/form/b/rel/path/");
+        [global.timeout-per-binary]
+        integration_tests = "5m"
+        "#;
 
-        let rel_path = path_relative_from(path_b, path_a);
-        assert!(rel_path.is_some());
+        let configs = Config::parse_config_toml(toml.as_bytes()).unwrap();
+        let config = &configs[0];
+        assert_eq!(
+            config.binary_timeout(Path::new("target/debug/integration_tests-0123456789abcdef")),
+            Duration::from_secs(300)
+        );
+        assert_eq!(
+            config.binary_timeout(Path::new("target/debug/integration_tests")),
+            Duration::from_secs(300)
+        );
+        assert_eq!(
+            config.binary_timeout(Path::new("target/debug/my_crate-0123456789abcdef")),
+            Duration::from_secs(30)
+        );
+    }
+
+    #[test]
+    fn output_per_config_directories() {
+        let toml = r#"[unit]
+        output-dir = "/tmp/coverage"
+        output-per-config = true
+
+        [integration]
+        output-dir = "/tmp/coverage"
+        output-per-config = true
+        "#;
+
+        let mut configs = Config::parse_config_toml(toml.as_bytes()).unwrap();
+        configs.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(
+            configs[0].report_directory(),
+            PathBuf::from("/tmp/coverage/integration")
+        );
+        assert_eq!(
+            configs[1].report_directory(),
+            PathBuf::from("/tmp/coverage/unit")
+        );
+
+        configs[0].output_per_config = false;
+        assert_eq!(
+            configs[0].report_directory(),
+            PathBuf::from("/tmp/coverage")
+        );
+
+        let toml = r#"[unit]
+        output-dir-per-config = true
+        "#;
+        let configs = Config::parse_config_toml(toml.as_bytes()).unwrap();
+        assert!(configs[0].output_per_config);
+        assert!(configs[0].validate().is_valid());
+        let mut config = Config::default();
+        config.output_per_config = true;
+        assert!(!config.validate().is_valid());
+    }
+
+    #[test]
+    fn validate_conflicting_features() {
+        let toml = r#"[features]
+        all-features = true
+        no-default-features = true
+        min-unit-coverage = 80.0
+        "#;
+
+        let configs = Config::parse_config_toml(toml.as_bytes()).unwrap();
+        let validation = configs[0].validate();
+        assert!(!validation.is_valid());
+        assert_eq!(validation.errors.len(), 1);
+        assert_eq!(validation.warnings.len(), 1);
+
+        let validation = Config::default().validate();
+        assert!(validation.is_valid());
+        assert!(validation.warnings.is_empty());
+    }
+
+    #[test]
+    fn validate_branch_threshold() {
+        let mut config = Config::default();
+        config.minimum_branch_coverage = Some(40.0);
+        config.branch_coverage = true;
+        let validation = config.validate();
+        assert!(validation.is_valid());
+        assert_eq!(
+            validation.warnings,
+            vec!["fail-under-branch isn't checked as branch coverage isn't collected yet"]
+        );
+    }
+
+    #[test]
+    fn validate_jobs() {
+        let toml = r#"[build]
+        jobs = 0
+        "#;
+        let configs = Config::parse_config_toml(toml.as_bytes()).unwrap();
+        let validation = configs[0].validate();
+        assert_eq!(
+            validation.errors,
+            vec!["jobs must be at least 1".to_string()]
+        );
+    }
+
+    #[test]
+    fn validate_badge_thresholds() {
+        let toml = r#"[build]
+        badge-thresholds = [90.0, 70.0]
+        "#;
+        let configs = Config::parse_config_toml(toml.as_bytes()).unwrap();
+        let validation = configs[0].validate();
+        assert_eq!(
+            validation.errors,
+            vec![
+                "badge-thresholds must be two increasing percentages between 0 and 100".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_profile() {
+        let mut config = Config::default();
+        config.profile = Some("coverage_ci-2".to_string());
+        assert!(config.validate().is_valid());
+        config.profile = Some("cover age".to_string());
+        let error = "profile `cover age` may only contain letters, numbers, `-` and `_`";
+        assert_eq!(config.validate().errors, vec![error.to_string()]);
+        config.profile = Some("coverage".to_string());
+        config.release = true;
+        let error = "release and profile can't both be set";
+        assert_eq!(config.validate().errors, vec![error.to_string()]);
+    }
+
+    #[test]
+    fn validate_manifest_path() {
+        let dir = env::current_dir().unwrap();
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--manifest-path -m [PATH]")
+            .get_matches_from_safe(vec!["tarpaulin", "-m", "."])
+            .unwrap();
+        let conf = ConfigWrapper::from(&matches);
+        assert_eq!(conf[0].manifest, dir.join("Cargo.toml"));
+        assert!(manifest_problem(&conf[0].manifest).is_none());
+
+        let mut config = Config::default();
+        config.manifest = dir.join("README.md");
+        let error = format!(
+            "manifest-path {} isn't a Cargo.toml file or a directory containing one",
+            config.manifest.display()
+        );
+        assert_eq!(config.validate().errors, vec![error]);
+        config.manifest = dir.join("missing").join("Cargo.toml");
+        let error = format!("manifest-path {} doesn't exist", config.manifest.display());
+        assert_eq!(config.validate().errors, vec![error]);
+    }
+
+    #[test]
+    fn test_threads_arg() {
+        let mut config = Config::default();
+        assert_eq!(config.test_threads_arg(), None);
+        config.implicit_test_threads = Some(1);
+        assert_eq!(
+            config.test_threads_arg(),
+            Some("--test-threads=1".to_string())
+        );
+        config.implicit_test_threads = Some(4);
+        assert_eq!(
+            config.test_threads_arg(),
+            Some("--test-threads=4".to_string())
+        );
+        config.varargs = vec!["--test-threads".to_string(), "2".to_string()];
+        assert_eq!(config.test_threads_arg(), None);
+
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--implicit-test-threads [N] --single-threaded")
+            .get_matches_from_safe(vec!["tarpaulin", "--single-threaded"])
+            .unwrap();
+        let conf = ConfigWrapper::from(&matches);
+        assert_eq!(conf[0].implicit_test_threads, Some(1));
+    }
+
+    #[test]
+    fn color_choice() {
+        assert!(Color::Always.use_color());
+        assert!(!Color::Never.use_color());
+        assert_eq!("never".parse::<Color>(), Ok(Color::Never));
+        let mut config = Config::default();
+        assert_eq!(config.set_from_env("color", "always"), Ok(true));
+        assert_eq!(config.color, Color::Always);
+    }
+
+    #[test]
+    fn timeout_signal_names() {
+        assert_eq!(parse_signal("SIGTERM"), Ok(Signal::SIGTERM));
+        assert_eq!(parse_signal("int"), Ok(Signal::SIGINT));
+        assert!(parse_signal("SIGNOPE").is_err());
+        let mut config = Config::default();
+        assert_eq!(config.timeout_signal, Signal::SIGKILL);
+        assert_eq!(config.set_from_env("timeout-signal", "TERM"), Ok(true));
+        assert_eq!(config.timeout_signal, Signal::SIGTERM);
+        assert!(config.set_from_env("timeout-signal", "SIGNOPE").is_err());
+    }
+
+    #[test]
+    fn validate_rustflags() {
+        let mut config = Config::default();
+        config.rustflags = vec!["-C link-arg=-fuse-ld=lld --cfg ci".to_string()];
+        assert!(config.validate().warnings.is_empty());
+        config
+            .rustflags
+            .push("-C debuginfo=0 -Copt-level=3".to_string());
+        assert_eq!(
+            instrumentation_conflicts(&config.rustflags),
+            vec!["-C debuginfo=0".to_string(), "-C opt-level=3".to_string()]
+        );
+        assert_eq!(config.validate().warnings.len(), 2);
+        assert!(config.validate().is_valid());
+    }
+
+    #[test]
+    fn validate_file_patterns() {
+        let matches = App::new("tarpaulin")
+            .args_from_usage(
+                "--exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'
+                 --include-files [FILE]... 'Only include given files in coverage results has * wildcard'",
+            )
+            .get_matches_from_safe(vec![
+                "tarpaulin",
+                "--exclude-files",
+                "*/lib.rs",
+                "!tests/*",
+                "--include-files",
+                "src/*",
+            ])
+            .unwrap();
+        let conf = ConfigWrapper::from(&matches);
+        assert!(conf[0].validate().is_valid());
+        assert!(conf.check_valid().is_ok());
+
+        let mut config = Config::default();
+        config.file_thresholds.insert("src/*.rs".to_string(), 90.0);
+        config.ignore_lines_matching = vec![r"^\s*log::.*".to_string()];
+        assert!(config.validate().is_valid());
+        config.excluded_files_raw = vec!["src/(".to_string()];
+        config.ignore_lines_matching = vec!["unreachable!(".to_string()];
+        assert_eq!(config.validate().errors.len(), 2);
+    }
+
+    #[test]
+    fn codecov_token() {
+        let mut config = Config::default();
+        assert_eq!(config.codecov_token(), None);
+        config.codecov = Some("token".to_string());
+        assert_eq!(config.codecov_token(), Some("token".to_string()));
+    }
+
+    #[test]
+    fn validate_test_code_options() {
+        let mut config = Config::default();
+        config.include_tests = true;
+        assert!(config.validate().is_valid());
+        config.ignore_tests = true;
+        assert_eq!(
+            config.validate().errors,
+            vec!["ignore-tests and include-tests can't both be set".to_string()]
+        );
+
+        let mut config = Config::default();
+        config.exclude_doc_tests_from_coverage = true;
+        assert!(config.validate().is_valid());
+        config.include_doctests_in_line_coverage = true;
+        assert!(!config.validate().is_valid());
+    }
+
+    #[test]
+    fn validate_overlapping_options() {
+        let mut config = Config::default();
+        config.locked = true;
+        config.offline = true;
+        assert!(config.validate().warnings.is_empty());
+        config.frozen = true;
+        let warning = "frozen already implies locked and offline";
+        assert_eq!(config.validate().warnings, vec![warning.to_string()]);
+
+        let mut config = Config::default();
+        config.coveralls = Some("key".to_string());
+        let warning = "coveralls is set without ciserver, the CI service is inferred from the \
+                       environment";
+        assert_eq!(config.validate().warnings, vec![warning.to_string()]);
+        config.ci_tool = Some(CiService::Travis);
+        assert!(config.validate().is_valid());
+        assert!(config.validate().warnings.is_empty());
+
+        let mut config = Config::default();
+        config.follow_exec = true;
+        config.engine = CoverageEngine::LlvmCov;
+        let warning = "follow-exec only applies to the ptrace engine";
+        assert_eq!(config.validate().warnings, vec![warning.to_string()]);
+    }
+
+    #[test]
+    fn strip_prefixes_before_mapping() {
+        let mut config = Config::default();
+        config.strip_prefixes = vec!["/build/*".to_string(), "/home".to_string()];
+        config.path_mappings = vec![("crate-1.0.0".to_string(), "vendor/crate".to_string())];
+        assert_eq!(
+            config.map_path(Path::new("/build/8f2a/crate-1.0.0/src/lib.rs")),
+            PathBuf::from("vendor/crate/src/lib.rs")
+        );
+        // Prefixes match whole directories and never strip the whole path
+        assert_eq!(
+            config.map_path(Path::new("/homework/src/lib.rs")),
+            PathBuf::from("/homework/src/lib.rs")
+        );
+        assert_eq!(config.map_path(Path::new("/home")), PathBuf::from("/home"));
+        assert_eq!(
+            config.map_path(Path::new("/home/rust/src/lib.rs")),
+            PathBuf::from("rust/src/lib.rs")
+        );
+    }
+
+    #[test]
+    fn bins_imply_run_type() {
+        let mut config = Config::default();
+        assert_eq!(config.build_run_types(), vec![RunType::Tests]);
+
+        config.bins = vec!["server".to_string()];
+        assert_eq!(
+            config.build_run_types(),
+            vec![RunType::Tests, RunType::Bins]
+        );
+
+        config.run_types = vec![RunType::Bins];
+        assert_eq!(config.build_run_types(), vec![RunType::Bins]);
+    }
+
+    #[test]
+    fn output_formats_deduplicated() {
+        let toml = r#"[global]
+        out = ["Html", "Lcov", "Html"]
+        coveralls = "hello"
+        report-uri = "https://codecov.io/upload"
+        "#;
+
+        let mut configs = Config::parse_config_toml(toml.as_bytes()).unwrap();
+        let config = configs.remove(0);
+        assert_eq!(config.generate.len(), 3);
+        assert_eq!(
+            config.all_output_formats(),
+            vec![OutputFile::Html, OutputFile::Lcov]
+        );
+        let validation = config.validate();
+        assert!(validation.is_valid());
+        let duplicates = "Output formats are listed more than once".to_string();
+        assert!(validation.warnings.contains(&duplicates));
+        let codecov = "report-uri points to codecov but a coveralls report is sent".to_string();
+        assert!(validation.warnings.contains(&codecov));
+
+        let matches = App::new("tarpaulin")
+            .args_from_usage(
+                "--ignore-config 'Ignore config files'
+                 --out -o [FMT]... 'Output format'",
+            )
+            .get_matches_from_safe(vec![
+                "tarpaulin",
+                "--ignore-config",
+                "-o",
+                "Lcov",
+                "Html",
+                "Lcov",
+            ])
+            .unwrap();
+        let config = ConfigWrapper::from(&matches).0.remove(0);
+        assert_eq!(
+            config.all_output_formats(),
+            vec![OutputFile::Lcov, OutputFile::Html]
+        );
+        assert!(config.validate().warnings.contains(&duplicates));
+
+        let config = Config::default().with_generate(vec![
+            OutputFile::Xml,
+            OutputFile::Xml,
+            OutputFile::Toml,
+        ]);
+        let validation = config.validate();
+        assert_eq!(validation.errors.len(), 1);
+        assert_eq!(validation.warnings.len(), 1);
+    }
+
+    #[test]
+    fn merge_explicit_args() {
+        let matches = App::new("tarpaulin")
+            .args_from_usage(
+                "--ignore-config 'Ignore config files'
+                 --release 'Build in release mode'
+                 --features [FEATURE]... 'Features to build'
+                 --timeout -t [SECONDS] 'Test timeout'
+                 --out -o [FMT]... 'Output format'
+                 --branch -b 'Branch coverage'
+                 --line -l 'Line coverage'",
+            )
+            .get_matches_from_safe(vec![
+                "tarpaulin",
+                "--ignore-config",
+                "--features",
+                "cli",
+                "-t",
+                "10",
+            ])
+            .unwrap();
+        let args_config = ConfigWrapper::from(&matches).0.remove(0);
+        assert!(args_config.args_set.contains("features"));
+        assert!(args_config.args_set.contains("timeout"));
+        assert!(!args_config.args_set.contains("release"));
+
+        let toml = r#"[file]
+        features = ["file"]
+        release = true
+        out = ["Lcov"]
+        timeout = "5m"
+        "#;
+        let configs = Config::parse_config_toml(toml.as_bytes());
+        let config = Config::get_config_vec(configs, args_config).0.remove(0);
+        assert_eq!(config.features, vec!["cli"]);
+        assert_eq!(config.test_timeout, Duration::from_secs(10));
+        assert!(config.release);
+        assert_eq!(config.generate, vec![OutputFile::Lcov]);
+    }
+
+    #[test]
+    fn invalid_config_file_not_ignored() {
+        let dir = env::temp_dir().join("tarpaulin-invalid-config-file");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tarpaulin.toml");
+        fs::write(&path, "[all]\ntimout = \"5s\"\n").unwrap();
+        let wrapper = Config::get_config_vec(Config::load_config_file(&path), Config::default());
+        assert!(wrapper.check_valid().is_err());
+
+        fs::write(&path, "[all]\ntimeout = \"5s\"\n").unwrap();
+        let wrapper = Config::get_config_vec(Config::load_config_file(&path), Config::default());
+        assert!(wrapper.check_valid().is_ok());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn print_merged_config() {
+        let toml = r#"[feature_a]
+        features = ["feature_a"]
+        timeout = "2m"
+        ciserver = "travis-ci"
+
+        [feature_a.timeout-per-binary]
+        integration_tests = "5m"
+        "#;
+        let configs = Config::parse_config_toml(toml.as_bytes()).unwrap();
+        let wrapper = ConfigWrapper(configs, 0);
+        let printed = wrapper.to_toml().unwrap();
+
+        let reparsed = Config::parse_config_toml(printed.as_bytes()).unwrap();
+        assert_eq!(reparsed.len(), 1);
+        assert_eq!(reparsed[0].name, "feature_a");
+        assert_eq!(reparsed[0].features, vec!["feature_a"]);
+        assert_eq!(reparsed[0].test_timeout, Duration::from_secs(120));
+        assert_eq!(reparsed[0].ci_tool, Some(CiService::Travis));
+        assert_eq!(
+            reparsed[0].binary_timeout(Path::new("integration_tests")),
+            Duration::from_secs(300)
+        );
+
+        let wrapper = ConfigWrapper(vec![Config::default()], 0);
+        assert!(wrapper.to_toml().unwrap().starts_with("[default]"));
+    }
+
+    #[test]
+    fn report_config_choice() {
+        let named = |name: &str| {
+            let mut config = Config::default();
+            config.name = name.to_string();
+            config
+        };
+        let wrapper = ConfigWrapper(vec![named("unit")], 0);
+        assert_eq!(wrapper.report_config().unwrap().name, "unit");
+        let mut wrapper = ConfigWrapper(vec![named("unit"), named("integration")], 0);
+        assert!(wrapper.report_config().is_err());
+        wrapper.0.push(named("report"));
+        assert_eq!(wrapper.report_config().unwrap().name, "report");
+        assert!(ConfigWrapper(vec![], 0).report_config().is_err());
+    }
+
+    #[test]
+    fn env_var_overrides() {
+        let overrides = vec![
+            ("timeout".to_string(), "5".to_string()),
+            ("branch".to_string(), "true".to_string()),
+            ("features".to_string(), "a, b,c".to_string()),
+            ("out".to_string(), "html,Lcov".to_string()),
+            ("exclude-files".to_string(), "fuzz/*".to_string()),
+            ("release".to_string(), "maybe".to_string()),
+            ("colour".to_string(), "dark".to_string()),
+        ];
+        let mut config = Config::default();
+        config.apply_env_overrides(&overrides);
+        assert_eq!(config.test_timeout, Duration::from_secs(5));
+        assert!(config.branch_coverage);
+        assert_eq!(config.features, vec!["a", "b", "c"]);
+        assert_eq!(config.generate, vec![OutputFile::Html, OutputFile::Lcov]);
+        assert!(config.exclude_path(Path::new("fuzz/src/lib.rs")));
+        assert!(!config.release);
+
+        assert_eq!(config.set_from_env("colour", "dark"), Ok(false));
+        assert!(config.set_from_env("timeout", "5s").is_err());
+        assert_eq!(cli_arg_names("source-cache"), vec!["no-source-cache"]);
+    }
+
+    #[test]
+    fn unknown_keys_rejected() {
+        let toml = r#"[all]
+        timout = "5s"
+        release = true
+        colour-scheme = "dark"
+        "#;
+        let err = Config::parse_config_toml(toml.as_bytes()).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("Unknown key `timout` in [all], did you mean `timeout`?"));
+        assert!(message.contains("Unknown key `colour-scheme` in [all]"));
+        assert!(!message.contains("`release`"));
+
+        let toml = r#"[all]
+        workspace = true
+        "#;
+        assert!(Config::parse_config_toml(toml.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn duplicate_tables_rejected() {
+        let dir = env::temp_dir().join("tarpaulin-duplicate-tables");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tarpaulin.yml");
+        fs::write(
+            &path,
+            "unit:\n  release: true\ndoc: {}\nunit:\n  release: false\n",
+        )
+        .unwrap();
+        let err = Config::load_config_file(&path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Duplicate config table 'unit' in tarpaulin.yml"
+        );
+        let wrapper = Config::get_config_vec(Config::load_config_file(&path), Config::default());
+        assert!(wrapper.check_valid().is_err());
+        let _ = fs::remove_dir_all(&dir);
+
+        let toml = "[unit]\nrelease = true\n\n[doc]\n\n[unit]\nrelease = false\n";
+        let err = Config::parse_config_toml(toml.as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "Duplicate config table 'unit' at line 6");
+        let yaml = "unit:\n  doc: {}\n  unit: {}\n# unit:\n";
+        assert_eq!(duplicate_yaml_config(yaml.as_bytes()), None);
+        let err = Config::parse_config_toml(b"[\"a/b\"]\nrelease = true\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Config name 'a/b' can't be used in file names"
+        );
+    }
+
+    #[test]
+    fn select_config_names() {
+        let selected = vec!["fast".to_string(), "unit-*".to_string()];
+        let excluded = vec!["*-slow".to_string()];
+        assert!(is_config_selected("fast", &selected, &excluded));
+        assert!(is_config_selected("unit-core", &selected, &excluded));
+        assert!(!is_config_selected("unit-slow", &selected, &excluded));
+        assert!(!is_config_selected("full", &selected, &excluded));
+        assert!(is_config_selected("full", &[], &[]));
+        assert!(!is_config_selected("full-slow", &[], &excluded));
+
+        let matches = App::new("tarpaulin")
+            .args_from_usage("--config-names [NAME]... 'Configs to run'")
+            .get_matches_from_safe(vec!["tarpaulin", "--config-names", "fast,full", "unit"])
+            .unwrap();
+        assert_eq!(
+            get_config_names(&matches, "config-names"),
+            vec!["fast".to_string(), "full".to_string(), "unit".to_string()]
+        );
+    }
+
+    #[test]
+    fn closest_key_suggestions() {
+        let keys = ["timeout", "release", "exclude-files"];
+        assert_eq!(closest_key("timout", &keys), Some("timeout"));
+        assert_eq!(closest_key("exclude_files", &keys), Some("exclude-files"));
+        assert_eq!(closest_key("colour-scheme", &keys), None);
+    }
+
+    #[test]
+    fn manifest_metadata_configs() {
+        let manifest = r#"[package]
+        name = "foo"
+        version = "0.1.0"
+
+        [package.metadata.tarpaulin.coverage]
+        release = true
+        out = ["Lcov"]
+
+        [dependencies]
+        "#;
+        let configs = Config::parse_manifest_config(manifest.as_bytes()).unwrap();
+        assert_eq!(configs.len(), 1);
+        assert_eq!(configs[0].name, "coverage");
+        assert!(configs[0].release);
+        assert_eq!(configs[0].generate, vec![OutputFile::Lcov]);
+
+        let manifest = r#"[package]
+        name = "foo"
+        version = "0.1.0"
+        "#;
+        assert!(Config::parse_manifest_config(manifest.as_bytes()).is_err());
+        assert!(!has_manifest_config(Path::new("Cargo.toml")));
+    }
+
+    #[test]
+    fn output_file_single_format() {
+        let config = Config::default().with_generate(vec![OutputFile::Lcov]);
+        assert_eq!(
+            config.output_path(&OutputFile::Lcov),
+            config.output_directory.join("lcov.info")
+        );
+
+        let config = config.with_output_file("/tmp/coverage/report.info");
+        assert!(config.check_output_file().is_ok());
+        assert_eq!(
+            config.output_path(&OutputFile::Lcov),
+            PathBuf::from("/tmp/coverage/report.info")
+        );
+
+        let config = config.with_generate(vec![OutputFile::Lcov, OutputFile::Html]);
+        assert!(config.check_output_file().is_err());
+        assert!(!config.validate().is_valid());
+    }
+
+    #[test]
+    fn env_filter_globs() {
+        let mut config = Config::default();
+        config.test_binary_env_filter = vec![
+            "AWS_*".to_string(),
+            "GITHUB_TOKEN".to_string(),
+            "*_SECRET".to_string(),
+        ];
+        assert!(config.is_env_filtered("AWS_ACCESS_KEY_ID"));
+        assert!(config.is_env_filtered("GITHUB_TOKEN"));
+        assert!(config.is_env_filtered("DEPLOY_SECRET"));
+        assert!(!config.is_env_filtered("GITHUB_TOKEN_NAME"));
+        assert!(!config.is_env_filtered("PATH"));
+    }
+
+    #[test]
+    fn relative_path_test() {
+        let path_a = Path::new("/this/should/form/a/rel/path/");
+        let path_b = Path::new("/this/should/form/b/rel/path/");
+
+        let rel_path = path_relative_from(path_b, path_a);
+        assert!(rel_path.is_some());
+        assert_eq!(
+            rel_path.unwrap().to_str().unwrap(),
+            "../../../b/rel/path",
+            "Wrong relative path"
+        );
+
+        let path_a = Path::new("/this/should/not/form/a/rel/path/");
+        let path_b = Path::new("./this/should/not/form/a/rel/path/");
+
+        let rel_path = path_relative_from(path_b, path_a);
+        assert_eq!(rel_path, None, "Did not expect relative path");
+
+        let path_a = Path::new("./this/should/form/a/rel/path/");
+        let path_b = Path::new("./this/should/form/b/rel/path/");
+
+        let rel_path = path_relative_from(path_b, path_a);
+        assert!(rel_path.is_some());
         assert_eq!(
             rel_path.unwrap().to_str().unwrap(),
             "../../../b/rel/path",
@@ -555,12 +2869,16 @@ mod tests {
         assert_eq!(configs[0].excluded_files_raw.len(), 1);
     }
 
-    #[test]
-    fn all_toml_options() {
-        let toml = r#"[all]
+    const ALL_OPTIONS_TOML: &str = r#"[all]
         debug = true
         verbose = true
         ignore-panics = true
+        suppress-panics-output = true
+        exclude-generated = true
+        generated-file-markers = ["autogenerated"]
+        ignore-lines-matching = ["unreachable!"]
+        exclusion-comment = ["nocov"]
+        block-exclusions = false
         count = true
         ignored = true
         force-clean = true
@@ -576,26 +2894,116 @@ mod tests {
         exclude = ["pack_2"]
         exclude-files = ["fuzz/*"]
         timeout = "5s"
+        doc-test-timeout = "3m"
+        timeout-signal = "SIGTERM"
+        timeout-signal-grace = "10s"
         release = true
         no-run = true
         locked = true
         frozen = true
         target-dir = "/tmp"
+        target = "aarch64-unknown-linux-gnu"
+        sysroot = "/opt/aarch64-sysroot"
+        source-dir = "/home/ci/project"
+        path-mapping = [["/home/runner/work/project", "/project"], ["/mnt", "/src"]]
+        strip-prefix = ["/root/.cargo/registry/src/*"]
         offline = true
+        target-cpu = "native"
+        rustflags = ["--cfg tarpaulin_ci"]
         Z = ["something-nightly"]
         out = ["Html"]
+        html-expand-uncovered = 30.0
+        source-cache = false
+        demangled-names = true
+        coverage-report-hook = "./upload.sh"
+        hook-fails-build = true
+        dedup-symbols = false
+        count-aggregation = "Sum"
+        color = "Never"
+        include-doctests-in-line-coverage = true
+        exclude-doc-tests-from-coverage = true
+        ignore-extern-crate-coverage = false
+        report-by-test-type = true
+        min-unit-coverage = 80.0
+        min-integration-coverage = 70.0
+        min-doctest-coverage = 50.0
+        ignore-build-dependencies = false
+        function-coverage-only = true
+        pgo-profile = "merged.profdata"
+        output-per-config = true
+        test-binary-env-filter = ["AWS_*"]
+        print-coverage-for = "src/lib.rs"
+        report-line-count = true
+        top-n = 10
+        minimum-coverage = 75.5
+        file-coverage-threshold = { "src/auth*" = 95.0, "src/gen*" = 50.0 }
+        compact-json = true
+        line-numbering = "ZeroBased"
+        output-file = "/tmp/report.xml"
+        include-files = ["src/*"]
+        skip-clean = true
+        jobs = 2
+        implicit-test-threads = 1
+        retry-count = 2
+        test = ["integration"]
+        bench = ["parsing"]
+        bin = ["server"]
+        binary-path = ["/ci/artifacts/integration-0123456789abcdef"]
+        function-coverage = true
+        include-tests = true
+        badge-thresholds = [60.0, 90.0]
+        codecov = "token"
+        codecov-url = "https://codecov.example.com"
+        github-summary = false
+        fail-under-line = 70.0
+        fail-under-branch = 40.0
+        compare-with-branch = "main"
+        max-regression = 0.5
+        diff-coverage = true
+        diff-from = "origin/main"
+        diff-coverage-minimum = 80.0
+        report-title = "Nightly coverage"
+        workspace-members = ["crates/foo"]
+        no-fail-fast = true
+        keep-going = true
+        cache-dir = "target/tarpaulin/cache"
+        clear-cache = true
+        report-tags = { os = "linux", feature = "async-backend" }
+        cargo-env = { DATABASE_URL = "postgres://localhost/test" }
+        test-env = { API_TOKEN = "secret" }
+        strip-dead-code = true
+        profile = "coverage"
+        follow-exec = true
+        nightly = true
+        engine = "llvm-cov"
         run-types = ["Doctests"]
         root = "/home/rust"
         manifest-path = "/home/rust/foo/Cargo.toml"
         ciserver = "travis-ci"
         args = ["--nocapture"]
         "#;
+
+    #[test]
+    fn all_toml_options() {
+        let toml = ALL_OPTIONS_TOML;
         let mut configs = Config::parse_config_toml(toml.as_bytes()).unwrap();
         assert_eq!(configs.len(), 1);
         let config = configs.remove(0);
         assert!(config.debug);
         assert!(config.verbose);
         assert!(config.ignore_panics);
+        assert!(config.suppress_panic_output);
+        assert!(config.exclude_generated);
+        assert_eq!(
+            config.generated_file_markers,
+            vec!["autogenerated".to_string()]
+        );
+        assert_eq!(
+            config.ignore_lines_matching,
+            vec!["unreachable!".to_string()]
+        );
+        assert_eq!(config.exclusion_line_comment, vec!["nocov".to_string()]);
+        assert!(!config.block_exclusions);
         assert!(config.count);
         assert!(config.run_ignored);
         assert!(config.force_clean);
@@ -611,7 +3019,47 @@ mod tests {
         assert!(config.locked);
         assert!(config.frozen);
         assert!(config.offline);
+        assert_eq!(config.target_cpu, Some("native".to_string()));
+        assert_eq!(config.target, Some("aarch64-unknown-linux-gnu".to_string()));
+        assert_eq!(config.sysroot, Some(PathBuf::from("/opt/aarch64-sysroot")));
+        assert_eq!(config.source_dir, Some(PathBuf::from("/home/ci/project")));
+        assert_eq!(
+            config.path_mappings,
+            vec![
+                (
+                    "/home/runner/work/project".to_string(),
+                    "/project".to_string()
+                ),
+                ("/mnt".to_string(), "/src".to_string()),
+            ]
+        );
+        assert_eq!(
+            config.map_path(Path::new("/home/runner/work/project/src/lib.rs")),
+            PathBuf::from("/project/src/lib.rs")
+        );
+        assert_eq!(
+            config.map_path(Path::new("/mnt/src/lib.rs")),
+            PathBuf::from("/src/src/lib.rs")
+        );
+        assert_eq!(
+            config.map_path(Path::new("/other/lib.rs")),
+            PathBuf::from("/other/lib.rs")
+        );
+        assert_eq!(
+            config.strip_prefixes,
+            vec!["/root/.cargo/registry/src/*".to_string()]
+        );
+        assert_eq!(
+            config.map_path(Path::new(
+                "/root/.cargo/registry/src/github.com-1ecc6299db9ec823/serde-1.0.104/src/lib.rs"
+            )),
+            PathBuf::from("serde-1.0.104/src/lib.rs")
+        );
+        assert_eq!(config.rustflags, vec!["--cfg tarpaulin_ci".to_string()]);
         assert_eq!(config.test_timeout, Duration::from_secs(5));
+        assert_eq!(config.doctest_timeout, Some(Duration::from_secs(180)));
+        assert_eq!(config.timeout_signal, Signal::SIGTERM);
+        assert_eq!(config.timeout_signal_grace, Duration::from_secs(10));
         assert_eq!(config.unstable_features.len(), 1);
         assert_eq!(config.unstable_features[0], "something-nightly");
         assert_eq!(config.varargs.len(), 1);
@@ -626,10 +3074,192 @@ mod tests {
         assert_eq!(config.exclude[0], "pack_2");
         assert_eq!(config.generate.len(), 1);
         assert_eq!(config.generate[0], OutputFile::Html);
+        assert_eq!(config.html_expand_uncovered, Some(30.0));
+        assert!(!config.source_cache);
+        assert!(config.demangled_names);
+        assert_eq!(
+            config.coverage_report_hook,
+            Some(PathBuf::from("./upload.sh"))
+        );
+        assert!(config.hook_fails_build);
+        assert!(!config.dedup_symbols);
+        assert_eq!(config.count_aggregation, CountAggregation::Sum);
+        assert_eq!(config.color, Color::Never);
+        assert!(config.include_doctests_in_line_coverage);
+        assert!(config.exclude_doc_tests_from_coverage);
+        assert!(!config.ignore_extern_crate_coverage);
+        assert!(config.report_by_test_type);
+        assert_eq!(config.min_unit_coverage, Some(80.0));
+        assert_eq!(config.min_integration_coverage, Some(70.0));
+        assert_eq!(config.min_doctest_coverage, Some(50.0));
+        assert!(!config.ignore_build_dependencies);
+        assert!(config.function_coverage_only);
+        assert_eq!(config.pgo_profile, Some(PathBuf::from("merged.profdata")));
+        assert!(config.output_per_config);
+        assert_eq!(config.test_binary_env_filter, vec!["AWS_*".to_string()]);
+        assert_eq!(config.print_coverage_for, Some(PathBuf::from("src/lib.rs")));
+        assert!(config.report_line_count);
+        assert_eq!(config.top_n, Some(10));
+        assert_eq!(config.minimum_coverage, Some(75.5));
+        assert_eq!(config.file_thresholds.len(), 2);
+        assert_eq!(config.file_thresholds.get("src/auth*"), Some(&95.0));
+        assert!(config.compact_json);
+        assert_eq!(config.line_numbering, LineNumbering::ZeroBased);
+        assert_eq!(config.output_file, Some(PathBuf::from("/tmp/report.xml")));
+        assert_eq!(config.included_files_raw, vec!["src/*".to_string()]);
+        assert!(config.skip_clean);
+        assert_eq!(config.jobs, Some(2));
+        assert_eq!(config.implicit_test_threads, Some(1));
+        assert_eq!(config.retry_count, 2);
+        assert_eq!(config.test_names, vec!["integration".to_string()]);
+        assert_eq!(config.bench_names, vec!["parsing".to_string()]);
+        assert_eq!(config.bins, vec!["server".to_string()]);
+        assert_eq!(
+            config.binary_paths,
+            vec![PathBuf::from("/ci/artifacts/integration-0123456789abcdef")]
+        );
+        assert!(config.function_coverage);
+        assert!(config.include_tests);
+        assert_eq!(config.badge_thresholds, (60.0, 90.0));
+        assert_eq!(config.codecov, Some("token".to_string()));
+        assert_eq!(
+            config.codecov_url,
+            Some("https://codecov.example.com".to_string())
+        );
+        assert!(!config.github_summary);
+        assert_eq!(config.minimum_line_coverage, Some(70.0));
+        assert_eq!(config.minimum_branch_coverage, Some(40.0));
+        assert_eq!(config.compare_branch, Some("main".to_string()));
+        assert_eq!(config.max_regression, 0.5);
+        assert!(config.diff_coverage);
+        assert_eq!(config.diff_from, Some("origin/main".to_string()));
+        assert_eq!(config.diff_coverage_minimum, Some(80.0));
+        assert_eq!(config.report_title, Some("Nightly coverage".to_string()));
+        assert_eq!(config.workspace_members, vec![PathBuf::from("crates/foo")]);
+        assert!(config.no_fail_fast);
+        assert!(config.keep_going);
+        assert_eq!(
+            config.cache_dir,
+            Some(PathBuf::from("target/tarpaulin/cache"))
+        );
+        assert!(config.clear_cache);
+        assert_eq!(config.report_tags.get("os"), Some(&"linux".to_string()));
+        assert_eq!(config.report_tags.len(), 2);
+        assert_eq!(
+            config.cargo_env.get("DATABASE_URL"),
+            Some(&"postgres://localhost/test".to_string())
+        );
+        assert_eq!(
+            config.test_env.get("API_TOKEN"),
+            Some(&"secret".to_string())
+        );
+        assert!(config.strip_dead_code);
+        assert_eq!(config.profile, Some("coverage".to_string()));
+        assert!(config.follow_exec);
+        assert!(config.nightly);
+        assert_eq!(config.engine, CoverageEngine::LlvmCov);
         assert_eq!(config.run_types.len(), 1);
         assert_eq!(config.run_types[0], RunType::Doctests);
         assert_eq!(config.ci_tool, Some(CiService::Travis));
         assert_eq!(config.root, Some("/home/rust".to_string()));
         assert_eq!(config.manifest, PathBuf::from("/home/rust/foo/Cargo.toml"));
     }
+
+    #[test]
+    fn custom_config_file_name() {
+        let project = env::temp_dir().join("tarpaulin-config-file-name");
+        let _ = fs::remove_dir_all(&project);
+        fs::create_dir_all(&project).unwrap();
+        fs::write(project.join("tarpaulin.toml"), "").unwrap();
+        fs::write(project.join("tarpaulin-backend.toml"), "").unwrap();
+
+        let config = Config::default().with_manifest(project.join("Cargo.toml"));
+        assert_eq!(
+            config.check_for_configs(),
+            Some(project.join("tarpaulin.toml"))
+        );
+        assert_eq!(
+            config.check_for_config_named(Some("tarpaulin-backend.toml")),
+            Some(project.join("tarpaulin-backend.toml"))
+        );
+        assert_eq!(
+            config.check_for_config_named(Some("tarpaulin-web.toml")),
+            None
+        );
+        let _ = fs::remove_dir_all(&project);
+    }
+
+    #[test]
+    fn yaml_config() {
+        let yaml = r#"
+feature_a:
+  features: ["feature_a"]
+  timeout: 5s
+  out: [Html, lcov]
+  file-coverage-threshold:
+    "src/auth*": 95.0
+"#;
+        let mut configs = Config::parse_config_yaml(yaml.as_bytes()).unwrap();
+        assert_eq!(configs.len(), 1);
+        let config = configs.remove(0);
+        assert_eq!(config.name, "feature_a");
+        assert_eq!(config.features, vec!["feature_a".to_string()]);
+        assert_eq!(config.test_timeout, Duration::from_secs(5));
+        assert_eq!(config.generate, vec![OutputFile::Html, OutputFile::Lcov]);
+        assert_eq!(config.file_thresholds.get("src/auth*"), Some(&95.0));
+    }
+
+    #[test]
+    fn workspace_member_configs() {
+        let toml = r#"timeout = "2m"
+        out = ["Html"]
+
+        [members.core]
+        features = ["simd"]
+
+        [members.cli]
+        timeout = "5m"
+        packages = ["cli", "cli-macros"]
+        "#;
+        let mut configs = Config::parse_config_toml(toml.as_bytes()).unwrap();
+        configs.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(configs.len(), 2);
+        assert_eq!(configs[0].name, "cli");
+        assert_eq!(configs[0].test_timeout, Duration::from_secs(300));
+        assert_eq!(
+            configs[0].packages,
+            vec!["cli".to_string(), "cli-macros".to_string()]
+        );
+        assert_eq!(configs[0].generate, vec![OutputFile::Html]);
+        assert_eq!(configs[1].name, "core");
+        assert_eq!(configs[1].test_timeout, Duration::from_secs(120));
+        assert_eq!(configs[1].packages, vec!["core".to_string()]);
+        assert_eq!(configs[1].features, vec!["simd".to_string()]);
+
+        let typo = "out = [\"Html\"]\n[members.core]\nfeatuers = [\"simd\"]\n";
+        let error = Config::parse_config_toml(typo.as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("did you mean `features`?"));
+
+        // A config named members is still a normal config
+        let named = "[members]\nfeatures = [\"simd\"]\n";
+        let configs = Config::parse_config_toml(named.as_bytes()).unwrap();
+        assert_eq!(configs[0].name, "members");
+    }
+
+    #[test]
+    fn all_yaml_options() {
+        let value: toml::Value = toml::from_str(ALL_OPTIONS_TOML).unwrap();
+        let yaml = serde_yaml::to_string(&value).unwrap();
+
+        let toml_config = Config::parse_config_toml(ALL_OPTIONS_TOML.as_bytes())
+            .unwrap()
+            .remove(0);
+        let yaml_config = Config::parse_config_yaml(yaml.as_bytes())
+            .unwrap()
+            .remove(0);
+        assert_eq!(
+            serde_json::to_value(&yaml_config).unwrap(),
+            serde_json::to_value(&toml_config).unwrap()
+        );
+    }
 }
@@ -2,11 +2,14 @@ use crate::config::types::*;
 use clap::{value_t, values_t, ArgMatches};
 use coveralls_api::CiService;
 use log::error;
+use nix::sys::signal::Signal;
 use regex::Regex;
 use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::fmt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::Duration;
 
@@ -30,15 +33,18 @@ pub(super) fn get_branch_cov(args: &ArgMatches) -> bool {
 
 pub(super) fn get_manifest(args: &ArgMatches) -> PathBuf {
     if let Some(path) = args.value_of("manifest-path") {
-        let path = PathBuf::from(path);
+        let mut path = PathBuf::from(path);
         if path.is_relative() {
-            return env::current_dir()
-                .unwrap()
-                .join(path)
-                .canonicalize()
-                .unwrap();
+            path = env::current_dir().unwrap().join(path);
         }
-        return path;
+        // A directory is taken to mean the manifest inside it
+        if path.is_dir() {
+            path.push("Cargo.toml");
+        }
+        if let Some(problem) = manifest_problem(&path) {
+            error!("{}", problem);
+        }
+        return path.canonicalize().unwrap_or(path);
     }
 
     let mut manifest = env::current_dir().unwrap();
@@ -57,6 +63,60 @@ pub(super) fn default_manifest() -> PathBuf {
     manifest.canonicalize().unwrap_or(manifest)
 }
 
+/// Describes why the path can't be used as the project manifest
+pub(super) fn manifest_problem(path: &Path) -> Option<String> {
+    if path.file_name() != Some("Cargo.toml".as_ref()) {
+        Some(format!(
+            "manifest-path {} isn't a Cargo.toml file or a directory containing one",
+            path.display()
+        ))
+    } else if !path.is_file() {
+        Some(format!("manifest-path {} doesn't exist", path.display()))
+    } else {
+        None
+    }
+}
+
+pub(super) fn default_generated_markers() -> Vec<String> {
+    vec!["@generated".to_string(), "DO NOT EDIT".to_string()]
+}
+
+pub(super) fn get_generated_markers(args: &ArgMatches) -> Vec<String> {
+    let markers = get_list(args, "generated-file-markers");
+    if markers.is_empty() {
+        default_generated_markers()
+    } else {
+        markers
+    }
+}
+
+pub(super) fn default_exclusion_comments() -> Vec<String> {
+    vec![
+        "tarpaulin: skip".to_string(),
+        "coverage: off".to_string(),
+        "LCOV_EXCL_LINE".to_string(),
+    ]
+}
+
+pub(super) fn get_exclusion_comments(args: &ArgMatches) -> Vec<String> {
+    let markers = get_list(args, "exclusion-comment");
+    if markers.is_empty() {
+        default_exclusion_comments()
+    } else {
+        markers
+    }
+}
+
+/// Paths of the prebuilt test executables, relative paths are resolved
+/// against the current directory
+pub(super) fn get_binary_paths(args: &ArgMatches) -> Vec<PathBuf> {
+    let cwd = env::current_dir().unwrap_or_default();
+    get_list(args, "binary-path")
+        .into_iter()
+        .map(|path| cwd.join(path))
+        .collect()
+}
+
 pub(super) fn get_target_dir(args: &ArgMatches) -> Option<PathBuf> {
     if let Some(path) = args.value_of("target-dir") {
         let path = PathBuf::from(path);
@@ -75,6 +135,98 @@ pub(super) fn get_target_dir(args: &ArgMatches) -> Option<PathBuf> {
     }
 }
 
+/// Finds a config which is defined more than once in a YAML config, only the
+/// unindented keys are looked at. The YAML parser keeps the last definition
+/// of a key while the TOML parser already rejects a table defined twice.
+pub(super) fn duplicate_yaml_config(buffer: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(buffer);
+    let mut seen = HashSet::new();
+    for line in text.lines() {
+        if line.starts_with(|c: char| c.is_whitespace() || c == '#' || c == '-') {
+            continue;
+        }
+        if let Some(name) = line.find(':').map(|i| &line[..i]) {
+            let name = name.trim().trim_matches(|c| c == '"' || c == '\'');
+            if !name.is_empty() && !seen.insert(name.to_string()) {
+                return Some(name.to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Message for an invalid TOML config, a table defined twice is reported as a
+/// duplicate config instead of toml's redefinition error
+pub(super) fn toml_error_message(e: &toml::de::Error) -> String {
+    let message = e.to_string();
+    let table = message
+        .strip_prefix("redefinition of table `")
+        .and_then(|rest| rest.find('`').map(|end| &rest[..end]));
+    match (table, e.line_col()) {
+        (Some(table), Some((line, _))) => {
+            format!("Duplicate config table '{}' at line {}", table, line + 1)
+        }
+        _ => message,
+    }
+}
+
+/// Config names label the output files of some reports so they can't contain
+/// characters which aren't allowed in file names
+pub(super) fn is_valid_config_name(name: &str) -> bool {
+    name != "."
+        && name != ".."
+        && !name
+            .chars()
+            .any(|c| c.is_control() || "/\\:*?\"<>|".contains(c))
+}
+
+/// A workspace config has `[members.<crate>]` tables instead of named configs
+pub(super) fn is_workspace_config(value: &toml::Value) -> bool {
+    match value.get("members").and_then(toml::Value::as_table) {
+        Some(members) => !members.is_empty() && members.values().all(toml::Value::is_table),
+        None => false,
+    }
+}
+
+/// Codegen options in the flags which undo the flags tarpaulin instruments the
+/// build with
+pub(super) fn instrumentation_conflicts(rustflags: &[String]) -> Vec<String> {
+    let flags = rustflags.join(" ");
+    let mut tokens = flags.split_whitespace();
+    let mut result = vec![];
+    while let Some(token) = tokens.next() {
+        let option = if token == "-C" {
+            tokens.next().unwrap_or_default()
+        } else if let Some(option) = token.strip_prefix("-C") {
+            option
+        } else {
+            continue;
+        };
+        let mut parts = option.splitn(2, '=');
+        let name = parts.next().unwrap_or_default();
+        let value = parts.next().unwrap_or_default();
+        let conflict = match name {
+            "debuginfo" => value != "2",
+            "opt-level" => value != "0",
+            "link-dead-code" => ["n", "no", "off"].contains(&value),
+            "strip" => value != "none",
+            _ => false,
+        };
+        if conflict {
+            result.push(format!("-C {}", option));
+        }
+    }
+    result
+}
+
+pub(super) fn get_target_cpu(args: &ArgMatches) -> Option<String> {
+    args.value_of("target-cpu").map(ToString::to_string)
+}
+
+pub(super) fn get_profile(args: &ArgMatches) -> Option<String> {
+    args.value_of("profile").map(ToString::to_string)
+}
+
 pub(super) fn get_root(args: &ArgMatches) -> Option<String> {
     args.value_of("root").map(ToString::to_string)
 }
@@ -87,6 +239,62 @@ pub(super) fn get_coveralls(args: &ArgMatches) -> Option<String> {
     args.value_of("coveralls").map(ToString::to_string)
 }
 
+/// An empty token means the flag was given without one
+pub(super) fn get_codecov(args: &ArgMatches) -> Option<String> {
+    if args.is_present("codecov") {
+        Some(args.value_of("codecov").unwrap_or_default().to_string())
+    } else {
+        None
+    }
+}
+
+pub(super) fn get_codecov_url(args: &ArgMatches) -> Option<String> {
+    args.value_of("codecov-url").map(ToString::to_string)
+}
+
+pub(super) fn get_compare_branch(args: &ArgMatches) -> Option<String> {
+    args.value_of("compare-with-branch")
+        .map(ToString::to_string)
+}
+
+pub(super) fn get_diff_from(args: &ArgMatches) -> Option<String> {
+    args.value_of("diff-from").map(ToString::to_string)
+}
+
+pub(super) fn get_report_title(args: &ArgMatches) -> Option<String> {
+    args.value_of("report-title").map(ToString::to_string)
+}
+
+pub(super) fn get_workspace_members(args: &ArgMatches) -> Vec<PathBuf> {
+    args.values_of("workspace-members")
+        .map(|v| v.map(PathBuf::from).collect())
+        .unwrap_or_default()
+}
+
+/// Splits a `key=value` pair such as a report tag or an environment variable
+pub(super) fn parse_key_value(tag: &str) -> Option<(String, String)> {
+    let mut parts = tag.splitn(2, '=');
+    let key = parts.next()?.trim();
+    let value = parts.next()?.trim();
+    if key.is_empty() {
+        None
+    } else {
+        Some((key.to_string(), value.to_string()))
+    }
+}
+
+pub(super) fn get_report_tags(args: &ArgMatches) -> HashMap<String, String> {
+    args.values_of("tag")
+        .map(|v| v.filter_map(parse_key_value).collect())
+        .unwrap_or_default()
+}
+
+pub(super) fn get_env_vars(args: &ArgMatches, key: &str) -> HashMap<String, String> {
+    args.values_of(key)
+        .map(|v| v.filter_map(parse_key_value).collect())
+        .unwrap_or_default()
+}
+
 pub(super) fn get_report_uri(args: &ArgMatches) -> Option<String> {
     args.value_of("report-uri").map(ToString::to_string)
 }
@@ -95,6 +303,53 @@ pub(super) fn get_outputs(args: &ArgMatches) -> Vec<OutputFile> {
     values_t!(args.values_of("out"), OutputFile).unwrap_or(vec![])
 }
 
+/// Matches a name against a pattern where `*` matches any characters
+pub(super) fn glob_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    if !name.starts_with(first) {
+        return false;
+    }
+    let mut rest = &name[first.len()..];
+    let mut parts = parts.collect::<Vec<_>>();
+    let last = match parts.pop() {
+        Some(last) => last,
+        None => return rest.is_empty(),
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Config names given to an argument, names can be repeated or comma
+/// separated
+pub(super) fn get_config_names(args: &ArgMatches, key: &str) -> Vec<String> {
+    get_list(args, key)
+        .iter()
+        .flat_map(|names| names.split(','))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Whether a config runs given the patterns of the configs to run and those to
+/// leave out, every config runs if no patterns to run are given
+pub(super) fn is_config_selected(name: &str, selected: &[String], excluded: &[String]) -> bool {
+    (selected.is_empty() || selected.iter().any(|pattern| glob_match(pattern, name)))
+        && !excluded.iter().any(|pattern| glob_match(pattern, name))
+}
+
+/// Removes repeated output formats keeping the first occurrence of each
+pub(super) fn dedup_outputs(outputs: &mut Vec<OutputFile>) {
+    let mut seen = HashSet::new();
+    outputs.retain(|o| seen.insert(o.clone()));
+}
+
 pub(super) fn get_output_directory(args: &ArgMatches) -> PathBuf {
     if let Some(path) = args.value_of("output-dir") {
         return PathBuf::from(path);
@@ -102,27 +357,255 @@ pub(super) fn get_output_directory(args: &ArgMatches) -> PathBuf {
     env::current_dir().unwrap()
 }
 
+pub(super) fn get_html_expand_uncovered(args: &ArgMatches) -> Option<f64> {
+    if args.is_present("html-expand-uncovered") {
+        Some(value_t!(args, "html-expand-uncovered", f64).unwrap_or(50.0))
+    } else {
+        None
+    }
+}
+
+/// Coverage percentages where the badge turns yellow and green
+pub(super) const DEFAULT_BADGE_THRESHOLDS: (f64, f64) = (50.0, 80.0);
+
+pub(super) fn get_badge_thresholds(args: &ArgMatches) -> (f64, f64) {
+    match values_t!(args.values_of("badge-thresholds"), f64) {
+        Ok(ref values) if values.len() == 2 => (values[0], values[1]),
+        _ => DEFAULT_BADGE_THRESHOLDS,
+    }
+}
+
+pub(super) fn get_coverage_report_hook(args: &ArgMatches) -> Option<PathBuf> {
+    args.value_of("coverage-report-hook").map(PathBuf::from)
+}
+
+pub(super) fn get_pgo_profile(args: &ArgMatches) -> Option<PathBuf> {
+    args.value_of("pgo-profile").map(PathBuf::from)
+}
+
+pub(super) fn get_print_coverage_for(args: &ArgMatches) -> Option<PathBuf> {
+    args.value_of("print-coverage-for").map(PathBuf::from)
+}
+
+/// Parses a path mapping written as `FROM:TO`
+pub fn parse_path_mapping(mapping: &str) -> Result<(String, String), String> {
+    match mapping.find(':') {
+        Some(i) if i > 0 => Ok((mapping[..i].to_string(), mapping[i + 1..].to_string())),
+        _ => Err(format!(
+            "path mappings must be written as FROM:TO, got {}",
+            mapping
+        )),
+    }
+}
+
+pub(super) fn get_path_mappings(args: &ArgMatches) -> Vec<(String, String)> {
+    args.values_of("path-mapping")
+        .map(|v| v.filter_map(|m| parse_path_mapping(m).ok()).collect())
+        .unwrap_or_default()
+}
+
+pub(super) fn get_cache_dir(args: &ArgMatches) -> Option<PathBuf> {
+    args.value_of("cache-dir").map(PathBuf::from)
+}
+
+pub(super) fn get_output_file(args: &ArgMatches) -> Option<PathBuf> {
+    args.value_of("output-file").map(PathBuf::from)
+}
+
+/// Finds the candidate closest to a misspelled key by edit distance, if any
+/// is close enough to be a likely typo
+pub(super) fn closest_key<'a>(key: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let max_distance = (key.len() / 3).max(2);
+    candidates
+        .iter()
+        .map(|c| (strsim::levenshtein(key, c), *c))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, c)| c)
+}
+
+/// Prefix of environment variables which override config options
+pub(super) const ENV_PREFIX: &str = "TARPAULIN_";
+
+/// Gets the config keys and values set by environment variables, the key is
+/// the rest of the variable name in lowercase with `_` replaced by `-`
+pub(super) fn get_env_overrides() -> Vec<(String, String)> {
+    env::vars()
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+        .map(|(name, value)| {
+            let key = name[ENV_PREFIX.len()..].to_lowercase().replace('_', "-");
+            (key, value)
+        })
+        .collect()
+}
+
+/// Parses a boolean environment variable, accepting the usual spellings
+pub(super) fn env_flag(value: &str) -> Result<bool, String> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "" | "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("{} is not a boolean", value)),
+    }
+}
+
+/// Splits a comma separated environment variable into its values
+pub(super) fn env_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Parses each value of a comma separated environment variable
+pub(super) fn env_parse_list<T: FromStr<Err = String>>(value: &str) -> Result<Vec<T>, String> {
+    env_list(value).iter().map(|v| v.parse()).collect()
+}
+
+/// Parses an environment variable holding a number
+pub(super) fn env_number<T: FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("{} is not a number", value))
+}
+
+/// Parses the two comma separated badge thresholds from an environment variable
+pub(super) fn env_badge_thresholds(value: &str) -> Result<(f64, f64), String> {
+    match env_list(value).as_slice() {
+        [low, high] => Ok((env_number(low)?, env_number(high)?)),
+        _ => Err(format!("{} is not two comma separated numbers", value)),
+    }
+}
+
+/// Parses comma separated `key=value` pairs from an environment variable
+pub(super) fn env_key_values(value: &str) -> Result<HashMap<String, String>, String> {
+    env_list(value)
+        .iter()
+        .map(|pair| parse_key_value(pair).ok_or_else(|| format!("{} isn't a key=value pair", pair)))
+        .collect()
+}
+
+/// Names of the command line arguments which set the config key, these are
+/// the key itself apart from options set by negated flags
+pub(super) fn cli_arg_names(key: &str) -> Vec<&str> {
+    match key {
+        "source-cache" => vec!["no-source-cache"],
+        "dedup-symbols" => vec!["no-dedup-symbols"],
+        "block-exclusions" => vec!["no-block-exclusions"],
+        "ignore-extern-crate-coverage" => vec!["include-extern-crate-coverage"],
+        "ignore-build-dependencies" => vec!["include-build-dependencies"],
+        "function-coverage" => vec!["function"],
+        "report-tags" => vec!["tag"],
+        "github-summary" => vec!["no-github-summary"],
+        "implicit-test-threads" => vec!["implicit-test-threads", "single-threaded"],
+        "all" | "workspace" => vec!["all", "workspace"],
+        "output-per-config" => vec!["output-per-config", "output-dir-per-config"],
+        "line" | "branch" => vec!["line", "branch"],
+        "z" => vec!["Z"],
+        other => vec![other],
+    }
+}
+
+pub(super) fn get_jobs(args: &ArgMatches) -> Option<usize> {
+    value_t!(args, "jobs", usize).ok()
+}
+
+pub(super) fn get_top_n(args: &ArgMatches) -> Option<usize> {
+    value_t!(args, "top-n", usize).ok()
+}
+
+pub(super) fn get_implicit_test_threads(args: &ArgMatches) -> Option<usize> {
+    if args.is_present("single-threaded") {
+        Some(1)
+    } else {
+        value_t!(args, "implicit-test-threads", usize).ok()
+    }
+}
+
+pub(super) fn get_color(args: &ArgMatches) -> Color {
+    value_t!(args, "color", Color).unwrap_or_default()
+}
+
+pub(super) fn get_retry_count(args: &ArgMatches) -> usize {
+    value_t!(args, "retry-count", usize).unwrap_or(0)
+}
+
+pub(super) fn get_line_numbering(args: &ArgMatches) -> LineNumbering {
+    value_t!(args, "line-numbering", LineNumbering).unwrap_or_default()
+}
+
+pub(super) fn get_engine(args: &ArgMatches) -> CoverageEngine {
+    value_t!(args, "engine", CoverageEngine).unwrap_or_default()
+}
+
+pub(super) fn get_count_aggregation(args: &ArgMatches) -> CountAggregation {
+    value_t!(args, "count-aggregation", CountAggregation).unwrap_or_default()
+}
+
+pub(super) fn get_percentage(args: &ArgMatches, key: &str) -> Option<f64> {
+    value_t!(args, key, f64).ok()
+}
+
 pub(super) fn get_run_types(args: &ArgMatches) -> Vec<RunType> {
     values_t!(args.values_of("run-types"), RunType).unwrap_or(vec![RunType::Tests])
 }
 
-pub(super) fn get_excluded(args: &ArgMatches) -> Vec<Regex> {
+pub(super) fn get_excluded(args: &ArgMatches) -> Vec<(bool, Regex)> {
     regexes_from_excluded(&get_list(args, "exclude-files"))
 }
 
-pub(super) fn regexes_from_excluded(strs: &[String]) -> Vec<Regex> {
-    let mut files = vec![];
+pub(super) fn get_included(args: &ArgMatches) -> Vec<Regex> {
+    regexes_from_patterns(&get_list(args, "include-files"))
+}
 
-    for temp_str in strs {
-        let s = &temp_str.replace(".", r"\.").replace("*", ".*");
+/// Compiles exclusion patterns in order, patterns prefixed with `!` are
+/// negations which re-include files matched by earlier patterns. The bool is
+/// true for negated patterns.
+pub(super) fn regexes_from_excluded(strs: &[String]) -> Vec<(bool, Regex)> {
+    strs.iter()
+        .filter_map(|s| {
+            let negated = s.starts_with('!');
+            let pattern = if negated { &s[1..] } else { s.as_str() };
+            wildcard_regex(pattern).map(|re| (negated, re))
+        })
+        .collect()
+}
 
-        if let Ok(re) = Regex::new(s) {
-            files.push(re);
-        } else {
-            error!("Invalid regex: {}", temp_str);
-        }
+/// Checks a pattern in the syntax of the excluded files, a leading `!`
+/// negates the pattern
+pub(super) fn check_wildcard_pattern(pattern: &str) -> Result<(), regex::Error> {
+    let pattern = pattern.strip_prefix('!').unwrap_or(pattern);
+    Regex::new(&wildcard_to_regex(pattern)).map(|_| ())
+}
+
+/// Compiles a wildcard pattern which has to match the whole path, such as
+/// the patterns of the file coverage thresholds
+pub fn anchored_wildcard_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^{}$", wildcard_to_regex(pattern)))
+}
+
+pub(super) fn regexes_from_patterns(strs: &[String]) -> Vec<Regex> {
+    strs.iter().filter_map(|s| wildcard_regex(s)).collect()
+}
+
+/// Converts a pattern where `*` matches any characters and `.` is literal to
+/// a regex
+fn wildcard_to_regex(pattern: &str) -> String {
+    pattern.replace(".", r"\.").replace("*", ".*")
+}
+
+/// Compiles a pattern where `*` matches any characters and `.` is literal
+fn wildcard_regex(pattern: &str) -> Option<Regex> {
+    let s = &wildcard_to_regex(pattern);
+
+    if let Ok(re) = Regex::new(s) {
+        Some(re)
+    } else {
+        error!("Invalid regex: {}", pattern);
+        None
     }
-    files
 }
 
 pub(super) fn get_timeout(args: &ArgMatches) -> Duration {
@@ -134,6 +617,91 @@ pub(super) fn get_timeout(args: &ArgMatches) -> Duration {
     }
 }
 
+pub(super) fn get_doctest_timeout(args: &ArgMatches) -> Option<Duration> {
+    value_t!(args.value_of("doc-test-timeout"), u64)
+        .ok()
+        .map(Duration::from_secs)
+}
+
+pub(super) fn get_timeout_signal(args: &ArgMatches) -> Signal {
+    args.value_of("timeout-signal")
+        .and_then(|s| parse_signal(s).ok())
+        .unwrap_or(Signal::SIGKILL)
+}
+
+pub(super) fn get_timeout_signal_grace(args: &ArgMatches) -> Duration {
+    let grace = value_t!(args.value_of("timeout-signal-grace"), u64).unwrap_or(5);
+    Duration::from_secs(grace)
+}
+
+/// Parses a signal name, the `SIG` prefix is optional and case is ignored so
+/// `SIGTERM`, `TERM` and `term` are all the same signal
+pub fn parse_signal(name: &str) -> Result<Signal, String> {
+    let name = name.trim().to_uppercase();
+    let name = if name.starts_with("SIG") {
+        name
+    } else {
+        format!("SIG{}", name)
+    };
+    Signal::from_str(&name).map_err(|_| format!("{} is not a signal", name))
+}
+
+/// Serializes the signal with its name
+pub fn serialize_signal<S>(signal: &Signal, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    s.serialize_str(signal.as_ref())
+}
+
+pub fn deserialize_signal<'de, D>(d: D) -> Result<Signal, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(d)?;
+    parse_signal(&name).map_err(de::Error::custom)
+}
+
+pub fn deserialize_timeouts<'de, D>(d: D) -> Result<HashMap<String, Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let timeouts: HashMap<String, humantime_serde::Serde<Duration>> = HashMap::deserialize(d)?;
+    Ok(timeouts
+        .into_iter()
+        .map(|(k, v)| (k, v.into_inner()))
+        .collect())
+}
+
+/// Serializes the timeouts as human readable durations like they're parsed
+pub fn serialize_timeouts<S>(timeouts: &HashMap<String, Duration>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let timeouts: BTreeMap<&String, humantime_serde::Serde<Duration>> = timeouts
+        .iter()
+        .map(|(binary, timeout)| (binary, (*timeout).into()))
+        .collect();
+    timeouts.serialize(s)
+}
+
+/// Serializes the CI service with the name it's parsed from
+pub fn serialize_ci_server<S>(ci: &Option<CiService>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match ci {
+        Some(CiService::Circle) => s.serialize_str("circle-ci"),
+        Some(CiService::Codeship) => s.serialize_str("codeship"),
+        Some(CiService::Jenkins) => s.serialize_str("jenkins"),
+        Some(CiService::Semaphore) => s.serialize_str("semaphore"),
+        Some(CiService::Travis) => s.serialize_str("travis-ci"),
+        Some(CiService::TravisPro) => s.serialize_str("travis-pro"),
+        Some(CiService::Other(name)) => s.serialize_str(name),
+        None => s.serialize_none(),
+    }
+}
+
 pub fn deserialize_ci_server<'de, D>(d: D) -> Result<Option<CiService>, D::Error>
 where
     D: Deserializer<'de>,
@@ -0,0 +1,71 @@
+//! JSON Schema of the config file, used by editors to validate and complete
+//! `tarpaulin.toml`
+use crate::config::Config;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{InstanceType, RootSchema, Schema, SchemaObject, StringValidation};
+use schemars::JsonSchema;
+use std::collections::HashMap;
+
+/// Durations are written as human readable strings such as "5s" or "1m 30s"
+pub(super) struct DurationString;
+
+impl JsonSchema for DurationString {
+    fn schema_name() -> String {
+        "Duration".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(r"^\s*([0-9]+\s*[a-zA-Z]+\s*)+$".to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// Schema of a config file, a table of named configs
+pub fn config_schema() -> RootSchema {
+    let mut settings = SchemaSettings::draft2019_09();
+    settings.meta_schema = Some("https://json-schema.org/draft/2020-12/schema".to_string());
+    let mut schema = settings
+        .into_generator()
+        .into_root_schema_for::<HashMap<String, Config>>();
+    schema.schema.metadata().title = Some("tarpaulin config file".to_string());
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn schema_describes_options() {
+        let schema = serde_json::to_value(config_schema()).unwrap();
+        assert_eq!(
+            schema["$schema"],
+            "https://json-schema.org/draft/2020-12/schema"
+        );
+        let config = &schema["$defs"]["Config"];
+        let timeout = &config["properties"]["timeout"];
+        assert_eq!(
+            timeout["description"],
+            "Duration to wait before a timeout occurs"
+        );
+        assert_eq!(timeout["$ref"], "#/$defs/Duration");
+        assert_eq!(schema["$defs"]["Duration"]["type"], "string");
+
+        let out = &schema["$defs"]["OutputFile"]["enum"];
+        assert!(out.as_array().unwrap().contains(&Value::from("Lcov")));
+        let run_types = &schema["$defs"]["RunType"]["enum"];
+        assert!(run_types
+            .as_array()
+            .unwrap()
+            .contains(&Value::from("Doctests")));
+        assert!(config["properties"].get("excluded_files").is_none());
+    }
+}
@@ -1,22 +1,28 @@
 use cargo::core::compiler::CompileMode;
 use clap::arg_enum;
 use coveralls_api::CiService;
+use schemars::JsonSchema;
+use serde::de::{self, Deserializer};
 use serde::{Deserialize, Serialize};
+use std::env;
 use std::str::FromStr;
 use void::Void;
 
 arg_enum! {
-    #[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Deserialize, Serialize)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Serialize, JsonSchema)]
     pub enum RunType {
         Tests,
         Doctests,
         Benchmarks,
         Examples,
+        Bins,
+        BuildScripts,
+        ProcMacros,
     }
 }
 
 arg_enum! {
-    #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Deserialize, Serialize)]
+    #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, JsonSchema)]
     pub enum OutputFile {
         Json,
         Toml,
@@ -24,6 +30,187 @@ arg_enum! {
         Xml,
         Html,
         Lcov,
+        SonarQube,
+        Badges,
+    }
+}
+
+arg_enum! {
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+    pub enum CountAggregation {
+        Max,
+        Sum,
+    }
+}
+
+arg_enum! {
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+    pub enum LineNumbering {
+        OneBased,
+        ZeroBased,
+    }
+}
+
+arg_enum! {
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+    pub enum Color {
+        Auto,
+        Always,
+        Never,
+    }
+}
+
+/// Deserializes a type using its `FromStr` implementation, for `arg_enum!`
+/// types this means config files accept the same case insensitive values as
+/// the command line
+fn deserialize_from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(de::Error::custom)
+}
+
+impl<'de> Deserialize<'de> for RunType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize_from_str(deserializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for OutputFile {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize_from_str(deserializer)
+    }
+}
+
+impl OutputFile {
+    /// Name of the file the report is written to in the output directory.
+    /// Reports printed to stdout have no file so this is empty.
+    pub fn default_filename(&self) -> &'static str {
+        match *self {
+            OutputFile::Json => "tarpaulin-report.json",
+            OutputFile::Toml => "tarpaulin-report.toml",
+            OutputFile::Stdout => "",
+            OutputFile::Xml => "cobertura.xml",
+            OutputFile::Html => "tarpaulin-report.html",
+            OutputFile::Lcov => "lcov.info",
+            OutputFile::SonarQube => "sonarqube.xml",
+            OutputFile::Badges => "tarpaulin-badge.svg",
+        }
+    }
+}
+
+impl Default for CountAggregation {
+    #[inline]
+    fn default() -> Self {
+        CountAggregation::Max
+    }
+}
+
+impl Default for LineNumbering {
+    #[inline]
+    fn default() -> Self {
+        LineNumbering::OneBased
+    }
+}
+
+impl LineNumbering {
+    /// Number to report for a line, lines are numbered from 1 in the debug
+    /// info and the coverage results
+    pub fn line_number(self, line: u64) -> u64 {
+        match self {
+            LineNumbering::OneBased => line,
+            LineNumbering::ZeroBased => line.saturating_sub(1),
+        }
+    }
+
+    /// Line in the coverage results for a reported number
+    pub fn source_line(self, number: u64) -> u64 {
+        match self {
+            LineNumbering::OneBased => number,
+            LineNumbering::ZeroBased => number + 1,
+        }
+    }
+}
+
+impl Default for Color {
+    #[inline]
+    fn default() -> Self {
+        Color::Auto
+    }
+}
+
+impl Color {
+    /// Whether output should be coloured, `Auto` colours it when the log is
+    /// written to a terminal and `NO_COLOR` isn't set
+    pub fn use_color(self) -> bool {
+        self.use_color_on(atty::Stream::Stderr)
+    }
+
+    /// Whether output written to the stream should be coloured
+    pub fn use_color_on(self, stream: atty::Stream) -> bool {
+        match self {
+            Color::Auto => env::var_os("NO_COLOR").is_none() && atty::is(stream),
+            Color::Always => true,
+            Color::Never => false,
+        }
+    }
+}
+
+/// How coverage is collected from the test executables
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, JsonSchema)]
+pub enum CoverageEngine {
+    /// Trace the tests with ptrace and count the breakpoints hit on each line
+    #[serde(rename = "ptrace")]
+    Ptrace,
+    /// Build with LLVM's source based coverage and read the profiles with
+    /// `llvm-cov`, this needs a nightly toolchain
+    #[serde(rename = "llvm-cov")]
+    LlvmCov,
+}
+
+impl CoverageEngine {
+    pub fn variants() -> [&'static str; 2] {
+        ["ptrace", "llvm-cov"]
+    }
+}
+
+impl Default for CoverageEngine {
+    #[inline]
+    fn default() -> Self {
+        CoverageEngine::Ptrace
+    }
+}
+
+impl FromStr for CoverageEngine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ptrace" => Ok(CoverageEngine::Ptrace),
+            "llvm-cov" | "llvmcov" => Ok(CoverageEngine::LlvmCov),
+            _ => Err(format!(
+                "{} isn't a coverage engine, expected one of: {}",
+                s,
+                CoverageEngine::variants().join(", ")
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CoverageEngine {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize_from_str(deserializer)
     }
 }
 
@@ -44,6 +231,9 @@ impl From<RunType> for CompileMode {
             RunType::Examples => CompileMode::Build,
             RunType::Doctests => CompileMode::Doctest,
             RunType::Benchmarks => CompileMode::Bench,
+            RunType::Bins => CompileMode::Build,
+            RunType::BuildScripts => CompileMode::Build,
+            RunType::ProcMacros => CompileMode::Test,
         }
     }
 }
@@ -65,3 +255,53 @@ impl FromStr for Ci {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_type_round_trip() {
+        for run_type in RunType::variants().iter() {
+            let parsed = RunType::from_str(run_type).unwrap();
+            assert_eq!(parsed.to_string(), *run_type);
+            assert_eq!(RunType::from_str(&parsed.to_string()), Ok(parsed));
+        }
+        assert_eq!(RunType::from_str("doctests"), Ok(RunType::Doctests));
+        assert_eq!(RunType::from_str("EXAMPLES"), Ok(RunType::Examples));
+        assert!(RunType::from_str("Unit").is_err());
+    }
+
+    #[test]
+    fn output_file_round_trip() {
+        for output in OutputFile::variants().iter() {
+            let parsed = OutputFile::from_str(output).unwrap();
+            assert_eq!(parsed.to_string(), *output);
+            assert_eq!(OutputFile::from_str(&parsed.to_string()), Ok(parsed));
+        }
+        assert_eq!(OutputFile::from_str("html"), Ok(OutputFile::Html));
+        assert_eq!(OutputFile::from_str("LCOV"), Ok(OutputFile::Lcov));
+    }
+
+    #[test]
+    fn output_file_default_filenames() {
+        assert_eq!(OutputFile::Html.default_filename(), "tarpaulin-report.html");
+        assert_eq!(OutputFile::Xml.default_filename(), "cobertura.xml");
+        assert_eq!(OutputFile::Lcov.default_filename(), "lcov.info");
+        assert_eq!(OutputFile::Json.default_filename(), "tarpaulin-report.json");
+        assert_eq!(OutputFile::Toml.default_filename(), "tarpaulin-report.toml");
+        assert_eq!(OutputFile::SonarQube.default_filename(), "sonarqube.xml");
+        assert_eq!(OutputFile::Badges.default_filename(), "tarpaulin-badge.svg");
+        assert_eq!(OutputFile::Stdout.default_filename(), "");
+    }
+
+    #[test]
+    fn run_type_deserialize_case_insensitive() {
+        #[derive(Deserialize)]
+        struct Types {
+            types: Vec<RunType>,
+        }
+        let types: Types = toml::from_str(r#"types = ["tests", "Doctests"]"#).unwrap();
+        assert_eq!(types.types, vec![RunType::Tests, RunType::Doctests]);
+    }
+}
@@ -0,0 +1,191 @@
+//! Public representation of the coverage collected by tarpaulin. This is a
+//! stable format intended for library users who want to write their own
+//! reporters without depending on the internal trace types. It's an export
+//! format rather than the type used between collection and reporting: only
+//! the LCOV report is written from it, the other reports and the thresholds
+//! still work on the `TraceMap`.
+use crate::traces::{CoverageStat, TraceMap};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Coverage of a project
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Coverage {
+    /// Coverage of each source file, sorted by path
+    pub files: Vec<FileCoverage>,
+}
+
+/// Coverage of a single source file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FileCoverage {
+    /// Path to the source file
+    pub path: PathBuf,
+    /// Coverable lines mapped from their line number
+    pub lines: BTreeMap<u32, LineData>,
+    /// Branches found in the file
+    pub branches: Vec<BranchData>,
+}
+
+/// Coverage of a single line
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LineData {
+    /// Number of times the line was hit
+    pub hits: u64,
+    /// Name of the function the line is in if known
+    pub function: Option<String>,
+}
+
+/// Coverage of a single branch
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BranchData {
+    /// Line the branch condition is on
+    pub line: u32,
+    /// Whether the condition has been observed as true
+    pub been_true: bool,
+    /// Whether the condition has been observed as false
+    pub been_false: bool,
+}
+
+impl FileCoverage {
+    /// Number of lines which were hit
+    pub fn covered(&self) -> usize {
+        self.lines.values().filter(|l| l.hits > 0).count()
+    }
+
+    /// Number of lines which could be hit
+    pub fn coverable(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+impl Coverage {
+    /// Returns the coverage of the given file if it's present
+    pub fn file(&self, path: &Path) -> Option<&FileCoverage> {
+        self.files.iter().find(|f| f.path == path)
+    }
+
+    /// Number of lines which were hit
+    pub fn covered(&self) -> usize {
+        self.files.iter().map(FileCoverage::covered).sum()
+    }
+
+    /// Number of lines which could be hit
+    pub fn coverable(&self) -> usize {
+        self.files.iter().map(FileCoverage::coverable).sum()
+    }
+
+    /// Line coverage as a fraction between 0 and 1
+    pub fn coverage_percentage(&self) -> f64 {
+        let coverable = self.coverable();
+        if coverable == 0 {
+            0.0
+        } else {
+            self.covered() as f64 / coverable as f64
+        }
+    }
+}
+
+impl<'a> From<&'a TraceMap> for Coverage {
+    fn from(traces: &'a TraceMap) -> Self {
+        let files = traces
+            .iter()
+            .map(|(path, traces)| {
+                let mut file = FileCoverage {
+                    path: path.clone(),
+                    ..Default::default()
+                };
+                for trace in traces {
+                    let line = trace.line as u32;
+                    match &trace.stats {
+                        CoverageStat::Line(hits) => {
+                            let data = file.lines.entry(line).or_insert_with(|| LineData {
+                                hits: 0,
+                                function: trace.fn_name.clone(),
+                            });
+                            data.hits += hits;
+                        }
+                        CoverageStat::Branch(state) => file.branches.push(BranchData {
+                            line,
+                            been_true: state.been_true,
+                            been_false: state.been_false,
+                        }),
+                        CoverageStat::Condition(states) => {
+                            file.branches.extend(states.iter().map(|s| BranchData {
+                                line,
+                                been_true: s.been_true,
+                                been_false: s.been_false,
+                            }))
+                        }
+                    }
+                }
+                file
+            })
+            .collect();
+        Coverage { files }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::Trace;
+
+    #[test]
+    fn from_trace_map() {
+        let mut traces = TraceMap::new();
+        traces.add_trace(
+            Path::new("src/lib.rs"),
+            Trace {
+                fn_name: Some("foo".to_string()),
+                ..Trace::new_stub(1, CoverageStat::Line(2))
+            },
+        );
+        traces.add_trace(
+            Path::new("src/lib.rs"),
+            Trace {
+                fn_name: Some("foo".to_string()),
+                ..Trace::new_stub(2, CoverageStat::Line(0))
+            },
+        );
+        traces.add_trace(
+            Path::new("src/main.rs"),
+            Trace {
+                fn_name: Some("foo".to_string()),
+                ..Trace::new_stub(4, CoverageStat::Line(1))
+            },
+        );
+
+        let coverage = Coverage::from(&traces);
+        assert_eq!(coverage.files.len(), 2);
+        assert_eq!(coverage.covered(), 2);
+        assert_eq!(coverage.coverable(), 3);
+
+        let lib = coverage.file(Path::new("src/lib.rs")).unwrap();
+        assert_eq!(lib.lines[&1].hits, 2);
+        assert_eq!(lib.lines[&1].function, Some("foo".to_string()));
+        assert_eq!(lib.lines[&2].hits, 0);
+        assert!(lib.branches.is_empty());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut traces = TraceMap::new();
+        traces.add_trace(
+            Path::new("src/lib.rs"),
+            Trace {
+                fn_name: Some("foo".to_string()),
+                ..Trace::new_stub(3, CoverageStat::Line(1))
+            },
+        );
+        let coverage = Coverage::from(&traces);
+
+        let json = serde_json::to_string(&coverage).unwrap();
+        assert_eq!(
+            json,
+            r#"{"files":[{"path":"src/lib.rs","lines":{"3":{"hits":1,"function":"foo"}},"branches":[]}]}"#
+        );
+        let parsed: Coverage = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, coverage);
+    }
+}
@@ -0,0 +1,167 @@
+//! Coverage of the lines changed in the git diff. In a review the coverage of
+//! the lines a change adds or modifies says more about whether it's tested
+//! than the coverage of the whole project.
+use crate::compare::git_output;
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::traces::{CoverageStat, TraceMap};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Lines added or changed in each file of the diff
+type ModifiedLines = BTreeMap<PathBuf, BTreeSet<u64>>;
+
+/// Start and length of the new side of a hunk header such as `-1,2 +3,4 @@`
+fn added_range(hunk: &str) -> Option<(u64, u64)> {
+    let range = hunk.split_whitespace().find_map(|r| r.strip_prefix('+'))?;
+    let mut parts = range.splitn(2, ',');
+    let start = parts.next()?.parse().ok()?;
+    let count = match parts.next() {
+        Some(count) => count.parse().ok()?,
+        None => 1,
+    };
+    Some((start, count))
+}
+
+/// Reads the modified lines from a unified diff, the paths in the diff are
+/// relative to the root of the repository
+fn parse_diff(diff: &str, root: &Path) -> ModifiedLines {
+    let mut result = ModifiedLines::new();
+    let mut file = None;
+    for line in diff.lines() {
+        if let Some(path) = line.strip_prefix("+++ ") {
+            // Deleted files are diffed against /dev/null
+            file = path.strip_prefix("b/").map(|p| root.join(p));
+        } else if let Some(hunk) = line.strip_prefix("@@ ") {
+            if let (Some(file), Some((start, count))) = (&file, added_range(hunk)) {
+                result
+                    .entry(file.clone())
+                    .or_default()
+                    .extend(start..start + count);
+            }
+        }
+    }
+    result
+}
+
+fn modified_lines(config: &Config) -> Result<ModifiedLines, RunError> {
+    let project_dir = config
+        .manifest
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let root = git_output(&project_dir, &["rev-parse", "--show-toplevel"])
+        .map_err(RunError::DiffCoverage)?;
+    let from = config.diff_from.as_deref().unwrap_or("HEAD");
+    let args = [
+        "diff",
+        "--unified=0",
+        "--no-color",
+        "--src-prefix=a/",
+        "--dst-prefix=b/",
+        from,
+    ];
+    let diff = git_output(&project_dir, &args).map_err(RunError::DiffCoverage)?;
+    Ok(parse_diff(&diff, Path::new(&root)))
+}
+
+/// Number of covered and coverable lines among the modified lines
+fn diff_coverage(result: &TraceMap, modified: &ModifiedLines) -> (usize, usize) {
+    let mut covered = 0;
+    let mut coverable = 0;
+    for (file, lines) in modified {
+        let mut hits = BTreeMap::new();
+        for trace in result.get_child_traces(file) {
+            if let CoverageStat::Line(h) = trace.stats {
+                if lines.contains(&trace.line) {
+                    *hits.entry(trace.line).or_insert(0) += h;
+                }
+            }
+        }
+        coverable += hits.len();
+        covered += hits.values().filter(|h| **h > 0).count();
+    }
+    (covered, coverable)
+}
+
+fn percentage(covered: usize, coverable: usize) -> f64 {
+    if coverable == 0 {
+        100.0f64
+    } else {
+        100.0f64 * covered as f64 / coverable as f64
+    }
+}
+
+/// Prints the coverage of the modified lines, returning a description of the
+/// failure if it's below the minimum
+pub(crate) fn report_diff_coverage(
+    config: &Config,
+    result: &TraceMap,
+) -> Result<Option<String>, RunError> {
+    let (covered, coverable) = diff_coverage(result, &modified_lines(config)?);
+    let percent = percentage(covered, coverable);
+    println!(
+        "|| Of {} modified lines, {} were covered ({:.1}%)",
+        coverable, covered, percent
+    );
+    match config.diff_coverage_minimum {
+        Some(minimum) if percent < minimum => Ok(Some(format!(
+            "coverage of modified lines {:.1}% is below {:.1}%",
+            percent, minimum
+        ))),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::Trace;
+
+    #[test]
+    fn modified_lines_of_diff() {
+        let diff = "diff --git a/src/lib.rs b/src/lib.rs
+index 1234567..89abcde 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -3 +3 @@ fn foo() {
+-    1
++    2
+@@ -10,0 +11,3 @@ fn bar() {
++a
++b
++c
+@@ -20,2 +23,0 @@
+-removed
+-removed
+diff --git a/src/old.rs b/src/old.rs
+deleted file mode 100644
+--- a/src/old.rs
++++ /dev/null
+@@ -1,2 +0,0 @@
+";
+        let modified = parse_diff(diff, Path::new("/repo"));
+        assert_eq!(modified.len(), 1);
+        let lines = modified[Path::new("/repo/src/lib.rs")]
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![3, 11, 12, 13]);
+    }
+
+    #[test]
+    fn coverage_of_modified_lines() {
+        let lib = Path::new("/repo/src/lib.rs");
+        let mut result = TraceMap::new();
+        result.add_trace(lib, Trace::new_stub(3, CoverageStat::Line(2)));
+        result.add_trace(lib, Trace::new_stub(11, CoverageStat::Line(0)));
+        result.add_trace(lib, Trace::new_stub(12, CoverageStat::Line(1)));
+        result.add_trace(lib, Trace::new_stub(40, CoverageStat::Line(0)));
+        let mut modified = ModifiedLines::new();
+        modified.insert(lib.to_path_buf(), vec![3, 11, 12, 13].into_iter().collect());
+        let (covered, coverable) = diff_coverage(&result, &modified);
+        assert_eq!((covered, coverable), (2, 3));
+        assert_eq!(format!("{:.1}", percentage(covered, coverable)), "66.7");
+        assert_eq!(percentage(0, 0), 100.0);
+    }
+}
@@ -1,5 +1,22 @@
 use crate::report::cobertura;
 use failure::Fail;
+/// Exit codes of tarpaulin so scripts can tell why a run failed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExitCode {
+    /// Coverage was collected and every check passed
+    Success = 0,
+    /// A test failed or crashed
+    TestFailed = 1,
+    /// Coverage is below a minimum or regressed compared to a branch
+    CoverageBelowThreshold = 2,
+    /// The config or the project manifest is invalid
+    ConfigError = 3,
+    /// Reading or writing a file failed
+    IoError = 4,
+    /// Building, instrumenting or tracing the tests failed
+    InstrumentationFailed = 5,
+}
+
 /// Error states that could be returned from tarpaulin
 #[derive(Fail, Debug)]
 pub enum RunError {
@@ -45,10 +62,59 @@ pub enum RunError {
     XML(cobertura::Error),
     #[fail(display = "Failed to generate Lcov report! Error: {}", _0)]
     Lcov(String),
+    #[fail(display = "Coverage report hook failed! Error: {}", _0)]
+    ReportHook(String),
+    #[fail(display = "Coverage is below the minimum! {}", _0)]
+    BelowMinimumCoverage(String),
+    #[fail(display = "Failed to collect coverage of branch! Error: {}", _0)]
+    CompareBranch(String),
+    #[fail(display = "Coverage regressed! {}", _0)]
+    CoverageRegression(String),
+    #[fail(display = "Failed to read the git diff! Error: {}", _0)]
+    DiffCoverage(String),
+    #[fail(display = "Failed to read PGO profile! Error: {}", _0)]
+    PgoProfile(String),
+    /// Invalid config or arguments
+    #[fail(display = "{}", _0)]
+    Config(String),
     #[fail(display = "Tarpaulin experienced an internal error")]
     Internal,
 }
 
+impl RunError {
+    /// The code tarpaulin exits with when it fails with this error
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            RunError::TestRuntime(_) | RunError::TestFailed | RunError::StateMachine(_) => {
+                ExitCode::TestFailed
+            }
+            RunError::BelowMinimumCoverage(_) | RunError::CoverageRegression(_) => {
+                ExitCode::CoverageBelowThreshold
+            }
+            RunError::Manifest(_)
+            | RunError::Packages(_)
+            | RunError::OutFormat(_)
+            | RunError::DiffCoverage(_)
+            | RunError::Config(_) => ExitCode::ConfigError,
+            RunError::Parse(_)
+            | RunError::IO(_)
+            | RunError::CovReport(_)
+            | RunError::Html(_)
+            | RunError::XML(_)
+            | RunError::Lcov(_)
+            | RunError::ReportHook(_)
+            | RunError::PgoProfile(_) => ExitCode::IoError,
+            RunError::Cargo(_)
+            | RunError::TestCompile(_)
+            | RunError::TestCoverage(_)
+            | RunError::Trace(_)
+            | RunError::NixError(_)
+            | RunError::CompareBranch(_)
+            | RunError::Internal => ExitCode::InstrumentationFailed,
+        }
+    }
+}
+
 impl From<std::io::Error> for RunError {
     fn from(e: std::io::Error) -> Self {
         RunError::IO(e)
@@ -1,7 +1,10 @@
 use crate::config::*;
 use crate::errors::*;
 use crate::process_handling::*;
-use crate::report::report_coverage;
+use crate::report::{
+    check_file_thresholds, check_metric_thresholds, check_minimum_coverage, report_coverage,
+    report_coverage_by_type,
+};
 use crate::source_analysis::LineAnalysis;
 use crate::statemachine::*;
 use crate::test_loader::*;
@@ -16,17 +19,33 @@ use cargo::ops::{
     TestOptions,
 };
 use cargo::util::{homedir, Config as CargoConfig};
-use log::{debug, info, trace, warn};
+use lazy_static::lazy_static;
+use log::{debug, error, info, trace, warn};
+use nix::sys::signal::{kill, Signal};
+use nix::sys::wait::waitpid;
 use nix::unistd::*;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::ffi::CString;
+use std::fmt;
+use std::fs;
+use std::os::unix::io::{FromRawFd, RawFd};
 use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
 use walkdir::WalkDir;
 
+pub mod annotate;
 pub mod breakpoint;
+mod cache;
+mod compare;
 pub mod config;
+pub mod coverage;
+mod diff;
 pub mod errors;
+mod llvm_cov;
+pub mod merge;
 mod process_handling;
 pub mod report;
 mod source_analysis;
@@ -37,26 +56,84 @@ pub mod traces;
 mod ptrace_control;
 
 static DOCTEST_FOLDER: &str = "target/doctests";
+/// File in the target directory recording the flags of the last build
+static BUILD_FLAGS_FILE: &str = "tarpaulin-build-flags";
+/// Flags the project is instrumented with, tarpaulin_include lets projects
+/// compile code only for coverage such as test helpers which should be covered
+static INSTRUMENTATION_FLAGS: &str = " -C relocation-model=dynamic-no-pic -C link-dead-code \
+                                      -C opt-level=0 -C debuginfo=2 --cfg tarpaulin_include ";
+
+lazy_static! {
+    /// `RUSTFLAGS` and `RUSTDOCFLAGS` tarpaulin was started with, the variables
+    /// are overwritten for each config so the original values are kept here
+    static ref ORIGINAL_FLAGS: (Option<String>, Option<String>) =
+        (env::var("RUSTFLAGS").ok(), env::var("RUSTDOCFLAGS").ok());
+}
+
+/// The types of test coverage can be broken down by
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum TestType {
+    /// Tests in the library or binary targets
+    Unit,
+    /// Tests in the `tests` directory
+    Integration,
+    /// Tests in documentation comments
+    Doctest,
+}
+
+impl fmt::Display for TestType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TestType::Unit => write!(f, "Unit tests"),
+            TestType::Integration => write!(f, "Integration tests"),
+            TestType::Doctest => write!(f, "Doctests"),
+        }
+    }
+}
 
 pub fn run(configs: &[Config]) -> Result<(), RunError> {
+    lazy_static::initialize(&ORIGINAL_FLAGS);
     let mut tracemap = TraceMap::new();
+    let mut by_type = BTreeMap::new();
     let mut ret = 0i32;
-    let mut failure = Ok(());
+    let mut failures = vec![];
+    let mut below_minimum = vec![];
+    let keep_going = configs.iter().any(|c| c.keep_going);
+
+    for config in configs.iter() {
+        config.check_output_file().map_err(RunError::OutFormat)?;
+    }
 
     for config in configs.iter() {
         if config.name == "report" {
             continue;
         }
-        match launch_tarpaulin(config) {
+        match launch_with_cache(config, &mut by_type) {
             Ok((t, r)) => {
+                let report_section = config.output_per_config && configs.len() > 1;
+                let check_coverage = config.minimum_coverage.is_some()
+                    || config.minimum_line_coverage.is_some()
+                    || config.minimum_branch_coverage.is_some()
+                    || !config.file_thresholds.is_empty();
+                if report_section || check_coverage {
+                    let mut section = t.clone();
+                    section.dedup();
+                    if report_section {
+                        report_coverage(config, &section)?;
+                    }
+                    below_minimum.extend(check_minimum_coverage(config, &section));
+                    below_minimum.extend(check_metric_thresholds(config, &section));
+                    below_minimum.extend(check_file_thresholds(config, &section));
+                }
                 tracemap.merge(&t);
                 ret |= r;
+                if r != 0 {
+                    failures.push((config_label(config), RunError::TestFailed));
+                }
             }
             Err(e) => {
                 info!("Failure {}", e);
-                if failure.is_ok() {
-                    failure = Err(e);
-                }
+                failures.push((config_label(config), e));
             }
         }
     }
@@ -72,9 +149,48 @@ pub fn run(configs: &[Config]) -> Result<(), RunError> {
             }
         }
         if !reported {
-            report_coverage(&configs[0], &tracemap)?;
+            // Merged results go in the top level output directory so they
+            // don't overwrite the results of the first config
+            let mut merged = configs[0].clone();
+            merged.output_per_config = false;
+            report_coverage(&merged, &tracemap)?;
+        }
+    }
+    let report_config = configs
+        .iter()
+        .find(|c| c.name == "report")
+        .or_else(|| configs.first());
+    if let Some(c) = report_config.filter(|c| c.report_by_test_type) {
+        report_coverage_by_type(c, &by_type, &tracemap)?;
+    }
+    let mut regression = None;
+    if let Some(c) = report_config {
+        if let Some(ref branch) = c.compare_branch {
+            regression = compare::compare_with_branch(c, &tracemap, branch)?;
+        }
+    }
+    if let Some(c) = report_config.filter(|c| c.diff_coverage) {
+        below_minimum.extend(diff::report_diff_coverage(c, &tracemap)?);
+    }
+    if keep_going && !failures.is_empty() {
+        error!("{} configs failed:", failures.len());
+        for (name, e) in &failures {
+            error!("    {}: {}", name, e);
         }
     }
+    // Failed tests are reported after the coverage checks as before
+    if let Some(i) = failures
+        .iter()
+        .position(|(_, e)| !matches!(e, RunError::TestFailed))
+    {
+        return Err(failures.remove(i).1);
+    }
+    if !below_minimum.is_empty() {
+        return Err(RunError::BelowMinimumCoverage(below_minimum.join(", ")));
+    }
+    if let Some(regression) = regression {
+        return Err(RunError::CoverageRegression(regression));
+    }
 
     if ret == 0 {
         Ok(())
@@ -83,11 +199,83 @@ pub fn run(configs: &[Config]) -> Result<(), RunError> {
     }
 }
 
+/// Name of the config for messages, configs from the command line have no
+/// name
+fn config_label(config: &Config) -> String {
+    if config.name.is_empty() {
+        "default".to_string()
+    } else {
+        config.name.clone()
+    }
+}
+
+/// Launches tarpaulin unless the config has cached results for unchanged
+/// source files. Results of passing runs are cached for the next run.
+fn launch_with_cache(
+    config: &Config,
+    by_type: &mut BTreeMap<TestType, TraceMap>,
+) -> Result<(TraceMap, i32), RunError> {
+    if config.cache_dir.is_none() {
+        return launch_tarpaulin_by_type(config, by_type);
+    }
+    let (result, config_by_type) = match cache::load(config) {
+        Some((traces, cached_by_type)) => (Ok((traces, 0)), cached_by_type),
+        None => {
+            let mut config_by_type = BTreeMap::new();
+            let result = launch_tarpaulin_by_type(config, &mut config_by_type);
+            if let Ok((ref traces, 0)) = result {
+                cache::store(config, traces, &config_by_type);
+            }
+            (result, config_by_type)
+        }
+    };
+    for (test_type, traces) in config_by_type {
+        merge_results(by_type.entry(test_type).or_default(), traces, config);
+    }
+    result
+}
+
 /// Launches tarpaulin with the given configuration.
 pub fn launch_tarpaulin(config: &Config) -> Result<(TraceMap, i32), RunError> {
+    launch_tarpaulin_by_type(config, &mut BTreeMap::new())
+}
+
+/// Launches tarpaulin with the given configuration. If coverage is reported
+/// by test type the coverage of each type of test is merged into `by_type`.
+pub fn launch_tarpaulin_by_type(
+    config: &Config,
+    by_type: &mut BTreeMap<TestType, TraceMap>,
+) -> Result<(TraceMap, i32), RunError> {
     if !config.name.is_empty() {
         info!("Running config {}", config.name);
     }
+    // Executables built with llvm-cov instrumentation write their own coverage
+    // so only the ptrace engine needs them to run natively
+    if let (Some(target), CoverageEngine::Ptrace) = (&config.target, config.engine) {
+        if host_triple().as_ref() != Some(target) {
+            warn!(
+                "{} isn't the host target, tarpaulin traces the tests with ptrace so they have to \
+                 run natively on the host, use `--engine llvm-cov` for tests which don't",
+                target
+            );
+        }
+    }
+    let needs_nightly = if config.nightly {
+        Some("--nightly")
+    } else if config.engine == CoverageEngine::LlvmCov {
+        Some("--engine llvm-cov")
+    } else {
+        None
+    };
+    if let Some(option) = needs_nightly {
+        let version = rustc_version().unwrap_or_else(|| "no rustc".to_string());
+        if !is_nightly(&version) {
+            return Err(RunError::Config(format!(
+                "{} needs a nightly toolchain, found {}",
+                option, version
+            )));
+        }
+    }
     setup_environment(&config);
     cargo::core::enable_nightly_features();
     let cwd = match config.manifest.parent() {
@@ -118,51 +306,216 @@ pub fn launch_tarpaulin(config: &Config) -> Result<(TraceMap, i32), RunError> {
 
     let workspace = Workspace::new(config.manifest.as_path(), &cargo_config)
         .map_err(|e| RunError::Manifest(e.to_string()))?;
+    if !config.binary_paths.is_empty() {
+        return run_prebuilt_binaries(&workspace, config, by_type);
+    }
 
-    let mut compile_options = get_compile_options(&config, &cargo_config)?;
+    let mut compile_options = get_compile_options(&config, &cargo_config, &workspace)?;
 
     info!("Running Tarpaulin");
 
-    if config.force_clean {
+    let flags_file = workspace
+        .target_dir()
+        .as_path_unlocked()
+        .join(BUILD_FLAGS_FILE);
+    let build_flags = get_build_flags(config);
+    if should_clean(config, &flags_file, &build_flags) {
         debug!("Cleaning project");
         // Clean isn't expected to fail and if it does it likely won't have an effect
         let clean_opt = CleanOptions {
             config: &cargo_config,
             spec: vec![],
             target: None,
-            profile_specified: config.force_clean,
+            profile_specified: true,
             profile_kind: ProfileKind::Dev,
             doc: false,
         };
         let _ = clean(&workspace, &clean_opt);
     }
+    if let Some(dir) = flags_file.parent() {
+        if fs::create_dir_all(dir).is_ok() && fs::write(&flags_file, &build_flags).is_err() {
+            debug!("Failed to record build flags in {}", flags_file.display());
+        }
+    }
     let mut result = TraceMap::new();
     let mut return_code = 0i32;
     let project_analysis = source_analysis::get_line_analysis(&workspace, config);
     info!("Building project");
-    for copt in compile_options.drain(..) {
+    for (run_type, copt) in compile_options.drain(..) {
         let run_result = match copt.build_config.mode {
+            CompileMode::Build if run_type == RunType::BuildScripts => {
+                run_build_scripts(&workspace, copt, &project_analysis, config)
+            }
             CompileMode::Build | CompileMode::Test | CompileMode::Bench => {
-                run_tests(&workspace, copt, &project_analysis, config)
+                run_tests(&workspace, copt, &project_analysis, config, by_type)
+            }
+            CompileMode::Doctest => {
+                run_doctests(&workspace, copt, &project_analysis, config, by_type)
             }
-            CompileMode::Doctest => run_doctests(&workspace, copt, &project_analysis, config),
             e => {
                 debug!("Internal tarpaulin error. Unsupported compile mode {:?}", e);
                 Err(RunError::Internal)
             }
         }?;
-        result.merge(&run_result.0);
+        merge_results(&mut result, run_result.0, config);
         return_code |= run_result.1;
     }
     result.dedup();
     Ok((result, return_code))
 }
 
+/// Target triple of the host rustc
+fn host_triple() -> Option<String> {
+    let output = Command::new("rustc").arg("-vV").output().ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|l| l.strip_prefix("host: "))
+        .map(|host| host.trim().to_string())
+}
+
+fn rustc_version() -> Option<String> {
+    let output = Command::new("rustc").arg("-V").output().ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Whether the `rustc -V` output is from a toolchain which accepts `-Z` flags,
+/// locally built compilers are `-dev` versions
+fn is_nightly(version: &str) -> bool {
+    version.contains("-nightly") || version.contains("-dev")
+}
+
+/// Flags the project is built with, a change in them means the existing build
+/// artefacts weren't built by this version of tarpaulin with this config
+fn get_build_flags(config: &Config) -> String {
+    let rustflags = build_env_vars(config)
+        .into_iter()
+        .filter(|(key, _)| key == "RUSTFLAGS")
+        .map(|(_, value)| value)
+        .last()
+        .unwrap_or_default();
+    format!("{}\n{}\n", env!("CARGO_PKG_VERSION"), rustflags)
+}
+
+/// Decides whether to clean the project before building. `force_clean`
+/// always cleans and `skip_clean` never does, otherwise the project is
+/// cleaned if the build flags changed since the last run. Without a record of
+/// the flags the artefacts may not be instrumented so it's cleaned as well.
+fn should_clean(config: &Config, flags_file: &Path, build_flags: &str) -> bool {
+    if config.force_clean {
+        true
+    } else if config.skip_clean {
+        false
+    } else {
+        match fs::read_to_string(flags_file) {
+            Ok(previous) => previous != build_flags,
+            Err(_) => true,
+        }
+    }
+}
+
+/// Merges the coverage results from a test binary into the combined results.
+/// If symbols are deduplicated lines shared between binaries keep the
+/// maximum hit count unless the counts should be summed.
+fn merge_results(result: &mut TraceMap, mut other: TraceMap, config: &Config) {
+    if config.dedup_symbols && config.count_aggregation == CountAggregation::Max {
+        other.dedup();
+        result.merge_max(&other);
+    } else {
+        result.merge(&other);
+    }
+}
+
+/// Records the coverage of a test binary against its type of test if
+/// coverage is reported by test type
+fn record_test_type(
+    by_type: &mut BTreeMap<TestType, TraceMap>,
+    test_type: TestType,
+    traces: &TraceMap,
+    config: &Config,
+) {
+    if config.report_by_test_type {
+        merge_results(
+            by_type.entry(test_type).or_default(),
+            traces.clone(),
+            config,
+        );
+    }
+}
+
+/// Integration tests are test targets, other tests are built from the
+/// library or binary targets
+/// Runs test executables built in an earlier step instead of building the
+/// project, the source paths in their debug information map the coverage back
+/// to the project
+fn run_prebuilt_binaries(
+    workspace: &Workspace,
+    config: &Config,
+    by_type: &mut BTreeMap<TestType, TraceMap>,
+) -> Result<(TraceMap, i32), RunError> {
+    info!("Running prebuilt test executables");
+    let analysis = source_analysis::get_line_analysis(workspace, config);
+    let mut result = TraceMap::new();
+    let mut return_code = 0i32;
+    for binary in &config.binary_paths {
+        if !binary.is_file() {
+            return Err(RunError::TestCoverage(format!(
+                "Test executable {} doesn't exist",
+                binary.display()
+            )));
+        }
+        if let Some(res) =
+            get_test_coverage(workspace, None, binary, &analysis, config, true, false)?
+        {
+            record_test_type(
+                by_type,
+                prebuilt_test_type(workspace, binary),
+                &res.0,
+                config,
+            );
+            merge_results(&mut result, res.0, config);
+            return_code |= res.1;
+        }
+    }
+    result.dedup();
+    Ok((result, return_code))
+}
+
+/// Test type of a prebuilt test executable, found from the target named like
+/// the executable without the hash cargo appends
+fn prebuilt_test_type(workspace: &Workspace, binary: &Path) -> TestType {
+    let name = binary
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    let stem = name.rsplitn(2, '-').last().unwrap_or(name);
+    if workspace
+        .members()
+        .any(|p| get_test_type(p, stem) == TestType::Integration)
+    {
+        TestType::Integration
+    } else {
+        TestType::Unit
+    }
+}
+
+fn get_test_type(package: &Package, name: &str) -> TestType {
+    if package
+        .targets()
+        .iter()
+        .any(|t| t.is_test() && t.name() == name)
+    {
+        TestType::Integration
+    } else {
+        TestType::Unit
+    }
+}
+
 fn run_tests(
     workspace: &Workspace,
     compile_options: CompileOptions,
     analysis: &HashMap<PathBuf, LineAnalysis>,
     config: &Config,
+    by_type: &mut BTreeMap<TestType, TraceMap>,
 ) -> Result<(TraceMap, i32), RunError> {
     let mut result = TraceMap::new();
     let mut return_code = 0i32;
@@ -174,11 +527,12 @@ fn run_tests(
                 return Ok((result, return_code));
             }
             // Examples are always in the binaries list with tests!
-            if config
-                .run_types
-                .iter()
-                .any(|x| !(*x == RunType::Tests || *x == RunType::Doctests))
-            {
+            if config.build_run_types().iter().any(|x| {
+                !(*x == RunType::Tests
+                    || *x == RunType::Doctests
+                    || *x == RunType::BuildScripts
+                    || *x == RunType::ProcMacros)
+            }) {
                 // If we have binaries we have other artefacts to run
                 for binary in comp.binaries {
                     if let Some(res) = get_test_coverage(
@@ -190,13 +544,14 @@ fn run_tests(
                         false,
                         false,
                     )? {
-                        result.merge(&res.0);
+                        merge_results(&mut result, res.0, config);
                         return_code |= res.1;
                     }
                 }
             }
             for &(ref package, ref name, ref path) in &comp.tests {
                 debug!("Processing {}", name);
+                let test_type = get_test_type(package, name);
                 if let Some(res) = get_test_coverage(
                     &workspace,
                     Some(package),
@@ -206,7 +561,8 @@ fn run_tests(
                     true,
                     false,
                 )? {
-                    result.merge(&res.0);
+                    record_test_type(by_type, test_type, &res.0, config);
+                    merge_results(&mut result, res.0, config);
                     return_code |= res.1;
                 }
                 if config.run_ignored {
@@ -219,7 +575,8 @@ fn run_tests(
                         true,
                         true,
                     )? {
-                        result.merge(&res.0);
+                        record_test_type(by_type, test_type, &res.0, config);
+                        merge_results(&mut result, res.0, config);
                         return_code |= res.1;
                     }
                 }
@@ -236,14 +593,24 @@ fn run_doctests(
     compile_options: CompileOptions,
     analysis: &HashMap<PathBuf, LineAnalysis>,
     config: &Config,
+    by_type: &mut BTreeMap<TestType, TraceMap>,
 ) -> Result<(TraceMap, i32), RunError> {
     info!("Running doctests");
+    // Doctests can be given a longer timeout than the other tests
+    let doctest_config;
+    let config = match config.doctest_timeout {
+        Some(timeout) => {
+            doctest_config = config.clone().with_timeout(timeout);
+            &doctest_config
+        }
+        None => config,
+    };
     let mut result = TraceMap::new();
     let mut return_code = 0i32;
 
     let opts = TestOptions {
         no_run: false,
-        no_fail_fast: false,
+        no_fail_fast: config.no_fail_fast,
         compile_opts: compile_options,
     };
     let _ = ops::run_tests(workspace, &opts, &[]);
@@ -274,30 +641,341 @@ fn run_doctests(
             if let Some(res) =
                 get_test_coverage(&workspace, None, dt.path(), analysis, config, true, false)?
             {
-                result.merge(&res.0);
+                let mut traces = res.0;
+                if config.include_doctests_in_line_coverage && res.1 == 0 {
+                    if let Some((file, line)) = doctest_source_location(dt.path(), analysis.keys())
+                    {
+                        traces.add_trace(
+                            &file,
+                            Trace {
+                                line,
+                                address: HashSet::new(),
+                                length: 0,
+                                stats: CoverageStat::Line(1),
+                                fn_name: None,
+                                mangled_name: None,
+                            },
+                        );
+                    }
+                }
+                record_test_type(by_type, TestType::Doctest, &traces, config);
+                merge_results(&mut result, traces, config);
                 return_code |= res.1;
             }
         }
     }
+    if config.exclude_doc_tests_from_coverage {
+        // The doctests still have to pass and are reported by test type but
+        // the lines they hit don't count towards the coverage
+        info!("Leaving the doctest coverage out of the results");
+        return Ok((TraceMap::new(), return_code));
+    }
     result.dedup();
     Ok((result, return_code))
 }
 
+/// Runs the build scripts of the workspace members. Cargo builds them with the
+/// project but only runs them when their inputs change so they're run again
+/// here with the environment cargo would give them.
+fn run_build_scripts(
+    workspace: &Workspace,
+    compile_options: CompileOptions,
+    analysis: &HashMap<PathBuf, LineAnalysis>,
+    config: &Config,
+) -> Result<(TraceMap, i32), RunError> {
+    info!("Running build scripts");
+    let mut result = TraceMap::new();
+    let mut return_code = 0i32;
+    let comp =
+        compile(&workspace, &compile_options).map_err(|e| RunError::TestCompile(e.to_string()))?;
+    if config.no_run {
+        info!("Project compiled successfully");
+        return Ok((result, return_code));
+    }
+    let build_dir = match comp.host_deps_output.parent() {
+        Some(dir) => dir.join("build"),
+        None => return Ok((result, return_code)),
+    };
+    let out_root = workspace
+        .target_dir()
+        .as_path_unlocked()
+        .join("tarpaulin")
+        .join("build-script-out");
+    let packages = workspace
+        .members()
+        .filter(|p| p.targets().iter().any(|t| t.is_custom_build()));
+    for package in packages {
+        let script = match find_build_script(&build_dir, &package.name()) {
+            Some(script) => script,
+            None => {
+                warn!("No build script executable found for {}", package.name());
+                continue;
+            }
+        };
+        let out_dir = out_root.join(package.name().as_str());
+        fs::create_dir_all(&out_dir)?;
+        let envars = get_build_script_environment(
+            package,
+            &out_dir,
+            &comp.host,
+            &comp.target,
+            &compile_options,
+            config,
+        );
+        if let Some(res) =
+            get_build_script_coverage(workspace, package, &script, &envars, analysis, config)?
+        {
+            merge_results(&mut result, res.0, config);
+            return_code |= res.1;
+        }
+    }
+    result.dedup();
+    Ok((result, return_code))
+}
+
+/// Finds the most recently built build script of a package. Cargo puts it in
+/// a `<package>-<hash>` folder of the build directory, the folders of earlier
+/// builds and the ones the script was run in are left alongside it.
+fn find_build_script(build_dir: &Path, package: &str) -> Option<PathBuf> {
+    let prefix = format!("{}-", package);
+    fs::read_dir(build_dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter(|e| match e.file_name().to_str() {
+            Some(name) if name.starts_with(&prefix) => {
+                name[prefix.len()..].chars().all(|c| c.is_ascii_hexdigit())
+            }
+            _ => false,
+        })
+        .map(|e| e.path().join("build-script-build"))
+        .filter_map(|p| {
+            let modified = p.metadata().and_then(|m| m.modified()).ok()?;
+            Some((modified, p))
+        })
+        .max()
+        .map(|(_, p)| p)
+}
+
+/// The environment variables cargo sets when running a build script. The
+/// script's `OUT_DIR` is a separate directory so the outputs of the real build
+/// aren't overwritten.
+fn get_build_script_environment(
+    package: &Package,
+    out_dir: &Path,
+    host: &str,
+    target: &str,
+    compile_options: &CompileOptions,
+    config: &Config,
+) -> Vec<(String, String)> {
+    let manifest_dir = package.manifest_path().parent().unwrap_or(Path::new(""));
+    let profile = if config.release { "release" } else { "debug" };
+    let mut envars = vec![
+        (
+            "CARGO_MANIFEST_DIR".to_string(),
+            manifest_dir.display().to_string(),
+        ),
+        ("CARGO_PKG_NAME".to_string(), package.name().to_string()),
+        (
+            "CARGO_PKG_VERSION".to_string(),
+            package.version().to_string(),
+        ),
+        ("OUT_DIR".to_string(), out_dir.display().to_string()),
+        ("HOST".to_string(), host.to_string()),
+        ("TARGET".to_string(), target.to_string()),
+        ("PROFILE".to_string(), profile.to_string()),
+        ("DEBUG".to_string(), (!config.release).to_string()),
+        (
+            "OPT_LEVEL".to_string(),
+            if config.release { "3" } else { "0" }.to_string(),
+        ),
+        (
+            "NUM_JOBS".to_string(),
+            compile_options.build_config.jobs.to_string(),
+        ),
+        (
+            "RUSTC".to_string(),
+            env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string()),
+        ),
+    ];
+    let features = package.summary().features();
+    let enabled = features.keys().filter(|f| {
+        config.all_features
+            || config.features.iter().any(|x| x == f.as_str())
+            || (f.as_str() == "default" && !config.no_default_features)
+    });
+    for feature in enabled {
+        let name = feature.to_uppercase().replace('-', "_");
+        envars.push((format!("CARGO_FEATURE_{}", name), "1".to_string()));
+    }
+    envars
+}
+
+/// Returns the coverage statistics of running a package's build script
+fn get_build_script_coverage(
+    project: &Workspace,
+    package: &Package,
+    script: &Path,
+    envars: &[(String, String)],
+    analysis: &HashMap<PathBuf, LineAnalysis>,
+    config: &Config,
+) -> Result<Option<(TraceMap, i32)>, RunError> {
+    if config.engine == CoverageEngine::LlvmCov {
+        let mut envs = test_env_vars(&build_script_config(config));
+        envs.extend(envars.iter().cloned());
+        let launch = llvm_cov::Launch {
+            path: script,
+            args: vec![],
+            envs,
+            dir: package.manifest_path().parent(),
+            suppress_panic_output: false,
+        };
+        return llvm_cov::collect_coverage(project, launch, analysis, config).map(Some);
+    }
+    if let Err(e) = limit_affinity() {
+        warn!("Failed to set processor affinity {}", e);
+    }
+    match fork() {
+        Ok(ForkResult::Parent { child }) => {
+            match collect_coverage(project, script, child, analysis, config) {
+                Ok(t) => Ok(Some(t)),
+                Err(e) => Err(RunError::TestCoverage(e.to_string())),
+            }
+        }
+        Ok(ForkResult::Child) => {
+            info!("Launching build script");
+            for (key, value) in envars {
+                env::set_var(key, value);
+            }
+            execute_build_script(script, package, config)?;
+            Ok(None)
+        }
+        Err(err) => Err(RunError::TestCoverage(format!(
+            "Failed to run build script {}, Error: {}",
+            script.display(),
+            err.to_string()
+        ))),
+    }
+}
+
+/// Launches a build script in its package directory like cargo does, unlike
+/// tests it takes no arguments
+fn execute_build_script(script: &Path, package: &Package, config: &Config) -> Result<(), RunError> {
+    let exec_path = CString::new(script.to_str().unwrap()).unwrap();
+    info!("running {}", script.display());
+    if let Some(parent) = package.manifest_path().parent() {
+        let _ = env::set_current_dir(parent);
+    }
+    let envars = get_test_environment(&build_script_config(config));
+    execute(exec_path.clone(), &[exec_path], envars.as_slice())
+}
+
+/// Build scripts are part of the build so the test variables aren't set
+fn build_script_config(config: &Config) -> Config {
+    Config {
+        test_env: HashMap::new(),
+        ..config.clone()
+    }
+}
+
+/// Finds the source file and line a persisted doctest was extracted from.
+/// Rustdoc names the doctest folder after the source path relative to the
+/// package with separators replaced by underscores followed by the line and
+/// an index i.e. `src_lib_rs_12_0`.
+fn doctest_source_location<'a>(
+    doctest: &Path,
+    mut files: impl Iterator<Item = &'a PathBuf>,
+) -> Option<(PathBuf, u64)> {
+    let folder = doctest.parent()?;
+    let package_root = folder.parent()?.parent()?.parent()?;
+    let name = folder.file_name()?.to_str()?;
+    let mut parts = name.rsplitn(3, '_');
+    let _index = parts.next()?;
+    let line = parts.next()?.parse::<u64>().ok()?;
+    let source = parts.next()?;
+    files
+        .find(|f| match f.strip_prefix(package_root) {
+            Ok(rel) => rel.to_string_lossy().replace('/', "_").replace('.', "_") == source,
+            Err(_) => false,
+        })
+        .map(|f| (f.clone(), line))
+}
+
+/// Names of the workspace members which are procedural macro crates
+fn get_proc_macro_packages(workspace: &Workspace) -> Vec<String> {
+    workspace
+        .members()
+        .filter(|p| p.targets().iter().any(|t| t.proc_macro()))
+        .map(|p| p.name().to_string())
+        .collect()
+}
+
+/// Resolves the directories of workspace members, relative to the workspace
+/// root, to the names of their packages
+fn get_workspace_member_packages(
+    workspace: &Workspace,
+    members: &[PathBuf],
+) -> Result<Vec<String>, RunError> {
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    members
+        .iter()
+        .map(|member| {
+            let dir = canonical(&workspace.root().join(member));
+            workspace
+                .members()
+                .find(|p| canonical(p.root()) == dir)
+                .map(|p| p.name().to_string())
+                .ok_or_else(|| {
+                    RunError::Packages(format!("{} is not a workspace member", member.display()))
+                })
+        })
+        .collect()
+}
+
 fn get_compile_options<'a>(
     config: &Config,
     cargo_config: &'a CargoConfig,
-) -> Result<Vec<CompileOptions<'a>>, RunError> {
+    workspace: &Workspace,
+) -> Result<Vec<(RunType, CompileOptions<'a>)>, RunError> {
     let mut result = Vec::new();
-    for run_type in &config.run_types {
+    if config.jobs == Some(0) {
+        return Err(RunError::Cargo("--jobs must be at least 1".to_string()));
+    }
+    if let Some(ref profile) = config.profile {
+        check_profile(workspace, profile)?;
+    }
+    let mut packages = config.packages.clone();
+    packages.extend(get_workspace_member_packages(
+        workspace,
+        &config.workspace_members,
+    )?);
+    for run_type in &config.build_run_types() {
         let mut copt = CompileOptions::new(cargo_config, (*run_type).into())
-            .map_err(|e| RunError::Cargo(e.to_string()))?;
-        if run_type == &RunType::Tests {
+            .map_err(|e| RunError::Cargo(format!("{} build options: {}", run_type, e)))?;
+        let filter_targets = !(config.test_names.is_empty() && config.bench_names.is_empty());
+        if run_type == &RunType::Tests && filter_targets {
+            // Like `cargo test --test foo` only the named targets are built
+            copt.filter = CompileFilter::new(
+                LibRule::False,
+                FilterRule::Just(vec![]),
+                FilterRule::Just(config.test_names.clone()),
+                FilterRule::Just(vec![]),
+                FilterRule::Just(config.bench_names.clone()),
+            );
+        } else if run_type == &RunType::Tests {
             if let CompileFilter::Default {
                 ref mut required_features_filterable,
             } = copt.filter
             {
                 *required_features_filterable = true;
             }
+        } else if run_type == &RunType::Benchmarks && !config.bench_names.is_empty() {
+            copt.filter = CompileFilter::new(
+                LibRule::False,
+                FilterRule::Just(vec![]),
+                FilterRule::Just(vec![]),
+                FilterRule::Just(vec![]),
+                FilterRule::Just(config.bench_names.clone()),
+            );
         } else if run_type == &RunType::Doctests {
             copt.filter = CompileFilter::new(
                 LibRule::True,
@@ -306,6 +984,38 @@ fn get_compile_options<'a>(
                 FilterRule::Just(vec![]),
                 FilterRule::Just(vec![]),
             );
+        } else if run_type == &RunType::Bins {
+            let bins = if config.bins.is_empty() {
+                FilterRule::All
+            } else {
+                FilterRule::Just(config.bins.clone())
+            };
+            copt.filter = CompileFilter::new(
+                LibRule::False,
+                bins,
+                FilterRule::Just(vec![]),
+                FilterRule::Just(vec![]),
+                FilterRule::Just(vec![]),
+            );
+        } else if run_type == &RunType::BuildScripts {
+            // Build scripts are built along with any library or binary
+            copt.filter = CompileFilter::new(
+                LibRule::Default,
+                FilterRule::All,
+                FilterRule::Just(vec![]),
+                FilterRule::Just(vec![]),
+                FilterRule::Just(vec![]),
+            );
+        } else if run_type == &RunType::ProcMacros {
+            // Proc macros only run inside the compiler, their unit tests are
+            // the only part which runs in a process tarpaulin can trace
+            copt.filter = CompileFilter::new(
+                LibRule::True,
+                FilterRule::Just(vec![]),
+                FilterRule::Just(vec![]),
+                FilterRule::Just(vec![]),
+                FilterRule::Just(vec![]),
+            );
         } else if run_type == &RunType::Examples {
             copt.filter = CompileFilter::new(
                 LibRule::True,
@@ -316,54 +1026,178 @@ fn get_compile_options<'a>(
             );
         }
 
+        if let Some(jobs) = config.jobs {
+            copt.build_config.jobs = jobs as u32;
+        }
         copt.features = config.features.clone();
         copt.all_features = config.all_features;
         copt.no_default_features = config.no_default_features;
-        copt.build_config.profile_kind = match config.release {
-            true => ProfileKind::Release,
-            false => ProfileKind::Dev,
+        copt.build_config.profile_kind = profile_kind(config);
+        copt.build_config.requested_target = config.target.clone();
+        copt.spec = match Packages::from_flags(config.all, config.exclude.clone(), packages.clone())
+        {
+            Ok(spec) => spec,
+            Err(e) => {
+                return Err(RunError::Packages(e.to_string()));
+            }
         };
-        copt.spec =
-            match Packages::from_flags(config.all, config.exclude.clone(), config.packages.clone())
-            {
-                Ok(spec) => spec,
-                Err(e) => {
-                    return Err(RunError::Packages(e.to_string()));
-                }
-            };
-        result.push(copt);
+        if run_type == &RunType::ProcMacros {
+            let macro_packages = get_proc_macro_packages(workspace)
+                .into_iter()
+                .filter(|p| packages.is_empty() || packages.contains(p))
+                .filter(|p| !config.exclude.contains(p))
+                .collect::<Vec<_>>();
+            if macro_packages.is_empty() {
+                warn!("No procedural macro crates in the workspace, skipping ProcMacros");
+                continue;
+            }
+            copt.spec = Packages::Packages(macro_packages);
+        }
+        result.push((*run_type, copt));
     }
     Ok(result)
 }
 
+fn profile_kind(config: &Config) -> ProfileKind {
+    match config.profile.as_deref() {
+        Some("dev") => ProfileKind::Dev,
+        Some("release") => ProfileKind::Release,
+        Some(profile) => ProfileKind::Custom(profile.to_string()),
+        None if config.release => ProfileKind::Release,
+        None => ProfileKind::Dev,
+    }
+}
+
+/// Checks the profile name is valid and, unless it's one of cargo's built in
+/// profiles, that it's defined in the workspace manifest and the manifest
+/// enables custom profiles
+fn check_profile(workspace: &Workspace, profile: &str) -> Result<(), RunError> {
+    if !is_valid_profile_name(profile) {
+        return Err(RunError::Cargo(format!(
+            "Invalid profile name `{}`, it may only contain letters, numbers, `-` and `_`",
+            profile
+        )));
+    }
+    if ["dev", "release", "test", "bench"].contains(&profile) {
+        return Ok(());
+    }
+    let path = workspace.root().join("Cargo.toml");
+    let manifest = fs::read_to_string(&path)
+        .ok()
+        .and_then(|m| m.parse::<toml::Value>().ok())
+        .unwrap_or_else(|| toml::Value::Table(Default::default()));
+    check_custom_profile(&manifest, profile, &path)
+}
+
+/// Checks the manifest can build with the custom profile, cargo only supports
+/// custom profiles with the unstable `named-profiles` feature which needs a
+/// nightly toolchain
+fn check_custom_profile(
+    manifest: &toml::Value,
+    profile: &str,
+    path: &Path,
+) -> Result<(), RunError> {
+    if manifest
+        .get("profile")
+        .and_then(|p| p.get(profile))
+        .is_none()
+    {
+        return Err(RunError::Cargo(format!(
+            "Profile `{}` isn't defined, add a [profile.{}] section to {}",
+            profile,
+            profile,
+            path.display()
+        )));
+    }
+    let named_profiles = manifest
+        .get("cargo-features")
+        .and_then(|f| f.as_array())
+        .map(|f| f.iter().any(|f| f.as_str() == Some("named-profiles")))
+        .unwrap_or(false);
+    if named_profiles {
+        Ok(())
+    } else {
+        Err(RunError::Cargo(format!(
+            "Custom profile `{}` needs the unstable named-profiles cargo feature and a nightly \
+             toolchain, add `cargo-features = [\"named-profiles\"]` to the top of {}",
+            profile,
+            path.display()
+        )))
+    }
+}
+
 fn setup_environment(config: &Config) {
-    env::set_var("TARPAULIN", "1");
-    let common_opts =
-        " -C relocation-model=dynamic-no-pic -C link-dead-code -C opt-level=0 -C debuginfo=2 ";
-    let rustflags = "RUSTFLAGS";
-    let mut value = common_opts.to_string();
+    for (key, value) in build_env_vars(config) {
+        env::set_var(key, value);
+    }
+}
+
+/// Variables set in tarpaulin's environment for the build, the test
+/// executables inherit them. The project's cargo-env comes last so it can
+/// override any variable.
+fn build_env_vars(config: &Config) -> Vec<(String, String)> {
+    let mut vars = vec![
+        ("TARPAULIN".to_string(), "1".to_string()),
+        (
+            "RUSTFLAGS".to_string(),
+            rust_flags(config, ORIGINAL_FLAGS.0.as_deref()),
+        ),
+        // doesn't matter if we don't use it
+        (
+            "RUSTDOCFLAGS".to_string(),
+            rustdoc_flags(config, ORIGINAL_FLAGS.1.as_deref()),
+        ),
+    ];
+    vars.extend(config.cargo_env.iter().map(|(k, v)| (k.clone(), v.clone())));
+    vars
+}
+
+/// Flags for the instrumented build, the inherited `RUSTFLAGS` come after the
+/// instrumentation flags and the flags from the config come last
+fn rust_flags(config: &Config, inherited: Option<&str>) -> String {
+    let mut value = INSTRUMENTATION_FLAGS.to_string();
     if config.release {
         value = format!("{}-C debug-assertions=off ", value);
     }
-    if let Ok(vtemp) = env::var(rustflags) {
-        value.push_str(vtemp.as_ref());
+    if let Some(cpu) = &config.target_cpu {
+        value = format!("{}-C target-cpu={} ", value, cpu);
     }
-    env::set_var(rustflags, value);
-    // doesn't matter if we don't use it
-    let rustdoc = "RUSTDOCFLAGS";
+    if config.engine == CoverageEngine::LlvmCov {
+        value = format!("{}-Z instrument-coverage ", value);
+    }
+    if config.nightly {
+        for flag in Config::nightly_defaults() {
+            value = format!("{}-Z {} ", value, flag);
+        }
+    }
+    if let Some(vtemp) = inherited {
+        value.push_str(vtemp);
+    }
+    if !config.rustflags.is_empty() {
+        value = format!("{} {}", value.trim_end(), config.rustflags.join(" "));
+    }
+    value
+}
+
+fn rustdoc_flags(config: &Config, inherited: Option<&str>) -> String {
     let mut value = format!(
         "{} --persist-doctests {} -Z unstable-options ",
-        common_opts, DOCTEST_FOLDER
+        INSTRUMENTATION_FLAGS, DOCTEST_FOLDER
     );
-    if let Ok(vtemp) = env::var(rustdoc) {
+    if config.engine == CoverageEngine::LlvmCov {
+        value.push_str("-Z instrument-coverage ");
+    }
+    if let Some(vtemp) = inherited {
         if !vtemp.contains("--persist-doctests") {
-            value.push_str(vtemp.as_ref());
+            value.push_str(vtemp);
         }
     }
-    env::set_var(rustdoc, value);
+    value
 }
 
-/// Returns the coverage statistics for a test executable in the given workspace
+/// Returns the coverage statistics for a test executable in the given workspace.
+/// A failing test executable is run again up to the retry count and the
+/// coverage of every run is merged
 pub fn get_test_coverage(
     project: &Workspace,
     package: Option<&Package>,
@@ -372,23 +1206,106 @@ pub fn get_test_coverage(
     config: &Config,
     can_quiet: bool,
     ignored: bool,
+) -> Result<Option<(TraceMap, i32)>, RunError> {
+    let run = || run_test_executable(project, package, test, analysis, config, can_quiet, ignored);
+    // Only test harnesses are retried, other executables may fail on purpose
+    let retries = if can_quiet { config.retry_count } else { 0 };
+    retry_failures(test, retries, run)
+}
+
+/// Runs the test again while it fails up to the number of retries. An error
+/// running a retry stops retrying and keeps the coverage of the earlier runs
+fn retry_failures(
+    test: &Path,
+    retries: usize,
+    mut run: impl FnMut() -> Result<Option<(TraceMap, i32)>, RunError>,
+) -> Result<Option<(TraceMap, i32)>, RunError> {
+    let mut result = run()?;
+    for attempt in 1..=retries {
+        let (traces, code) = match result {
+            Some((ref mut traces, ref mut code)) if *code != 0 => (traces, code),
+            _ => break,
+        };
+        warn!(
+            "{} failed, retrying (attempt {} of {})",
+            test.display(),
+            attempt,
+            retries
+        );
+        match run() {
+            Ok(Some((retry, retry_code))) => {
+                traces.merge(&retry);
+                *code = retry_code;
+            }
+            Ok(None) => break,
+            Err(e) => {
+                warn!("Failed to retry {}: {}", test.display(), e);
+                break;
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn run_test_executable(
+    project: &Workspace,
+    package: Option<&Package>,
+    test: &Path,
+    analysis: &HashMap<PathBuf, LineAnalysis>,
+    config: &Config,
+    can_quiet: bool,
+    ignored: bool,
 ) -> Result<Option<(TraceMap, i32)>, RunError> {
     if !test.exists() {
         return Ok(None);
     }
+    if config.engine == CoverageEngine::LlvmCov {
+        let mut envs = test_env_vars(config);
+        envs.extend(backtrace_var(config));
+        let launch = llvm_cov::Launch {
+            path: test,
+            args: test_args(ignored, can_quiet, config),
+            envs,
+            dir: package.and_then(|p| p.manifest_path().parent()),
+            suppress_panic_output: config.suppress_panic_output,
+        };
+        return llvm_cov::collect_coverage(project, launch, analysis, config).map(Some);
+    }
     if let Err(e) = limit_affinity() {
         warn!("Failed to set processor affinity {}", e);
     }
+    // The test writes to stderr through a pipe so panic messages can be
+    // filtered out of its output
+    let stderr_pipe = if config.suppress_panic_output {
+        Some(pipe().map_err(|e| {
+            RunError::TestRuntime(format!(
+                "Failed to create a pipe for the test output: {}",
+                e
+            ))
+        })?)
+    } else {
+        None
+    };
     match fork() {
         Ok(ForkResult::Parent { child }) => {
-            match collect_coverage(project, test, child, analysis, config) {
+            let stderr = stderr_pipe.map(|(output, input)| {
+                let _ = close(input);
+                // The read end is only owned by the parent after the fork
+                forward_stderr(unsafe { fs::File::from_raw_fd(output) })
+            });
+            let result = collect_coverage(project, test, child, analysis, config);
+            // The test has been reaped, the rest of its output is still read
+            if let Some(stderr) = stderr {
+                let _ = stderr.join();
+            }
+            match result {
                 Ok(t) => Ok(Some(t)),
                 Err(e) => Err(RunError::TestCoverage(e.to_string())),
             }
         }
         Ok(ForkResult::Child) => {
             info!("Launching test");
-            execute_test(test, package, ignored, can_quiet, config)?;
+            execute_test(test, package, ignored, can_quiet, stderr_pipe, config)?;
             Ok(None)
         }
         Err(err) => Err(RunError::TestCoverage(format!(
@@ -409,11 +1326,33 @@ fn collect_coverage(
 ) -> Result<(TraceMap, i32), RunError> {
     let mut ret_code = 0;
     let mut traces = generate_tracemap(project, test_path, analysis, config)?;
+    let timeout = config.binary_timeout(test_path);
     {
         trace!("Test PID is {}", test);
         let (mut state, mut data) = create_state_machine(test, &mut traces, config);
+        if config.follow_exec {
+            data.follow_exec(project.root(), analysis);
+        }
         loop {
-            state = state.step(&mut data, config)?;
+            state = match state.step(&mut data, config, timeout) {
+                Ok(state) => state,
+                Err(e) if config.no_fail_fast => {
+                    // Keep the coverage collected so far and stop the test
+                    // so the remaining binaries can run
+                    error!(
+                        "Failed collecting coverage of {}: {}",
+                        test_path.display(),
+                        e
+                    );
+                    stop_test(test, &mut data, config);
+                    ret_code = 1;
+                    break;
+                }
+                Err(e) => {
+                    stop_test(test, &mut data, config);
+                    return Err(e);
+                }
+            };
             if state.is_finished() {
                 if let TestState::End(i) = state {
                     ret_code = i;
@@ -425,12 +1364,57 @@ fn collect_coverage(
     Ok((traces, ret_code))
 }
 
+/// Stops a test which timed out or failed. The timeout signal is sent first
+/// and coverage is still collected while the test handles it, if it hasn't
+/// exited after the grace period it's killed.
+fn stop_test(test: Pid, data: &mut impl StateData, config: &Config) {
+    let exited = stop_timed_out(test, config, || {
+        let mut state = TestState::Waiting {
+            start_time: Instant::now(),
+        };
+        while !state.is_finished() {
+            match state.step(data, config, config.timeout_signal_grace) {
+                Ok(s) => state = s,
+                Err(_) => break,
+            }
+        }
+        state.is_finished()
+    });
+    if !exited {
+        let _ = waitpid(test, None);
+    }
+}
+
+/// Sends the timeout signal to a test which timed out, `wait_for_exit` waits
+/// up to the grace period and returns whether the test exited. A test which
+/// doesn't exit is killed and still has to be waited on, returns whether the
+/// test exited on its own.
+pub(crate) fn stop_timed_out(
+    test: Pid,
+    config: &Config,
+    wait_for_exit: impl FnOnce() -> bool,
+) -> bool {
+    if config.timeout_signal != Signal::SIGKILL && kill(test, config.timeout_signal).is_ok() {
+        if wait_for_exit() {
+            return true;
+        }
+        warn!(
+            "Test didn't exit within {}s of {}, killing it",
+            config.timeout_signal_grace.as_secs(),
+            config.timeout_signal.as_ref()
+        );
+    }
+    let _ = kill(test, Signal::SIGKILL);
+    false
+}
+
 /// Launches the test executable
 fn execute_test(
     test: &Path,
     package: Option<&Package>,
     ignored: bool,
     can_quiet: bool,
+    stderr_pipe: Option<(RawFd, RawFd)>,
     config: &Config,
 ) -> Result<(), RunError> {
     let exec_path = CString::new(test.to_str().unwrap()).unwrap();
@@ -441,36 +1425,186 @@ fn execute_test(
         }
     }
 
-    let mut envars: Vec<CString> = Vec::new();
-
-    for (key, value) in env::vars() {
-        let mut temp = String::new();
-        temp.push_str(key.as_str());
-        temp.push('=');
-        temp.push_str(value.as_str());
-        envars.push(CString::new(temp).unwrap());
+    let mut envars = get_test_environment(config);
+    if let Some((key, value)) = backtrace_var(config) {
+        envars.push(CString::new(format!("{}={}", key, value)).unwrap());
     }
-    let mut argv = if ignored {
-        vec![exec_path.clone(), CString::new("--ignored").unwrap()]
-    } else {
-        vec![exec_path.clone()]
-    };
-    if config.verbose {
-        envars.push(CString::new("RUST_BACKTRACE=1").unwrap());
-    } else if can_quiet {
-        argv.push(CString::new("--quiet").unwrap());
+    if let Some((output, input)) = stderr_pipe {
+        let _ = close(output);
+        redirect_stderr(input)?;
     }
-    for s in &config.varargs {
-        argv.push(CString::new(s.as_bytes()).unwrap_or_default());
+    let mut argv = vec![exec_path.clone()];
+    for s in test_args(ignored, can_quiet, config) {
+        argv.push(CString::new(s).unwrap_or_default());
     }
 
     execute(exec_path, &argv, envars.as_slice())
 }
 
+/// Arguments passed to a test executable
+fn test_args(ignored: bool, can_quiet: bool, config: &Config) -> Vec<String> {
+    let mut args = vec![];
+    if ignored {
+        args.push("--ignored".to_string());
+    }
+    if !config.verbose && can_quiet {
+        args.push("--quiet".to_string());
+    }
+    // Only the test harness understands --test-threads
+    if let Some(threads) = config.test_threads_arg().filter(|_| can_quiet) {
+        args.push(threads);
+    }
+    args.extend(config.varargs.iter().cloned());
+    args
+}
+
+/// `RUST_BACKTRACE` setting for the tests, backtraces are hidden with the
+/// panic output and shown when verbose
+fn backtrace_var(config: &Config) -> Option<(String, String)> {
+    if config.suppress_panic_output {
+        Some(("RUST_BACKTRACE".to_string(), "0".to_string()))
+    } else if config.verbose {
+        Some(("RUST_BACKTRACE".to_string(), "1".to_string()))
+    } else {
+        None
+    }
+}
+
+/// Environment variables passed to the test executables, variables matching
+/// the test binary environment filter are left out
+fn test_env_vars(config: &Config) -> Vec<(String, String)> {
+    let mut envars = env::vars()
+        .filter(|(k, _)| !config.is_env_filtered(k) && !config.test_env.contains_key(k))
+        .collect::<Vec<_>>();
+    // Variables only for the tests aren't set in tarpaulin's environment so
+    // the build doesn't see them
+    envars.extend(config.test_env.iter().map(|(k, v)| (k.clone(), v.clone())));
+    envars
+}
+
+fn get_test_environment(config: &Config) -> Vec<CString> {
+    test_env_vars(config)
+        .into_iter()
+        .map(|(key, value)| CString::new(format!("{}={}", key, value)).unwrap())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn build_script_lookup() {
+        let build_dir = env::temp_dir().join("tarpaulin-build-script-lookup");
+        let _ = fs::remove_dir_all(&build_dir);
+        assert_eq!(find_build_script(&build_dir, "foo"), None);
+
+        for dir in &["foo-0a1b", "foo-bar-2c3d", "foo-4e5f"] {
+            fs::create_dir_all(build_dir.join(dir)).unwrap();
+        }
+        fs::write(build_dir.join("foo-bar-2c3d/build-script-build"), "").unwrap();
+        assert_eq!(find_build_script(&build_dir, "foo"), None);
+
+        fs::write(build_dir.join("foo-0a1b/build-script-build"), "").unwrap();
+        assert_eq!(
+            find_build_script(&build_dir, "foo"),
+            Some(build_dir.join("foo-0a1b/build-script-build"))
+        );
+        assert_eq!(
+            find_build_script(&build_dir, "foo-bar"),
+            Some(build_dir.join("foo-bar-2c3d/build-script-build"))
+        );
+        let _ = fs::remove_dir_all(&build_dir);
+    }
+
+    #[test]
+    fn workspace_member_lookup() {
+        let manifest = env::current_dir()
+            .unwrap()
+            .join("tests/data/proc_macro_workspace/Cargo.toml");
+        let cargo_config = CargoConfig::default().unwrap();
+        let workspace = Workspace::new(&manifest, &cargo_config).unwrap();
+
+        let members = vec![PathBuf::from("macros"), PathBuf::from("./app")];
+        let packages = get_workspace_member_packages(&workspace, &members).unwrap();
+        assert_eq!(packages, vec!["macros".to_string(), "app".to_string()]);
+        let missing = get_workspace_member_packages(&workspace, &[PathBuf::from("lib")]);
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn profile_lookup() {
+        let manifest = env::current_dir()
+            .unwrap()
+            .join("tests/data/proc_macro_workspace/Cargo.toml");
+        let cargo_config = CargoConfig::default().unwrap();
+        let workspace = Workspace::new(&manifest, &cargo_config).unwrap();
+
+        assert!(check_profile(&workspace, "release").is_ok());
+        assert!(check_profile(&workspace, "coverage").is_err());
+        assert!(check_profile(&workspace, "cover age").is_err());
+
+        let path = Path::new("Cargo.toml");
+        let profile = "[profile.coverage]\nopt-level = 0\n";
+        let manifest = profile.parse().unwrap();
+        assert!(check_custom_profile(&manifest, "coverage", path).is_err());
+        let manifest = format!("cargo-features = [\"named-profiles\"]\n{}", profile)
+            .parse()
+            .unwrap();
+        assert!(check_custom_profile(&manifest, "coverage", path).is_ok());
+        assert!(check_custom_profile(&manifest, "profiling", path).is_err());
+
+        let mut config = Config::default();
+        assert_eq!(profile_kind(&config), ProfileKind::Dev);
+        config.release = true;
+        assert_eq!(profile_kind(&config), ProfileKind::Release);
+        config.release = false;
+        config.profile = Some("coverage".to_string());
+        assert_eq!(
+            profile_kind(&config),
+            ProfileKind::Custom("coverage".to_string())
+        );
+    }
+
+    #[test]
+    fn clean_decision() {
+        let flags_file = env::temp_dir().join("tarpaulin-clean-decision-flags");
+        let _ = fs::remove_file(&flags_file);
+        let mut config = Config::default();
+        assert!(should_clean(
+            &config,
+            &flags_file,
+            "0.11.0\n-C opt-level=0\n"
+        ));
+
+        fs::write(&flags_file, "0.10.0\n-C opt-level=0\n").unwrap();
+        assert!(should_clean(
+            &config,
+            &flags_file,
+            "0.11.0\n-C opt-level=0\n"
+        ));
+        assert!(!should_clean(
+            &config,
+            &flags_file,
+            "0.10.0\n-C opt-level=0\n"
+        ));
+
+        config.skip_clean = true;
+        assert!(!should_clean(
+            &config,
+            &flags_file,
+            "0.11.0\n-C opt-level=0\n"
+        ));
+        config.skip_clean = false;
+        config.force_clean = true;
+        assert!(should_clean(
+            &config,
+            &flags_file,
+            "0.10.0\n-C opt-level=0\n"
+        ));
+        let _ = fs::remove_file(&flags_file);
+    }
+
     #[test]
     fn check_env() {
         let conf = Config::default();
@@ -479,4 +1613,204 @@ mod tests {
         let tarp_var = env::var("TARPAULIN").unwrap();
         assert_eq!(tarp_var, "1");
     }
+
+    #[test]
+    fn doctest_attributed_to_comment_line() {
+        let files = vec![
+            PathBuf::from("/project/src/lib.rs"),
+            PathBuf::from("/project/src/utils/mod.rs"),
+        ];
+        let doctest = Path::new("/project/target/doctests/src_utils_mod_rs_12_0/rust_out");
+        assert_eq!(
+            doctest_source_location(doctest, files.iter()),
+            Some((PathBuf::from("/project/src/utils/mod.rs"), 12))
+        );
+        let doctest = Path::new("/project/target/doctests/src_lib_rs_3_1/rust_out");
+        assert_eq!(
+            doctest_source_location(doctest, files.iter()),
+            Some((PathBuf::from("/project/src/lib.rs"), 3))
+        );
+        let doctest = Path::new("/project/target/doctests/src_main_rs_3_0/rust_out");
+        assert_eq!(doctest_source_location(doctest, files.iter()), None);
+    }
+
+    #[test]
+    fn filtered_test_environment() {
+        env::set_var("TARPAULIN_SECRET_TOKEN", "hunter2");
+        env::set_var("TARPAULIN_VISIBLE", "1");
+        let mut conf = Config::default();
+        conf.test_binary_env_filter = vec!["TARPAULIN_SECRET_*".to_string()];
+
+        let envars = get_test_environment(&conf);
+        let has_var = |name: &str| {
+            envars
+                .iter()
+                .any(|v| v.to_string_lossy().starts_with(&format!("{}=", name)))
+        };
+        assert!(!has_var("TARPAULIN_SECRET_TOKEN"));
+        assert!(has_var("TARPAULIN_VISIBLE"));
+    }
+
+    #[test]
+    fn nightly_toolchains() {
+        assert!(is_nightly("rustc 1.44.0-nightly (f509b26a7 2020-03-18)"));
+        assert!(is_nightly("rustc 1.44.0-dev"));
+        assert!(!is_nightly("rustc 1.42.0 (b8cedc004 2020-03-09)"));
+        assert!(!is_nightly("rustc 1.43.0-beta.1 (1bbc8a5b4 2020-03-12)"));
+        assert!(!is_nightly(""));
+    }
+
+    #[test]
+    fn cargo_env_overrides_environment() {
+        let mut conf = Config::default();
+        conf.cargo_env
+            .insert("RUSTFLAGS".to_string(), "-C opt-level=3".to_string());
+
+        let vars = build_env_vars(&conf);
+        let rustflags = vars
+            .iter()
+            .filter(|(k, _)| k == "RUSTFLAGS")
+            .collect::<Vec<_>>();
+        assert_eq!(rustflags.len(), 2);
+        assert_eq!(vars.last(), rustflags.last().copied());
+        assert_eq!(rustflags[1].1, "-C opt-level=3");
+    }
+
+    #[test]
+    fn test_env_only_for_tests() {
+        env::set_var("TARPAULIN_TEST_ENV_TEST", "inherited");
+        let mut conf = Config::default();
+        conf.test_env
+            .insert("TARPAULIN_TEST_ENV_TEST".to_string(), "set".to_string());
+        conf.test_env
+            .insert("TARPAULIN_TEST_ENV_NEW".to_string(), "new".to_string());
+
+        let build_vars = build_env_vars(&conf);
+        assert!(build_vars
+            .iter()
+            .all(|(k, _)| !k.starts_with("TARPAULIN_TEST_ENV_")));
+        let envars = get_test_environment(&conf)
+            .iter()
+            .map(|v| v.to_string_lossy().to_string())
+            .filter(|v| v.starts_with("TARPAULIN_TEST_ENV_"))
+            .collect::<Vec<_>>();
+        assert_eq!(envars.len(), 2);
+        assert!(envars.contains(&"TARPAULIN_TEST_ENV_TEST=set".to_string()));
+        assert!(envars.contains(&"TARPAULIN_TEST_ENV_NEW=new".to_string()));
+    }
+
+    #[test]
+    fn target_cpu_in_rustflags() {
+        let mut conf = Config::default();
+        conf.target_cpu = Some("native".to_string());
+
+        let rustflags = rust_flags(&conf, None);
+        assert!(rustflags.contains("-C target-cpu=native"));
+    }
+
+    #[test]
+    fn user_rustflags_after_instrumentation() {
+        let mut conf = Config::default();
+        conf.rustflags = vec!["--cfg tarpaulin_ci".to_string()];
+
+        let rustflags = rust_flags(&conf, Some("-D warnings"));
+        let instrumentation = rustflags.find("-C link-dead-code").unwrap();
+        let inherited = rustflags.find("-D warnings").unwrap();
+        assert!(instrumentation < inherited);
+        assert!(inherited < rustflags.find("--cfg tarpaulin_ci").unwrap());
+    }
+
+    #[test]
+    fn failing_tests_retried() {
+        let file = Path::new("src/lib.rs");
+        let run_with = |lines: &[u64], code| -> Result<Option<(TraceMap, i32)>, RunError> {
+            let mut traces = TraceMap::new();
+            for line in lines {
+                traces.add_trace(file, Trace::new_stub(*line, CoverageStat::Line(1)));
+            }
+            Ok(Some((traces, code)))
+        };
+        let retry = |runs: Vec<Result<Option<(TraceMap, i32)>, RunError>>| {
+            let mut runs = runs.into_iter();
+            let (traces, code) = retry_failures(file, 3, || runs.next().unwrap())
+                .unwrap()
+                .unwrap();
+            (traces.covered_in_path(file), code, runs.len())
+        };
+
+        let passing = vec![run_with(&[1], 0), run_with(&[2], 0)];
+        assert_eq!(retry(passing), (1, 0, 1));
+
+        let flaky = vec![run_with(&[1], 101), run_with(&[2], 0), run_with(&[3], 0)];
+        assert_eq!(retry(flaky), (2, 0, 1));
+
+        let failing = (0..4).map(|l| run_with(&[l], 101)).collect();
+        assert_eq!(retry(failing), (4, 101, 0));
+
+        // Coverage of the first run is kept when a retry can't be run
+        let broken = vec![
+            run_with(&[1], 101),
+            Err(RunError::TestRuntime("no ptrace".to_string())),
+            run_with(&[2], 0),
+        ];
+        assert_eq!(retry(broken), (1, 101, 1));
+    }
+
+    #[test]
+    fn flags_same_for_each_config() {
+        let mut conf = Config::default();
+        conf.nightly = true;
+        conf.rustflags = vec!["--cfg tarpaulin_ci".to_string()];
+        let first = build_env_vars(&conf);
+        setup_environment(&conf);
+        assert_eq!(build_env_vars(&conf), first);
+    }
+
+    #[test]
+    fn build_flags_from_config() {
+        let mut conf = Config::default();
+        conf.rustflags = vec!["--cfg tarpaulin_ci".to_string()];
+        let flags = get_build_flags(&conf);
+        assert!(flags.contains("--cfg tarpaulin_ci"));
+        assert_eq!(get_build_flags(&conf), flags);
+        conf.release = true;
+        assert_ne!(get_build_flags(&conf), flags);
+    }
+
+    #[test]
+    fn every_config_runs_before_failing() {
+        let project = env::temp_dir().join("tarpaulin-run-configs");
+        let _ = fs::remove_dir_all(&project);
+        fs::create_dir_all(project.join("src")).unwrap();
+        fs::write(
+            project.join("Cargo.toml"),
+            "[package]\nname = \"configs\"\n",
+        )
+        .unwrap();
+        let lib = project.join("src/lib.rs");
+        fs::write(&lib, "pub fn foo() {}\n").unwrap();
+
+        // The first config has cached results, the second can't be built
+        let mut cached = Config::default();
+        cached.name = "cached".to_string();
+        cached.manifest = project.join("Cargo.toml");
+        cached.cache_dir = Some(project.join("target/cache"));
+        cached.generate = vec![OutputFile::Json];
+        cached.output_directory = project.join("target/out");
+        let mut traces = TraceMap::new();
+        traces.add_trace(&lib, Trace::new_stub(1, CoverageStat::Line(1)));
+        cache::store(&cached, &traces, &BTreeMap::new());
+        let mut broken = Config::default();
+        broken.name = "broken".to_string();
+        broken.manifest = project.join("missing/Cargo.toml");
+
+        let report = cached.output_path(&OutputFile::Json);
+        for keep_going in &[false, true] {
+            broken.keep_going = *keep_going;
+            let _ = fs::remove_file(&report);
+            assert!(run(&[cached.clone(), broken.clone()]).is_err());
+            assert!(report.exists());
+        }
+        let _ = fs::remove_dir_all(&project);
+    }
 }
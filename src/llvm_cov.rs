@@ -0,0 +1,367 @@
+//! Coverage collection with LLVM's source based coverage. The project is built
+//! with `-Z instrument-coverage` so every executable writes its counters to a
+//! `.profraw` file on exit, these are merged with `llvm-profdata` and exported
+//! as an LCOV tracefile with `llvm-cov`.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::process_handling::forward_stderr;
+use crate::report::lcov::read_tracefile;
+use crate::source_analysis::{LineAnalysis, SourceAnalysisQuery};
+use crate::stop_timed_out;
+use crate::traces::TraceMap;
+use cargo::core::Workspace;
+use log::{debug, error, info, warn};
+use nix::unistd::Pid;
+use rustc_demangle::demangle;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to sleep between checks whether an executable has exited
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// An instrumented executable to run
+pub(crate) struct Launch<'a> {
+    pub path: &'a Path,
+    pub args: Vec<String>,
+    pub envs: Vec<(String, String)>,
+    /// Directory to run in, tarpaulin's working directory if unset
+    pub dir: Option<&'a Path>,
+    /// Filter panic messages out of the executable's stderr
+    pub suppress_panic_output: bool,
+}
+
+/// Runs an executable built with `-Z instrument-coverage` and returns the
+/// coverage of the run and its exit code
+pub(crate) fn collect_coverage(
+    project: &Workspace,
+    launch: Launch,
+    analysis: &HashMap<PathBuf, LineAnalysis>,
+    config: &Config,
+) -> Result<(TraceMap, i32), RunError> {
+    let name = launch
+        .path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let profile_dir = project
+        .target_dir()
+        .as_path_unlocked()
+        .join("tarpaulin")
+        .join("profraw")
+        .join(&name);
+    // Profiles of earlier runs would be counted again
+    if profile_dir.exists() {
+        fs::remove_dir_all(&profile_dir)?;
+    }
+    fs::create_dir_all(&profile_dir)?;
+
+    info!("running {}", launch.path.display());
+    let mut command = Command::new(launch.path);
+    command
+        .args(&launch.args)
+        .env_clear()
+        .envs(launch.envs)
+        .env(
+            "LLVM_PROFILE_FILE",
+            profile_dir.join(profile_pattern(&name)),
+        );
+    if let Some(dir) = launch.dir {
+        command.current_dir(dir);
+    }
+    if launch.suppress_panic_output {
+        command.stderr(Stdio::piped());
+    }
+    let mut child = command.spawn().map_err(|e| {
+        RunError::TestRuntime(format!("Failed to run {}: {}", launch.path.display(), e))
+    })?;
+    let stderr = child.stderr.take().map(forward_stderr);
+    let status = wait_for_exit(child, config.binary_timeout(launch.path), config)?;
+    if let Some(stderr) = stderr {
+        let _ = stderr.join();
+    }
+    let code = match status {
+        Some(status) => status.code().unwrap_or(1),
+        None if config.no_fail_fast => {
+            error!("{} timed out", launch.path.display());
+            1
+        }
+        None => {
+            return Err(RunError::TestRuntime(format!(
+                "{} timed out",
+                launch.path.display()
+            )))
+        }
+    };
+
+    let profiles = fs::read_dir(&profile_dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().map(|e| e == "profraw").unwrap_or(false))
+        .collect::<Vec<_>>();
+    if profiles.is_empty() {
+        warn!("{} didn't write a coverage profile", launch.path.display());
+        return Ok((TraceMap::new(), code));
+    }
+    let profdata = profile_dir.join(format!("{}.profdata", name));
+    let output = Command::new(llvm_tool("llvm-profdata"))
+        .args(&["merge", "-sparse", "-o"])
+        .arg(&profdata)
+        .args(&profiles)
+        .output()
+        .map_err(|e| tool_error("llvm-profdata", e))?;
+    if !output.status.success() {
+        return Err(RunError::TestCoverage(format!(
+            "llvm-profdata failed to merge the profiles of {}: {}",
+            launch.path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let output = Command::new(llvm_tool("llvm-cov"))
+        .args(&["export", "-format=lcov"])
+        .arg(format!("-instr-profile={}", profdata.display()))
+        .arg(launch.path)
+        .output()
+        .map_err(|e| tool_error("llvm-cov", e))?;
+    if !output.status.success() {
+        return Err(RunError::TestCoverage(format!(
+            "llvm-cov failed to export the coverage of {}: {}",
+            launch.path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let traces = read_tracefile(output.stdout.as_slice()).map_err(|e| {
+        RunError::TestCoverage(format!("Failed to read the llvm-cov export: {}", e))
+    })?;
+    Ok((
+        filter_traces(traces, project.root(), analysis, config),
+        code,
+    ))
+}
+
+/// `LLVM_PROFILE_FILE` pattern for an executable, `%p` adds the process id so
+/// child processes don't overwrite the profile of their parent and `%m` the
+/// binary signature so different binaries don't merge into one file
+fn profile_pattern(name: &str) -> String {
+    format!("{}-%p-%m.profraw", name)
+}
+
+/// Waits for the executable to exit, returns `None` if it timed out. On a
+/// timeout the timeout signal is sent first so the executable can still write
+/// its profile, if it hasn't exited after the grace period it's killed.
+fn wait_for_exit(
+    mut child: Child,
+    timeout: Duration,
+    config: &Config,
+) -> Result<Option<ExitStatus>, RunError> {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    let pid = Pid::from_raw(child.id() as i32);
+    let exited = stop_timed_out(pid, config, || {
+        let start = Instant::now();
+        while start.elapsed() < config.timeout_signal_grace {
+            match child.try_wait() {
+                Ok(Some(_)) => return true,
+                Ok(None) => thread::sleep(POLL_INTERVAL),
+                Err(_) => return false,
+            }
+        }
+        false
+    });
+    if !exited {
+        let _ = child.wait();
+    }
+    Ok(None)
+}
+
+/// Path of an LLVM tool, the versions from rustup's `llvm-tools-preview`
+/// component match the LLVM rustc uses so they're preferred over the ones
+/// in the `PATH`
+fn llvm_tool(name: &str) -> PathBuf {
+    let sysroot = Command::new("rustc")
+        .args(&["--print", "sysroot"])
+        .output()
+        .ok()
+        .map(|o| PathBuf::from(String::from_utf8_lossy(&o.stdout).trim()));
+    match (sysroot, crate::host_triple()) {
+        (Some(sysroot), Some(host)) => {
+            let path = tool_in_sysroot(&sysroot, &host, name);
+            if path.exists() {
+                return path;
+            }
+            debug!("{} isn't in the rustc sysroot, using the PATH", name);
+            PathBuf::from(name)
+        }
+        _ => PathBuf::from(name),
+    }
+}
+
+fn tool_in_sysroot(sysroot: &Path, host: &str, name: &str) -> PathBuf {
+    sysroot
+        .join("lib")
+        .join("rustlib")
+        .join(host)
+        .join("bin")
+        .join(name)
+}
+
+fn tool_error(name: &str, e: std::io::Error) -> RunError {
+    RunError::TestCoverage(format!(
+        "Failed to run {}, install it with `rustup component add llvm-tools-preview`: {}",
+        name, e
+    ))
+}
+
+/// Drops the coverage of files tarpaulin wouldn't report with the ptrace
+/// engine and names functions like the debug info does
+fn filter_traces(
+    traces: TraceMap,
+    project: &Path,
+    analysis: &HashMap<PathBuf, LineAnalysis>,
+    config: &Config,
+) -> TraceMap {
+    let base_dir = config.get_base_dir();
+    let include_external = !config.ignore_extern_crate_coverage;
+    let mut result = TraceMap::new();
+    for (path, file_traces) in traces.iter() {
+        let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if path.starts_with(project.join("target"))
+            || (config.ignore_tests && path.starts_with(project.join("tests")))
+            || !(include_external || path.starts_with(&base_dir))
+        {
+            continue;
+        }
+        for trace in file_traces {
+            let line = trace.line as usize;
+            if (config.exclude_path(&path) && !analysis.is_included(&path, &line))
+                || analysis.should_ignore(&path, &line)
+            {
+                continue;
+            }
+            let mut trace = trace.clone();
+            if let Some(mangled) = trace.fn_name.take() {
+                trace.fn_name = Some(function_name(&mangled, config));
+                trace.mangled_name = Some(mangled);
+            }
+            result.add_trace(&path, trace);
+            if config.include_tests && analysis.is_test_line(&path, &line) {
+                result.mark_test_line(&path, line as u64);
+            }
+        }
+    }
+    result
+}
+
+/// llvm-cov names functions by their symbol, the debug info name is the last
+/// segment of the demangled path
+fn function_name(mangled: &str, config: &Config) -> String {
+    let demangled = format!("{:#}", demangle(mangled));
+    if config.demangled_names {
+        demangled
+    } else {
+        demangled
+            .rsplit("::")
+            .next()
+            .unwrap_or(&demangled)
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_analysis::Lines;
+    use std::collections::HashSet;
+
+    #[test]
+    fn function_names() {
+        let mut config = Config::default();
+        let mangled = "_ZN3foo3bar17h05af221e174051e9E";
+        assert_eq!(function_name(mangled, &config), "bar");
+        config.demangled_names = true;
+        assert_eq!(function_name(mangled, &config), "foo::bar");
+        assert_eq!(function_name("main", &config), "main");
+    }
+
+    #[test]
+    fn tools_in_sysroot() {
+        let path = tool_in_sysroot(
+            Path::new("/home/rust/.rustup/toolchains/nightly"),
+            "x86_64-unknown-linux-gnu",
+            "llvm-cov",
+        );
+        assert_eq!(
+            path,
+            PathBuf::from(
+                "/home/rust/.rustup/toolchains/nightly/lib/rustlib/x86_64-unknown-linux-gnu/bin/\
+                 llvm-cov"
+            )
+        );
+        assert_eq!(profile_pattern("tests-1a2b"), "tests-1a2b-%p-%m.profraw");
+    }
+
+    #[test]
+    fn export_filtered_like_ptrace() {
+        let project = std::env::temp_dir().join("tarpaulin-llvm-cov-filter");
+        let _ = fs::remove_dir_all(&project);
+        for dir in &["src", "tests", "target/debug/build"] {
+            fs::create_dir_all(project.join(dir)).unwrap();
+        }
+        let project = project.canonicalize().unwrap();
+        let files = [
+            "src/lib.rs",
+            "src/generated.rs",
+            "tests/api.rs",
+            "target/debug/build/out.rs",
+        ];
+        let mut export = String::new();
+        for file in &files {
+            fs::write(project.join(file), "").unwrap();
+            export.push_str(&format!(
+                "SF:{}\nDA:1,1\nDA:2,0\nDA:3,4\nend_of_record\n",
+                project.join(file).display()
+            ));
+        }
+        let traces = read_tracefile(export.as_bytes()).unwrap();
+
+        let toml = format!(
+            "[filter]\nroot = \"{}\"\nignore-tests = true\n\
+             exclude-files = [\"src/generated.rs\"]\n",
+            project.display()
+        );
+        let config = Config::parse_config_toml(toml.as_bytes())
+            .unwrap()
+            .remove(0);
+        let line_analysis = |ignore: usize, include: usize| LineAnalysis {
+            ignore: vec![Lines::Line(ignore)].into_iter().collect(),
+            cover: HashSet::new(),
+            test_lines: HashSet::new(),
+            include: vec![Lines::Line(include)].into_iter().collect(),
+        };
+        let mut analysis = HashMap::new();
+        // Line 3 of the excluded file is in a `#[cfg(tarpaulin_include)]` item
+        analysis.insert(project.join("src/lib.rs"), line_analysis(2, 0));
+        analysis.insert(project.join("src/generated.rs"), line_analysis(0, 3));
+
+        let filtered = filter_traces(traces, &project, &analysis, &config);
+        let lines = |file: &str| {
+            filtered
+                .get_child_traces(&project.join(file))
+                .iter()
+                .map(|t| t.line)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(filtered.files().len(), 2);
+        assert_eq!(lines("src/lib.rs"), vec![1, 3]);
+        assert_eq!(lines("src/generated.rs"), vec![3]);
+        let _ = fs::remove_dir_all(&project);
+    }
+}
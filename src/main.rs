@@ -1,10 +1,16 @@
+use cargo_tarpaulin::annotate::print_file_coverage;
 use cargo_tarpaulin::config::*;
+use cargo_tarpaulin::errors::RunError;
+use cargo_tarpaulin::merge::{merge_coverage, PathRewrite};
 use cargo_tarpaulin::run;
-use clap::{crate_version, App, Arg, ArgSettings, SubCommand};
-use env_logger::Builder;
-use log::trace;
+use clap::{crate_version, value_t, values_t, App, Arg, ArgMatches, ArgSettings, SubCommand};
+use env_logger::{Builder, WriteStyle};
+use log::{info, trace};
+use std::env;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 fn is_dir(d: String) -> Result<(), String> {
     if Path::new(&d).is_dir() {
@@ -14,7 +20,35 @@ fn is_dir(d: String) -> Result<(), String> {
     }
 }
 
-fn set_up_logging(debug: bool, verbose: bool) {
+fn is_key_value(t: String) -> Result<(), String> {
+    match t.find('=') {
+        Some(i) if i > 0 => Ok(()),
+        _ => Err(String::from("values must be written as key=value")),
+    }
+}
+
+fn is_signal(s: String) -> Result<(), String> {
+    parse_signal(&s).map(|_| ())
+}
+
+fn is_path_mapping(m: String) -> Result<(), String> {
+    parse_path_mapping(&m).map(|_| ())
+}
+
+fn is_path_rewrite(r: String) -> Result<(), String> {
+    r.parse::<PathRewrite>().map(|_| ())
+}
+
+/// Whether log messages are coloured, this can change once the configs are
+/// loaded so it's checked for each message
+static COLOR_LOG: AtomicBool = AtomicBool::new(false);
+
+fn set_log_color(color: Color) {
+    COLOR_LOG.store(color.use_color(), Ordering::Relaxed);
+}
+
+fn set_up_logging(debug: bool, verbose: bool, color: Color) {
+    set_log_color(color);
     let mut builder = Builder::new();
 
     // NOTE: This overwrites RUST_LOG
@@ -28,24 +62,126 @@ fn set_up_logging(debug: bool, verbose: bool) {
 
     builder
         .format_timestamp(None)
+        .write_style(WriteStyle::Always)
         .format(|buf, record| {
-            let level_style = buf.default_level_style(record.level());
-            writeln!(
-                buf,
-                "[{} tarpaulin] {}",
-                level_style.value(record.level()),
-                record.args()
-            )
+            if COLOR_LOG.load(Ordering::Relaxed) {
+                let level_style = buf.default_level_style(record.level());
+                writeln!(
+                    buf,
+                    "[{} tarpaulin] {}",
+                    level_style.value(record.level()),
+                    record.args()
+                )
+            } else {
+                writeln!(buf, "[{} tarpaulin] {}", record.level(), record.args())
+            }
         })
         .init();
 }
 
+/// Parses and validates a config file without building or running anything
+fn check_config(args: &ArgMatches) -> Result<(), RunError> {
+    let path = match args.value_of("config") {
+        Some(path) => PathBuf::from(path),
+        None => Config::default()
+            .check_for_config_named(args.value_of("config-file-name"))
+            .ok_or_else(|| {
+                RunError::Config(
+                    "No tarpaulin config file or Cargo.toml metadata found".to_string(),
+                )
+            })?,
+    };
+    let configs = Config::load_config_file(&path)
+        .map_err(|e| RunError::Config(format!("Failed to load {}: {}", path.display(), e)))?;
+    let errors = log_validation(&configs);
+    if errors == 0 {
+        info!("{} is valid", path.display());
+        Ok(())
+    } else {
+        Err(RunError::Config(format!(
+            "{} has {} errors",
+            path.display(),
+            errors
+        )))
+    }
+}
+
+/// Prints a source file with the coverage of each line from a JSON report
+fn diff(args: &ArgMatches) -> Result<(), RunError> {
+    let report = PathBuf::from(args.value_of("in").unwrap_or_default());
+    let file = PathBuf::from(args.value_of("FILE").unwrap_or_default());
+    let root = match args.value_of("root") {
+        Some(root) => PathBuf::from(root),
+        None => env::current_dir()?,
+    };
+    let color = value_t!(args, "color", Color).unwrap_or_default();
+    print_file_coverage(&report, &file, &root, color)
+}
+
+/// Merges coverage reports from several runs into one report
+fn merge(args: &ArgMatches) -> Result<(), RunError> {
+    let reports = args
+        .values_of("REPORTS")
+        .map(|v| v.map(PathBuf::from).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let rewrites =
+        values_t!(args.values_of("path-rewrite"), PathRewrite).unwrap_or_else(|_| vec![]);
+    let output_dir = match args.value_of("output-dir") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::current_dir()?,
+    };
+    let mut config = Config::default()
+        .with_generate(values_t!(args.values_of("out"), OutputFile).unwrap_or_else(|_| vec![]))
+        .with_output_directory(output_dir);
+    if let Some(root) = args.value_of("root") {
+        config = config.with_root(root);
+    }
+    config.manifest = config.get_base_dir().join("Cargo.toml");
+    // Merged reports keep the hit counts and branches of the runs
+    config.count = true;
+    config.branch_coverage = true;
+    merge_coverage(&config, &reports, &rewrites)
+}
+
+/// Generates reports from the JSON report of an earlier run. The output
+/// options come from the arguments and the project config like a normal run.
+fn report(args: &ArgMatches) -> Result<(), RunError> {
+    let input = PathBuf::from(args.value_of("in").unwrap_or_default());
+    let configs = ConfigWrapper::from(args);
+    configs.check_valid().map_err(RunError::Config)?;
+    let mut config = configs.report_config().map_err(RunError::Config)?.clone();
+    // The report keeps the hit counts and branches of the run
+    config.count = true;
+    config.branch_coverage = true;
+    merge_coverage(&config, &[input], &[])
+}
+
+/// Asks about the project and writes a starter config file
+fn init(args: &ArgMatches) -> Result<(), RunError> {
+    let manifest = match args.value_of("manifest-path") {
+        Some(path) => PathBuf::from(path),
+        None => Config::default().manifest,
+    };
+    let path = init_config(&manifest, args.is_present("force"))?;
+    info!("Wrote {}", path.display());
+    Ok(())
+}
+
 const CI_SERVER_HELP: &'static str = "Name of service, supported services are:
 travis-ci, travis-pro, circle-ci, semaphore, jenkins and codeship.
 If you are interfacing with coveralls.io or another site you can \
 also specify a name that they will recognise. Refer to their documentation for this.";
 
-fn main() -> Result<(), String> {
+/// Runs tarpaulin, a failed run exits with the exit code of its error so
+/// scripts can tell why it failed
+fn main() {
+    if let Err(e) = cargo_tarpaulin() {
+        eprintln!("Error: {}", e);
+        process::exit(e.exit_code() as i32);
+    }
+}
+
+fn cargo_tarpaulin() -> Result<(), RunError> {
     let args = App::new("cargo-tarpaulin")
         .author("Daniel McKenna, <danielmckenna93@gmail.com>")
         .about("Tool to analyse test coverage of cargo projects")
@@ -53,14 +189,92 @@ fn main() -> Result<(), String> {
         .bin_name("cargo")
         .subcommand(SubCommand::with_name("tarpaulin")
             .about("Tool to analyse test coverage of cargo projects")
+            .subcommand(SubCommand::with_name("check-config")
+                .about("Validate a tarpaulin config file without running coverage")
+                .args(&[
+                    Arg::from_usage("--config [FILE] 'Path to the config file, defaults to the project config file'"),
+                    Arg::from_usage("--config-file-name [NAME] 'Name of the config file to look for in the project root instead of tarpaulin.toml'")
+                        .conflicts_with("config"),
+                ]))
+            .subcommand(SubCommand::with_name("config")
+                .about("Manage tarpaulin config files")
+                .subcommand(SubCommand::with_name("init")
+                    .about("Answer a few questions about the project to write a starter tarpaulin.toml")
+                    .args(&[
+                        Arg::from_usage("--force 'Replace an existing tarpaulin.toml'"),
+                        Arg::from_usage("--manifest-path [PATH] 'Path to Cargo.toml, the config is written next to it'"),
+                    ])))
+            .subcommand(SubCommand::with_name("diff")
+                .about("Print a source file with the coverage of each line from the JSON report of an earlier run")
+                .args(&[
+                    Arg::from_usage("<FILE> 'Source file to print'"),
+                    Arg::from_usage("--in <PATH> 'JSON report of an earlier run to read the coverage from'"),
+                    Arg::from_usage("--root -r [DIR]  'Directory relative paths in the report are resolved against, defaults to the current directory'")
+                        .validator(is_dir),
+                    Arg::from_usage("--color [WHEN] 'When to colour the output, Auto colours a terminal unless NO_COLOR is set (default is Auto)'")
+                        .possible_values(&Color::variants()),
+                ]))
+            .subcommand(SubCommand::with_name("merge")
+                .about("Merge the JSON or LCOV reports of several coverage runs into one report")
+                .args(&[
+                    Arg::from_usage("<REPORTS>... 'Reports to merge, files ending in .json are read as JSON reports and others as LCOV tracefiles'"),
+                    Arg::from_usage("--path-rewrite [FROM=TO]... 'Replace the FROM prefix of source paths in the reports with TO'")
+                        .validator(is_path_rewrite),
+                    Arg::from_usage("--out -o [FMT]   'Output format of the merged report'")
+                        .possible_values(&OutputFile::variants())
+                        .multiple(true),
+                    Arg::from_usage("--output-dir [PATH] 'Specify a custom directory to write report files'"),
+                    Arg::from_usage("--root -r [DIR]  'Directory relative paths in the reports are resolved against'")
+                        .validator(is_dir),
+                ]))
+            .subcommand(SubCommand::with_name("report")
+                .about("Generate reports from the JSON report of an earlier run without running the tests")
+                .args(&[
+                    Arg::from_usage("--in <PATH> 'JSON report of an earlier run to generate the reports from'"),
+                    Arg::from_usage("--config [FILE] 'Path to a toml file specifying a list of options this will override any other options set'"),
+                    Arg::from_usage("--ignore-config 'Ignore any project config files'"),
+                    Arg::from_usage("--out -o [FMT]   'Output format of coverage report'")
+                        .possible_values(&OutputFile::variants())
+                        .multiple(true),
+                    Arg::from_usage("--output-dir [PATH] 'Specify a custom directory to write report files'"),
+                    Arg::from_usage("--output-file [FILE] 'Write the report to this exact path, requires exactly one output format'"),
+                    Arg::from_usage("--root -r [DIR]  'Directory relative paths in the reports are resolved against'")
+                        .validator(is_dir),
+                    Arg::from_usage("--html-expand-uncovered [PERCENT] 'Expand files below this coverage percentage in the HTML report (default is 50)'")
+                        .min_values(0),
+                    Arg::from_usage("--badge-thresholds [PERCENT] 'Coverage percentages where the badge turns yellow and green (default is 50 80)'")
+                        .number_of_values(2),
+                    Arg::from_usage("--coveralls [KEY]  'Coveralls key, either the repo token, or if you're using travis use $TRAVIS_JOB_ID and specify travis-{ci|pro} in --ciserver'"),
+                    Arg::from_usage("--report-uri [URI] 'URI to send report to, only used if the option --coveralls is used'"),
+                    Arg::from_usage("--ciserver [SERVICE] 'CI server being used, if unspecified tarpaulin may automatically infer for coveralls uploads'")
+                        .help(CI_SERVER_HELP),
+                    Arg::from_usage("--codecov [TOKEN] 'Upload the coverage to codecov.io, the token defaults to $CODECOV_TOKEN'")
+                        .min_values(0),
+                    Arg::from_usage("--codecov-url [URL] 'URL of a self-hosted Codecov instance, only used if the option --codecov is used'"),
+                    Arg::from_usage("--path-mapping [FROM:TO]... 'Replace FROM with TO in the source paths of the reports, the first mapping found in a path is used'")
+                        .number_of_values(1)
+                        .validator(is_path_mapping),
+                    Arg::from_usage("--strip-prefix [PREFIX]... 'Remove PREFIX from the start of the source paths of the reports before the path mappings, * matches any characters in a directory name'")
+                        .number_of_values(1),
+                ]))
             .version(concat!("version: ", crate_version!()))
             .args_from_usage(
                  "--config [FILE] 'Path to a toml file specifying a list of options this will override any other options set'
                  --ignore-config 'Ignore any project config files'
+                 --config-names [NAME]... 'Only run the configs with these names, * matches any characters'
+                 --exclude-config-names [NAME]... 'Don't run the configs with these names, * matches any characters'
+                 --config-schema 'Print the JSON Schema of the config file and exit'
+                 --print-config 'Print the configs in use after merging config files, environment variables and arguments as TOML and exit'
                  --debug 'Show debug output - this is used for diagnosing issues with tarpaulin'
                  --verbose -v 'Show extra output'
                  --ignore-tests 'Ignore lines of test functions when collecting coverage'
                  --ignore-panics 'Ignore panic macros in tests'
+                 --suppress-panics-output 'Hide the panic messages test executables print to stderr, their other output is still shown'
+                 --exclude-generated 'Exclude files detected as generated code from coverage results'
+                 --generated-file-markers [MARKER]... 'Markers in a file header used to detect generated code (default is @generated and DO NOT EDIT)'
+                 --ignore-lines-matching [REGEX]... 'Ignore source lines matching the regex, they are still shown in the HTML report'
+                 --no-block-exclusions 'Don't exclude the code between coverage:off and coverage:on comments'
+                 --exclusion-comment [MARKER]... 'Markers in a comment which exclude the line from coverage (default is tarpaulin: skip, coverage: off and LCOV_EXCL_LINE)'
                  --count   'Counts the number of hits during coverage'
                  --ignored -i 'Run ignored tests as well'
                  --line -l    'Line coverage'
@@ -76,26 +290,121 @@ fn main() -> Result<(), String> {
                  --workspace 'Test all packages in the workspace'
                  --packages -p [PACKAGE]... 'Package id specifications for which package should be build. See cargo help pkgid for more info'
                  --exclude -e [PACKAGE]... 'Package id specifications to exclude from coverage. See cargo help pkgid for more info'
-                 --exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard'
+                 --exclude-files [FILE]... 'Exclude given files from coverage results has * wildcard, patterns starting with ! re-include files'
+                 --include-files [FILE]... 'Only include given files in coverage results has * wildcard, excluded files are still left out'
                  --timeout -t [SECONDS] 'Integer for the maximum time in seconds without response from test before timeout (default is 1 minute).'
+                 --doc-test-timeout [SECONDS] 'Timeout of the doctests in seconds, defaults to the timeout of the other tests'
+                 --timeout-signal-grace [SECONDS] 'Seconds a timed out test has to exit after the timeout signal before it's killed (default is 5)'
                  --release   'Build in release mode.'
                  --no-run 'Compile tests but don't run coverage'
                  --locked 'Do not update Cargo.lock'
                  --frozen 'Do not update Cargo.lock or any caches'
                  --target-dir [DIR] 'Directory for all generated artifacts'
+                 --target [TRIPLE] 'Target triple to build for, the tests still have to run on the host'
+                 --sysroot [DIR] 'Sysroot of a cross compiled build, it's removed from the start of source paths in the debug info'
+                 --source-dir [DIR] 'Where the sources are when the tests were built with them in another directory, such as in a container'
+                 --target-cpu [CPU] 'CPU to generate code for, passed to rustc as -C target-cpu'
                  --offline 'Run without accessing the network'
+                 --coverage-report-hook [PATH] 'Executable to run after the coverage reports are generated'
+                 --hook-fails-build 'Fail if the coverage report hook exits with an error'
+                 --include-doctests-in-line-coverage 'Count passing doctests as covering the doc comment they were written in'
+                 --exclude-doc-tests-from-coverage 'Run the doctests but leave the lines they hit out of the coverage results'
+                 --include-extern-crate-coverage 'Include coverage of source files outside the project such as path dependencies'
+                 --report-by-test-type 'Report coverage for unit tests, integration tests and doctests separately'
+                 --min-unit-coverage [PERCENT] 'Fail if unit test coverage is below this percentage, requires --report-by-test-type'
+                 --min-integration-coverage [PERCENT] 'Fail if integration test coverage is below this percentage, requires --report-by-test-type'
+                 --min-doctest-coverage [PERCENT] 'Fail if doctest coverage is below this percentage, requires --report-by-test-type'
+                 --include-build-dependencies 'Include the source of crates only used as build dependencies in coverage results'
+                 --no-dedup-symbols 'Sum the hits of code shared between test binaries instead of deduplicating them'
+                 --demangled-names 'Use full demangled symbol paths for function names in reports'
+                 --no-source-cache 'Read source files from disk every time they're needed when generating reports'
+                 --function-coverage-only 'Only instrument function entry points and report the percentage of functions called'
+                 --pgo-profile [PATH] 'PGO profile (.profraw or .profdata) to check the coverage results against, requires llvm-profdata'
+                 --output-per-config 'Write the reports of each config file section to a subdirectory of the output directory'
+                 --output-dir-per-config 'Alias for --output-per-config'
+                 --test-binary-env-filter [VAR]... 'Environment variables to remove from the environment of test executables, * matches any characters'
+                 --print-coverage-for [FILE] 'Print the source of a file annotated with the hit count of each line'
+                 --report-line-count 'Print the covered and coverable lines of each file sorted by coverage, worst first'
+                 --top-n [N] 'Only print the line counts of the N worst covered files, used with --report-line-count'
+                 --minimum-coverage [PERCENT] 'Fail if the coverage percentage is below this value'
+                 --compact-json 'Write the JSON report without pretty printing'
+                 --output-file [FILE] 'Write the report to this exact path, requires exactly one output format'
+                 --skip-clean 'Never clean the project before building, reusing existing build artefacts'
+                 --jobs -j [N] 'Number of parallel jobs, defaults to # of CPUs'
+                 --implicit-test-threads [N] 'Number of threads the tests run on, passed to the test executables as --test-threads unless the test arguments set it'
+                 --retry-count [N] 'Run a failing test executable again up to N times, merging the coverage of each run'
+                 --single-threaded 'Run the tests on a single thread, the same as --implicit-test-threads 1'
+                 --test [NAME]... 'Only run the named test targets, like cargo test --test'
+                 --bench [NAME]... 'Only run the named bench targets, like cargo test --bench'
+                 --bin [NAME]... 'Run the named binary targets, they must exit for coverage to be collected'
+                 --binary-path [PATH]... 'Run these prebuilt test executables instead of building the project'
+                 --function 'Report function coverage, the percentage of functions called, alongside line coverage'
+                 --keep-going 'Report every failed config at the end instead of only the first failure'
+                 --include-tests 'Report the coverage of test code such as test functions and #[cfg(test)] modules separately'
+                 --no-github-summary 'Don't write a coverage summary to $GITHUB_STEP_SUMMARY when running in GitHub Actions'
+                 --fail-under-line [PERCENT] 'Fail if the line coverage percentage is below this value'
+                 --fail-under-branch [PERCENT] 'Fail if the branch coverage percentage is below this value, not checked until branch coverage is implemented'
+                 --compare-with-branch [BRANCH] 'Also collect the coverage of a git branch and report the change in coverage'
+                 --max-regression [PERCENT] 'Fail if coverage dropped by more than this percentage compared to --compare-with-branch (default is 0)'
+                 --diff-coverage 'Report the coverage of the lines changed in the git diff'
+                 --diff-from [REF] 'Git reference the diff of --diff-coverage is taken against (default is HEAD)'
+                 --diff-coverage-minimum [PERCENT] 'Fail if the coverage of the lines changed in the git diff is below this value'
+                 --report-title [TITLE] 'Title of the HTML report, defaults to naming the package'
+                 --workspace-members [DIR]... 'Directories of workspace members to build relative to the workspace root, an alternative to --packages'
+                 --no-fail-fast 'Run the remaining test binaries when one crashes or times out, keeping the coverage it collected'
+                 --cache-dir [DIR] 'Cache coverage results in this directory and reuse them while no source file changes'
+                 --clear-cache 'Remove the cached coverage results before running'
+                 --strip-dead-code 'Leave out lines with no machine code or only in functions the linker removed from the coverage'
+                 --profile [NAME] 'Cargo profile to build with, custom profiles must be defined in the workspace Cargo.toml and need the nightly only named-profiles cargo feature'
+                 --nightly 'Build with the recommended nightly only rustc flags for more accurate coverage'
+                 --follow-exec 'Collect coverage from binaries of the project that tests run as child processes'
                  -Z [FEATURES]...   'List of unstable nightly only flags'")
             .args(&[
+                Arg::from_usage("--config-file-name [NAME] 'Name of the config file to look for in the project root instead of tarpaulin.toml'")
+                    .conflicts_with_all(&["config", "ignore-config"]),
                 Arg::from_usage("--out -o [FMT]   'Output format of coverage report'")
                     .possible_values(&OutputFile::variants())
                     .multiple(true),
                 Arg::from_usage("--output-dir [PATH] 'Specify a custom directory to write report files'"),
+                Arg::from_usage("--html-expand-uncovered [PERCENT] 'Expand files below this coverage percentage in the HTML report (default is 50)'")
+                    .min_values(0),
+                Arg::from_usage("--badge-thresholds [PERCENT] 'Coverage percentages where the badge turns yellow and green (default is 50 80)'")
+                    .number_of_values(2),
+                Arg::from_usage("--codecov [TOKEN] 'Upload the coverage to codecov.io, the token defaults to $CODECOV_TOKEN'")
+                    .min_values(0),
+                Arg::from_usage("--codecov-url [URL] 'URL of a self-hosted Codecov instance, only used if the option --codecov is used'"),
+                Arg::from_usage("--tag [KEY=VALUE]... 'Metadata to label the JSON, HTML and LCOV reports with'")
+                    .validator(is_key_value),
+                Arg::from_usage("--cargo-env [KEY=VALUE]... 'Environment variables to set for cargo and the test executables'")
+                    .number_of_values(1)
+                    .validator(is_key_value),
+                Arg::from_usage("--test-env [KEY=VALUE]... 'Environment variables to set only for the test executables, not the build'")
+                    .number_of_values(1)
+                    .validator(is_key_value),
+                Arg::from_usage("--path-mapping [FROM:TO]... 'Replace FROM with TO in the source paths of the reports, the first mapping found in a path is used'")
+                    .number_of_values(1)
+                    .validator(is_path_mapping),
+                Arg::from_usage("--strip-prefix [PREFIX]... 'Remove PREFIX from the start of the source paths of the reports before the path mappings, * matches any characters in a directory name'")
+                    .number_of_values(1),
+                Arg::from_usage("--timeout-signal [SIGNAL] 'Signal sent to a test which timed out such as SIGTERM or SIGINT, SIGKILL is sent if it doesn't exit in time (default is SIGKILL)'")
+                    .validator(is_signal),
+                Arg::from_usage("--rustflags [FLAGS]... 'Flags passed to rustc after the instrumentation flags and RUSTFLAGS'")
+                    .number_of_values(1)
+                    .allow_hyphen_values(true),
+                Arg::from_usage("--color [WHEN] 'When to colour the output, Auto colours a terminal unless NO_COLOR is set (default is Auto)'")
+                    .possible_values(&Color::variants()),
+                Arg::from_usage("--line-numbering [NUMBERING] 'Whether line numbers in the JSON report start at 1 or 0 (default is OneBased)'")
+                    .possible_values(&LineNumbering::variants()),
+                Arg::from_usage("--engine [ENGINE] 'How coverage is collected, llvm-cov needs a nightly toolchain (default is ptrace)'")
+                    .possible_values(&CoverageEngine::variants()),
+                Arg::from_usage("--count-aggregation [AGG] 'How hits for lines shared between test binaries are combined (default is Max)'")
+                    .possible_values(&CountAggregation::variants()),
                 Arg::from_usage("--run-types [TYPE] 'Type of the coverage run'")
                     .possible_values(&RunType::variants())
                     .multiple(true),
                 Arg::from_usage("--root -r [DIR]  'Calculates relative paths to root directory. If --manifest-path isn't specified it will look for a Cargo.toml in root'")
                     .validator(is_dir),
-                Arg::from_usage("--manifest-path [PATH] 'Path to Cargo.toml'"),
+                Arg::from_usage("--manifest-path -m [PATH] 'Path to Cargo.toml or the directory containing it'"),
                 Arg::from_usage("--ciserver [SERVICE] 'CI server being used, if unspecified tarpaulin may automatically infer for coveralls uploads'")
                     .help(CI_SERVER_HELP),
                 Arg::with_name("args")
@@ -106,11 +415,56 @@ fn main() -> Result<(), String> {
         .get_matches();
 
     let args = args.subcommand_matches("tarpaulin").unwrap_or(&args);
-    set_up_logging(args.is_present("debug"), args.is_present("verbose"));
+    if let Some(args) = args.subcommand_matches("check-config") {
+        set_up_logging(false, false, Color::Auto);
+        return check_config(args);
+    }
+    if let Some(args) = args
+        .subcommand_matches("config")
+        .and_then(|args| args.subcommand_matches("init"))
+    {
+        set_up_logging(false, false, Color::Auto);
+        return init(args);
+    }
+    if let Some(args) = args.subcommand_matches("diff") {
+        set_up_logging(false, false, Color::Auto);
+        return diff(args);
+    }
+    if let Some(args) = args.subcommand_matches("merge") {
+        set_up_logging(false, false, Color::Auto);
+        return merge(args);
+    }
+    if let Some(args) = args.subcommand_matches("report") {
+        set_up_logging(false, false, Color::Auto);
+        return report(args);
+    }
+    if args.is_present("config-schema") {
+        let schema =
+            serde_json::to_string_pretty(&config_schema()).map_err(|e| RunError::IO(e.into()))?;
+        println!("{}", schema);
+        return Ok(());
+    }
+    let color = value_t!(args, "color", Color).unwrap_or_default();
+    set_up_logging(args.is_present("debug"), args.is_present("verbose"), color);
     let config = ConfigWrapper::from(args);
+    if let Some(config) = config.0.first() {
+        set_log_color(config.color);
+    } else {
+        return Err(RunError::Config(
+            "No configs match the given config names".to_string(),
+        ));
+    }
+    if args.is_present("print-config") {
+        let toml = config
+            .to_toml()
+            .map_err(|e| RunError::Config(e.to_string()))?;
+        print!("{}", toml);
+        return Ok(());
+    }
+    config.check_valid().map_err(RunError::Config)?;
 
     trace!("Debug mode activated");
     // Since this is the last function we run and don't do any error mitigations (other than
     // printing the error to the user it's fine to unwrap here
-    run(&config.0).map_err(|e| e.to_string())
+    run(&config.0)
 }
@@ -0,0 +1,222 @@
+//! Merges the coverage reports of several runs, such as the jobs of a CI
+//! matrix, into one. Hits of the same line are summed across the reports and
+//! branches are covered if they were covered in any report. Runs on different
+//! machines can record the same sources under different paths, so paths can
+//! be rewritten before the reports are combined.
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::report::{json, lcov, report_coverage};
+use crate::traces::TraceMap;
+use log::info;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Replaces the leading `from` components of a source path with `to`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathRewrite {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+impl FromStr for PathRewrite {
+    type Err = String;
+
+    /// Parses a rewrite written as `FROM=TO`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.find('=') {
+            Some(i) if i > 0 => Ok(PathRewrite {
+                from: PathBuf::from(&s[..i]),
+                to: PathBuf::from(&s[i + 1..]),
+            }),
+            _ => Err(format!(
+                "path rewrites must be written as FROM=TO, got {}",
+                s
+            )),
+        }
+    }
+}
+
+impl PathRewrite {
+    fn apply(&self, path: &Path) -> Option<PathBuf> {
+        path.strip_prefix(&self.from)
+            .ok()
+            .map(|rest| self.to.join(rest))
+    }
+}
+
+/// Applies the first matching rewrite to the path, relative paths left after
+/// rewriting are resolved against the root
+fn rewrite_path(path: &Path, rewrites: &[PathRewrite], root: &Path) -> PathBuf {
+    let path = rewrites
+        .iter()
+        .find_map(|r| r.apply(path))
+        .unwrap_or_else(|| path.to_path_buf());
+    if path.is_relative() {
+        root.join(path)
+    } else {
+        path
+    }
+}
+
+/// Reads a JSON report if the file has a `.json` extension and an LCOV
+/// tracefile otherwise
+fn load_report(path: &Path) -> Result<TraceMap, RunError> {
+    if path.extension().map(|e| e == "json").unwrap_or(false) {
+        json::import(path)
+    } else {
+        lcov::import(path)
+    }
+}
+
+fn rewrite_paths(report: &TraceMap, rewrites: &[PathRewrite], root: &Path) -> TraceMap {
+    let mut result = TraceMap::new();
+    for (path, traces) in report.iter() {
+        let path = rewrite_path(path, rewrites, root);
+        for trace in traces {
+            result.add_trace(&path, trace.clone());
+        }
+    }
+    result
+}
+
+/// Reads and combines the coverage of each report
+pub fn merge_reports(
+    reports: &[PathBuf],
+    rewrites: &[PathRewrite],
+    root: &Path,
+) -> Result<TraceMap, RunError> {
+    let mut result = TraceMap::new();
+    for report in reports {
+        info!("Merging {}", report.display());
+        let traces = load_report(report)?;
+        result.merge_reports(&rewrite_paths(&traces, rewrites, root));
+    }
+    Ok(result)
+}
+
+/// Merges the reports and writes the result in the formats of the config
+pub fn merge_coverage(
+    config: &Config,
+    reports: &[PathBuf],
+    rewrites: &[PathRewrite],
+) -> Result<(), RunError> {
+    let result = merge_reports(reports, rewrites, &config.get_base_dir())?;
+    report_coverage(config, &result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OutputFile;
+    use crate::traces::{CoverageStat, Trace};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn path_rewrites() {
+        let rewrites = vec![
+            "/home/runner/work/project=/src/project".parse().unwrap(),
+            "/Users/runner/project=/src/project".parse().unwrap(),
+        ];
+        let root = Path::new("/src/project");
+        assert_eq!(
+            rewrite_path(
+                Path::new("/home/runner/work/project/src/lib.rs"),
+                &rewrites,
+                root
+            ),
+            Path::new("/src/project/src/lib.rs")
+        );
+        assert_eq!(
+            rewrite_path(
+                Path::new("/Users/runner/project/src/lib.rs"),
+                &rewrites,
+                root
+            ),
+            Path::new("/src/project/src/lib.rs")
+        );
+        assert_eq!(
+            rewrite_path(Path::new("src/main.rs"), &rewrites, root),
+            Path::new("/src/project/src/main.rs")
+        );
+        assert_eq!(
+            rewrite_path(
+                Path::new("/home/runner/work/project2/lib.rs"),
+                &rewrites,
+                root
+            ),
+            Path::new("/home/runner/work/project2/lib.rs")
+        );
+        assert!("=/src".parse::<PathRewrite>().is_err());
+        assert!("/src".parse::<PathRewrite>().is_err());
+    }
+
+    #[test]
+    fn merge_lcov_and_json() {
+        let dir = env::temp_dir().join("tarpaulin-merge");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let linux = dir.join("linux.info");
+        fs::write(
+            &linux,
+            "TN:\nSF:/home/ci/project/src/lib.rs\nDA:1,2\nDA:2,0\nLF:2\nLH:1\nend_of_record\n",
+        )
+        .unwrap();
+        let windows = dir.join("windows.json");
+        fs::write(
+            &windows,
+            r#"{"files": [{"path": "src/lib.rs", "lines": [{"line": 1, "hits": 1},
+                {"line": 2, "hits": 3}, {"line": 3, "hits": 0}]}]}"#,
+        )
+        .unwrap();
+
+        let rewrites = vec!["/home/ci/project=/project".parse().unwrap()];
+        let root = Path::new("/project");
+        let merged = merge_reports(&[linux, windows], &rewrites, root).unwrap();
+        let lib = Path::new("/project/src/lib.rs");
+        assert_eq!(merged.files(), vec![lib]);
+        let hits = merged
+            .get_child_traces(lib)
+            .iter()
+            .map(|t| (t.line, t.stats.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hits,
+            vec![
+                (1, CoverageStat::Line(3)),
+                (2, CoverageStat::Line(3)),
+                (3, CoverageStat::Line(0)),
+            ]
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn report_from_json_report() {
+        let dir = env::temp_dir().join("tarpaulin-report-json");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        let lib = dir.join("src/lib.rs");
+        fs::write(&lib, "pub fn foo() {\n    bar();\n}\n").unwrap();
+        let mut traces = TraceMap::new();
+        for (line, hits) in &[(1, 2), (2, 0)] {
+            traces.add_trace(&lib, Trace::new_stub(*line, CoverageStat::Line(*hits)));
+        }
+
+        let mut config = Config::default();
+        config.manifest = dir.join("Cargo.toml");
+        config.root = Some(dir.display().to_string());
+        config.output_directory = dir.clone();
+        config.generate = vec![OutputFile::Json];
+        report_coverage(&config, &traces).unwrap();
+
+        config.generate = vec![OutputFile::Lcov];
+        let json = config.output_path(&OutputFile::Json);
+        merge_coverage(&config, &[json], &[]).unwrap();
+        let lcov = lcov::import(&config.output_path(&OutputFile::Lcov)).unwrap();
+        assert_eq!(lcov.files(), vec![&lib]);
+        assert_eq!(lcov.get_child_traces(&lib), traces.get_child_traces(&lib));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -6,6 +6,7 @@ use nix::sched::*;
 use nix::unistd::*;
 use nix::Error;
 use std::ffi::{CStr, CString};
+use std::os::unix::io::RawFd;
 
 #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm"))]
 type Persona = c_long;
@@ -49,6 +50,12 @@ pub fn limit_affinity() -> nix::Result<()> {
     sched_setaffinity(this, &cpu_set)
 }
 
+/// Redirects stderr of the current process to the file descriptor
+pub fn redirect_stderr(fd: RawFd) -> nix::Result<()> {
+    dup2(fd, nix::libc::STDERR_FILENO)?;
+    close(fd)
+}
+
 pub fn execute(program: CString, argv: &[CString], envar: &[CString]) -> Result<(), RunError> {
     disable_aslr().map_err(|e| RunError::TestRuntime(format!("ASLR disable failed: {}", e)))?;
 
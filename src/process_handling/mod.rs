@@ -9,3 +9,7 @@ pub mod mac;
 
 #[cfg(target_os = "macos")]
 pub use mac::*;
+
+mod panic_output;
+
+pub use panic_output::*;
@@ -0,0 +1,164 @@
+//! Hides the panic messages of test executables for `--suppress-panics-output`
+//! while the rest of their stderr output is still shown.
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::thread::{self, JoinHandle};
+
+/// Where the filter is in the output of a panic
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PanicState {
+    /// Not in a panic message
+    Output,
+    /// In a message printed before the location, it ends with the location
+    Quoted,
+    /// The location was printed, the first line of the message follows
+    Location,
+    /// In a message printed after the location, indented lines continue it
+    Message,
+}
+
+impl Default for PanicState {
+    fn default() -> Self {
+        PanicState::Output
+    }
+}
+
+/// Tracks whether lines of output are part of a panic message. Before Rust
+/// 1.73 a panic prints `thread '<name>' panicked at '<message>', <file>:<line>:<column>`
+/// where the message can span several lines. Newer versions print
+/// `thread '<name>' panicked at <file>:<line>:<column>:` with the message on
+/// the following lines, the lines of assertion messages after the first are
+/// indented. The first panic of a process is followed by a note about showing
+/// backtraces.
+#[derive(Debug, Default)]
+pub struct PanicFilter {
+    state: PanicState,
+}
+
+impl PanicFilter {
+    /// Returns true if the line isn't part of a panic message
+    pub fn keep(&mut self, line: &str) -> bool {
+        if line.starts_with("thread '") && line.contains("' panicked at ") {
+            self.state = if line.ends_with(':') && ends_with_location(&line[..line.len() - 1]) {
+                PanicState::Location
+            } else if ends_with_location(line) {
+                PanicState::Output
+            } else {
+                PanicState::Quoted
+            };
+            return false;
+        }
+        if line.starts_with("note: run with `RUST_BACKTRACE=") {
+            self.state = PanicState::Output;
+            return false;
+        }
+        match self.state {
+            PanicState::Output => true,
+            PanicState::Quoted => {
+                if ends_with_location(line) {
+                    self.state = PanicState::Output;
+                }
+                false
+            }
+            PanicState::Location => {
+                self.state = PanicState::Message;
+                false
+            }
+            PanicState::Message if line.starts_with(char::is_whitespace) => false,
+            PanicState::Message => {
+                self.state = PanicState::Output;
+                true
+            }
+        }
+    }
+}
+
+/// Checks for the `:<line>:<column>` which ends a panic message
+fn ends_with_location(line: &str) -> bool {
+    let is_number = |s: Option<&str>| {
+        s.map(|s| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()))
+            .unwrap_or(false)
+    };
+    let mut parts = line.rsplitn(3, ':');
+    is_number(parts.next()) && is_number(parts.next())
+}
+
+/// Copies the output to tarpaulin's stderr without panic messages, the output
+/// is read on another thread until the writing end is closed. Join the thread
+/// once the process is reaped so none of its output is lost.
+pub fn forward_stderr<R: Read + Send + 'static>(output: R) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut filter = PanicFilter::default();
+        let mut output = BufReader::new(output);
+        let mut line = vec![];
+        while let Ok(n) = output.read_until(b'\n', &mut line) {
+            if n == 0 {
+                break;
+            }
+            let text = String::from_utf8_lossy(&line);
+            if filter.keep(text.trim_end_matches(|c| c == '\n' || c == '\r')) {
+                let _ = io::stderr().write_all(&line);
+            }
+            line.clear();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panic_messages_filtered() {
+        let output = "running 2 tests
+thread 'tests::fails' panicked at 'assertion failed: `(left == right)`
+  left: `1`,
+ right: `2`', src/lib.rs:10:9
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+eprintln from a test
+thread 'tests::other' panicked at 'explicit panic', src/lib.rs:14:9
+test result: ok. 2 passed; 0 failed";
+        let mut filter = PanicFilter::default();
+        let kept = output
+            .lines()
+            .filter(|l| filter.keep(l))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kept,
+            vec![
+                "running 2 tests",
+                "eprintln from a test",
+                "test result: ok. 2 passed; 0 failed"
+            ]
+        );
+    }
+
+    #[test]
+    fn panic_messages_after_location_filtered() {
+        let output = "running 3 tests
+thread 'tests::fails' panicked at src/lib.rs:10:9:
+assertion `left == right` failed
+  left: 1
+ right: 2
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+thread 'tests::other' panicked at src/lib.rs:14:9:
+explicit panic
+eprintln from a test
+thread 'tests::multi' panicked at src/lib.rs:20:9:
+first line
+    second line
+test result: ok. 3 passed; 0 failed";
+        let mut filter = PanicFilter::default();
+        let kept = output
+            .lines()
+            .filter(|l| filter.keep(l))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kept,
+            vec![
+                "running 3 tests",
+                "eprintln from a test",
+                "test result: ok. 3 passed; 0 failed"
+            ]
+        );
+    }
+}
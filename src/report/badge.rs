@@ -0,0 +1,116 @@
+//! A coverage badge in the flat style of Shields.io so projects can host
+//! their badge without an external service. The label and value are drawn
+//! as two rounded rectangles with the value coloured by the coverage.
+use crate::config::{Config, OutputFile};
+use crate::errors::RunError;
+use crate::traces::TraceMap;
+use std::fs::File;
+use std::io::Write;
+
+const LABEL: &str = "coverage";
+const RED: &str = "#e05d44";
+const YELLOW: &str = "#dfb317";
+const GREEN: &str = "#4c1";
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let file_path = config.output_path(&OutputFile::Badges);
+    let mut file = File::create(file_path)
+        .map_err(|e| RunError::CovReport(format!("File is not writeable: {}", e)))?;
+    let percent = coverage_data.coverage_percentage() * 100.0f64;
+    file.write_all(badge(percent, config.badge_thresholds).as_bytes())?;
+    Ok(())
+}
+
+/// Colour of the badge, red below the first threshold, yellow below the
+/// second and green otherwise
+fn colour(percent: f64, (low, high): (f64, f64)) -> &'static str {
+    if percent < low {
+        RED
+    } else if percent < high {
+        YELLOW
+    } else {
+        GREEN
+    }
+}
+
+/// Approximate width of text in the 11px Verdana used by Shields.io badges
+/// plus the padding either side of it
+fn segment_width(text: &str) -> usize {
+    text.chars().count() * 7 + 10
+}
+
+fn badge(percent: f64, thresholds: (f64, f64)) -> String {
+    // A project with nothing coverable gives NaN
+    let percent = if percent.is_nan() { 0.0 } else { percent };
+    let value = format!("{:.2}%", percent);
+    let fill = colour(percent, thresholds);
+    let label_width = segment_width(LABEL);
+    let value_width = segment_width(&value);
+    let width = label_width + value_width;
+    // Text is positioned by its centre
+    let label_x = label_width as f64 / 2.0;
+    let value_x = label_width as f64 + value_width as f64 / 2.0;
+    let alt = format!("{}: {}", LABEL, value);
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{alt}">
+  <title>{alt}</title>
+  <linearGradient id="s" x2="0" y2="100%">
+    <stop offset="0" stop-color="#bbb" stop-opacity=".1"/>
+    <stop offset="1" stop-opacity=".1"/>
+  </linearGradient>
+  <clipPath id="r">
+    <rect width="{width}" height="20" rx="3" fill="#fff"/>
+  </clipPath>
+  <g clip-path="url(#r)">
+    <rect width="{label_width}" height="20" fill="#555"/>
+    <rect x="{label_width}" width="{value_width}" height="20" fill="{fill}"/>
+    <rect width="{width}" height="20" fill="url(#s)"/>
+  </g>
+  <g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+    <text x="{label_x}" y="15" fill="#010101" fill-opacity=".3">{label}</text>
+    <text x="{label_x}" y="14">{label}</text>
+    <text x="{value_x}" y="15" fill="#010101" fill-opacity=".3">{value}</text>
+    <text x="{value_x}" y="14">{value}</text>
+  </g>
+</svg>
+"##,
+        width = width,
+        alt = alt,
+        label_width = label_width,
+        value_width = value_width,
+        fill = fill,
+        label_x = label_x,
+        value_x = value_x,
+        label = LABEL,
+        value = value,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn badge_colours() {
+        let thresholds = (50.0, 80.0);
+        assert_eq!(colour(12.5, thresholds), RED);
+        assert_eq!(colour(50.0, thresholds), YELLOW);
+        assert_eq!(colour(79.99, thresholds), YELLOW);
+        assert_eq!(colour(80.0, thresholds), GREEN);
+        assert_eq!(colour(100.0, thresholds), GREEN);
+    }
+
+    #[test]
+    fn badge_contents() {
+        let svg = badge(85.123, (50.0, 80.0));
+        assert!(svg.contains(r#"role="img""#));
+        assert!(svg.contains(r#"aria-label="coverage: 85.12%""#));
+        assert!(svg.contains("<title>coverage: 85.12%</title>"));
+        assert!(svg.contains(GREEN));
+        assert!(!svg.contains(RED));
+
+        let svg = badge(std::f64::NAN, (50.0, 80.0));
+        assert!(svg.contains("<title>coverage: 0.00%</title>"));
+        assert!(svg.contains(RED));
+    }
+}
@@ -38,7 +38,7 @@
 ///   </packages>
 /// </coverage>
 /// ```
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::error;
 use std::fmt;
 use std::fs::File;
@@ -53,8 +53,8 @@ use quick_xml::{
 
 use chrono::offset::Utc;
 
-use crate::config::Config;
-use crate::traces::{CoverageStat, Trace, TraceMap};
+use crate::config::{Config, OutputFile};
+use crate::traces::{CoverageStat, LogicState, Trace, TraceMap};
 
 pub fn report(traces: &TraceMap, config: &Config) -> Result<(), Error> {
     let result = Report::render(config, traces)?;
@@ -97,33 +97,35 @@ impl Report {
         let timestamp = Utc::now().timestamp();
         let sources = render_sources(config);
         let packages = render_packages(config, traces);
-        let mut line_rate = 0.0;
-        let mut branch_rate = 0.0;
-
-        if packages.len() > 0 {
-            line_rate = packages.iter().map(|x| x.line_rate).sum::<f64>() / packages.len() as f64;
-            branch_rate =
-                packages.iter().map(|x| x.branch_rate).sum::<f64>() / packages.len() as f64;
-        }
+        let lines_covered = traces.total_covered();
+        let lines_valid = traces.total_coverable();
+        let (branches_covered, branches_valid) = branch_counts(&traces.all_traces());
 
         Ok(Report {
             timestamp: timestamp,
-            lines_covered: traces.total_covered(),
-            lines_valid: traces.total_coverable(),
-            line_rate: line_rate,
-            branches_covered: 0,
-            branches_valid: 0,
-            branch_rate: branch_rate,
+            lines_covered,
+            lines_valid,
+            line_rate: rate(lines_covered, lines_valid),
+            branches_covered,
+            branches_valid,
+            branch_rate: rate(branches_covered, branches_valid),
             sources: sources,
             packages: packages,
         })
     }
 
     pub fn export(&self, config: &Config) -> Result<(), Error> {
-        let file_path = config.output_directory.join("cobertura.xml");
+        let file_path = config.output_path(&OutputFile::Xml);
         let mut file =
             File::create(file_path).map_err(|e| Error::ExportError(quick_xml::Error::Io(e)))?;
 
+        let result = self.to_xml()?;
+        file.write_all(&result)
+            .map_err(|e| Error::ExportError(quick_xml::Error::Io(e)))
+    }
+
+    /// Renders the report as a cobertura XML document
+    fn to_xml(&self) -> Result<Vec<u8>, Error> {
         let mut writer = Writer::new(Cursor::new(vec![]));
         writer
             .write_event(Event::Decl(BytesDecl::new(b"1.0", None, None)))
@@ -163,9 +165,7 @@ impl Report {
             .write_event(Event::End(BytesEnd::borrowed(cov_tag)))
             .map_err(|e| Error::ExportError(e))?;
 
-        let result = writer.into_inner().into_inner();
-        file.write_all(&result)
-            .map_err(|e| Error::ExportError(quick_xml::Error::Io(e)))
+        Ok(writer.into_inner().into_inner())
     }
 
     fn export_header<T: Write>(&self, writer: &mut Writer<T>) -> Result<(), quick_xml::Error> {
@@ -271,15 +271,93 @@ impl Report {
                 } => {
                     l.push_attribute(("number", number.to_string().as_ref()));
                     l.push_attribute(("hits", hits.to_string().as_ref()));
+                    l.push_attribute(("branch", "false"));
+                    writer.write_event(Event::Empty(l))?;
+                }
+                Line::Branch {
+                    ref number,
+                    ref hits,
+                    ref conditions,
+                } => {
+                    let covered = conditions.iter().map(|c| c.covered).sum();
+                    let valid = conditions.iter().map(|c| c.valid).sum();
+                    let condition_coverage = format!(
+                        "{}% ({}/{})",
+                        (rate(covered, valid) * 100.0).round(),
+                        covered,
+                        valid
+                    );
+                    l.push_attribute(("number", number.to_string().as_ref()));
+                    l.push_attribute(("hits", hits.to_string().as_ref()));
+                    l.push_attribute(("branch", "true"));
+                    l.push_attribute(("condition-coverage", condition_coverage.as_ref()));
+                    writer.write_event(Event::Start(l))?;
+                    self.export_conditions(conditions, writer)?;
+                    writer.write_event(Event::End(BytesEnd::borrowed(line_tag)))?;
                 }
-                Line::Branch { .. } => {}
             }
-            writer.write_event(Event::Empty(l))?;
         }
         writer
             .write_event(Event::End(BytesEnd::borrowed(lines_tag)))
             .map(|_| ())
     }
+
+    fn export_conditions<T: Write>(
+        &self,
+        conditions: &[Condition],
+        writer: &mut Writer<T>,
+    ) -> Result<(), quick_xml::Error> {
+        let conditions_tag = b"conditions";
+        let condition_tag = b"condition";
+
+        writer.write_event(Event::Start(BytesStart::borrowed(
+            conditions_tag,
+            conditions_tag.len(),
+        )))?;
+        for condition in conditions {
+            let coverage = format!(
+                "{}%",
+                (rate(condition.covered, condition.valid) * 100.0).round()
+            );
+            let mut c = BytesStart::borrowed(condition_tag, condition_tag.len());
+            c.push_attribute(("number", condition.number.to_string().as_ref()));
+            c.push_attribute(("type", condition.cond_type.as_str()));
+            c.push_attribute(("coverage", coverage.as_ref()));
+            writer.write_event(Event::Empty(c))?;
+        }
+        writer
+            .write_event(Event::End(BytesEnd::borrowed(conditions_tag)))
+            .map(|_| ())
+    }
+}
+
+/// Fraction of the valid items which are covered, 0 if there are none
+fn rate(covered: usize, valid: usize) -> f64 {
+    if valid == 0 {
+        0.0
+    } else {
+        covered as f64 / valid as f64
+    }
+}
+
+/// Logical conditions recorded in a trace, each one has two branches
+fn logic_states(stats: &CoverageStat) -> Vec<LogicState> {
+    match stats {
+        CoverageStat::Line(_) => vec![],
+        CoverageStat::Branch(state) => vec![*state],
+        CoverageStat::Condition(states) => states.clone(),
+    }
+}
+
+/// Number of branches covered and the total number of branches in the traces
+fn branch_counts(traces: &[&Trace]) -> (usize, usize) {
+    traces
+        .iter()
+        .flat_map(|t| logic_states(&t.stats))
+        .fold((0, 0), |(covered, valid), state| {
+            let hit = state.been_true as usize + state.been_false as usize;
+            (covered + hit, valid + 2)
+        })
 }
 
 fn render_sources(config: &Config) -> Vec<PathBuf> {
@@ -312,11 +390,12 @@ fn render_package(config: &Config, traces: &TraceMap, pkg: &Path) -> Package {
 
     let line_cover = traces.covered_in_path(pkg) as f64;
     let line_rate = line_cover / (traces.coverable_in_path(pkg) as f64);
+    let (branches_covered, branches_valid) = branch_counts(&traces.get_child_traces(pkg));
 
     Package {
         name: name,
         line_rate: line_rate,
-        branch_rate: 0.0,
+        branch_rate: rate(branches_covered, branches_valid),
         complexity: 0.0,
         classes: render_classes(config, traces, pkg),
     }
@@ -361,17 +440,15 @@ fn render_class(config: &Config, traces: &TraceMap, file: &Path) -> Class {
 
     let covered = traces.covered_in_path(file) as f64;
     let line_rate = covered / traces.coverable_in_path(file) as f64;
-    let lines = traces
-        .get_child_traces(file)
-        .iter()
-        .map(|x| render_line(x))
-        .collect();
+    let file_traces = traces.get_child_traces(file);
+    let (branches_covered, branches_valid) = branch_counts(&file_traces);
+    let lines = render_lines(&file_traces);
 
     Class {
         name: name,
         file_name: file_name,
         line_rate: line_rate,
-        branch_rate: 0.0,
+        branch_rate: rate(branches_covered, branches_valid),
         complexity: 0.0,
         lines: lines,
         methods: vec![],
@@ -409,25 +486,51 @@ enum Line {
     },
 }
 
-fn render_line(trace: &Trace) -> Line {
-    match &trace.stats {
-        CoverageStat::Line(hits) => Line::Plain {
-            number: trace.line as usize,
-            hits: *hits as usize,
-        },
-
-        // TODO: Branches in cobertura are given a fresh number as a label,
-        // which would require having some form of context when rendering.
-        //
-        _ => panic!("Not currently supported"),
+/// Combines the traces on each line into a single line, lines with logical
+/// conditions are rendered as branches
+fn render_lines(traces: &[&Trace]) -> Vec<Line> {
+    let mut lines: BTreeMap<usize, (usize, Vec<LogicState>)> = BTreeMap::new();
+    for trace in traces {
+        let line = lines.entry(trace.line as usize).or_default();
+        match trace.stats {
+            CoverageStat::Line(hits) => line.0 += hits as usize,
+            ref stats => line.1.extend(logic_states(stats)),
+        }
     }
+    lines
+        .into_iter()
+        .map(|(number, (hits, states))| {
+            if states.is_empty() {
+                Line::Plain { number, hits }
+            } else {
+                let conditions = states
+                    .iter()
+                    .enumerate()
+                    .map(|(i, state)| Condition {
+                        number: i,
+                        cond_type: ConditionType::Jump,
+                        covered: state.been_true as usize + state.been_false as usize,
+                        valid: 2,
+                    })
+                    .collect();
+                Line::Branch {
+                    number,
+                    hits,
+                    conditions,
+                }
+            }
+        })
+        .collect()
 }
 
 #[derive(Debug)]
 struct Condition {
     number: usize,
     cond_type: ConditionType,
-    coverage: f64,
+    /// Number of branches of the condition which were taken
+    covered: usize,
+    /// Number of branches of the condition
+    valid: usize,
 }
 
 // Condition types
@@ -435,3 +538,91 @@ struct Condition {
 enum ConditionType {
     Jump,
 }
+
+impl ConditionType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ConditionType::Jump => "jump",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::Reader;
+    use std::collections::HashMap;
+
+    #[test]
+    fn xml_structure() {
+        let file = std::env::current_dir().unwrap().join("src/lib.rs");
+        let mut traces = TraceMap::new();
+        traces.add_trace(&file, Trace::new_stub(1, CoverageStat::Line(1)));
+        traces.add_trace(&file, Trace::new_stub(2, CoverageStat::Line(0)));
+        let state = LogicState {
+            been_true: true,
+            been_false: false,
+        };
+        traces.add_trace(&file, Trace::new_stub(2, CoverageStat::Branch(state)));
+
+        let report = Report::render(&Config::default(), &traces).unwrap();
+        let xml = String::from_utf8(report.to_xml().unwrap()).unwrap();
+
+        let mut reader = Reader::from_str(&xml);
+        let mut buf = vec![];
+        let mut elements = vec![];
+        let mut attributes = vec![];
+        loop {
+            match reader.read_event(&mut buf).unwrap() {
+                Event::Start(ref e) | Event::Empty(ref e) => {
+                    let name = String::from_utf8_lossy(e.name()).to_string();
+                    let attrs = e
+                        .attributes()
+                        .map(|a| {
+                            let a = a.unwrap();
+                            let key = String::from_utf8_lossy(a.key).to_string();
+                            (key, String::from_utf8_lossy(&a.value).to_string())
+                        })
+                        .collect::<HashMap<_, _>>();
+                    elements.push(name.clone());
+                    attributes.push((name, attrs));
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        for element in &[
+            "coverage",
+            "packages",
+            "package",
+            "classes",
+            "class",
+            "lines",
+            "line",
+            "conditions",
+            "condition",
+        ] {
+            assert!(elements.contains(&element.to_string()), "{}", element);
+        }
+
+        let coverage = &attributes[0].1;
+        assert_eq!(coverage["line-rate"], "0.5");
+        assert_eq!(coverage["branch-rate"], "0.5");
+        assert_eq!(coverage["branches-valid"], "2");
+
+        let lines = attributes
+            .iter()
+            .filter(|(name, _)| name == "line")
+            .map(|(_, attrs)| attrs)
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["number"], "1");
+        assert_eq!(lines[0]["hits"], "1");
+        assert_eq!(lines[0]["branch"], "false");
+        assert_eq!(lines[1]["number"], "2");
+        assert_eq!(lines[1]["branch"], "true");
+        assert_eq!(lines[1]["condition-coverage"], "50% (1/2)");
+    }
+}
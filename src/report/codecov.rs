@@ -0,0 +1,241 @@
+//! Uploads coverage to Codecov using the v4 upload API. The upload is done in
+//! two steps, first the build is described to `/upload/v4` which responds with
+//! the URL of the report and a URL to `PUT` the report to. The report is the
+//! list of files in the project followed by a report in Codecov's JSON format:
+//! ```text
+//! src/lib.rs
+//! <<<<<< network
+//! # path=codecov.json
+//! {"coverage":{"src/lib.rs":{"1":1,"2":0,"3":"1/2"}}}
+//! <<<<<< EOF
+//! ```
+//! See https://docs.codecov.io/reference#upload
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::report::coveralls::get_git_info;
+use crate::traces::{CoverageStat, TraceMap};
+use curl::easy::{Easy, List};
+use log::{info, trace, warn};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Read;
+
+const CODECOV_URL: &str = "https://codecov.io";
+
+/// Combines the traces of each line into the hits for line coverage or
+/// "covered/total" for lines with branches
+fn to_json(coverage_data: &TraceMap, config: &Config) -> Value {
+    let mut files = Map::new();
+    for (path, traces) in coverage_data.iter() {
+        let mut lines: BTreeMap<u64, (u64, usize, usize)> = BTreeMap::new();
+        for trace in traces {
+            let line = lines.entry(trace.line).or_default();
+            match trace.stats {
+                CoverageStat::Line(hits) => line.0 += hits,
+                CoverageStat::Branch(state) => {
+                    line.1 += state.been_true as usize + state.been_false as usize;
+                    line.2 += 2;
+                }
+                CoverageStat::Condition(ref states) => {
+                    for state in states {
+                        line.1 += state.been_true as usize + state.been_false as usize;
+                        line.2 += 2;
+                    }
+                }
+            }
+        }
+        let lines = lines
+            .into_iter()
+            .map(|(line, (hits, covered, branches))| {
+                let value = if config.branch_coverage && branches > 0 {
+                    Value::from(format!("{}/{}", covered, branches))
+                } else {
+                    Value::from(hits)
+                };
+                (line.to_string(), value)
+            })
+            .collect::<Map<_, _>>();
+        let path = config.strip_base_dir(path).display().to_string();
+        files.insert(path, Value::Object(lines));
+    }
+    json!({ "coverage": files })
+}
+
+/// The body of the upload, Codecov uses the file list to map the paths in
+/// reports onto the repository
+fn upload_body(coverage_data: &TraceMap, config: &Config) -> String {
+    let mut body = String::new();
+    for file in coverage_data.files() {
+        body.push_str(&config.strip_base_dir(file).display().to_string());
+        body.push('\n');
+    }
+    body.push_str("<<<<<< network\n# path=codecov.json\n");
+    body.push_str(&to_json(coverage_data, config).to_string());
+    body.push_str("\n<<<<<< EOF\n");
+    body
+}
+
+/// Describes the build to Codecov, the CI service is detected from the
+/// environment and the commit and branch are taken from git otherwise
+fn upload_query(config: &Config) -> Vec<(&'static str, String)> {
+    let var = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+    let mut query = vec![(
+        "package",
+        format!("tarpaulin-{}", env!("CARGO_PKG_VERSION")),
+    )];
+    if let Some(token) = config.codecov_token() {
+        query.push(("token", token));
+    }
+    let ci = if var("GITHUB_ACTIONS").is_some() {
+        Some((
+            "github-actions",
+            "GITHUB_RUN_ID",
+            "GITHUB_REPOSITORY",
+            "GITHUB_SHA",
+        ))
+    } else if var("TRAVIS").is_some() {
+        Some((
+            "travis",
+            "TRAVIS_JOB_ID",
+            "TRAVIS_REPO_SLUG",
+            "TRAVIS_COMMIT",
+        ))
+    } else {
+        None
+    };
+    let mut commit = None;
+    if let Some((service, build, slug, sha)) = ci {
+        query.push(("service", service.to_string()));
+        query.extend(var(build).map(|v| ("build", v)));
+        query.extend(var(slug).map(|v| ("slug", v)));
+        commit = var(sha);
+    }
+    match get_git_info(&config.manifest) {
+        Ok(git_info) => {
+            query.push(("commit", commit.unwrap_or(git_info.head.id)));
+            query.push(("branch", git_info.branch));
+        }
+        Err(err) => {
+            warn!("Failed to collect git info: {}", err);
+            query.extend(commit.map(|c| ("commit", c)));
+        }
+    }
+    query
+}
+
+/// Sends a request with curl, which coveralls-api already uses for its
+/// uploads. The body is sent with a `PUT` and an empty `POST` is sent without
+/// one, an error status of the response is returned as an error.
+fn request(url: &str, headers: &[&str], body: Option<&str>) -> Result<String, curl::Error> {
+    let mut easy = Easy::new();
+    easy.url(url)?;
+    easy.fail_on_error(true)?;
+    let mut list = List::new();
+    for header in headers {
+        list.append(header)?;
+    }
+    easy.http_headers(list)?;
+    match body {
+        Some(body) => {
+            easy.upload(true)?;
+            easy.in_filesize(body.len() as u64)?;
+        }
+        None => {
+            easy.post(true)?;
+            easy.post_field_size(0)?;
+        }
+    }
+    let mut body = body.unwrap_or_default().as_bytes();
+    let mut response = vec![];
+    {
+        let mut transfer = easy.transfer();
+        transfer.read_function(|buf| Ok(body.read(buf).unwrap_or(0)))?;
+        transfer.write_function(|data| {
+            response.extend_from_slice(data);
+            Ok(data.len())
+        })?;
+        transfer.perform()?;
+    }
+    Ok(String::from_utf8_lossy(&response).into_owned())
+}
+
+fn send(body: String, config: &Config) -> Result<String, String> {
+    let url = config.codecov_url.as_deref().unwrap_or(CODECOV_URL);
+    let mut encoder = Easy::new();
+    let query = upload_query(config)
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, encoder.url_encode(value.as_bytes())))
+        .collect::<Vec<_>>()
+        .join("&");
+    let url = format!("{}/upload/v4?{}", url.trim_end_matches('/'), query);
+    let response = request(&url, &["Accept: text/plain"], None).map_err(|e| e.to_string())?;
+    trace!("Codecov response {:?}", response);
+    let mut lines = response.lines();
+    let report_url = lines.next().unwrap_or_default().to_string();
+    let upload_url = lines
+        .next()
+        .ok_or_else(|| format!("No upload URL in response: {}", response))?;
+    let headers = ["Content-Type: text/plain", "x-amz-acl: public-read"];
+    request(upload_url, &headers, Some(&body)).map_err(|e| e.to_string())?;
+    Ok(report_url)
+}
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let body = upload_body(coverage_data, config);
+    if config.debug {
+        info!("Attempting to write codecov report to codecov.txt");
+        let file_path = config.report_directory().join("codecov.txt");
+        let _ = fs::write(file_path, &body);
+    }
+    match config.codecov_url {
+        Some(ref url) => info!("Sending coverage data to {}", url),
+        None => info!("Sending coverage data to codecov.io"),
+    }
+    match send(body, config) {
+        Ok(url) => {
+            info!("Codecov report: {}", url);
+            Ok(())
+        }
+        Err(e) => Err(RunError::CovReport(format!("Codecov send failed. {}", e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{LogicState, Trace};
+
+    #[test]
+    fn codecov_upload_body() {
+        let file = env::current_dir().unwrap().join("src/lib.rs");
+        let mut traces = TraceMap::new();
+        traces.add_trace(&file, Trace::new_stub(1, CoverageStat::Line(3)));
+        traces.add_trace(&file, Trace::new_stub(2, CoverageStat::Line(0)));
+        traces.add_trace(&file, Trace::new_stub(3, CoverageStat::Line(1)));
+        let state = LogicState {
+            been_true: true,
+            been_false: false,
+        };
+        traces.add_trace(&file, Trace::new_stub(3, CoverageStat::Branch(state)));
+
+        let mut config = Config::default();
+        let report = to_json(&traces, &config);
+        assert_eq!(
+            report,
+            json!({"coverage": {"src/lib.rs": {"1": 3, "2": 0, "3": 1}}})
+        );
+
+        config.branch_coverage = true;
+        let body = upload_body(&traces, &config);
+        let mut lines = body.lines();
+        assert_eq!(lines.next(), Some("src/lib.rs"));
+        assert_eq!(lines.next(), Some("<<<<<< network"));
+        assert_eq!(lines.next(), Some("# path=codecov.json"));
+        let report: Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(report["coverage"]["src/lib.rs"]["3"], "1/2");
+        assert_eq!(lines.next(), Some("<<<<<< EOF"));
+        assert_eq!(lines.next(), None);
+    }
+}
@@ -7,7 +7,7 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-fn get_git_info(manifest_path: &Path) -> Result<GitInfo, String> {
+pub(super) fn get_git_info(manifest_path: &Path) -> Result<GitInfo, String> {
     let dir_path = manifest_path
         .parent()
         .ok_or_else(|| format!("failed to get parent for path: {}", manifest_path.display()))?;
@@ -78,29 +78,40 @@ fn get_identity(ci_tool: &Option<CiService>, key: &str) -> Identity {
     }
 }
 
+/// Coverage of each file in the format coveralls expects, the source is read
+/// from where it is on this machine and reported under the mapped path
+fn get_sources(coverage_data: &TraceMap, config: &Config) -> Vec<Source> {
+    let mut sources = vec![];
+    for file in &coverage_data.files() {
+        let rel_path = config.strip_base_dir(&config.map_path(file));
+        let mut lines: HashMap<usize, usize> = HashMap::new();
+        let fcov = coverage_data.get_child_traces(file);
+
+        for c in &fcov {
+            match c.stats {
+                CoverageStat::Line(hits) => {
+                    lines.insert(c.line as usize, hits as usize);
+                }
+                _ => {
+                    info!("Support for coverage statistic not implemented or supported for coveralls.io");
+                }
+            }
+        }
+        match Source::new(&rel_path, file, &lines, &None, false) {
+            Ok(source) => sources.push(source),
+            Err(e) => warn!("Failed to read {} for coveralls: {}", file.display(), e),
+        }
+    }
+    sources
+}
+
 pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
     if let Some(ref key) = config.coveralls {
         let id = get_identity(&config.ci_tool, key);
 
         let mut report = CoverallsReport::new(id);
-        for file in &coverage_data.files() {
-            let rel_path = config.strip_base_dir(file);
-            let mut lines: HashMap<usize, usize> = HashMap::new();
-            let fcov = coverage_data.get_child_traces(file);
-
-            for c in &fcov {
-                match c.stats {
-                    CoverageStat::Line(hits) => {
-                        lines.insert(c.line as usize, hits as usize);
-                    }
-                    _ => {
-                        info!("Support for coverage statistic not implemented or supported for coveralls.io");
-                    }
-                }
-            }
-            if let Ok(source) = Source::new(&rel_path, file, &lines, &None, false) {
-                report.add_source(source);
-            }
+        for source in get_sources(coverage_data, config) {
+            report.add_source(source);
         }
 
         match get_git_info(&config.manifest) {
@@ -124,7 +135,7 @@ pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError>
         if config.debug {
             if let Ok(text) = serde_json::to_string(&report) {
                 info!("Attempting to write coveralls report to coveralls.json");
-                let file_path = config.output_directory.join("coveralls.json");
+                let file_path = config.report_directory().join("coveralls.json");
                 let _ = fs::write(file_path, text);
             } else {
                 warn!("Failed to serialise coverage report");
@@ -143,3 +154,27 @@ pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError>
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::Trace;
+    use std::env;
+
+    #[test]
+    fn mapped_sources_read_from_origin() {
+        let root = env::current_dir().unwrap();
+        let mut config = Config::default();
+        config.path_mappings = vec![(root.display().to_string(), "/ci/project".to_string())];
+        let mut result = TraceMap::new();
+        result.add_trace(
+            &root.join("src/lib.rs"),
+            Trace::new_stub(1, CoverageStat::Line(1)),
+        );
+
+        let sources = get_sources(&result, &config);
+        assert_eq!(sources.len(), 1);
+        let source = serde_json::to_value(&sources[0]).unwrap();
+        assert_eq!(source["name"], "/ci/project/src/lib.rs");
+    }
+}
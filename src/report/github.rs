@@ -0,0 +1,109 @@
+//! Coverage summary for the job summary page of a GitHub Actions run. Actions
+//! sets `$GITHUB_STEP_SUMMARY` to a file each step can append Markdown to.
+//! See https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions#adding-a-job-summary
+use crate::config::Config;
+use crate::errors::RunError;
+use crate::traces::TraceMap;
+use log::info;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+/// Appends the summary to `$GITHUB_STEP_SUMMARY` if tarpaulin is running in
+/// GitHub Actions and the summary isn't turned off
+pub fn write_step_summary(config: &Config, result: &TraceMap) -> Result<(), RunError> {
+    if !config.github_summary || env::var("GITHUB_ACTIONS").ok().as_deref() != Some("true") {
+        return Ok(());
+    }
+    if let Some(path) = env::var_os("GITHUB_STEP_SUMMARY") {
+        info!("Writing coverage summary to $GITHUB_STEP_SUMMARY");
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| RunError::CovReport(format!("Failed to open step summary: {}", e)))?;
+        file.write_all(summary(config, result).as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Red, yellow or green circle for the coverage using the badge thresholds
+fn indicator(percent: f64, (low, high): (f64, f64)) -> &'static str {
+    if percent < low {
+        ":red_circle:"
+    } else if percent < high {
+        ":yellow_circle:"
+    } else {
+        ":green_circle:"
+    }
+}
+
+fn summary(config: &Config, result: &TraceMap) -> String {
+    let mut summary = if config.name.is_empty() {
+        String::new()
+    } else {
+        format!("### {}\n\n", config.name)
+    };
+    summary.push_str(&format!(
+        "## Coverage: {:.2}%\n\n",
+        result.coverage_percentage() * 100.0f64
+    ));
+    summary.push_str("| File | Lines | Covered | Percent | |\n");
+    summary.push_str("| --- | ---: | ---: | ---: | --- |\n");
+    for file in result.files() {
+        let coverable = result.coverable_in_path(file);
+        if coverable == 0 {
+            continue;
+        }
+        let covered = result.covered_in_path(file);
+        let percent = 100.0f64 * covered as f64 / coverable as f64;
+        summary.push_str(&format!(
+            "| {} | {} | {} | {:.2}% | {} |\n",
+            config.strip_base_dir(file).display(),
+            coverable,
+            covered,
+            percent,
+            indicator(percent, config.badge_thresholds)
+        ));
+    }
+    summary.push('\n');
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{CoverageStat, Trace};
+
+    #[test]
+    fn summary_table() {
+        let dir = env::current_dir().unwrap();
+        let mut traces = TraceMap::new();
+        traces.add_trace(
+            &dir.join("src/lib.rs"),
+            Trace::new_stub(1, CoverageStat::Line(1)),
+        );
+        traces.add_trace(
+            &dir.join("src/lib.rs"),
+            Trace::new_stub(2, CoverageStat::Line(0)),
+        );
+        traces.add_trace(
+            &dir.join("src/main.rs"),
+            Trace::new_stub(1, CoverageStat::Line(2)),
+        );
+
+        let config = Config::default();
+        let summary = summary(&config, &traces);
+        let lines = summary.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "## Coverage: 66.67%");
+        assert_eq!(lines[2], "| File | Lines | Covered | Percent | |");
+        assert_eq!(
+            lines[4],
+            "| src/lib.rs | 2 | 1 | 50.00% | :yellow_circle: |"
+        );
+        assert_eq!(
+            lines[5],
+            "| src/main.rs | 1 | 1 | 100.00% | :green_circle: |"
+        );
+    }
+}
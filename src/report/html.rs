@@ -1,9 +1,12 @@
-use crate::config::Config;
+use crate::config::{Config, OutputFile};
 use crate::errors::*;
-use crate::report::{get_previous_result, safe_json};
+use crate::report::{get_previous_result, safe_json, source_cache::SourceCache};
+use crate::source_analysis::lines_matching;
 use crate::traces::{Trace, TraceMap};
+use regex::Regex;
 use serde::Serialize;
-use std::fs::{read_to_string, File};
+use std::collections::BTreeMap;
+use std::fs::File;
 use std::io::Write;
 
 #[derive(Serialize)]
@@ -11,8 +14,17 @@ struct SourceFile {
     pub path: Vec<String>,
     pub content: String,
     pub traces: Vec<Trace>,
+    pub ignored: Vec<usize>,
     pub covered: usize,
     pub coverable: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub called_functions: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_functions: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_covered: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_coverable: Option<usize>,
 }
 
 #[derive(Serialize)]
@@ -20,12 +32,17 @@ struct CoverageReport {
     pub files: Vec<SourceFile>,
 }
 
-fn get_json(coverage_data: &TraceMap) -> Result<String, RunError> {
+fn get_json(
+    coverage_data: &TraceMap,
+    cache: &mut SourceCache,
+    ignore_patterns: &[Regex],
+    config: &Config,
+) -> Result<String, RunError> {
     let mut report = CoverageReport { files: Vec::new() };
 
     for (path, traces) in coverage_data.iter() {
-        let content = match read_to_string(path) {
-            Ok(k) => k,
+        let content = match cache.get(path) {
+            Ok(k) => k.to_string(),
             Err(e) => {
                 return Err(RunError::Html(format!(
                     "Unable to read source file to string: {}",
@@ -34,15 +51,44 @@ fn get_json(coverage_data: &TraceMap) -> Result<String, RunError> {
             }
         };
 
+        let (called_functions, total_functions) = if config.function_coverage {
+            let functions = coverage_data.functions_in_path(path);
+            let called = functions.iter().filter(|f| f.hits > 0).count();
+            (Some(called), Some(functions.len()))
+        } else {
+            (None, None)
+        };
+        // Test code is shown separately from the code under test
+        let (covered, coverable, test_covered, test_coverable) = if config.include_tests {
+            (
+                coverage_data.production_covered_in_path(path),
+                coverage_data.production_coverable_in_path(path),
+                Some(coverage_data.test_covered_in_path(path)),
+                Some(coverage_data.test_coverable_in_path(path)),
+            )
+        } else {
+            (
+                coverage_data.covered_in_path(path),
+                coverage_data.coverable_in_path(path),
+                None,
+                None,
+            )
+        };
         report.files.push(SourceFile {
-            path: path
+            path: config
+                .map_path(path)
                 .components()
                 .map(|c| c.as_os_str().to_string_lossy().to_string())
                 .collect(),
+            ignored: lines_matching(&content, ignore_patterns),
             content,
             traces: traces.clone(),
-            covered: coverage_data.covered_in_path(path),
-            coverable: coverage_data.coverable_in_path(path),
+            covered,
+            coverable,
+            called_functions,
+            total_functions,
+            test_covered,
+            test_coverable,
         });
     }
 
@@ -50,8 +96,58 @@ fn get_json(coverage_data: &TraceMap) -> Result<String, RunError> {
         .map_err(|e| RunError::Html(format!("Report isn't serializable: {}", e.to_string())))
 }
 
-pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
-    let file_path = config.output_directory.join("tarpaulin-report.html");
+/// Name of the package in the manifest, workspaces without a root package
+/// have no name
+fn package_name(config: &Config) -> Option<String> {
+    let manifest = std::fs::read_to_string(&config.manifest).ok()?;
+    let manifest: toml::Value = toml::from_str(&manifest).ok()?;
+    manifest
+        .get("package")?
+        .get("name")?
+        .as_str()
+        .map(ToString::to_string)
+}
+
+fn report_title(config: &Config) -> String {
+    match (&config.report_title, package_name(config)) {
+        (Some(title), _) => title.clone(),
+        (None, Some(name)) => format!("Tarpaulin Coverage Report — {}", name),
+        (None, None) => "Tarpaulin Coverage Report".to_string(),
+    }
+}
+
+/// Table of the report tags shown above the report
+fn tags_table(config: &Config) -> String {
+    if config.report_tags.is_empty() {
+        return String::new();
+    }
+    let tags = config.report_tags.iter().collect::<BTreeMap<_, _>>();
+    let rows = tags
+        .into_iter()
+        .map(|(key, value)| {
+            format!(
+                "<tr><th>{}</th><td>{}</td></tr>",
+                escape_html(key),
+                escape_html(value)
+            )
+        })
+        .collect::<String>();
+    format!("<table class=\"report-tags\">{}</table>", rows)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn export(
+    coverage_data: &TraceMap,
+    config: &Config,
+    cache: &mut SourceCache,
+) -> Result<(), RunError> {
+    let file_path = config.output_path(&OutputFile::Html);
     let mut file = match File::create(file_path) {
         Ok(k) => k,
         Err(e) => {
@@ -62,34 +158,50 @@ pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError>
         }
     };
 
-    let report_json = get_json(coverage_data)?;
+    let ignore_patterns = config.ignored_line_patterns();
+    let report_json = get_json(coverage_data, cache, &ignore_patterns, config)?;
     let previous_report_json = match get_previous_result(&config) {
-        Some(result) => get_json(&result)?,
+        Some(result) => get_json(&result, cache, &ignore_patterns, config)?,
         None => String::from("null"),
     };
 
+    let expand_threshold = match config.html_expand_uncovered {
+        Some(threshold) => threshold.to_string(),
+        None => String::from("null"),
+    };
+
+    let title = escape_html(&report_title(config));
+    let tags = tags_table(config);
     let html_write = match write!(
         file,
         r##"<!doctype html>
 <html>
 <head>
     <meta charset="utf-8">
+    <title>{}</title>
     <style>{}</style>
 </head>
 <body>
+    <h1 class="report-title">{}</h1>
+    {}
     <div id="root"></div>
     <script>
         var data = {};
         var previousData = {};
+        var expandThreshold = {};
+        var showHits = {};
     </script>
-    <script crossorigin src="https://unpkg.com/react@16/umd/react.production.min.js"></script>
-    <script crossorigin src="https://unpkg.com/react-dom@16/umd/react-dom.production.min.js"></script>
     <script>{}</script>
 </body>
 </html>"##,
+        title,
         include_str!("report_viewer.css"),
+        title,
+        tags,
         report_json,
         previous_report_json,
+        expand_threshold,
+        config.count,
         include_str!("report_viewer.js")
     ) {
         Ok(_) => (),
@@ -98,3 +210,149 @@ pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError>
 
     Ok(html_write)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::CoverageStat;
+    use std::path::Path;
+
+    #[test]
+    fn ignored_lines_in_report() {
+        let path = Path::new("tests/data/simple_project/src/lib.rs");
+        let mut traces = TraceMap::new();
+        traces.add_trace(path, Trace::new_stub(5, CoverageStat::Line(1)));
+        traces.add_trace(path, Trace::new_stub(8, CoverageStat::Line(0)));
+        let patterns = vec![Regex::new(r"^\s*10$").unwrap()];
+        let mut cache = SourceCache::new(true);
+
+        let config = Config::default();
+        let json = get_json(&traces, &mut cache, &patterns, &config).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let file = &report["files"][0];
+        assert_eq!(file["ignored"], serde_json::json!([6]));
+        assert_eq!(file["covered"], 1);
+        assert_eq!(file["coverable"], 2);
+        assert_eq!(file["traces"][0]["stats"]["Line"], 1);
+        assert_eq!(file["traces"][1]["stats"]["Line"], 0);
+        assert!(file.get("total_functions").is_none());
+    }
+
+    #[test]
+    fn function_counts_in_report() {
+        let path = Path::new("tests/data/simple_project/src/lib.rs");
+        let mut traces = TraceMap::new();
+        let mut entry = Trace::new_stub(1, CoverageStat::Line(1));
+        entry.fn_name = Some("foo".to_string());
+        traces.add_trace(path, entry);
+        let mut entry = Trace::new_stub(5, CoverageStat::Line(0));
+        entry.fn_name = Some("bar".to_string());
+        traces.add_trace(path, entry);
+        traces.add_trace(path, Trace::new_stub(6, CoverageStat::Line(0)));
+        let mut cache = SourceCache::new(true);
+
+        let mut config = Config::default();
+        config.function_coverage = true;
+        let json = get_json(&traces, &mut cache, &[], &config).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let file = &report["files"][0];
+        assert_eq!(file["called_functions"], 1);
+        assert_eq!(file["total_functions"], 2);
+    }
+
+    #[test]
+    fn mapped_path_in_report() {
+        let path = Path::new("tests/data/simple_project/src/lib.rs");
+        let mut traces = TraceMap::new();
+        traces.add_trace(path, Trace::new_stub(5, CoverageStat::Line(1)));
+        let mut cache = SourceCache::new(true);
+
+        let mut config = Config::default();
+        config.path_mappings = vec![("tests/data".to_string(), "/ci".to_string())];
+        let json = get_json(&traces, &mut cache, &[], &config).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let file = &report["files"][0];
+        assert_eq!(
+            file["path"],
+            serde_json::json!(["/", "ci", "simple_project", "src", "lib.rs"])
+        );
+        assert_eq!(file["content"], cache.get(path).unwrap());
+    }
+
+    #[test]
+    fn report_is_self_contained() {
+        let path = Path::new("tests/data/simple_project/src/lib.rs");
+        let mut traces = TraceMap::new();
+        traces.add_trace(path, Trace::new_stub(5, CoverageStat::Line(3)));
+        let mut cache = SourceCache::new(true);
+        let mut config = Config::default();
+        config.output_directory = std::env::temp_dir().join("tarpaulin-html-self-contained");
+        std::fs::create_dir_all(&config.output_directory).unwrap();
+        config.count = true;
+
+        export(&traces, &config, &mut cache).unwrap();
+        let html = std::fs::read_to_string(config.output_path(&OutputFile::Html)).unwrap();
+        assert!(!html.contains("<script src"));
+        assert!(!html.contains("<script crossorigin"));
+        assert!(html.contains("var showHits = true;"));
+        assert!(html.contains("code-line_partial"));
+    }
+
+    #[test]
+    fn title_of_report() {
+        let mut config = Config::default();
+        config.manifest = Path::new("tests/data/simple_project/Cargo.toml").to_path_buf();
+        assert_eq!(
+            report_title(&config),
+            "Tarpaulin Coverage Report — simple_project"
+        );
+        config.manifest = Path::new("tests/data/missing/Cargo.toml").to_path_buf();
+        assert_eq!(report_title(&config), "Tarpaulin Coverage Report");
+        config.report_title = Some("<Nightly> coverage".to_string());
+        assert_eq!(report_title(&config), "<Nightly> coverage");
+        assert_eq!(
+            escape_html(&report_title(&config)),
+            "&lt;Nightly&gt; coverage"
+        );
+    }
+
+    #[test]
+    fn tags_in_report() {
+        let mut config = Config::default();
+        assert_eq!(tags_table(&config), "");
+        config
+            .report_tags
+            .insert("os".to_string(), "linux".to_string());
+        config
+            .report_tags
+            .insert("feature".to_string(), "<async>".to_string());
+        assert_eq!(
+            tags_table(&config),
+            "<table class=\"report-tags\">\
+             <tr><th>feature</th><td>&lt;async&gt;</td></tr>\
+             <tr><th>os</th><td>linux</td></tr>\
+             </table>"
+        );
+    }
+
+    #[test]
+    fn test_code_in_report() {
+        let path = Path::new("tests/data/simple_project/src/lib.rs");
+        let mut traces = TraceMap::new();
+        traces.add_trace(path, Trace::new_stub(5, CoverageStat::Line(1)));
+        traces.add_trace(path, Trace::new_stub(8, CoverageStat::Line(0)));
+        traces.add_trace(path, Trace::new_stub(12, CoverageStat::Line(1)));
+        traces.mark_test_line(path, 12);
+        let mut cache = SourceCache::new(true);
+        let mut config = Config::default();
+        config.include_tests = true;
+
+        let json = get_json(&traces, &mut cache, &[], &config).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let file = &report["files"][0];
+        assert_eq!(file["covered"], 1);
+        assert_eq!(file["coverable"], 2);
+        assert_eq!(file["test_covered"], 1);
+        assert_eq!(file["test_coverable"], 1);
+    }
+}
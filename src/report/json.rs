@@ -0,0 +1,402 @@
+use crate::config::{Config, LineNumbering, OutputFile};
+use crate::errors::RunError;
+use crate::traces::{CoverageStat, FunctionHits, LogicState, Trace, TraceMap};
+use chrono::offset::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize)]
+struct Metadata {
+    version: &'static str,
+    timestamp: String,
+    command: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, String>,
+    line_numbering: LineNumbering,
+}
+
+#[derive(Deserialize, Serialize)]
+struct LineReport {
+    line: u64,
+    hits: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    branches: Vec<LogicState>,
+}
+
+#[derive(Serialize)]
+struct FunctionReport {
+    called_functions: usize,
+    total_functions: usize,
+    coverage_percent: f64,
+    functions: Vec<FunctionHits>,
+}
+
+#[derive(Serialize)]
+struct FileReport {
+    path: String,
+    covered_lines: usize,
+    coverable_lines: usize,
+    coverage_percent: f64,
+    lines: Vec<LineReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    functions: Option<FunctionReport>,
+}
+
+#[derive(Serialize)]
+struct JsonReport {
+    metadata: Metadata,
+    files: Vec<FileReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    test_code_coverage: Option<f64>,
+}
+
+/// The parts of a file report needed to recreate its traces
+#[derive(Deserialize)]
+struct ImportedFile {
+    path: PathBuf,
+    lines: Vec<LineReport>,
+    #[serde(default)]
+    functions: Option<ImportedFunctions>,
+}
+
+#[derive(Deserialize)]
+struct ImportedFunctions {
+    functions: Vec<FunctionHits>,
+}
+
+#[derive(Default, Deserialize)]
+struct ImportedMetadata {
+    /// Reports written before the numbering could be changed are 1-based
+    #[serde(default)]
+    line_numbering: LineNumbering,
+}
+
+#[derive(Deserialize)]
+struct ImportedReport {
+    #[serde(default)]
+    metadata: ImportedMetadata,
+    files: Vec<ImportedFile>,
+}
+
+fn percentage(covered: usize, coverable: usize) -> f64 {
+    if coverable == 0 {
+        0.0
+    } else {
+        100.0f64 * covered as f64 / coverable as f64
+    }
+}
+
+fn function_report(functions: Vec<FunctionHits>) -> FunctionReport {
+    let called_functions = functions.iter().filter(|f| f.hits > 0).count();
+    FunctionReport {
+        called_functions,
+        total_functions: functions.len(),
+        coverage_percent: percentage(called_functions, functions.len()),
+        functions,
+    }
+}
+
+fn render(coverage_data: &TraceMap, config: &Config) -> JsonReport {
+    let metadata = Metadata {
+        version: env!("CARGO_PKG_VERSION"),
+        timestamp: Utc::now().to_rfc3339(),
+        command: env::args().collect::<Vec<_>>().join(" "),
+        tags: config
+            .report_tags
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect(),
+        line_numbering: config.line_numbering,
+    };
+    let files = coverage_data
+        .iter()
+        .map(|(path, traces)| {
+            // Traces are sorted by line so traces on the same line are adjacent
+            let mut lines: Vec<LineReport> = vec![];
+            for trace in traces {
+                if lines.last().map(|l| l.line) != Some(trace.line) {
+                    lines.push(LineReport {
+                        line: trace.line,
+                        hits: 0,
+                        branches: vec![],
+                    });
+                }
+                if let Some(line) = lines.last_mut() {
+                    match trace.stats {
+                        CoverageStat::Line(hits) => line.hits += hits,
+                        CoverageStat::Branch(state) => line.branches.push(state),
+                        CoverageStat::Condition(ref states) => line.branches.extend(states),
+                    }
+                }
+            }
+            for line in &mut lines {
+                line.line = config.line_numbering.line_number(line.line);
+                if !config.count {
+                    line.hits = line.hits.min(1);
+                }
+                if !config.branch_coverage {
+                    line.branches.clear();
+                }
+            }
+            let covered_lines = coverage_data.covered_in_path(path);
+            let coverable_lines = coverage_data.coverable_in_path(path);
+            let coverage_percent = percentage(covered_lines, coverable_lines);
+            let functions = if config.function_coverage {
+                let mut functions = coverage_data.functions_in_path(path);
+                for function in &mut functions {
+                    function.line = config.line_numbering.line_number(function.line);
+                }
+                Some(function_report(functions))
+            } else {
+                None
+            };
+            FileReport {
+                path: config.strip_base_dir(path).display().to_string(),
+                covered_lines,
+                coverable_lines,
+                coverage_percent,
+                lines,
+                functions,
+            }
+        })
+        .collect();
+    let test_code_coverage = if config.include_tests {
+        let all = Path::new("");
+        let covered = coverage_data.test_covered_in_path(all);
+        Some(percentage(
+            covered,
+            coverage_data.test_coverable_in_path(all),
+        ))
+    } else {
+        None
+    };
+    JsonReport {
+        metadata,
+        files,
+        test_code_coverage,
+    }
+}
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let file_path = config.output_path(&OutputFile::Json);
+    let file = File::create(file_path)
+        .map_err(|e| RunError::CovReport(format!("File is not writeable: {}", e)))?;
+
+    let report = render(coverage_data, config);
+    let result = if config.compact_json {
+        serde_json::to_writer(file, &report)
+    } else {
+        serde_json::to_writer_pretty(file, &report)
+    };
+    result.map_err(|e| RunError::CovReport(format!("Failed to write JSON report: {}", e)))
+}
+
+/// Reads the coverage from a JSON report, paths are left relative to the root
+/// of the project the report was written for
+pub fn import(path: &Path) -> Result<TraceMap, RunError> {
+    let file = File::open(path)
+        .map_err(|e| RunError::CovReport(format!("Failed to open {}: {}", path.display(), e)))?;
+    let report: ImportedReport = serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+        RunError::CovReport(format!(
+            "Failed to read JSON report {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    Ok(to_traces(report))
+}
+
+fn to_traces(report: ImportedReport) -> TraceMap {
+    let numbering = report.metadata.line_numbering;
+    let mut traces = TraceMap::new();
+    for file in report.files {
+        let functions = file
+            .functions
+            .map(|f| f.functions)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|f| (numbering.source_line(f.line), f.name))
+            .collect::<BTreeMap<_, _>>();
+        for line in file.lines {
+            let line_no = numbering.source_line(line.line);
+            let trace = |stats| Trace {
+                line: line_no,
+                address: HashSet::new(),
+                length: 1,
+                stats,
+                fn_name: None,
+                mangled_name: None,
+            };
+            let mut line_trace = trace(CoverageStat::Line(line.hits));
+            line_trace.fn_name = functions.get(&line_no).cloned();
+            traces.add_trace(&file.path, line_trace);
+            match line.branches.len() {
+                0 => {}
+                1 => traces.add_trace(&file.path, trace(CoverageStat::Branch(line.branches[0]))),
+                _ => traces.add_trace(&file.path, trace(CoverageStat::Condition(line.branches))),
+            }
+        }
+    }
+    traces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::Trace;
+
+    #[test]
+    fn line_hits() {
+        let file = env::current_dir().unwrap().join("src/lib.rs");
+        let mut traces = TraceMap::new();
+        traces.add_trace(&file, Trace::new_stub(1, CoverageStat::Line(5)));
+        traces.add_trace(&file, Trace::new_stub(2, CoverageStat::Line(0)));
+        let state = LogicState {
+            been_true: true,
+            been_false: false,
+        };
+        traces.add_trace(&file, Trace::new_stub(2, CoverageStat::Branch(state)));
+
+        let mut config = Config::default();
+        let report = serde_json::to_value(render(&traces, &config)).unwrap();
+        let file = &report["files"][0];
+        assert_eq!(file["path"], "src/lib.rs");
+        assert_eq!(file["covered_lines"], 1);
+        assert_eq!(file["coverable_lines"], 2);
+        assert_eq!(file["coverage_percent"], 50.0);
+        assert_eq!(
+            file["lines"],
+            serde_json::json!([{"line": 1, "hits": 1}, {"line": 2, "hits": 0}])
+        );
+        assert_eq!(report["metadata"]["version"], env!("CARGO_PKG_VERSION"));
+        assert!(report["metadata"].get("tags").is_none());
+
+        config.count = true;
+        config.branch_coverage = true;
+        config
+            .report_tags
+            .insert("os".to_string(), "linux".to_string());
+        let report = serde_json::to_value(render(&traces, &config)).unwrap();
+        assert_eq!(
+            report["metadata"]["tags"],
+            serde_json::json!({"os": "linux"})
+        );
+        assert_eq!(
+            report["files"][0]["lines"],
+            serde_json::json!([
+                {"line": 1, "hits": 5},
+                {"line": 2, "hits": 0, "branches": [{"been_true": true, "been_false": false}]}
+            ])
+        );
+    }
+
+    #[test]
+    fn function_coverage() {
+        let file = env::current_dir().unwrap().join("src/lib.rs");
+        let mut traces = TraceMap::new();
+        let mut called = Trace::new_stub(1, CoverageStat::Line(2));
+        called.fn_name = Some("called".to_string());
+        let mut uncalled = Trace::new_stub(4, CoverageStat::Line(0));
+        uncalled.fn_name = Some("uncalled".to_string());
+        traces.add_trace(&file, called);
+        traces.add_trace(&file, Trace::new_stub(2, CoverageStat::Line(2)));
+        traces.add_trace(&file, uncalled);
+
+        let mut config = Config::default();
+        let report = serde_json::to_value(render(&traces, &config)).unwrap();
+        assert!(report["files"][0].get("functions").is_none());
+
+        config.function_coverage = true;
+        let report = serde_json::to_value(render(&traces, &config)).unwrap();
+        assert_eq!(
+            report["files"][0]["functions"],
+            serde_json::json!({
+                "called_functions": 1,
+                "total_functions": 2,
+                "coverage_percent": 50.0,
+                "functions": [
+                    {"name": "called", "line": 1, "hits": 2},
+                    {"name": "uncalled", "line": 4, "hits": 0}
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_code_percentage() {
+        let file = env::current_dir().unwrap().join("src/lib.rs");
+        let mut traces = TraceMap::new();
+        traces.add_trace(&file, Trace::new_stub(1, CoverageStat::Line(1)));
+        traces.add_trace(&file, Trace::new_stub(10, CoverageStat::Line(1)));
+        traces.add_trace(&file, Trace::new_stub(11, CoverageStat::Line(0)));
+        traces.mark_test_line(&file, 10);
+        traces.mark_test_line(&file, 11);
+
+        let mut config = Config::default();
+        let report = serde_json::to_value(render(&traces, &config)).unwrap();
+        assert!(report.get("test_code_coverage").is_none());
+
+        config.include_tests = true;
+        let report = serde_json::to_value(render(&traces, &config)).unwrap();
+        assert_eq!(report["test_code_coverage"], 50.0);
+    }
+
+    #[test]
+    fn report_round_trip() {
+        let file = env::current_dir().unwrap().join("src/lib.rs");
+        let mut traces = TraceMap::new();
+        let mut called = Trace::new_stub(1, CoverageStat::Line(4));
+        called.fn_name = Some("called".to_string());
+        traces.add_trace(&file, called);
+        traces.add_trace(&file, Trace::new_stub(2, CoverageStat::Line(0)));
+        let state = LogicState {
+            been_true: true,
+            been_false: false,
+        };
+        traces.add_trace(&file, Trace::new_stub(2, CoverageStat::Branch(state)));
+
+        let mut config = Config::default();
+        config.count = true;
+        config.branch_coverage = true;
+        config.function_coverage = true;
+        for numbering in &[LineNumbering::OneBased, LineNumbering::ZeroBased] {
+            config.line_numbering = *numbering;
+            let report = serde_json::to_string(&render(&traces, &config)).unwrap();
+            let imported = to_traces(serde_json::from_str(&report).unwrap());
+            let path = Path::new("src/lib.rs");
+            assert_eq!(imported.files(), vec![path]);
+            let lines = imported.get_child_traces(path);
+            assert_eq!(lines.len(), 3);
+            assert_eq!(lines[0].line, 1);
+            assert_eq!(lines[0].stats, CoverageStat::Line(4));
+            assert_eq!(lines[0].fn_name.as_deref(), Some("called"));
+            assert!(lines[1..].contains(&&Trace::new_stub(2, CoverageStat::Branch(state))));
+            assert_eq!(imported.total_covered(), 2);
+        }
+    }
+
+    #[test]
+    fn zero_based_lines() {
+        let file = env::current_dir().unwrap().join("src/lib.rs");
+        let mut traces = TraceMap::new();
+        let mut called = Trace::new_stub(1, CoverageStat::Line(1));
+        called.fn_name = Some("called".to_string());
+        traces.add_trace(&file, called);
+        traces.add_trace(&file, Trace::new_stub(2, CoverageStat::Line(0)));
+
+        let mut config = Config::default();
+        config.function_coverage = true;
+        config.line_numbering = LineNumbering::ZeroBased;
+        let report = serde_json::to_value(render(&traces, &config)).unwrap();
+        assert_eq!(report["metadata"]["line_numbering"], "ZeroBased");
+        assert_eq!(
+            report["files"][0]["lines"],
+            serde_json::json!([{"line": 0, "hits": 1}, {"line": 1, "hits": 0}])
+        );
+        assert_eq!(report["files"][0]["functions"]["functions"][0]["line"], 0);
+    }
+}
@@ -1,11 +1,14 @@
-use crate::config::Config;
+use crate::config::{Config, OutputFile};
+use crate::coverage::Coverage;
 use crate::errors::RunError;
-use crate::traces::{CoverageStat, TraceMap};
+use crate::traces::{CoverageStat, LogicState, Trace, TraceMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 
 pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
-    let file_path = config.output_directory.join("lcov.info");
+    let file_path = config.output_path(&OutputFile::Lcov);
     let mut file = match File::create(file_path) {
         Ok(k) => k,
         Err(e) => {
@@ -16,65 +19,319 @@ pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError>
         }
     };
 
-    for (path, traces) in coverage_data.iter() {
+    write_tags(&config.report_tags, &mut file)?;
+    write_tracefile(coverage_data, &mut file)
+}
+
+/// Writes the report tags as comments at the start of the tracefile, lcov
+/// tools skip lines starting with `#`
+fn write_tags<W: Write>(tags: &HashMap<String, String>, file: &mut W) -> Result<(), RunError> {
+    let tags = tags.iter().collect::<BTreeMap<_, _>>();
+    for (key, value) in tags {
+        writeln!(file, "# {}={}", key, value)?;
+    }
+    Ok(())
+}
+
+/// Writes the coverage data in the LCOV tracefile format. See the geninfo
+/// man page for a description of the format
+/// http://ltp.sourceforge.net/coverage/lcov/geninfo.1.php
+fn write_tracefile<W: Write>(coverage_data: &TraceMap, file: &mut W) -> Result<(), RunError> {
+    let coverage = Coverage::from(coverage_data);
+    for source in &coverage.files {
         writeln!(file, "TN:")?;
-        writeln!(file, "SF:{}", path.to_str().unwrap())?;
-
-        let mut fns: Vec<String> = vec![];
-        let mut fnda: Vec<String> = vec![];
-        let mut da: Vec<(u64, u64)> = vec![];
-
-        for trace in traces {
-            if trace.fn_name.is_some() {
-                let fn_name = trace.fn_name.clone().unwrap();
-                let fn_hits = match trace.stats {
-                    CoverageStat::Line(hits) => hits,
-                    _ => {
-                        return Err(RunError::Lcov(
-                            "Function doesn't have hits number".to_string(),
-                        ))
-                    }
-                };
-
-                fns.push(format!("FN:{},{}", trace.line, fn_name));
-                fnda.push(format!("FNDA:{},{}", fn_hits, fn_name));
-            }
+        writeln!(file, "SF:{}", source.path.to_str().unwrap())?;
 
-            match trace.stats {
-                CoverageStat::Line(hits) => da.push((trace.line, hits)),
-                _ => (),
-            };
+        let fns = source
+            .lines
+            .iter()
+            .filter_map(|(line, data)| data.function.as_ref().map(|name| (line, name, data.hits)))
+            .collect::<Vec<_>>();
+        for (line, name, _) in fns.iter() {
+            writeln!(file, "FN:{},{}", line, name)?;
         }
 
-        for fn_line in fns.iter() {
-            writeln!(file, "{}", fn_line)?;
+        for (_, name, hits) in fns.iter() {
+            writeln!(file, "FNDA:{},{}", hits, name)?;
         }
 
         writeln!(file, "FNF:{}", fns.len())?;
+        writeln!(
+            file,
+            "FNH:{}",
+            fns.iter().filter(|(_, _, hits)| *hits > 0).count()
+        )?;
 
-        for fnda_line in fnda {
-            writeln!(file, "{}", fnda_line)?;
+        // Each condition on a line is a pair of branches for its outcomes
+        let mut brda: Vec<(u32, usize, bool)> = vec![];
+        for (i, branch) in source.branches.iter().enumerate() {
+            let on_line = source.branches[..i]
+                .iter()
+                .filter(|b| b.line == branch.line)
+                .count();
+            brda.push((branch.line, 2 * on_line, branch.been_true));
+            brda.push((branch.line, 2 * on_line + 1, branch.been_false));
         }
 
-        for (line, hits) in da.iter() {
-            writeln!(file, "DA:{},{}", line, hits)?;
+        // BRDA:<line number>,<block number>,<branch number>,<taken>
+        for (line, branch, taken) in brda.iter() {
+            writeln!(
+                file,
+                "BRDA:{},0,{},{}",
+                line,
+                branch,
+                if *taken { "1" } else { "-" }
+            )?;
+        }
+        if !brda.is_empty() {
+            writeln!(file, "BRF:{}", brda.len())?;
+            writeln!(
+                file,
+                "BRH:{}",
+                brda.iter().filter(|(_, _, taken)| *taken).count()
+            )?;
         }
 
-        writeln!(file, "LF:{}", da.len())?;
-        writeln!(
-            file,
-            "LH:{}",
-            da.iter().filter(|(_, hits)| *hits != 0).count()
-        )?;
+        for (line, data) in source.lines.iter() {
+            writeln!(file, "DA:{},{}", line, data.hits)?;
+        }
 
-        // TODO: add support for branching
-        // BRDA (BRDA:<line number>,<block number>,<branch number>,<hits>)
-        // BRF (branches found)
-        // BRH (branches hit)
-        // More at http://ltp.sourceforge.net/coverage/lcov/geninfo.1.php
+        writeln!(file, "LF:{}", source.coverable())?;
+        writeln!(file, "LH:{}", source.covered())?;
 
         writeln!(file, "end_of_record")?;
     }
 
     Ok(())
 }
+
+/// Reads the coverage from an LCOV tracefile
+pub fn import(path: &Path) -> Result<TraceMap, RunError> {
+    let file = File::open(path)
+        .map_err(|e| RunError::Lcov(format!("Failed to open {}: {}", path.display(), e)))?;
+    read_tracefile(BufReader::new(file))
+}
+
+fn parse_number(record: &str, value: &str) -> Result<u64, RunError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| RunError::Lcov(format!("Invalid record {}", record)))
+}
+
+/// Coverage of a source file while its record is being read
+#[derive(Default)]
+struct SourceRecord {
+    path: PathBuf,
+    functions: HashMap<u64, String>,
+    lines: BTreeMap<u64, u64>,
+    branches: BTreeMap<u64, BTreeMap<u64, bool>>,
+}
+
+impl SourceRecord {
+    fn add_to(self, traces: &mut TraceMap) {
+        let trace = |line, stats| Trace {
+            line,
+            address: HashSet::new(),
+            length: 1,
+            stats,
+            fn_name: None,
+            mangled_name: None,
+        };
+        for (line, hits) in self.lines {
+            let mut line_trace = trace(line, CoverageStat::Line(hits));
+            line_trace.fn_name = self.functions.get(&line).cloned();
+            traces.add_trace(&self.path, line_trace);
+        }
+        for (line, branches) in self.branches {
+            // Branches are written as pairs of the true and false outcomes
+            let taken = branches.values().collect::<Vec<_>>();
+            let states = taken
+                .chunks(2)
+                .map(|pair| LogicState {
+                    been_true: *pair[0],
+                    been_false: pair.get(1).map(|t| **t).unwrap_or(false),
+                })
+                .collect::<Vec<_>>();
+            let stats = if states.len() == 1 {
+                CoverageStat::Branch(states[0])
+            } else {
+                CoverageStat::Condition(states)
+            };
+            traces.add_trace(&self.path, trace(line, stats));
+        }
+    }
+}
+
+/// Reads the `SF`, `FN`, `DA` and `BRDA` records of a tracefile, the summary
+/// records are ignored as they're recalculated from the traces
+pub(crate) fn read_tracefile<R: BufRead>(reader: R) -> Result<TraceMap, RunError> {
+    let mut traces = TraceMap::new();
+    let mut source: Option<SourceRecord> = None;
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        let (record, value) = match line.find(':') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => (line, ""),
+        };
+        if record == "SF" {
+            source = Some(SourceRecord {
+                path: PathBuf::from(value),
+                ..Default::default()
+            });
+            continue;
+        } else if record == "end_of_record" {
+            if let Some(source) = source.take() {
+                source.add_to(&mut traces);
+            }
+            continue;
+        }
+        let source = match source.as_mut() {
+            Some(source) => source,
+            None => continue,
+        };
+        // Function names can contain commas so only the first one is split on
+        let fields = if record == "FN" {
+            value.splitn(2, ',').collect::<Vec<_>>()
+        } else {
+            value.split(',').collect::<Vec<_>>()
+        };
+        match (record, fields.as_slice()) {
+            ("FN", [line_no, name]) => {
+                let line_no = parse_number(line, line_no)?;
+                source.functions.insert(line_no, name.to_string());
+            }
+            ("DA", [line_no, hits, ..]) => {
+                let line_no = parse_number(line, line_no)?;
+                *source.lines.entry(line_no).or_default() += parse_number(line, hits)?;
+            }
+            ("BRDA", [line_no, _, branch, taken]) => {
+                let line_no = parse_number(line, line_no)?;
+                let branch = parse_number(line, branch)?;
+                let taken = *taken != "-" && parse_number(line, taken)? > 0;
+                source
+                    .branches
+                    .entry(line_no)
+                    .or_default()
+                    .insert(branch, taken);
+            }
+            _ => {}
+        }
+    }
+    if let Some(source) = source {
+        source.add_to(&mut traces);
+    }
+    Ok(traces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{LogicState, Trace};
+    use std::path::Path;
+
+    #[test]
+    fn tags_as_comments() {
+        let mut tags = HashMap::new();
+        tags.insert("os".to_string(), "linux".to_string());
+        tags.insert("feature".to_string(), "async-backend".to_string());
+        let mut output = vec![];
+        write_tags(&tags, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "# feature=async-backend\n# os=linux\n"
+        );
+    }
+
+    #[test]
+    fn tracefile_records() {
+        let mut traces = TraceMap::new();
+        let path = Path::new("/project/src/lib.rs");
+        traces.add_trace(
+            path,
+            Trace {
+                fn_name: Some("foo".to_string()),
+                ..Trace::new_stub(1, CoverageStat::Line(2))
+            },
+        );
+        traces.add_trace(path, Trace::new_stub(2, CoverageStat::Line(2)));
+        traces.add_trace(
+            path,
+            Trace {
+                fn_name: Some("bar".to_string()),
+                ..Trace::new_stub(5, CoverageStat::Line(0))
+            },
+        );
+        let state = LogicState {
+            been_true: true,
+            been_false: false,
+        };
+        traces.add_trace(path, Trace::new_stub(2, CoverageStat::Branch(state)));
+
+        let mut output = vec![];
+        write_tracefile(&traces, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let records = output.lines().collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                "TN:",
+                "SF:/project/src/lib.rs",
+                "FN:1,foo",
+                "FN:5,bar",
+                "FNDA:2,foo",
+                "FNDA:0,bar",
+                "FNF:2",
+                "FNH:1",
+                "BRDA:2,0,0,1",
+                "BRDA:2,0,1,-",
+                "BRF:2",
+                "BRH:1",
+                "DA:1,2",
+                "DA:2,2",
+                "DA:5,0",
+                "LF:3",
+                "LH:2",
+                "end_of_record",
+            ]
+        );
+    }
+
+    #[test]
+    fn tracefile_round_trip() {
+        let mut traces = TraceMap::new();
+        let path = Path::new("/project/src/lib.rs");
+        traces.add_trace(
+            path,
+            Trace {
+                fn_name: Some("Map<K, V>::get".to_string()),
+                ..Trace::new_stub(1, CoverageStat::Line(2))
+            },
+        );
+        traces.add_trace(path, Trace::new_stub(2, CoverageStat::Line(0)));
+        let state = LogicState {
+            been_true: true,
+            been_false: false,
+        };
+        traces.add_trace(path, Trace::new_stub(2, CoverageStat::Branch(state)));
+        traces.add_trace(
+            path,
+            Trace::new_stub(3, CoverageStat::Condition(vec![state, state])),
+        );
+
+        let mut output = vec![];
+        write_tags(&HashMap::new(), &mut output).unwrap();
+        write_tracefile(&traces, &mut output).unwrap();
+        let read = read_tracefile(output.as_slice()).unwrap();
+        assert_eq!(read.files(), vec![path]);
+        let mut expected = traces.get_child_traces(path);
+        let mut actual = read.get_child_traces(path);
+        expected.sort_by_key(|t| (t.line, t.stats.to_string()));
+        actual.sort_by_key(|t| (t.line, t.stats.to_string()));
+        assert_eq!(actual, expected);
+
+        let invalid = "SF:/project/src/lib.rs\nDA:one,1\nend_of_record\n";
+        assert!(read_tracefile(invalid.as_bytes()).is_err());
+    }
+}
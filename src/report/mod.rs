@@ -1,17 +1,31 @@
 use crate::config::*;
 use crate::errors::*;
+use crate::report::source_cache::SourceCache;
 use crate::test_loader::TracerData;
 use crate::traces::*;
-use log::{error, info};
+use crate::TestType;
+use log::{error, info, warn};
 use serde::Serialize;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::fs::{create_dir_all, File};
 use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
+pub mod badge;
 pub mod cobertura;
+pub mod codecov;
 pub mod coveralls;
+pub mod github;
 pub mod html;
+pub mod json;
 pub mod lcov;
+mod pgo;
 mod safe_json;
+pub mod sonarqube;
+pub mod source_cache;
 /// Trait for report formats to implement.
 /// Currently reports must be serializable using serde
 pub trait Report<Out: Serialize> {
@@ -24,11 +38,34 @@ pub trait Report<Out: Serialize> {
 pub fn report_coverage(config: &Config, result: &TraceMap) -> Result<(), RunError> {
     if !result.is_empty() {
         info!("Coverage Results:");
-        if config.verbose {
-            print_missing_lines(config, result);
+        if config.function_coverage_only {
+            print_function_summary(config, result);
+        } else {
+            if config.verbose {
+                print_missing_lines(config, result);
+            }
+            print_summary(config, result);
+            if config.report_line_count {
+                print_line_counts(config, result);
+            }
+            if config.include_tests {
+                print_test_code_summary(result);
+            }
+            if config.function_coverage {
+                print_function_summary(config, result);
+            }
+        }
+        let mut cache = SourceCache::new(config.source_cache);
+        if let Some(ref file) = config.print_coverage_for {
+            print_file_coverage(config, result, file, &mut cache);
+        }
+        let mapped = map_paths(config, result);
+        generate_requested_reports(config, result, &mapped, &mut cache)?;
+        github::write_step_summary(config, &mapped)?;
+        run_report_hook(config, result)?;
+        if let Some(ref profile) = config.pgo_profile {
+            pgo::check_profile(profile, result)?;
         }
-        print_summary(config, result);
-        generate_requested_reports(config, result)?;
         if let Some(project_dir) = config.manifest.parent() {
             let mut report_dir = project_dir.join("target");
             report_dir.push("tarpaulin");
@@ -48,31 +85,232 @@ pub fn report_coverage(config: &Config, result: &TraceMap) -> Result<(), RunErro
     }
 }
 
-fn generate_requested_reports(config: &Config, result: &TraceMap) -> Result<(), RunError> {
+/// Checks the coverage against the minimum coverage set in the config,
+/// returning a description of the failure if it's below the minimum
+pub fn check_minimum_coverage(config: &Config, result: &TraceMap) -> Option<String> {
+    let minimum = config.minimum_coverage?;
+    let percent = result.coverage_percentage() * 100.0f64;
+    if percent < minimum {
+        Some(format!(
+            "{}{:.2}% coverage is below the minimum of {:.2}%",
+            config_prefix(config),
+            percent,
+            minimum
+        ))
+    } else {
+        None
+    }
+}
+
+/// Checks the line and branch coverage against their own minimums, returning
+/// a description of each metric below its minimum
+pub fn check_metric_thresholds(config: &Config, result: &TraceMap) -> Vec<String> {
+    let metrics = [
+        (
+            "line",
+            config.minimum_line_coverage,
+            result.line_coverage_percentage(),
+        ),
+        (
+            "branch",
+            config.minimum_branch_coverage,
+            result.branch_coverage_percentage(),
+        ),
+    ];
+    let mut failures = vec![];
+    for (metric, minimum, percent) in metrics.iter() {
+        let minimum = match minimum {
+            Some(minimum) => *minimum,
+            None => continue,
+        };
+        let percent = percent * 100.0f64;
+        if percent.is_nan() {
+            warn!(
+                "No {} coverage was collected to check fail-under-{}",
+                metric, metric
+            );
+        } else if percent < minimum {
+            failures.push(format!(
+                "{}{:.2}% {} coverage is below the minimum of {:.2}%",
+                config_prefix(config),
+                percent,
+                metric,
+                minimum
+            ));
+        }
+    }
+    failures
+}
+
+/// Prefix for messages about a named config
+fn config_prefix(config: &Config) -> String {
+    if config.name.is_empty() {
+        String::new()
+    } else {
+        format!("{}: ", config.name)
+    }
+}
+
+/// Checks the coverage of each file against the most specific matching file
+/// threshold, the longest pattern is taken as the most specific. Returns a
+/// description of each file below its threshold.
+pub fn check_file_thresholds(config: &Config, result: &TraceMap) -> Vec<String> {
+    let mut thresholds = config
+        .file_thresholds
+        .iter()
+        // Invalid patterns are reported when the config is validated
+        .filter_map(|(pattern, threshold)| {
+            anchored_wildcard_regex(pattern)
+                .ok()
+                .map(|re| (pattern.len(), re, *threshold))
+        })
+        .collect::<Vec<_>>();
+    thresholds.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let mut failures = vec![];
+    for file in result.files() {
+        let path = config.strip_base_dir(file);
+        let name = path.to_string_lossy();
+        let coverable = result.coverable_in_path(file);
+        let threshold = thresholds.iter().find(|(_, re, _)| re.is_match(&name));
+        if let Some((_, _, threshold)) = threshold.filter(|_| coverable > 0) {
+            let percent = 100.0f64 * result.covered_in_path(file) as f64 / coverable as f64;
+            if percent < *threshold {
+                let failure = format!(
+                    "{}: {:.2}% coverage is below the file threshold of {:.2}%",
+                    name, percent, threshold
+                );
+                error!("{}", failure);
+                failures.push(failure);
+            }
+        }
+    }
+    failures
+}
+
+/// Prints the coverage of each type of test and checks it against the minimum
+/// coverage set for that type. The breakdown is also saved as json alongside
+/// the run report.
+pub fn report_coverage_by_type(
+    config: &Config,
+    by_type: &BTreeMap<TestType, TraceMap>,
+    overall: &TraceMap,
+) -> Result<(), RunError> {
+    let mut summary = BTreeMap::new();
+    let mut failures = vec![];
+    println!("|| Coverage by test type:");
+    for (test_type, traces) in by_type.iter() {
+        let percent = traces.coverage_percentage() * 100.0f64;
+        println!("|| {}: {:.2}%", test_type, percent);
+        summary.insert(test_type.to_string(), percent);
+        let minimum = match test_type {
+            TestType::Unit => config.min_unit_coverage,
+            TestType::Integration => config.min_integration_coverage,
+            TestType::Doctest => config.min_doctest_coverage,
+        };
+        if let Some(minimum) = minimum {
+            if percent < minimum {
+                failures.push(format!(
+                    "{} coverage {:.2}% is below {:.2}%",
+                    test_type, percent, minimum
+                ));
+            }
+        }
+    }
+    let percent = overall.coverage_percentage() * 100.0f64;
+    println!("|| Overall: {:.2}%", percent);
+    summary.insert("Overall".to_string(), percent);
+
+    if let Some(project_dir) = config.manifest.parent() {
+        let report_dir = project_dir.join("target").join("tarpaulin");
+        let _ = create_dir_all(&report_dir);
+        let file = File::create(report_dir.join("coverage-by-type.json"))
+            .map_err(|_| RunError::CovReport("Failed to create test type report".to_string()))?;
+        serde_json::to_writer(&file, &summary)
+            .map_err(|_| RunError::CovReport("Failed to save test type report".to_string()))?;
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(RunError::BelowMinimumCoverage(failures.join(", ")))
+    }
+}
+
+/// The results with the strip prefixes and path mappings of the config
+/// applied
+fn map_paths<'a>(config: &Config, result: &'a TraceMap) -> Cow<'a, TraceMap> {
+    if config.path_mappings.is_empty() && config.strip_prefixes.is_empty() {
+        return Cow::Borrowed(result);
+    }
+    let mut mapped = TraceMap::new();
+    for (path, traces) in result.iter() {
+        let mapped_path = config.map_path(path);
+        for trace in traces {
+            if result.is_test_line(path, trace.line) {
+                mapped.mark_test_line(&mapped_path, trace.line);
+            }
+            mapped.add_trace(&mapped_path, trace.clone());
+        }
+    }
+    Cow::Owned(mapped)
+}
+
+/// Writes the reports to the mapped paths, reports which include the source
+/// read it from the unmapped results and map the paths themselves
+fn generate_requested_reports(
+    config: &Config,
+    result: &TraceMap,
+    mapped: &TraceMap,
+    cache: &mut SourceCache,
+) -> Result<(), RunError> {
     if config.is_coveralls() {
         coveralls::export(result, config)?;
         info!("Coverage data sent");
     }
+    if config.is_codecov() {
+        codecov::export(mapped, config)?;
+        info!("Coverage data sent to codecov");
+    }
+
+    if let Some(parent) = config.output_file.as_ref().and_then(|f| f.parent()) {
+        if !parent.as_os_str().is_empty() && create_dir_all(parent).is_err() {
+            return Err(RunError::OutFormat(format!(
+                "Failed to create or locate output file directory: {:?}",
+                parent,
+            )));
+        }
+    }
 
-    if !config.is_default_output_dir() {
-        if create_dir_all(&config.output_directory).is_err() {
+    let report_dir = config.report_directory();
+    if !config.is_default_output_dir() || report_dir != config.output_directory {
+        if create_dir_all(&report_dir).is_err() {
             return Err(RunError::OutFormat(format!(
                 "Failed to create or locate custom output directory: {:?}",
-                config.output_directory,
+                report_dir,
             )));
         }
     }
 
-    for g in &config.generate {
+    for g in &config.all_output_formats() {
         match *g {
             OutputFile::Xml => {
-                cobertura::report(result, config).map_err(|e| RunError::XML(e))?;
+                cobertura::report(mapped, config).map_err(|e| RunError::XML(e))?;
             }
             OutputFile::Html => {
-                html::export(result, config)?;
+                html::export(result, config, cache)?;
             }
             OutputFile::Lcov => {
-                lcov::export(result, config)?;
+                lcov::export(mapped, config)?;
+            }
+            OutputFile::Json => {
+                json::export(mapped, config)?;
+            }
+            OutputFile::SonarQube => {
+                sonarqube::export(mapped, config)?;
+            }
+            OutputFile::Badges => {
+                badge::export(mapped, config)?;
             }
             _ => {
                 return Err(RunError::OutFormat(
@@ -84,6 +322,47 @@ fn generate_requested_reports(config: &Config, result: &TraceMap) -> Result<(),
     Ok(())
 }
 
+/// Runs the user provided hook after all reports are written. Coverage
+/// information and report locations are passed to it via environment variables
+fn run_report_hook(config: &Config, result: &TraceMap) -> Result<(), RunError> {
+    let hook = match config.coverage_report_hook {
+        Some(ref hook) => hook,
+        None => return Ok(()),
+    };
+    info!("Running coverage report hook {}", hook.display());
+    let mut command = Command::new(hook);
+    command
+        .env(
+            "TARPAULIN_COVERAGE",
+            format!("{:.2}", result.coverage_percentage() * 100.0f64),
+        )
+        .env("TARPAULIN_COVERED", result.total_covered().to_string())
+        .env("TARPAULIN_COVERABLE", result.total_coverable().to_string());
+    for g in &config.all_output_formats() {
+        let var = match *g {
+            OutputFile::Html => "TARPAULIN_HTML_REPORT",
+            OutputFile::Xml => "TARPAULIN_XML_REPORT",
+            OutputFile::Lcov => "TARPAULIN_LCOV_REPORT",
+            OutputFile::Json => "TARPAULIN_JSON_REPORT",
+            OutputFile::SonarQube => "TARPAULIN_SONARQUBE_REPORT",
+            OutputFile::Badges => "TARPAULIN_BADGE_REPORT",
+            _ => continue,
+        };
+        command.env(var, config.output_path(g));
+    }
+    let status = command
+        .status()
+        .map_err(|e| RunError::ReportHook(format!("Failed to run hook: {}", e)))?;
+    if status.success() {
+        Ok(())
+    } else if config.hook_fails_build {
+        Err(RunError::ReportHook(format!("Hook exited with {}", status)))
+    } else {
+        warn!("Coverage report hook exited with {}", status);
+        Ok(())
+    }
+}
+
 fn print_missing_lines(config: &Config, result: &TraceMap) {
     println!("|| Uncovered Lines:");
     for (ref key, ref value) in result.iter() {
@@ -108,6 +387,85 @@ fn print_missing_lines(config: &Config, result: &TraceMap) {
     }
 }
 
+/// Prints the source of a file annotated with the hits of each line in the
+/// style of gcov
+fn print_file_coverage(config: &Config, result: &TraceMap, file: &Path, cache: &mut SourceCache) {
+    let path = if file.is_absolute() {
+        file.to_path_buf()
+    } else {
+        config.get_base_dir().join(file)
+    };
+    match cache.get(&path) {
+        Ok(source) if result.contains_file(&path) => {
+            print!(
+                "{}",
+                annotate_source(source, &result.get_child_traces(&path), Annotation::Gcov)
+            );
+        }
+        _ => println!("no coverage data for {}.", file.display()),
+    }
+}
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const GRAY: &str = "\x1b[90m";
+const RESET: &str = "\x1b[0m";
+
+/// How `annotate_source` marks the coverage of each line
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Annotation {
+    /// The hit count and line number in the style of gcov, uncovered lines
+    /// are marked with `#####` and lines which aren't coverable with `-`
+    Gcov,
+    /// The line number with `+` before covered lines, `-` before uncovered
+    /// lines and a space before lines which aren't coverable
+    Markers { color: bool },
+}
+
+/// Prefixes each line of the source with its coverage
+pub(crate) fn annotate_source(source: &str, traces: &[&Trace], annotation: Annotation) -> String {
+    let lines = source.lines().collect::<Vec<_>>();
+    let width = lines.len().to_string().len();
+    let mut annotated = String::new();
+    for (i, text) in lines.iter().enumerate() {
+        let line = i as u64 + 1;
+        let hits = traces
+            .iter()
+            .filter(|t| t.line == line)
+            .filter_map(|t| match t.stats {
+                CoverageStat::Line(hits) => Some(hits),
+                _ => None,
+            })
+            .fold(None, |acc, hits| Some(acc.unwrap_or(0) + hits));
+        let prefix = match annotation {
+            Annotation::Gcov => {
+                let count = match hits {
+                    Some(0) => "#####".to_string(),
+                    Some(hits) => hits.to_string(),
+                    None => "-".to_string(),
+                };
+                format!("{:>9}:{:>5}:", count, line)
+            }
+            Annotation::Markers { color } => {
+                let (marker, style) = match hits {
+                    Some(0) => ('-', RED),
+                    Some(_) => ('+', GREEN),
+                    None => (' ', GRAY),
+                };
+                if color {
+                    format!("{}{} {:>w$}{} ", style, marker, line, RESET, w = width)
+                } else {
+                    format!("{} {:>w$} ", marker, line, w = width)
+                }
+            }
+        };
+        annotated.push_str(&prefix);
+        annotated.push_str(text);
+        annotated.push('\n');
+    }
+    annotated
+}
+
 fn get_previous_result(config: &Config) -> Option<TraceMap> {
     // Check for previous report
     if let Some(project_dir) = config.manifest.parent() {
@@ -178,6 +536,101 @@ fn print_summary(config: &Config, result: &TraceMap) {
     }
 }
 
+/// Covered and coverable lines of each file with coverable lines sorted by
+/// coverage percentage, worst first and limited to the top n files
+fn line_counts(config: &Config, result: &TraceMap) -> Vec<(PathBuf, usize, usize, f64)> {
+    let mut counts = result
+        .files()
+        .into_iter()
+        .map(|file| {
+            let covered = result.covered_in_path(file);
+            let coverable = result.coverable_in_path(file);
+            (file.clone(), covered, coverable)
+        })
+        .filter(|(_, _, coverable)| *coverable > 0)
+        .map(|(file, covered, coverable)| {
+            let percent = 100.0f64 * covered as f64 / coverable as f64;
+            (file, covered, coverable, percent)
+        })
+        .collect::<Vec<_>>();
+    counts.sort_by(|a, b| a.3.partial_cmp(&b.3).unwrap_or(Ordering::Equal));
+    if let Some(n) = config.top_n {
+        counts.truncate(n);
+    }
+    counts
+}
+
+fn print_line_counts(config: &Config, result: &TraceMap) {
+    println!("|| Line counts, worst coverage first:");
+    println!(
+        "|| {:>8} {:>9} {:>8}  File",
+        "Covered", "Coverable", "Percent"
+    );
+    for (file, covered, coverable, percent) in line_counts(config, result) {
+        println!(
+            "|| {:>8} {:>9} {:>7.2}%  {}",
+            covered,
+            coverable,
+            percent,
+            config.strip_base_dir(&file).display()
+        );
+    }
+}
+
+/// Prints the coverage of test code and the code under test separately
+fn print_test_code_summary(result: &TraceMap) {
+    let all = Path::new("");
+    println!(
+        "|| {:.2}% production code coverage, {}/{} lines covered",
+        100.0f64 * result.production_coverage_percentage(),
+        result.production_covered_in_path(all),
+        result.production_coverable_in_path(all)
+    );
+    if result.has_test_lines() {
+        println!(
+            "|| {:.2}% test code coverage, {}/{} lines covered",
+            100.0f64 * result.test_coverage_percentage(),
+            result.test_covered_in_path(all),
+            result.test_coverable_in_path(all)
+        );
+    } else {
+        println!("|| No test code found");
+    }
+}
+
+/// Maps the name of each function in the traces to whether it was called
+fn function_coverage(result: &TraceMap) -> BTreeMap<String, bool> {
+    let mut functions = BTreeMap::new();
+    for trace in result.all_traces() {
+        if let (Some(name), CoverageStat::Line(hits)) = (&trace.fn_name, &trace.stats) {
+            *functions.entry(name.clone()).or_insert(false) |= *hits > 0;
+        }
+    }
+    functions
+}
+
+fn print_function_summary(config: &Config, result: &TraceMap) {
+    let functions = function_coverage(result);
+    if config.verbose {
+        println!("|| Uncalled Functions:");
+        for (name, _) in functions.iter().filter(|(_, called)| !**called) {
+            println!("|| {}", name);
+        }
+    }
+    let called = functions.values().filter(|c| **c).count();
+    let percent = if functions.is_empty() {
+        0.0f64
+    } else {
+        100.0f64 * called as f64 / functions.len() as f64
+    };
+    println!(
+        "|| \n{:.2}% function coverage, {}/{} functions called",
+        percent,
+        called,
+        functions.len()
+    );
+}
+
 fn accumulate_lines(
     (mut acc, mut group): (Vec<String>, Vec<u64>),
     next: u64,
@@ -203,3 +656,267 @@ fn accumulate_lines(
         (acc, group)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimum_coverage_threshold() {
+        let mut result = TraceMap::new();
+        result.add_trace(
+            Path::new("src/lib.rs"),
+            Trace {
+                fn_name: Some("foo".to_string()),
+                ..Trace::new_stub(1, CoverageStat::Line(1))
+            },
+        );
+        result.add_trace(
+            Path::new("src/lib.rs"),
+            Trace {
+                fn_name: Some("foo".to_string()),
+                ..Trace::new_stub(2, CoverageStat::Line(0))
+            },
+        );
+
+        let mut config = Config::default();
+        assert_eq!(check_minimum_coverage(&config, &result), None);
+        config.minimum_coverage = Some(0.0);
+        assert_eq!(check_minimum_coverage(&config, &result), None);
+        config.minimum_coverage = Some(50.0);
+        assert_eq!(check_minimum_coverage(&config, &result), None);
+        config.minimum_coverage = Some(100.0);
+        config.name = "unit".to_string();
+        assert_eq!(
+            check_minimum_coverage(&config, &result),
+            Some("unit: 50.00% coverage is below the minimum of 100.00%".to_string())
+        );
+    }
+
+    #[test]
+    fn metric_coverage_thresholds() {
+        let mut result = TraceMap::new();
+        result.add_trace(
+            Path::new("src/lib.rs"),
+            Trace {
+                fn_name: Some("foo".to_string()),
+                ..Trace::new_stub(1, CoverageStat::Line(1))
+            },
+        );
+        result.add_trace(
+            Path::new("src/lib.rs"),
+            Trace {
+                fn_name: Some("foo".to_string()),
+                ..Trace::new_stub(2, CoverageStat::Line(1))
+            },
+        );
+        let state = LogicState {
+            been_true: true,
+            been_false: false,
+        };
+        let mut branch = Trace {
+            fn_name: Some("foo".to_string()),
+            ..Trace::new_stub(2, CoverageStat::Line(0))
+        };
+        branch.stats = CoverageStat::Branch(state);
+        result.add_trace(Path::new("src/lib.rs"), branch);
+
+        let mut config = Config::default();
+        assert!(check_metric_thresholds(&config, &result).is_empty());
+        config.minimum_line_coverage = Some(100.0);
+        config.minimum_branch_coverage = Some(50.0);
+        assert!(check_metric_thresholds(&config, &result).is_empty());
+        config.minimum_coverage = Some(100.0);
+        config.minimum_branch_coverage = Some(75.0);
+        assert_eq!(
+            check_metric_thresholds(&config, &result),
+            vec!["50.00% branch coverage is below the minimum of 75.00%".to_string()]
+        );
+        assert!(check_minimum_coverage(&config, &result).is_some());
+
+        let mut lines = TraceMap::new();
+        lines.add_trace(
+            Path::new("src/lib.rs"),
+            Trace {
+                fn_name: Some("foo".to_string()),
+                ..Trace::new_stub(1, CoverageStat::Line(0))
+            },
+        );
+        assert_eq!(
+            check_metric_thresholds(&config, &lines),
+            vec!["0.00% line coverage is below the minimum of 100.00%".to_string()]
+        );
+    }
+
+    #[test]
+    fn file_coverage_thresholds() {
+        let base = std::env::current_dir().unwrap();
+        let mut result = TraceMap::new();
+        let auth = base.join("src/auth.rs");
+        let gen = base.join("src/gen.rs");
+        for line in 1..=10 {
+            result.add_trace(
+                &auth,
+                Trace {
+                    fn_name: Some("auth".to_string()),
+                    ..Trace::new_stub(line, CoverageStat::Line((line < 10) as u64))
+                },
+            );
+            result.add_trace(
+                &gen,
+                Trace {
+                    fn_name: Some("gen".to_string()),
+                    ..Trace::new_stub(line, CoverageStat::Line((line < 7) as u64))
+                },
+            );
+        }
+
+        let mut config = Config::default();
+        assert!(check_file_thresholds(&config, &result).is_empty());
+        config.file_thresholds.insert("src/*.rs".to_string(), 50.0);
+        config.file_thresholds.insert("src/auth*".to_string(), 95.0);
+        // Patterns match the whole path so this doesn't match src/gen.rs
+        config.file_thresholds.insert("src/gen.r".to_string(), 99.0);
+        assert_eq!(
+            check_file_thresholds(&config, &result),
+            vec!["src/auth.rs: 90.00% coverage is below the file threshold of 95.00%"]
+        );
+    }
+
+    #[test]
+    fn annotated_source_output() {
+        let source = "fn main() {\n    foo();\n    bar();\n}";
+        let traces = vec![
+            Trace {
+                fn_name: Some("main".to_string()),
+                ..Trace::new_stub(1, CoverageStat::Line(1))
+            },
+            Trace {
+                fn_name: Some("main".to_string()),
+                ..Trace::new_stub(2, CoverageStat::Line(1))
+            },
+            Trace {
+                fn_name: Some("main".to_string()),
+                ..Trace::new_stub(2, CoverageStat::Line(1))
+            },
+            Trace {
+                fn_name: Some("main".to_string()),
+                ..Trace::new_stub(3, CoverageStat::Line(0))
+            },
+        ];
+
+        let traces = traces.iter().collect::<Vec<_>>();
+        let annotated = annotate_source(source, &traces, Annotation::Gcov);
+        let expected = [
+            "        1:    1:fn main() {",
+            "        2:    2:    foo();",
+            "    #####:    3:    bar();",
+            "        -:    4:}",
+        ];
+        assert_eq!(annotated.lines().collect::<Vec<_>>(), expected);
+
+        let markers = Annotation::Markers { color: false };
+        assert_eq!(
+            annotate_source(source, &traces, markers),
+            "+ 1 fn main() {\n+ 2     foo();\n- 3     bar();\n  4 }\n"
+        );
+        let markers = Annotation::Markers { color: true };
+        assert_eq!(
+            annotate_source("}\n", &traces, markers),
+            format!("{}+ 1{} }}\n", GREEN, RESET)
+        );
+        assert_eq!(
+            annotate_source("}\n", &traces[3..], markers),
+            format!("{}  1{} }}\n", GRAY, RESET)
+        );
+    }
+
+    #[test]
+    fn function_only_report() {
+        let mut result = TraceMap::new();
+        result.add_trace(
+            Path::new("src/lib.rs"),
+            Trace {
+                fn_name: Some("called".to_string()),
+                ..Trace::new_stub(1, CoverageStat::Line(3))
+            },
+        );
+        result.add_trace(
+            Path::new("src/lib.rs"),
+            Trace {
+                fn_name: Some("uncalled".to_string()),
+                ..Trace::new_stub(8, CoverageStat::Line(0))
+            },
+        );
+        result.add_trace(
+            Path::new("src/main.rs"),
+            Trace {
+                fn_name: Some("main".to_string()),
+                ..Trace::new_stub(2, CoverageStat::Line(1))
+            },
+        );
+
+        let functions = function_coverage(&result);
+        assert_eq!(functions.len(), 3);
+        assert!(functions["called"]);
+        assert!(!functions["uncalled"]);
+        assert!(functions["main"]);
+    }
+
+    #[test]
+    fn line_counts_worst_first() {
+        let mut result = TraceMap::new();
+        result.add_trace(
+            Path::new("src/good.rs"),
+            Trace {
+                fn_name: Some("a".to_string()),
+                ..Trace::new_stub(1, CoverageStat::Line(1))
+            },
+        );
+        result.add_trace(
+            Path::new("src/bad.rs"),
+            Trace {
+                fn_name: Some("b".to_string()),
+                ..Trace::new_stub(1, CoverageStat::Line(0))
+            },
+        );
+        result.add_trace(
+            Path::new("src/bad.rs"),
+            Trace {
+                fn_name: Some("c".to_string()),
+                ..Trace::new_stub(2, CoverageStat::Line(0))
+            },
+        );
+        result.add_trace(
+            Path::new("src/half.rs"),
+            Trace {
+                fn_name: Some("d".to_string()),
+                ..Trace::new_stub(1, CoverageStat::Line(1))
+            },
+        );
+        result.add_trace(
+            Path::new("src/half.rs"),
+            Trace {
+                fn_name: Some("e".to_string()),
+                ..Trace::new_stub(2, CoverageStat::Line(0))
+            },
+        );
+
+        let mut config = Config::default();
+        let counts = line_counts(&config, &result);
+        let files = counts.iter().map(|c| c.0.as_path()).collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            vec![
+                Path::new("src/bad.rs"),
+                Path::new("src/half.rs"),
+                Path::new("src/good.rs")
+            ]
+        );
+        assert_eq!((counts[1].1, counts[1].2), (1, 2));
+        assert_eq!(counts[1].3, 50.0);
+
+        config.top_n = Some(1);
+        assert_eq!(line_counts(&config, &result).len(), 1);
+    }
+}
@@ -0,0 +1,182 @@
+//! Cross references the collected coverage with the function counts in a PGO
+//! profile. Functions one of them saw called and the other didn't point to
+//! coverage tarpaulin may have got wrong.
+use crate::errors::RunError;
+use crate::traces::{CoverageStat, TraceMap};
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+/// A function where tarpaulin and the PGO profile disagree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Discrepancy {
+    /// Function was hit in the coverage but never entered in the profile
+    NotInProfile(String),
+    /// Function was entered in the profile but not hit in the coverage
+    NotInCoverage(String),
+}
+
+/// Reads the profile and warns about every function where it disagrees with
+/// the coverage results
+pub fn check_profile(profile: &Path, result: &TraceMap) -> Result<(), RunError> {
+    info!(
+        "Comparing coverage against PGO profile {}",
+        profile.display()
+    );
+    let counts = read_profile(profile)?;
+    let discrepancies = find_discrepancies(&counts, result);
+    for d in &discrepancies {
+        match d {
+            Discrepancy::NotInProfile(name) => {
+                warn!("{} is covered but was never called in the profile", name)
+            }
+            Discrepancy::NotInCoverage(name) => {
+                warn!("{} was called in the profile but isn't covered", name)
+            }
+        }
+    }
+    info!(
+        "Found {} discrepancies between coverage and PGO profile",
+        discrepancies.len()
+    );
+    Ok(())
+}
+
+/// Uses `llvm-profdata` to get the entry count of each function in a
+/// `.profraw` or `.profdata` file
+fn read_profile(profile: &Path) -> Result<BTreeMap<String, u64>, RunError> {
+    let output = Command::new("llvm-profdata")
+        .args(&["show", "--all-functions", "--counts"])
+        .arg(profile)
+        .output()
+        .map_err(|e| RunError::PgoProfile(format!("Failed to run llvm-profdata: {}", e)))?;
+    if output.status.success() {
+        Ok(parse_profile(&String::from_utf8_lossy(&output.stdout)))
+    } else {
+        Err(RunError::PgoProfile(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ))
+    }
+}
+
+/// Parses the output of `llvm-profdata show --all-functions` into the entry
+/// count of each mangled function name
+fn parse_profile(text: &str) -> BTreeMap<String, u64> {
+    let mut counts = BTreeMap::new();
+    let mut current = None;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if line.starts_with("  ") && !line.starts_with("    ") && trimmed.ends_with(':') {
+            // Local symbols are prefixed with the name of their source file
+            let name = trimmed.trim_end_matches(':');
+            current = name.rsplit(':').next().map(ToString::to_string);
+        } else if trimmed.starts_with("Function count:") {
+            let count = trimmed
+                .trim_start_matches("Function count:")
+                .trim()
+                .parse()
+                .ok();
+            if let (Some(name), Some(count)) = (current.take(), count) {
+                counts.insert(name, count);
+            }
+        }
+    }
+    counts
+}
+
+/// Compares functions present in both the profile and the coverage results
+fn find_discrepancies(counts: &BTreeMap<String, u64>, result: &TraceMap) -> Vec<Discrepancy> {
+    let mut called = BTreeMap::new();
+    for trace in result.all_traces() {
+        if let (Some(name), CoverageStat::Line(hits)) = (&trace.mangled_name, &trace.stats) {
+            *called.entry(name.as_str()).or_insert(false) |= *hits > 0;
+        }
+    }
+    let mut discrepancies = vec![];
+    for (name, covered) in called {
+        match counts.get(name) {
+            Some(0) if covered => discrepancies.push(Discrepancy::NotInProfile(name.to_string())),
+            Some(n) if *n > 0 && !covered => {
+                discrepancies.push(Discrepancy::NotInCoverage(name.to_string()))
+            }
+            _ => {}
+        }
+    }
+    discrepancies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::Trace;
+
+    const PROFILE: &str = "Counters:
+  _ZN7project3foo17h0123456789abcdefE:
+    Hash: 0x0000000000000001
+    Counters: 1
+    Function count: 4
+  lib.rs:_ZN7project3bar17h0123456789abcdefE:
+    Hash: 0x0000000000000002
+    Counters: 1
+    Function count: 0
+  _ZN7project3baz17h0123456789abcdefE:
+    Hash: 0x0000000000000003
+    Counters: 1
+    Function count: 2
+Instrumentation level: IR
+Functions shown: 3
+";
+
+    #[test]
+    fn parse_function_counts() {
+        let counts = parse_profile(PROFILE);
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts["_ZN7project3foo17h0123456789abcdefE"], 4);
+        assert_eq!(counts["_ZN7project3bar17h0123456789abcdefE"], 0);
+        assert_eq!(counts["_ZN7project3baz17h0123456789abcdefE"], 2);
+    }
+
+    #[test]
+    fn coverage_disagreeing_with_profile() {
+        let mut result = TraceMap::new();
+        let path = Path::new("src/lib.rs");
+        result.add_trace(
+            path,
+            Trace {
+                mangled_name: Some("_ZN7project3foo17h0123456789abcdefE".to_string()),
+                ..Trace::new_stub(1, CoverageStat::Line(1))
+            },
+        );
+        result.add_trace(
+            path,
+            Trace {
+                mangled_name: Some("_ZN7project3bar17h0123456789abcdefE".to_string()),
+                ..Trace::new_stub(5, CoverageStat::Line(1))
+            },
+        );
+        result.add_trace(
+            path,
+            Trace {
+                mangled_name: Some("_ZN7project3baz17h0123456789abcdefE".to_string()),
+                ..Trace::new_stub(9, CoverageStat::Line(0))
+            },
+        );
+        result.add_trace(
+            path,
+            Trace {
+                mangled_name: Some("_ZN7project3qux17h0123456789abcdefE".to_string()),
+                ..Trace::new_stub(12, CoverageStat::Line(0))
+            },
+        );
+
+        let discrepancies = find_discrepancies(&parse_profile(PROFILE), &result);
+        assert_eq!(
+            discrepancies,
+            vec![
+                Discrepancy::NotInProfile("_ZN7project3bar17h0123456789abcdefE".to_string()),
+                Discrepancy::NotInCoverage("_ZN7project3baz17h0123456789abcdefE".to_string()),
+            ]
+        );
+    }
+}
@@ -1,4 +1,41 @@
-const e = React.createElement;
+const Fragment = 'fragment';
+
+// Builds DOM nodes with the signature of React.createElement, the report is a
+// single file so it can't load a UI library from a CDN
+function e(type, props, ...children) {
+  if (typeof type === 'function') {
+    return type({...props, children});
+  }
+  const node = type === Fragment ? document.createDocumentFragment() : document.createElement(type);
+  for (const [key, value] of Object.entries(props || {})) {
+    if (value === null || value === undefined || value === false) {
+      continue;
+    }
+    if (key === 'className') {
+      node.className = value;
+    } else if (key.startsWith('on')) {
+      node.addEventListener(key.slice(2).toLowerCase(), value);
+    } else {
+      node.setAttribute(key, value);
+    }
+  }
+  appendChildren(node, children);
+  return node;
+}
+
+function appendChildren(node, children) {
+  for (const child of children) {
+    if (child === null || child === undefined || child === false) {
+      continue;
+    } else if (Array.isArray(child)) {
+      appendChildren(node, child);
+    } else if (child instanceof Node) {
+      node.appendChild(child);
+    } else {
+      node.appendChild(document.createTextNode(String(child)));
+    }
+  }
+}
 
 function pathToString(path) {
   if (path[0] === '/') {
@@ -35,6 +72,31 @@ function findCommonPath(files) {
   return commonPath;
 }
 
+const showFunctions = data.files.some(file => file.total_functions != null);
+const showTests = data.files.some(file => file.test_coverable != null);
+
+function sumOptionalStats(files) {
+  const sum = key => files.reduce((total, file) => total + (file[key] || 0), 0);
+  return {
+    called_functions: sum('called_functions'),
+    total_functions: sum('total_functions'),
+    test_covered: sum('test_covered'),
+    test_coverable: sum('test_coverable'),
+  };
+}
+
+function testCoverage(file) {
+  const coverage = file.test_coverable ? file.test_covered / file.test_coverable * 100 : -1;
+  return file.test_covered + ' / ' + file.test_coverable +
+    (coverage >= 0 ? ' (' + coverage.toFixed(2) + '%)' : '');
+}
+
+function functionCoverage(file) {
+  const coverage = file.total_functions ? file.called_functions / file.total_functions * 100 : -1;
+  return file.called_functions + ' / ' + file.total_functions +
+    (coverage >= 0 ? ' (' + coverage.toFixed(2) + '%)' : '');
+}
+
 function findFolders(files) {
   if (!files || !files.length) {
     return [];
@@ -62,6 +124,7 @@ function findFolders(files) {
       children,
       covered: children.reduce((sum, file) => sum + file.covered, 0),
       coverable: children.reduce((sum, file) => sum + file.coverable, 0),
+      ...sumOptionalStats(children),
       prevRun: {
         covered: children.reduce((sum, file) => sum + file.prevRun.covered, 0),
         coverable: children.reduce((sum, file) => sum + file.prevRun.coverable, 0),
@@ -75,82 +138,93 @@ function findFolders(files) {
   ];
 }
 
-class App extends React.Component {
-  constructor(...args) {
-    super(...args);
+let commonPath = [];
+// Folders closed in the file tree, kept between renders
+const collapsedFolders = new Set();
 
-    this.state = {
-      current: [],
-    };
-  }
-
-  componentDidMount() {
-    this.updateStateFromLocation();
-    window.addEventListener("hashchange", () => this.updateStateFromLocation(), false);
-  }
-
-  updateStateFromLocation() {
-    if (window.location.hash.length > 1) {
-      const current = window.location.hash.substr(1).split('/');
-      this.setState({current});
-    } else {
-      this.setState({current: []});
-    }
-  }
+// Location hash of a file or folder, its path below the common path
+function hashOf(file) {
+  return [...file.parent, ...file.path].slice(commonPath.length).join('/');
+}
 
-  getCurrentPath() {
-    let file = this.props.root;
-    let path = [file];
-    for (let p of this.state.current) {
-      file = file.children.find(file => file.path[0] === p);
+function getCurrentPath(root) {
+  let file = root;
+  let path = [file];
+  if (window.location.hash.length > 1) {
+    const current = decodeURIComponent(window.location.hash.substr(1)).split('/');
+    for (let p of current) {
+      file = file.children && file.children.find(file => file.path[0] === p);
       if (!file) {
         return path;
       }
       path.push(file);
     }
-    return path;
   }
+  return path;
+}
 
-  render() {
-    const path = this.getCurrentPath();
-    const file = path[path.length - 1];
-
-    let w = null;
-    if (file.is_folder) {
-      w = e(FilesList, {
-        folder: file,
-        onSelectFile: this.selectFile.bind(this),
-        onBack: path.length > 1 ? this.back.bind(this) : null,
-      });
-    } else {
-      w = e(DisplayFile, {
-        file,
-        onBack: this.back.bind(this),
-      });
-    }
+function navigate(file) {
+  window.location.hash = hashOf(file);
+}
 
-    return e('div', {className: 'app'}, w);
+function App({root}) {
+  const path = getCurrentPath(root);
+  const file = path[path.length - 1];
+  const onBack = path.length > 1 ? () => navigate(path[path.length - 2]) : null;
+
+  let w = null;
+  if (file.is_folder) {
+    w = e(FilesList, {
+      folder: file,
+      onSelectFile: navigate,
+      onBack,
+    });
+  } else {
+    w = e(DisplayFile, {file, onBack});
   }
 
-  selectFile(file) {
-    this.setState(({current}) => {
-      return {current: [...current, file.path[0]]};
-    }, () => this.updateHash());
-  }
+  return e('div', {className: 'app'},
+    e(FileTree, {root, current: file}),
+    e('div', {className: 'app__main'}, w)
+  );
+}
 
-  back(file) {
-    this.setState(({current}) => {
-      return {current: current.slice(0, current.length - 1)};
-    }, () => this.updateHash());
-  }
+function coverageClass(prefix, file) {
+  const coverage = file.coverable ? file.covered / file.coverable * 100 : -1;
+  return (coverage >= 0 && coverage < 50 ? ` ${prefix}_low` : '')
+    + (coverage >= 50 && coverage < 80 ? ` ${prefix}_medium` : '')
+    + (coverage >= 80 ? ` ${prefix}_high` : '');
+}
 
-  updateHash() {
-    if (!this.state.current || !this.state.current.length) {
-      window.location = '#';
-    } else {
-      window.location = '#' + this.state.current.join('/');
-    }
+function FileTree({root, current}) {
+  return e('nav', {className: 'file-tree'},
+    root.children.map(file => e(TreeNode, {file, current}))
+  );
+}
+
+function TreeNode({file, current}) {
+  const hash = hashOf(file);
+  const coverage = file.coverable ? file.covered / file.coverable * 100 : -1;
+  const link = e('a', {
+      className: 'file-tree__link'
+        + coverageClass('file-tree__link', file)
+        + (file === current ? ' file-tree__link_current' : ''),
+      href: '#' + hash,
+    },
+    pathToString(file.path),
+    coverage >= 0 ? e('span', {className: 'file-tree__coverage'}, coverage.toFixed(0) + '%') : null
+  );
+  if (!file.is_folder) {
+    return e('div', {className: 'file-tree__file'}, link);
   }
+  return e('details', {
+      className: 'file-tree__folder',
+      open: !collapsedFolders.has(hash),
+      onToggle: event => event.target.open ? collapsedFolders.delete(hash) : collapsedFolders.add(hash),
+    },
+    e('summary', null, link),
+    file.children.map(child => e(TreeNode, {file: child, current}))
+  );
 }
 
 function FilesList({folder, onSelectFile, onBack}) {
@@ -161,7 +235,9 @@ function FilesList({folder, onSelectFile, onBack}) {
       e('thead', {className: 'files-list__head'},
         e('tr', null,
           e('th', null, "Path"),
-          e('th', null, "Coverage")
+          e('th', null, showTests ? "Code coverage" : "Coverage"),
+          showTests ? e('th', null, "Test code coverage") : null,
+          showFunctions ? e('th', null, "Functions") : null
         )
       ),
       e('tbody', {className: 'files-list__body'},
@@ -171,12 +247,16 @@ function FilesList({folder, onSelectFile, onBack}) {
   );
 }
 
+function isExpanded(file, coverage) {
+  return expandThreshold !== null && !file.is_folder && coverage >= 0 && coverage < expandThreshold;
+}
+
 function File({file, onClick}) {
   const coverage = file.coverable ? file.covered / file.coverable * 100 : -1;
   const coverageDelta = file.prevRun &&
     (file.covered / file.coverable * 100 - file.prevRun.covered / file.prevRun.coverable * 100);
 
-  return e('tr', {
+  const row = e('tr', {
       className: 'files-list__file'
         + (coverage >= 0 && coverage < 50 ? ' files-list__file_low': '')
         + (coverage >= 50 && coverage < 80 ? ' files-list__file_medium': '')
@@ -190,6 +270,18 @@ function File({file, onClick}) {
       (coverage >= 0 ? ' (' + coverage.toFixed(2) + '%)' : ''),
       e('span', {title: 'Change from the previous run'},
         (coverageDelta ? ` (${coverageDelta > 0 ? '+' : ''}${coverageDelta.toFixed(2)}%)` : ''))
+    ),
+    showTests ? e('td', null, testCoverage(file)) : null,
+    showFunctions ? e('td', null, functionCoverage(file)) : null
+  );
+
+  if (!isExpanded(file, coverage)) {
+    return row;
+  }
+  return e(Fragment, null,
+    row,
+    e('tr', {className: 'files-list__expanded'},
+      e('td', {colSpan: 2 + showTests + showFunctions}, e(FileContent, {file}))
     )
   );
 }
@@ -213,28 +305,86 @@ function FileHeader({file, onBack}) {
       (file.coverable ? ' (' + coverage.toFixed(2) + '%)' : ''),
       e('span', {title: 'Change from the previous run'},
         (coverageDelta ? ` (${coverageDelta > 0 ? '+' : ''}${coverageDelta.toFixed(2)}%)` : ''))
-    )
+    ),
+    showTests ? e('div', {className: 'file-header__stat'},
+      'Test code covered: ' + testCoverage(file)
+    ) : null,
+    showFunctions ? e('div', {className: 'file-header__stat'},
+      'Functions called: ' + functionCoverage(file)
+    ) : null
   );
 }
 
+// Combines the traces on a line, lines with some of their branches taken are
+// partially covered
+function lineCoverage(traces) {
+  let covered = 0;
+  let coverable = 0;
+  let hits = null;
+  let branches = 0;
+  let branchesCovered = 0;
+  for (const trace of traces) {
+    if ('Line' in trace.stats) {
+      coverable += 1;
+      covered += trace.stats.Line > 0 ? 1 : 0;
+      hits = Math.max(hits || 0, trace.stats.Line);
+    } else {
+      const states = trace.stats.Branch ? [trace.stats.Branch] : trace.stats.Condition || [];
+      for (const state of states) {
+        const taken = state.been_true + state.been_false;
+        coverable += 2;
+        covered += taken;
+        branches += 2;
+        branchesCovered += taken;
+      }
+    }
+  }
+  return {covered, coverable, hits, branches, branchesCovered};
+}
+
+function lineTitle({hits, branches, branchesCovered}) {
+  const title = [];
+  if (hits !== null) {
+    title.push('Hits: ' + hits);
+  }
+  if (branches) {
+    title.push('Branches covered: ' + branchesCovered + ' of ' + branches);
+  }
+  return title.join('\n');
+}
+
 function FileContent({file}) {
+  const tracesByLine = new Map();
+  for (const trace of file.traces) {
+    tracesByLine.set(trace.line, [...(tracesByLine.get(trace.line) || []), trace]);
+  }
   return e('div', {className: 'file-content'},
     file.content.split(/\r?\n/).map((line, index) => {
-      const trace = file.traces.find(trace => trace.line === index + 1);
-      const covered = trace && trace.stats.Line;
-      const uncovered = trace && !trace.stats.Line;
+      const traces = tracesByLine.get(index + 1);
+      const coverage = traces && lineCoverage(traces);
+      const covered = coverage && coverage.covered === coverage.coverable;
+      const partial = coverage && coverage.covered > 0 && !covered;
+      const uncovered = coverage && coverage.covered === 0;
+      const ignored = !traces && file.ignored.includes(index + 1);
       return e('pre', {
           className: 'code-line'
             + (covered ? ' code-line_covered' : '')
-            + (uncovered ? ' code-line_uncovered' : ''),
-          title: trace ? JSON.stringify(trace.stats, null, 2) : null,
-        }, line);
+            + (partial ? ' code-line_partial' : '')
+            + (uncovered ? ' code-line_uncovered' : '')
+            + (ignored ? ' code-line_ignored' : ''),
+          title: coverage ? lineTitle(coverage) : null,
+        },
+        e('span', {className: 'code-line__number'}, index + 1),
+        showHits ? e('span', {className: 'code-line__hits'},
+          coverage && coverage.hits !== null ? coverage.hits + '\u00d7' : '') : null,
+        line
+      );
     })
   );
 }
 
 (function(){
-  const commonPath = findCommonPath(data.files);
+  commonPath = findCommonPath(data.files);
   const prevFilesMap = new Map();
 
   previousData && previousData.files.forEach((file) => {
@@ -262,11 +412,18 @@ function FileContent({file}) {
     parent: [],
     covered: children.reduce((sum, file) => sum + file.covered, 0),
     coverable: children.reduce((sum, file) => sum + file.coverable, 0),
+    ...sumOptionalStats(children),
     prevRun: {
       covered: children.reduce((sum, file) => sum + file.prevRun.covered, 0),
       coverable: children.reduce((sum, file) => sum + file.prevRun.coverable, 0),
     }
   };
 
-  ReactDOM.render(e(App, {root, prevFilesMap}), document.getElementById('root'));
+  const render = () => {
+    const container = document.getElementById('root');
+    container.textContent = '';
+    container.appendChild(e(App, {root}));
+  };
+  window.addEventListener('hashchange', render, false);
+  render();
 }());
@@ -0,0 +1,177 @@
+//! The XML structure for the SonarQube generic coverage report is:
+//! ```xml
+//! <coverage version="1">
+//!   <file path="src/lib.rs">
+//!     <lineToCover lineNumber="1" covered="true"/>
+//!     <lineToCover lineNumber="3" covered="false" branchesToCover="2" coveredBranches="1"/>
+//!   </file>
+//! </coverage>
+//! ```
+//! See https://docs.sonarqube.org/latest/analysis/generic-test/
+use crate::config::{Config, OutputFile};
+use crate::errors::RunError;
+use crate::traces::{CoverageStat, TraceMap};
+use quick_xml::{
+    events::{BytesDecl, BytesEnd, BytesStart, Event},
+    Writer,
+};
+use std::fs::File;
+use std::io::{Cursor, Write};
+
+struct LineToCover {
+    line: u64,
+    covered: bool,
+    branches: usize,
+    covered_branches: usize,
+}
+
+pub fn export(coverage_data: &TraceMap, config: &Config) -> Result<(), RunError> {
+    let file_path = config.output_path(&OutputFile::SonarQube);
+    let mut file = File::create(file_path)
+        .map_err(|e| RunError::CovReport(format!("File is not writeable: {}", e)))?;
+    let xml = to_xml(coverage_data, config)
+        .map_err(|e| RunError::CovReport(format!("Failed to write SonarQube report: {}", e)))?;
+    file.write_all(&xml)?;
+    Ok(())
+}
+
+/// Combines the traces for each line, SonarQube expects a single
+/// `lineToCover` per line
+fn lines_to_cover(coverage_data: &TraceMap, config: &Config) -> Vec<(String, Vec<LineToCover>)> {
+    coverage_data
+        .iter()
+        .map(|(path, traces)| {
+            // Traces are sorted by line so traces on the same line are adjacent
+            let mut lines: Vec<LineToCover> = vec![];
+            for trace in traces {
+                if lines.last().map(|l| l.line) != Some(trace.line) {
+                    lines.push(LineToCover {
+                        line: trace.line,
+                        covered: false,
+                        branches: 0,
+                        covered_branches: 0,
+                    });
+                }
+                if let Some(line) = lines.last_mut() {
+                    let states = match trace.stats {
+                        CoverageStat::Line(hits) => {
+                            line.covered |= hits > 0;
+                            continue;
+                        }
+                        CoverageStat::Branch(state) => vec![state],
+                        CoverageStat::Condition(ref states) => states.clone(),
+                    };
+                    for state in states {
+                        line.branches += 2;
+                        line.covered_branches += state.been_true as usize;
+                        line.covered_branches += state.been_false as usize;
+                    }
+                }
+            }
+            let path = config.strip_base_dir(path).display().to_string();
+            (path, lines)
+        })
+        .collect()
+}
+
+fn to_xml(coverage_data: &TraceMap, config: &Config) -> Result<Vec<u8>, quick_xml::Error> {
+    let mut writer = Writer::new(Cursor::new(vec![]));
+    writer.write_event(Event::Decl(BytesDecl::new(b"1.0", None, None)))?;
+
+    let cov_tag = b"coverage";
+    let mut cov = BytesStart::borrowed(cov_tag, cov_tag.len());
+    cov.push_attribute(("version", "1"));
+    writer.write_event(Event::Start(cov))?;
+
+    let file_tag = b"file";
+    for (path, lines) in lines_to_cover(coverage_data, config) {
+        let mut file = BytesStart::borrowed(file_tag, file_tag.len());
+        file.push_attribute(("path", path.as_ref()));
+        writer.write_event(Event::Start(file))?;
+        for line in &lines {
+            let mut elem = BytesStart::owned_name("lineToCover");
+            elem.push_attribute(("lineNumber", line.line.to_string().as_ref()));
+            elem.push_attribute(("covered", line.covered.to_string().as_ref()));
+            if config.branch_coverage && line.branches > 0 {
+                elem.push_attribute(("branchesToCover", line.branches.to_string().as_ref()));
+                elem.push_attribute((
+                    "coveredBranches",
+                    line.covered_branches.to_string().as_ref(),
+                ));
+            }
+            writer.write_event(Event::Empty(elem))?;
+        }
+        writer.write_event(Event::End(BytesEnd::borrowed(file_tag)))?;
+    }
+
+    writer.write_event(Event::End(BytesEnd::borrowed(cov_tag)))?;
+    Ok(writer.into_inner().into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traces::{LogicState, Trace};
+    use quick_xml::Reader;
+    use std::collections::HashMap;
+
+    #[test]
+    fn generic_coverage_structure() {
+        let file = std::env::current_dir().unwrap().join("src/lib.rs");
+        let mut traces = TraceMap::new();
+        traces.add_trace(&file, Trace::new_stub(1, CoverageStat::Line(1)));
+        traces.add_trace(&file, Trace::new_stub(2, CoverageStat::Line(0)));
+        let state = LogicState {
+            been_true: true,
+            been_false: false,
+        };
+        traces.add_trace(&file, Trace::new_stub(2, CoverageStat::Branch(state)));
+
+        let mut config = Config::default();
+        config.branch_coverage = true;
+        let xml = to_xml(&traces, &config).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+
+        let mut reader = Reader::from_str(&xml);
+        reader.check_end_names(true);
+        let mut buf = vec![];
+        let mut elements = vec![];
+        loop {
+            match reader.read_event(&mut buf).unwrap() {
+                Event::Start(ref e) | Event::Empty(ref e) => {
+                    let name = String::from_utf8_lossy(e.name()).to_string();
+                    let attrs = e
+                        .attributes()
+                        .map(|a| {
+                            let a = a.unwrap();
+                            let key = String::from_utf8_lossy(a.key).to_string();
+                            (key, String::from_utf8_lossy(&a.value).to_string())
+                        })
+                        .collect::<HashMap<_, _>>();
+                    elements.push((name, attrs));
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        assert_eq!(elements.len(), 4);
+        assert_eq!(elements[0].0, "coverage");
+        assert_eq!(elements[0].1["version"], "1");
+        assert_eq!(elements[1].0, "file");
+        assert_eq!(elements[1].1["path"], "src/lib.rs");
+
+        let first = &elements[2];
+        assert_eq!(first.0, "lineToCover");
+        assert_eq!(first.1["lineNumber"], "1");
+        assert_eq!(first.1["covered"], "true");
+        assert!(!first.1.contains_key("branchesToCover"));
+
+        let second = &elements[3];
+        assert_eq!(second.1["lineNumber"], "2");
+        assert_eq!(second.1["covered"], "false");
+        assert_eq!(second.1["branchesToCover"], "2");
+        assert_eq!(second.1["coveredBranches"], "1");
+    }
+}
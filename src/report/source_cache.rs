@@ -0,0 +1,124 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::read_to_string;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Maximum number of files held in the cache
+const MAX_FILES: usize = 1000;
+/// Maximum amount of source in bytes held in the cache
+const MAX_BYTES: usize = 100 * 1024 * 1024;
+
+/// Caches the source files read while generating reports so each
+/// file is read from disk at most once. When full the least recently used
+/// file is evicted.
+#[derive(Debug, Default)]
+pub struct SourceCache {
+    /// If disabled files are read from disk on every access
+    enabled: bool,
+    /// Contents of each cached file
+    files: HashMap<PathBuf, String>,
+    /// Cached paths ordered from least to most recently used
+    usage: VecDeque<PathBuf>,
+    /// Total size of the cached source
+    bytes: usize,
+    /// Number of accesses served from the cache
+    hits: usize,
+}
+
+impl SourceCache {
+    /// Creates a new empty cache
+    pub fn new(enabled: bool) -> Self {
+        SourceCache {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the contents of the source file at the given path, reading it
+    /// from disk if it isn't in the cache
+    pub fn get(&mut self, path: &Path) -> io::Result<&str> {
+        if self.enabled && self.files.contains_key(path) {
+            self.hits += 1;
+            self.touch(path);
+        } else {
+            let source = read_to_string(path)?;
+            self.insert(path, source);
+        }
+        Ok(self.files[path].as_str())
+    }
+
+    /// Number of file accesses which didn't need to read from disk
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Number of files currently cached
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns true if no files are cached
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    fn touch(&mut self, path: &Path) {
+        if let Some(i) = self.usage.iter().position(|p| p == path) {
+            if let Some(p) = self.usage.remove(i) {
+                self.usage.push_back(p);
+            }
+        }
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some(source) = self.files.remove(path) {
+            self.bytes -= source.len();
+            self.usage.retain(|p| p != path);
+        }
+    }
+
+    fn insert(&mut self, path: &Path, source: String) {
+        self.remove(path);
+        let size = source.len();
+        let max_files = if self.enabled { MAX_FILES } else { 1 };
+        while self.files.len() >= max_files || self.bytes + size > MAX_BYTES {
+            match self.usage.pop_front() {
+                Some(old) => self.remove(&old),
+                None => break,
+            }
+        }
+        self.bytes += size;
+        self.usage.push_back(path.to_path_buf());
+        self.files.insert(path.to_path_buf(), source);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_access_hits_cache() {
+        let mut cache = SourceCache::new(true);
+        let path = Path::new("Cargo.toml");
+        let first = cache.get(path).unwrap().to_string();
+        assert_eq!(cache.hits(), 0);
+        let second = cache.get(path).unwrap().to_string();
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(first, second);
+        assert_eq!(first, read_to_string(path).unwrap());
+        assert!(first.ends_with('\n'));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn disabled_cache_never_hits() {
+        let mut cache = SourceCache::new(false);
+        let path = Path::new("Cargo.toml");
+        cache.get(path).unwrap();
+        cache.get(path).unwrap();
+        cache.get(Path::new("README.md")).unwrap();
+        assert_eq!(cache.hits(), 0);
+        assert_eq!(cache.len(), 1);
+    }
+}
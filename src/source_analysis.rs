@@ -1,7 +1,9 @@
 use crate::config::{Config, RunType};
+use cargo::core::dependency::Kind;
 use cargo::core::Workspace;
+use cargo::ops;
 use lazy_static::lazy_static;
-use log::trace;
+use log::{trace, warn};
 use proc_macro2::{Span, TokenStream, TokenTree};
 use quote::ToTokens;
 use regex::Regex;
@@ -33,6 +35,16 @@ pub struct LineAnalysis {
     /// But may be ignored. Doesn't make sense to cover ALL the lines so this
     /// is just an index.
     pub cover: HashSet<usize>,
+    /// Lines of test code such as test functions and `#[cfg(test)]` modules
+    pub test_lines: HashSet<Lines>,
+    /// Lines of items marked with `#[cfg(tarpaulin_include)]`, these are
+    /// covered even if the file is excluded or they're ignored test code
+    pub include: HashSet<Lines>,
+    /// Files and directories of `#[cfg(test)]` modules declared in this file
+    test_mods: HashSet<PathBuf>,
+    /// Files and directories of `#[cfg(tarpaulin_include)]` modules declared
+    /// in this file
+    include_mods: HashSet<PathBuf>,
 }
 
 /// When the LineAnalysis results are mapped to their files there needs to be
@@ -40,6 +52,8 @@ pub struct LineAnalysis {
 /// this trait
 pub trait SourceAnalysisQuery {
     fn should_ignore(&self, path: &Path, l: &usize) -> bool;
+    fn is_test_line(&self, path: &Path, l: &usize) -> bool;
+    fn is_included(&self, path: &Path, l: &usize) -> bool;
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -56,6 +70,20 @@ impl SourceAnalysisQuery for HashMap<PathBuf, LineAnalysis> {
             false
         }
     }
+
+    fn is_test_line(&self, path: &Path, l: &usize) -> bool {
+        match self.get(path) {
+            Some(analysis) => analysis.is_test_line(*l),
+            None => false,
+        }
+    }
+
+    fn is_included(&self, path: &Path, l: &usize) -> bool {
+        match self.get(path) {
+            Some(analysis) => analysis.is_included(*l),
+            None => false,
+        }
+    }
 }
 
 impl LineAnalysis {
@@ -64,6 +92,10 @@ impl LineAnalysis {
         LineAnalysis {
             ignore: HashSet::new(),
             cover: HashSet::new(),
+            test_lines: HashSet::new(),
+            include: HashSet::new(),
+            test_mods: HashSet::new(),
+            include_mods: HashSet::new(),
         }
     }
 
@@ -140,6 +172,59 @@ impl LineAnalysis {
         }
     }
 
+    /// Marks the lines of the provided tokens as test code
+    pub fn mark_test_tokens<T>(&mut self, tokens: T)
+    where
+        T: ToTokens,
+    {
+        if !self.test_lines.contains(&Lines::All) {
+            for token in tokens.into_token_stream() {
+                let span = token.span();
+                for i in span.start().line..(span.end().line + 1) {
+                    self.test_lines.insert(Lines::Line(i));
+                }
+            }
+        }
+    }
+
+    /// Marks the whole file as test code
+    pub fn mark_all_test(&mut self) {
+        self.test_lines.clear();
+        self.test_lines.insert(Lines::All);
+    }
+
+    /// Shows whether the line is part of the test code
+    pub fn is_test_line(&self, line: usize) -> bool {
+        self.test_lines.contains(&Lines::Line(line)) || self.test_lines.contains(&Lines::All)
+    }
+
+    /// Marks the lines of the provided tokens as included in coverage
+    pub fn include_tokens<T>(&mut self, tokens: T)
+    where
+        T: ToTokens,
+    {
+        if !self.include.contains(&Lines::All) {
+            for token in tokens.into_token_stream() {
+                let span = token.span();
+                for i in span.start().line..(span.end().line + 1) {
+                    self.include.insert(Lines::Line(i));
+                }
+            }
+        }
+    }
+
+    /// Marks the whole file as included in coverage
+    pub fn include_all(&mut self) {
+        self.include.clear();
+        self.include.insert(Lines::All);
+    }
+
+    /// Shows whether the line is in an item marked with
+    /// `#[cfg(tarpaulin_include)]`
+    pub fn is_included(&self, line: usize) -> bool {
+        self.include.contains(&Lines::Line(line)) || self.include.contains(&Lines::All)
+    }
+
     /// Shows whether the line should be ignored by tarpaulin
     pub fn should_ignore(&self, line: usize) -> bool {
         self.ignore.contains(&Lines::Line(line)) || self.ignore.contains(&Lines::All)
@@ -163,6 +248,76 @@ fn is_source_file(entry: &DirEntry) -> bool {
     p.extension() == Some(OsStr::new("rs"))
 }
 
+/// Number of lines at the start of a file searched for generated code markers
+const GENERATED_HEADER_LINES: usize = 10;
+
+/// Checks the header of a source file for any of the markers used by code
+/// generators to flag their output.
+fn is_generated_file(content: &str, markers: &[String]) -> bool {
+    content
+        .lines()
+        .take(GENERATED_HEADER_LINES)
+        .any(|l| markers.iter().any(|m| l.contains(m.as_str())))
+}
+
+/// Returns the line numbers of the lines matching any of the given patterns
+pub fn lines_matching(content: &str, patterns: &[Regex]) -> Vec<usize> {
+    if patterns.is_empty() {
+        return vec![];
+    }
+    content
+        .lines()
+        .enumerate()
+        .filter(|&(_, l)| patterns.iter().any(|p| p.is_match(l)))
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+/// Returns the line numbers of the lines with a comment containing any of the
+/// exclusion markers
+pub fn lines_with_exclusion_comment(content: &str, markers: &[String]) -> Vec<usize> {
+    if markers.is_empty() {
+        return vec![];
+    }
+    content
+        .lines()
+        .enumerate()
+        .filter(|&(_, l)| {
+            let comment = match (l.find("//"), l.find("/*")) {
+                (Some(a), Some(b)) => &l[a.min(b)..],
+                (Some(a), None) | (None, Some(a)) => &l[a..],
+                (None, None) => return false,
+            };
+            markers.iter().any(|m| comment.contains(m.as_str()))
+        })
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+/// Returns the line numbers between `coverage:off` and `coverage:on` comments,
+/// including the lines of the markers. Nested blocks are part of the outermost
+/// block and a block which isn't closed runs until the end of the file
+pub fn lines_in_excluded_blocks(content: &str) -> Vec<usize> {
+    let mut depth = 0usize;
+    let mut result = vec![];
+    for (i, line) in content.lines().enumerate() {
+        let comment = line
+            .find("//")
+            .map(|start| line[start + 2..].trim())
+            .unwrap_or_default();
+        if comment.starts_with("coverage:off") {
+            depth += 1;
+        }
+        if depth > 0 {
+            result.push(i + 1);
+        }
+        if comment.starts_with("coverage:on") {
+            depth = depth.saturating_sub(1);
+        }
+    }
+    result
+}
+
 fn is_target_folder(entry: &DirEntry, root: &Path) -> bool {
     let target = root.join("target");
     entry.path().starts_with(&target)
@@ -173,6 +328,9 @@ pub fn get_line_analysis(project: &Workspace, config: &Config) -> HashMap<PathBu
     let mut result: HashMap<PathBuf, LineAnalysis> = HashMap::new();
 
     let mut ignored_files: HashSet<PathBuf> = HashSet::new();
+    let mut test_files: HashSet<PathBuf> = HashSet::new();
+    let mut included_files: HashSet<PathBuf> = HashSet::new();
+    let line_patterns = config.ignored_line_patterns();
 
     let walker = WalkDir::new(project.root()).into_iter();
     for e in walker
@@ -185,8 +343,11 @@ pub fn get_line_analysis(project: &Workspace, config: &Config) -> HashMap<PathBu
                 e.path(),
                 project.root(),
                 &config,
+                &line_patterns,
                 &mut result,
                 &mut ignored_files,
+                &mut test_files,
+                &mut included_files,
             );
         } else {
             let mut analysis = LineAnalysis::new();
@@ -200,12 +361,64 @@ pub fn get_line_analysis(project: &Workspace, config: &Config) -> HashMap<PathBu
         analysis.ignore_all();
         result.insert(e.to_path_buf(), analysis);
     }
+    for e in &test_files {
+        if let Some(analysis) = result.get_mut(e) {
+            analysis.mark_all_test();
+        }
+    }
+    for e in &included_files {
+        if let Some(analysis) = result.get_mut(e) {
+            analysis.include_all();
+        }
+    }
+    if config.ignore_build_dependencies {
+        for dir in get_build_dependency_dirs(project) {
+            let walker = WalkDir::new(dir).into_iter();
+            for e in walker.filter_map(|e| e.ok()).filter(|e| is_source_file(e)) {
+                let mut analysis = LineAnalysis::new();
+                analysis.ignore_all();
+                result.insert(e.path().to_path_buf(), analysis);
+            }
+        }
+    }
 
     debug_printout(&result, config);
 
     result
 }
 
+/// Finds the source directories of crates which are only used as build
+/// dependencies of the workspace members
+fn get_build_dependency_dirs(project: &Workspace) -> Vec<PathBuf> {
+    let mut build_only = HashSet::new();
+    let mut other = HashSet::new();
+    for member in project.members() {
+        for dep in member.dependencies() {
+            if dep.kind() == Kind::Build {
+                build_only.insert(dep.package_name());
+            } else {
+                other.insert(dep.package_name());
+            }
+        }
+    }
+    if build_only.is_empty() {
+        return vec![];
+    }
+    let (packages, resolve) = match ops::resolve_ws(project) {
+        Ok(r) => r,
+        Err(e) => {
+            warn!("Failed to resolve build dependencies: {}", e);
+            return vec![];
+        }
+    };
+    resolve
+        .iter()
+        .filter(|id| build_only.contains(&id.name()) && !other.contains(&id.name()))
+        .filter_map(|id| packages.get_one(id).ok())
+        .map(|p| p.root().to_path_buf())
+        .collect()
+}
+
 pub fn debug_printout(result: &HashMap<PathBuf, LineAnalysis>, config: &Config) {
     if config.debug {
         for (ref path, ref analysis) in result {
@@ -278,12 +491,16 @@ struct Context<'a> {
 }
 
 /// Analyses a package of the target crate.
+#[allow(clippy::too_many_arguments)]
 fn analyse_package(
     path: &Path,
     root: &Path,
     config: &Config,
+    line_patterns: &[Regex],
     result: &mut HashMap<PathBuf, LineAnalysis>,
     filtered_files: &mut HashSet<PathBuf>,
+    test_files: &mut HashSet<PathBuf>,
+    included_files: &mut HashSet<PathBuf>,
 ) {
     if let Some(file) = path.to_str() {
         let skip_cause_test = config.ignore_tests && path.starts_with(root.join("tests"));
@@ -294,6 +511,14 @@ fn analyse_package(
             if let Ok(mut file) = file {
                 let mut content = String::new();
                 let _ = file.read_to_string(&mut content);
+                if config.exclude_generated
+                    && is_generated_file(&content, &config.generated_file_markers)
+                {
+                    let mut analysis = LineAnalysis::new();
+                    analysis.ignore_all();
+                    result.insert(path.to_path_buf(), analysis);
+                    return;
+                }
                 let file = parse_file(&content);
                 if let Ok(file) = file {
                     let mut analysis = LineAnalysis::new();
@@ -304,21 +529,31 @@ fn analyse_package(
                         ignore_mods: RefCell::new(HashSet::new()),
                     };
 
+                    if path.starts_with(root.join("tests")) {
+                        analysis.mark_all_test();
+                    }
                     find_ignorable_lines(&content, &mut analysis);
+                    analysis.add_to_ignore(&lines_matching(&content, line_patterns));
+                    analysis.add_to_ignore(&lines_with_exclusion_comment(
+                        &content,
+                        &config.exclusion_line_comment,
+                    ));
+                    if config.block_exclusions {
+                        analysis.add_to_ignore(&lines_in_excluded_blocks(&content));
+                    }
                     process_items(&file.items, &ctx, &mut analysis);
+                    for f in analysis.test_mods.drain() {
+                        insert_module_files(f, test_files);
+                    }
+                    for f in analysis.include_mods.drain() {
+                        insert_module_files(f, included_files);
+                    }
                     // Check there's no conflict!
                     result.insert(path.to_path_buf(), analysis);
 
                     let mut ignored_files = ctx.ignore_mods.into_inner();
                     for f in ignored_files.drain() {
-                        if f.is_file() {
-                            filtered_files.insert(f);
-                        } else {
-                            let walker = WalkDir::new(f).into_iter();
-                            for e in walker.filter_map(|e| e.ok()).filter(|e| is_source_file(e)) {
-                                filtered_files.insert(e.path().to_path_buf());
-                            }
-                        }
+                        insert_module_files(f, filtered_files);
                     }
                     // This could probably be done with the DWARF if I could find a discriminating factor
                     // to why lib.rs:1 shows up as a real line!
@@ -331,6 +566,19 @@ fn analyse_package(
     }
 }
 
+/// Adds the source files of a module to the set, for a directory module this
+/// is every source file in the directory
+fn insert_module_files(module: PathBuf, files: &mut HashSet<PathBuf>) {
+    if module.is_file() {
+        files.insert(module);
+    } else {
+        let walker = WalkDir::new(module).into_iter();
+        for e in walker.filter_map(|e| e.ok()).filter(|e| is_source_file(e)) {
+            files.insert(e.path().to_path_buf());
+        }
+    }
+}
+
 /// Finds lines from the raw string which are ignorable.
 /// These are often things like close braces, semi colons that may regiser as
 /// false positives.
@@ -414,6 +662,8 @@ fn process_statements(stmts: &[Stmt], ctx: &Context, analysis: &mut LineAnalysis
 fn visit_mod(module: &ItemMod, analysis: &mut LineAnalysis, ctx: &Context) {
     analysis.ignore_tokens(module.mod_token);
     let mut check_insides = true;
+    let mut test_mod = false;
+    let included = has_include_attr(&module.attrs);
     for attr in &module.attrs {
         if let Ok(x) = attr.parse_meta() {
             if check_cfg_attr(&x) {
@@ -423,52 +673,125 @@ fn visit_mod(module: &ItemMod, analysis: &mut LineAnalysis, ctx: &Context) {
                 }
                 check_insides = false;
                 break;
-            } else if ctx.config.ignore_tests && x.path().is_ident("cfg") {
-                if let Meta::List(ref ml) = x {
-                    for nested in &ml.nested {
-                        if let NestedMeta::Meta(Meta::Path(ref i)) = *nested {
-                            if i.is_ident("test") {
-                                check_insides = false;
-                                analysis.ignore_tokens(module.mod_token);
-                                if let Some((ref braces, _)) = module.content {
-                                    analysis.ignore_span(braces.span);
-                                }
-                            }
-                        }
+            } else if is_cfg_test(&x) {
+                test_mod = true;
+                if ctx.config.ignore_tests && !included {
+                    check_insides = false;
+                    analysis.ignore_tokens(module.mod_token);
+                    if let Some((ref braces, _)) = module.content {
+                        analysis.ignore_span(braces.span);
                     }
                 }
             }
         }
     }
     if check_insides {
+        if test_mod {
+            analysis.mark_test_tokens(module);
+            if module.content.is_none() {
+                analysis.test_mods.insert(module_path(module, ctx));
+            }
+        }
+        if included {
+            analysis.include_tokens(module);
+            if module.content.is_none() {
+                analysis.include_mods.insert(module_path(module, ctx));
+            }
+        }
         if let Some((_, ref items)) = module.content {
             process_items(items, ctx, analysis);
         }
     } else {
-        // Get the file or directory name of the module
-        let mut p = if let Some(parent) = ctx.file.parent() {
-            parent.join(module.ident.to_string())
-        } else {
-            PathBuf::from(module.ident.to_string())
-        };
-        if !p.exists() {
-            p.set_extension("rs");
+        ctx.ignore_mods
+            .borrow_mut()
+            .insert(module_path(module, ctx));
+    }
+}
+
+/// Gets the file or directory name of a module declared in the current file
+fn module_path(module: &ItemMod, ctx: &Context) -> PathBuf {
+    let mut p = if let Some(parent) = ctx.file.parent() {
+        parent.join(module.ident.to_string())
+    } else {
+        PathBuf::from(module.ident.to_string())
+    };
+    if !p.exists() {
+        p.set_extension("rs");
+    }
+    p
+}
+
+/// Checks for a `#[cfg(test)]` attribute
+fn is_cfg_test(attr: &Meta) -> bool {
+    if !attr.path().is_ident("cfg") {
+        return false;
+    }
+    match *attr {
+        Meta::List(ref ml) => ml.nested.iter().any(|nested| match *nested {
+            NestedMeta::Meta(Meta::Path(ref i)) => i.is_ident("test"),
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
+/// Checks for a `cfg` attribute which needs `tarpaulin_include`, such as
+/// `#[cfg(tarpaulin_include)]` or `#[cfg(any(test, tarpaulin_include))]`
+fn is_cfg_tarpaulin_include(attr: &Meta) -> bool {
+    fn needs_include(nested: &Punctuated<NestedMeta, Comma>) -> bool {
+        nested.iter().any(|n| match n {
+            NestedMeta::Meta(Meta::Path(ref p)) => p.is_ident("tarpaulin_include"),
+            NestedMeta::Meta(Meta::List(ref ml)) => {
+                !ml.path.is_ident("not") && needs_include(&ml.nested)
+            }
+            _ => false,
+        })
+    }
+    match *attr {
+        Meta::List(ref ml) if ml.path.is_ident("cfg") => needs_include(&ml.nested),
+        _ => false,
+    }
+}
+
+/// Checks if the item is forced into coverage with `#[cfg(tarpaulin_include)]`
+fn has_include_attr(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter_map(|a| a.parse_meta().ok())
+        .any(|m| is_cfg_tarpaulin_include(&m))
+}
+
+/// Checks if an attribute marks a test function. As well as `#[test]` this
+/// covers the test attributes of async runtimes like `#[tokio::test]` and
+/// common test frameworks
+fn is_test_attribute(path: &syn::Path) -> bool {
+    const TEST_ATTRIBUTES: &[&str] = &["rstest", "test_case", "quickcheck", "wasm_bindgen_test"];
+    match path.segments.last() {
+        Some(segment) if segment.ident == "test" => true,
+        Some(segment) => {
+            path.segments.len() == 1 && TEST_ATTRIBUTES.iter().any(|a| segment.ident == a)
         }
-        ctx.ignore_mods.borrow_mut().insert(p);
+        None => false,
     }
 }
 
 fn visit_fn(func: &ItemFn, analysis: &mut LineAnalysis, ctx: &Context) {
     let mut test_func = false;
+    let mut test_attr = false;
     let mut ignored_attr = false;
     let mut is_inline = false;
     let mut ignore_span = false;
+    let mut test_only = false;
+    let included = has_include_attr(&func.attrs);
     for attr in &func.attrs {
-        if let Ok(x) = attr.parse_meta() {
+        if is_test_attribute(&attr.path) {
+            // Attributes like `#[test_case(1 ; "one")]` aren't valid meta.
+            // Only `#[test]` functions are ignored by `--ignore-tests`
+            test_func |= attr.path.is_ident("test");
+            test_attr = true;
+        } else if let Ok(x) = attr.parse_meta() {
             let id = x.path();
-            if id.is_ident("test") {
-                test_func = true;
-            } else if id.is_ident("derive") {
+            if id.is_ident("derive") {
                 analysis.ignore_span(attr.bracket_token.span);
             } else if id.is_ident("inline") {
                 is_inline = true;
@@ -477,14 +800,24 @@ fn visit_fn(func: &ItemFn, analysis: &mut LineAnalysis, ctx: &Context) {
             } else if check_cfg_attr(&x) {
                 ignore_span = true;
                 break;
+            } else if is_cfg_test(&x) {
+                test_only = true;
             }
         }
     }
     if ignore_span {
         analysis.ignore_tokens(func);
-    } else if (test_func && ctx.config.ignore_tests) || (ignored_attr && !ctx.config.run_ignored) {
+    } else if (test_func && ctx.config.ignore_tests && !included)
+        || (ignored_attr && !ctx.config.run_ignored)
+    {
         analysis.ignore_tokens(func);
     } else {
+        if test_attr || test_only {
+            analysis.mark_test_tokens(func);
+        }
+        if included {
+            analysis.include_tokens(func);
+        }
         if is_inline {
             // We need to force cover!
             analysis.cover_span(func.block.brace_token.span, Some(ctx.file_contents));
@@ -508,12 +841,13 @@ fn visit_fn(func: &ItemFn, analysis: &mut LineAnalysis, ctx: &Context) {
 
 fn check_attr_list(attrs: &[Attribute], ctx: &Context, analysis: &mut LineAnalysis) -> bool {
     let mut check_cover = true;
+    let included = has_include_attr(attrs);
     for attr in attrs {
         analysis.ignore_tokens(attr);
         if let Ok(x) = attr.parse_meta() {
             if check_cfg_attr(&x) {
                 check_cover = false;
-            } else if ctx.config.ignore_tests && x.path().is_ident("cfg") {
+            } else if ctx.config.ignore_tests && !included && x.path().is_ident("cfg") {
                 if let Meta::List(ref ml) = x {
                     let mut skip = false;
                     for c in &ml.nested {
@@ -561,6 +895,9 @@ fn check_cfg_attr(attr: &Meta) -> bool {
 fn visit_trait(trait_item: &ItemTrait, analysis: &mut LineAnalysis, ctx: &Context) {
     let check_cover = check_attr_list(&trait_item.attrs, ctx, analysis);
     if check_cover {
+        if has_include_attr(&trait_item.attrs) {
+            analysis.include_tokens(trait_item);
+        }
         for item in &trait_item.items {
             if let TraitItem::Method(ref i) = *item {
                 if check_attr_list(&i.attrs, ctx, analysis) {
@@ -595,6 +932,9 @@ fn visit_trait(trait_item: &ItemTrait, analysis: &mut LineAnalysis, ctx: &Contex
 fn visit_impl(impl_blk: &ItemImpl, analysis: &mut LineAnalysis, ctx: &Context) {
     let check_cover = check_attr_list(&impl_blk.attrs, ctx, analysis);
     if check_cover {
+        if has_include_attr(&impl_blk.attrs) {
+            analysis.include_tokens(impl_blk);
+        }
         for item in &impl_blk.items {
             if let ImplItem::Method(ref i) = *item {
                 if check_attr_list(&i.attrs, ctx, analysis) {
@@ -986,6 +1326,83 @@ mod tests {
     use super::*;
     use syn::parse_file;
 
+    #[test]
+    fn generated_file_detection() {
+        let markers = vec!["@generated".to_string(), "DO NOT EDIT".to_string()];
+        assert!(is_generated_file(
+            "// This file was @generated by prost-build.\npub struct Foo;",
+            &markers
+        ));
+        assert!(is_generated_file(
+            "#![allow(dead_code)]\n// DO NOT EDIT\nfn main() {}",
+            &markers
+        ));
+        assert!(!is_generated_file("fn main() {}\n", &markers));
+
+        let late_marker = format!("{}// @generated\n", "\n".repeat(GENERATED_HEADER_LINES));
+        assert!(!is_generated_file(&late_marker, &markers));
+    }
+
+    #[test]
+    fn ignore_lines_matching_patterns() {
+        let patterns = vec![Regex::new("unreachable!").unwrap()];
+        let content = "fn foo(x: u8) -> u8 {\n    match x {\n        0 => 1,\n        _ => unreachable!(),\n    }\n}";
+        assert_eq!(lines_matching(content, &patterns), vec![4]);
+        assert!(lines_matching(content, &[]).is_empty());
+
+        let mut analysis = LineAnalysis::new();
+        analysis.add_to_ignore(&lines_matching(content, &patterns));
+        assert!(analysis.should_ignore(4));
+        assert!(!analysis.should_ignore(3));
+    }
+
+    #[test]
+    fn ignore_lines_matching_regexes() {
+        let mut config = Config::default();
+        config.ignore_lines_matching = vec![r"^\s*log::.*".to_string(), r"todo!\(\)".to_string()];
+        let patterns = config.ignored_line_patterns();
+        let content =
+            "fn foo() {\n    log::info!(\"foo\");\n    let x = log::Level::Info;\n    todo!()\n}";
+        assert_eq!(lines_matching(content, &patterns), vec![2, 4]);
+    }
+
+    #[test]
+    fn exclusion_comments() {
+        let markers = vec!["tarpaulin: skip".to_string(), "LCOV_EXCL_LINE".to_string()];
+        let content = "fn foo(x: u8) -> u8 {
+    if x == 0 {
+        return 1; // tarpaulin: skip
+    }
+    log(\"tarpaulin: skip\");
+    x /* LCOV_EXCL_LINE */
+}";
+        assert_eq!(lines_with_exclusion_comment(content, &markers), vec![3, 6]);
+        assert!(lines_with_exclusion_comment(content, &[]).is_empty());
+    }
+
+    #[test]
+    fn block_exclusions() {
+        let content = "fn foo() {}
+// coverage:off
+fn bar() {
+    // coverage:off
+    baz();
+    // coverage:on
+    qux();
+}
+// coverage:on
+fn quux() {}
+// coverage:on
+fn corge() {}
+// coverage:off
+fn grault() {}";
+        assert_eq!(
+            lines_in_excluded_blocks(content),
+            vec![2, 3, 4, 5, 6, 7, 8, 9, 13, 14]
+        );
+        assert!(lines_in_excluded_blocks("// coverage: off\nfn foo() {}").is_empty());
+    }
+
     #[test]
     fn line_analysis_works() {
         let mut la = LineAnalysis::new();
@@ -1268,6 +1685,35 @@ mod tests {
         assert!(!lines.ignore.contains(&Lines::Line(2)));
     }
 
+    #[test]
+    fn tarpaulin_include_forces_coverage() {
+        let mut config = Config::default();
+        config.ignore_tests = true;
+        let mut lines = LineAnalysis::new();
+        let ctx = Context {
+            config: &config,
+            file_contents: "#[cfg(test)]
+                #[cfg(tarpaulin_include)]
+                mod helpers {
+                    fn boo(){
+                        assert!(true);
+                    }
+                }
+                #[cfg(any(test, tarpaulin_include))]
+                mod more;
+                #[cfg(not(tarpaulin_include))]
+                fn foo() {}",
+            file: Path::new("src/lib.rs"),
+            ignore_mods: RefCell::new(HashSet::new()),
+        };
+        let parser = parse_file(ctx.file_contents).unwrap();
+        process_items(&parser.items, &ctx, &mut lines);
+        assert!(!lines.should_ignore(5));
+        assert!(lines.is_included(5));
+        assert!(!lines.is_included(11));
+        assert!(lines.include_mods.contains(Path::new("src/more.rs")));
+    }
+
     #[test]
     fn filter_tests() {
         let config = Config::default();
@@ -1329,6 +1775,78 @@ mod tests {
         assert!(lines.ignore.contains(&Lines::Line(3)));
     }
 
+    #[test]
+    fn mark_test_code() {
+        let config = Config::default();
+        let mut lines = LineAnalysis::new();
+        let ctx = Context {
+            config: &config,
+            file_contents: "fn foo() -> u32 {
+                1
+            }
+            #[tokio::test]
+            async fn async_test() {
+                assert_eq!(foo(), 1);
+            }
+            #[test_case(1 ; \"one\")]
+            fn cases(x: u32) {
+                assert_eq!(foo(), x);
+            }
+            #[cfg(test)]
+            mod tests {
+                fn helper() -> u32 {
+                    foo()
+                }
+            }",
+            file: Path::new(""),
+            ignore_mods: RefCell::new(HashSet::new()),
+        };
+        let parser = parse_file(ctx.file_contents).unwrap();
+        process_items(&parser.items, &ctx, &mut lines);
+        assert!(!lines.is_test_line(1));
+        assert!(!lines.is_test_line(2));
+        for line in 4..=17 {
+            assert!(lines.is_test_line(line), "line {} is test code", line);
+        }
+        assert!(!lines.should_ignore(6));
+        assert!(!lines.should_ignore(15));
+
+        let ctx = Context {
+            config: &config,
+            file_contents: "#[cfg(test)]\nmod tests;",
+            file: Path::new("src/lib.rs"),
+            ignore_mods: RefCell::new(HashSet::new()),
+        };
+        let parser = parse_file(ctx.file_contents).unwrap();
+        process_items(&parser.items, &ctx, &mut lines);
+        assert!(lines.test_mods.contains(Path::new("src/tests.rs")));
+    }
+
+    #[test]
+    fn ignore_tests_keeps_other_test_attributes() {
+        let mut config = Config::default();
+        config.ignore_tests = true;
+        let mut lines = LineAnalysis::new();
+        let ctx = Context {
+            config: &config,
+            file_contents: "#[test]
+            fn sync_test() {
+                assert!(true);
+            }
+            #[tokio::test]
+            async fn async_test() {
+                assert!(true);
+            }",
+            file: Path::new(""),
+            ignore_mods: RefCell::new(HashSet::new()),
+        };
+        let parser = parse_file(ctx.file_contents).unwrap();
+        process_items(&parser.items, &ctx, &mut lines);
+        assert!(lines.should_ignore(3));
+        assert!(!lines.should_ignore(7));
+        assert!(lines.is_test_line(7));
+    }
+
     #[test]
     fn filter_test_utilities() {
         let mut config = Config::default();
@@ -1,13 +1,18 @@
 use crate::config::Config;
 use crate::errors::RunError;
+use crate::source_analysis::LineAnalysis;
 use crate::statemachine::*;
-use log::{debug, trace};
+use crate::test_loader::generate_binary_tracemap;
+use log::{debug, info, trace};
 use nix::errno::Errno;
+use nix::libc::{c_long, pid_t};
 use nix::sys::signal::Signal;
 use nix::sys::wait::*;
 use nix::unistd::Pid;
 use nix::Error as NixErr;
 use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
 
 pub fn create_state_machine<'a>(
     test: Pid,
@@ -45,6 +50,13 @@ impl From<&Pid> for ProcessInfo {
     }
 }
 
+/// A process the test exec'd which is followed with `--follow-exec`, its
+/// addresses belong to another binary so it has its own breakpoints and traces
+struct ExecedProcess {
+    breakpoints: HashMap<u64, Breakpoint>,
+    traces: TraceMap,
+}
+
 /// Handle to linux process state
 pub struct LinuxData<'a> {
     /// Recent results from waitpid to be handled by statemachine
@@ -61,6 +73,24 @@ pub struct LinuxData<'a> {
     config: &'a Config,
     /// Thread count. Hopefully getting rid of in future
     thread_count: isize,
+    /// Project directory and source analysis used to instrument exec'd
+    /// processes, only set when following them
+    exec_sources: Option<(&'a Path, &'a HashMap<PathBuf, LineAnalysis>)>,
+    /// Exec'd processes being followed
+    processes: HashMap<Pid, ExecedProcess>,
+    /// Process each thread or forked child of an exec'd process belongs to
+    process_threads: HashMap<Pid, Pid>,
+    /// Coverage of exec'd processes which exited, their addresses could clash
+    /// with the test's so they're only merged into the results at the end
+    finished: TraceMap,
+}
+
+impl<'a> Drop for LinuxData<'a> {
+    /// Tracing can stop before the test exits, such as when it times out, so
+    /// the coverage of exec'd processes is merged here as well
+    fn drop(&mut self) {
+        self.merge_followed();
+    }
 }
 
 impl<'a> StateData for LinuxData<'a> {
@@ -205,7 +235,9 @@ impl<'a> StateData for LinuxData<'a> {
                     )))
                 }
                 WaitStatus::Stopped(c, s) => {
-                    let sig = if self.config.forward_signals {
+                    // The timeout signal is always delivered so the test can
+                    // handle it and exit
+                    let sig = if self.config.forward_signals || *s == self.config.timeout_signal {
                         Some(*s)
                     } else {
                         None
@@ -226,8 +258,20 @@ impl<'a> StateData for LinuxData<'a> {
                     for ref mut value in self.breakpoints.values_mut() {
                         value.thread_killed(*child);
                     }
+                    if let Some(owner) = self.process_threads.remove(child) {
+                        if let Some(process) = self.processes.get_mut(&owner) {
+                            for value in process.breakpoints.values_mut() {
+                                value.thread_killed(*child);
+                            }
+                        }
+                        // An exec'd process may exit after the test
+                        if owner == *child {
+                            self.finish_process(owner);
+                        }
+                    }
                     trace!("Exited {:?} parent {:?}", child, self.parent);
                     if child == &self.parent {
+                        self.merge_followed();
                         Ok((TestState::End(*ec), TracerAction::Nothing))
                     } else {
                         // Process may have already been destroyed. This is just incase
@@ -292,7 +336,77 @@ impl<'a> LinuxData<'a> {
             traces,
             config,
             thread_count: 0,
+            exec_sources: None,
+            processes: HashMap::new(),
+            process_threads: HashMap::new(),
+            finished: TraceMap::new(),
+        }
+    }
+
+    /// Collects coverage from processes the test execs instead of detaching
+    /// from them, their binaries are looked up in the project directory
+    pub fn follow_exec(&mut self, project: &'a Path, analysis: &'a HashMap<PathBuf, LineAnalysis>) {
+        self.exec_sources = Some((project, analysis));
+    }
+
+    /// Instruments a process the test exec'd, returning false if it can't be
+    /// followed because it isn't a binary of the project
+    fn instrument_exec(&mut self, pid: Pid) -> bool {
+        let (project, analysis) = match self.exec_sources {
+            Some(sources) => sources,
+            None => return false,
+        };
+        let exe = match fs::read_link(format!("/proc/{}/exe", pid)) {
+            Ok(exe) => exe,
+            Err(_) => return false,
+        };
+        let traces = match generate_binary_tracemap(project, &exe, analysis, self.config) {
+            Ok(traces) if !traces.is_empty() => traces,
+            _ => {
+                trace!("No coverage to collect from {}", exe.display());
+                return false;
+            }
+        };
+        let mut breakpoints = HashMap::new();
+        for trace in traces.all_traces() {
+            for addr in &trace.address {
+                if let Ok(bp) = Breakpoint::new(pid, *addr) {
+                    breakpoints.insert(*addr, bp);
+                }
+            }
         }
+        info!("Following {} into {}", pid, exe.display());
+        // The process may have exec'd a followed binary before
+        self.finish_process(pid);
+        self.process_threads.insert(pid, pid);
+        self.processes.insert(
+            pid,
+            ExecedProcess {
+                breakpoints,
+                traces,
+            },
+        );
+        true
+    }
+
+    /// Stops following an exec'd process, its coverage is kept until it's
+    /// merged into the results
+    fn finish_process(&mut self, pid: Pid) {
+        if let Some(process) = self.processes.remove(&pid) {
+            self.process_threads.retain(|_, owner| *owner != pid);
+            self.finished.merge(&process.traces);
+        }
+    }
+
+    /// Adds the coverage of every exec'd process to the results, including
+    /// the ones which are still running
+    fn merge_followed(&mut self) {
+        let followed = self.processes.keys().copied().collect::<Vec<_>>();
+        for pid in followed {
+            self.finish_process(pid);
+        }
+        self.traces.merge(&self.finished);
+        self.finished = TraceMap::new();
     }
 
     fn handle_ptrace_event(
@@ -309,6 +423,7 @@ impl<'a> LinuxData<'a> {
                     Ok(t) => {
                         trace!("New thread spawned {}", t);
                         self.thread_count += 1;
+                        self.record_child_process(child, t);
                         Ok((
                             TestState::wait_state(),
                             TracerAction::Continue(child.into()),
@@ -323,14 +438,24 @@ impl<'a> LinuxData<'a> {
                 },
                 PTRACE_EVENT_FORK | PTRACE_EVENT_VFORK => {
                     trace!("Caught fork event");
+                    if let Ok(t) = get_event_data(child) {
+                        self.record_child_process(child, t);
+                    }
                     Ok((
                         TestState::wait_state(),
                         TracerAction::Continue(child.into()),
                     ))
                 }
                 PTRACE_EVENT_EXEC => {
-                    trace!("Child execed other process - detaching ptrace");
-                    Ok((TestState::wait_state(), TracerAction::Detach(child.into())))
+                    if self.instrument_exec(child) {
+                        Ok((
+                            TestState::wait_state(),
+                            TracerAction::Continue(child.into()),
+                        ))
+                    } else {
+                        trace!("Child execed other process - detaching ptrace");
+                        Ok((TestState::wait_state(), TracerAction::Detach(child.into())))
+                    }
                 }
                 PTRACE_EVENT_EXIT => {
                     trace!("Child exiting");
@@ -352,16 +477,33 @@ impl<'a> LinuxData<'a> {
         }
     }
 
+    /// Threads and forks of a followed exec'd process share its binary, so
+    /// they're recorded as belonging to it
+    fn record_child_process(&mut self, parent: Pid, child: c_long) {
+        if let Some(owner) = self.process_threads.get(&parent).copied() {
+            self.process_threads
+                .insert(Pid::from_raw(child as pid_t), owner);
+        }
+    }
+
     fn collect_coverage_data(
         &mut self,
         visited_pcs: &mut HashSet<u64>,
     ) -> Result<UpdateContext, RunError> {
         let mut action = None;
+        let process = match self.process_threads.get(&self.current) {
+            Some(owner) => self.processes.get_mut(owner),
+            None => None,
+        };
+        let (breakpoints, traces) = match process {
+            Some(process) => (&mut process.breakpoints, &mut process.traces),
+            None => (&mut self.breakpoints, &mut *self.traces),
+        };
         if let Ok(rip) = current_instruction_pointer(self.current) {
             let rip = (rip - 1) as u64;
             trace!("Hit address 0x{:x}", rip);
-            if self.breakpoints.contains_key(&rip) {
-                let bp = &mut self.breakpoints.get_mut(&rip).unwrap();
+            if breakpoints.contains_key(&rip) {
+                let bp = &mut breakpoints.get_mut(&rip).unwrap();
                 let updated = if visited_pcs.contains(&rip) {
                     let _ = bp.jump_to(self.current);
                     (true, TracerAction::Continue(self.current.into()))
@@ -377,7 +519,7 @@ impl<'a> LinuxData<'a> {
                     }
                 };
                 if updated.0 {
-                    if let Some(ref mut t) = self.traces.get_trace_mut(rip) {
+                    if let Some(ref mut t) = traces.get_trace_mut(rip) {
                         if let CoverageStat::Line(ref mut x) = t.stats {
                             trace!("Incrementing hit count for trace");
                             *x += 1;
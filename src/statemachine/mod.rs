@@ -4,7 +4,7 @@ use crate::errors::RunError;
 use crate::ptrace_control::*;
 use crate::traces::*;
 use log::error;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 #[cfg(target_os = "linux")]
 pub mod linux;
@@ -117,13 +117,19 @@ impl TestState {
         }
     }
 
-    /// Updates the state machine state
-    pub fn step<T: StateData>(self, data: &mut T, config: &Config) -> Result<TestState, RunError> {
+    /// Updates the state machine state, the test times out if it doesn't
+    /// respond within the given timeout
+    pub fn step<T: StateData>(
+        self,
+        data: &mut T,
+        config: &Config,
+        timeout: Duration,
+    ) -> Result<TestState, RunError> {
         match self {
             TestState::Start { start_time } => {
                 if let Some(s) = data.start()? {
                     Ok(s)
-                } else if start_time.elapsed() >= config.test_timeout {
+                } else if start_time.elapsed() >= timeout {
                     Err(RunError::TestRuntime(
                         "Error: Timed out when starting test".to_string(),
                     ))
@@ -135,7 +141,7 @@ impl TestState {
             TestState::Waiting { start_time } => {
                 if let Some(s) = data.wait()? {
                     Ok(s)
-                } else if start_time.elapsed() >= config.test_timeout {
+                } else if start_time.elapsed() >= timeout {
                     Err(RunError::TestRuntime(
                         "Error: Timed out waiting for test response".to_string(),
                     ))
@@ -3,17 +3,19 @@ use crate::source_analysis::*;
 use crate::traces::*;
 use cargo::core::Workspace;
 use gimli::*;
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use memmap::MmapOptions;
-use object::{File as OFile, Object};
+use object::{File as OFile, Object, ObjectSection, SectionKind};
 use rustc_demangle::demangle;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
-/// Describes a function as `low_pc`, `high_pc` and bool representing `is_test`.
-type FuncDesc = (u64, u64, FunctionType, Option<String>);
+/// Describes a function as `low_pc`, `high_pc`, `FunctionType`, function name
+/// and mangled symbol name.
+type FuncDesc = (u64, u64, FunctionType, Option<String>, Option<String>);
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum FunctionType {
@@ -57,11 +59,29 @@ pub struct TracerData {
     pub length: u64,
     /// Function name
     pub fn_name: Option<String>,
+    /// Mangled symbol name of the function
+    pub mangled_name: Option<String>,
+}
+
+impl TracerData {
+    /// Returns true if the trace is at the entry point of a function
+    fn is_function_entry(&self) -> bool {
+        match self.trace_type {
+            LineType::TestEntry(_) | LineType::FunctionEntry(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Demangles a symbol name to a readable path without the trailing hash
+fn demangle_name(mangled: &str) -> String {
+    format!("{:#}", demangle(mangled))
 }
 
 fn generate_func_desc<R, Offset>(
     die: &DebuggingInformationEntry<R, Offset>,
     debug_str: &DebugStr<R>,
+    config: &Config,
 ) -> Result<FuncDesc>
 where
     R: Reader<Offset = Offset>,
@@ -73,7 +93,7 @@ where
     let linkage = die.attr_value(DW_AT_linkage_name)?;
     let fn_name = die.attr_value(DW_AT_name)?;
 
-    let fn_name: Option<String> = match fn_name {
+    let mut fn_name: Option<String> = match fn_name {
         Some(AttributeValue::DebugStrRef(offset)) => debug_str
             .get_str(offset)
             .and_then(|r| r.to_string().map(|s| s.to_string()))
@@ -92,12 +112,17 @@ where
         Some(AttributeValue::Udata(x)) => x,
         _ => 0u64,
     };
+    let mut mangled_name = None;
     if let Some(AttributeValue::DebugStrRef(offset)) = linkage {
-        let name = debug_str
+        let mangled = debug_str
             .get_str(offset)
             .and_then(|r| r.to_string().map(|s| s.to_string()))
             .unwrap_or_else(|_| "".into());
-        let name = demangle(name.as_ref()).to_string();
+        let name = demangle(mangled.as_ref()).to_string();
+        if config.demangled_names && !mangled.is_empty() {
+            fn_name = Some(demangle_name(&mangled));
+        }
+        mangled_name = Some(mangled);
         // Simplest test is whether it's in tests namespace.
         // Rust guidelines recommend all tests are in a tests module.
         func_type = if name.contains("tests::") {
@@ -108,7 +133,7 @@ where
             FunctionType::Standard
         };
     }
-    Ok((low, high, func_type, fn_name))
+    Ok((low, high, func_type, fn_name, mangled_name))
 }
 
 /// Finds all function entry points and returns a vector
@@ -117,6 +142,7 @@ fn get_entry_points<R, Offset>(
     debug_info: &CompilationUnitHeader<R, Offset>,
     debug_abbrev: &Abbreviations,
     debug_str: &DebugStr<R>,
+    config: &Config,
 ) -> Vec<FuncDesc>
 where
     R: Reader<Offset = Offset>,
@@ -129,7 +155,7 @@ where
     while let Ok(Some((_, node))) = cursor.next_dfs() {
         // Function DIE
         if node.tag() == DW_TAG_subprogram {
-            if let Ok(fd) = generate_func_desc(node, debug_str) {
+            if let Ok(fd) = generate_func_desc(node, debug_str, config) {
                 result.push(fd);
             }
         }
@@ -137,11 +163,81 @@ where
     result
 }
 
+/// Address ranges of the executable sections. Functions removed by the linker
+/// keep their line table rows, with addresses outside of these ranges.
+fn text_ranges(obj: &OFile) -> Vec<Range<u64>> {
+    obj.sections()
+        .filter(|s| s.kind() == SectionKind::Text)
+        .map(|s| s.address()..s.address() + s.size())
+        .collect()
+}
+
+/// Maps the source directories of a project built somewhere else, such as in
+/// a container, to the source directory on this machine
+struct SourceRemap<'a> {
+    /// Directory the compilation unit was built in
+    build_dir: Option<PathBuf>,
+    source_dir: &'a Path,
+    /// Directories which were already warned about
+    unmapped: &'a mut HashSet<PathBuf>,
+}
+
+impl SourceRemap<'_> {
+    /// Replaces the build directory at the start of a directory which doesn't
+    /// exist on this machine with the source directory
+    fn apply(&mut self, path: PathBuf) -> PathBuf {
+        if path.exists() {
+            return path;
+        }
+        let mapped = self
+            .build_dir
+            .as_ref()
+            .and_then(|dir| path.strip_prefix(dir).ok())
+            .map(|rest| self.source_dir.join(rest));
+        match mapped {
+            Some(mapped) => mapped,
+            None => {
+                // The standard library sources are never on this machine
+                if !path.starts_with("/rustc") && self.unmapped.insert(path.clone()) {
+                    warn!("Can't map {} to the source directory", path.display());
+                }
+                path
+            }
+        }
+    }
+}
+
+/// Whether the directory is a dependency in the cargo registry or a git
+/// checkout, these aren't built in the project directory
+fn is_dependency_dir(dir: &Path) -> bool {
+    let components = dir
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    components
+        .windows(2)
+        .any(|w| (w[0] == "registry" && w[1] == "src") || (w[0] == "git" && w[1] == "checkouts"))
+}
+
+/// Paths under the sysroot of a cross compiled build are moved to the root of
+/// the host filesystem
+fn remap_sysroot(path: PathBuf, sysroot: Option<&Path>) -> PathBuf {
+    match sysroot.and_then(|s| path.strip_prefix(s).ok()) {
+        Some(rest) => Path::new("/").join(rest),
+        None => path,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn get_addresses_from_program<R, Offset>(
     prog: IncompleteLineProgram<R>,
     debug_strs: &DebugStr<R>,
-    entries: &Vec<(u64, LineType, &Option<String>)>,
+    entries: &Vec<(u64, LineType, &Option<String>, &Option<String>)>,
     project: &Path,
+    sysroot: Option<&Path>,
+    mut source_remap: Option<SourceRemap>,
+    include_external: bool,
+    live_ranges: Option<&[Range<u64>]>,
     result: &mut HashMap<SourceLocation, Vec<TracerData>>,
 ) -> Result<()>
 where
@@ -157,6 +253,11 @@ where
             if !ln_row.is_stmt() || ln_row.line().is_none() {
                 continue;
             }
+            if let Some(ranges) = live_ranges {
+                if !ranges.iter().any(|r| r.contains(&ln_row.address())) {
+                    continue;
+                }
+            }
             if let Some(file) = ln_row.file(header) {
                 let mut path = project.to_path_buf();
                 if let Some(dir) = file.directory(header) {
@@ -164,6 +265,10 @@ where
                         path.push(temp);
                     }
                 }
+                path = remap_sysroot(path, sysroot);
+                if let Some(ref mut remap) = source_remap {
+                    path = remap.apply(path);
+                }
 
                 if let Ok(p) = path.canonicalize() {
                     path = p;
@@ -177,8 +282,9 @@ where
                     path.starts_with(project.join("target"))
                 };
 
-                // Source is part of project so we cover it.
-                if !is_target && path.starts_with(project) {
+                // Source is part of project so we cover it. Unless external
+                // coverage is requested.
+                if !is_target && (include_external || path.starts_with(project)) {
                     if let Some(file) = ln_row.file(header) {
                         let line = ln_row.line().unwrap();
                         let file = file.path_name();
@@ -189,12 +295,14 @@ where
                                 continue;
                             }
                             let address = ln_row.address();
-                            let (desc, fn_name) = entries
+                            let (desc, fn_name, mangled_name) = entries
                                 .iter()
-                                .filter(|&&(addr, _, _)| addr == address)
-                                .map(|&(_, t, fn_name)| (t, fn_name.to_owned()))
+                                .filter(|&&(addr, _, _, _)| addr == address)
+                                .map(|&(_, t, fn_name, mangled_name)| {
+                                    (t, fn_name.to_owned(), mangled_name.to_owned())
+                                })
                                 .nth(0)
-                                .unwrap_or((LineType::Unknown, None));
+                                .unwrap_or((LineType::Unknown, None, None));
                             let loc = SourceLocation { path, line };
                             if desc != LineType::TestMain {
                                 let trace = TracerData {
@@ -202,6 +310,7 @@ where
                                     trace_type: desc,
                                     length: 1,
                                     fn_name,
+                                    mangled_name,
                                 };
                                 if result.contains_key(&loc) {
                                     let x = result.get_mut(&loc).unwrap();
@@ -238,6 +347,14 @@ fn get_line_addresses(
     let debug_line = obj.section_data_by_name(".debug_line").unwrap_or_default();
     let debug_line = DebugLine::new(&debug_line, endian);
 
+    let base_dir = config.get_base_dir();
+    let include_external = !config.ignore_extern_crate_coverage;
+    let live_ranges = if config.strip_dead_code {
+        Some(text_ranges(obj))
+    } else {
+        None
+    };
+    let mut unmapped = HashSet::new();
     let mut iter = debug_info.units();
     while let Ok(Some(cu)) = iter.next() {
         let addr_size = cu.address_size();
@@ -245,13 +362,13 @@ fn get_line_addresses(
             Ok(a) => a,
             _ => continue,
         };
-        let entry_points = get_entry_points(&cu, &abbr, &debug_strings);
+        let entry_points = get_entry_points(&cu, &abbr, &debug_strings, config);
         let entries = entry_points
             .iter()
-            .map(|(a, b, c, fn_name)| match c {
-                FunctionType::Test => (*a, LineType::TestEntry(*b), fn_name),
-                FunctionType::Standard => (*a, LineType::FunctionEntry(*b), fn_name),
-                FunctionType::Generated => (*a, LineType::TestMain, fn_name),
+            .map(|(a, b, c, fn_name, mangled)| match c {
+                FunctionType::Test => (*a, LineType::TestEntry(*b), fn_name, mangled),
+                FunctionType::Standard => (*a, LineType::FunctionEntry(*b), fn_name, mangled),
+                FunctionType::Generated => (*a, LineType::TestMain, fn_name, mangled),
             })
             .collect::<Vec<_>>();
 
@@ -262,10 +379,30 @@ fn get_line_addresses(
             };
             let prog = debug_line.program(offset, addr_size, None, None)?;
             let mut temp_map: HashMap<SourceLocation, Vec<TracerData>> = HashMap::new();
+            let comp_dir = match root.attr_value(DW_AT_comp_dir) {
+                Ok(Some(AttributeValue::DebugStrRef(o))) => debug_strings.get_str(o).ok(),
+                Ok(Some(AttributeValue::String(s))) => Some(s),
+                _ => None,
+            };
+            let source_remap = config.source_dir.as_ref().map(|source_dir| SourceRemap {
+                build_dir: comp_dir
+                    .and_then(|d| d.to_string().ok().map(|d| PathBuf::from(d.to_string())))
+                    .filter(|d| !is_dependency_dir(d)),
+                source_dir,
+                unmapped: &mut unmapped,
+            });
 
-            if let Err(e) =
-                get_addresses_from_program(prog, &debug_strings, &entries, project, &mut temp_map)
-            {
+            if let Err(e) = get_addresses_from_program(
+                prog,
+                &debug_strings,
+                &entries,
+                project,
+                config.sysroot.as_deref(),
+                source_remap,
+                include_external,
+                live_ranges.as_deref(),
+                &mut temp_map,
+            ) {
                 debug!("Potential issue reading test addresses {}", e);
             } else {
                 // Deduplicate addresses
@@ -277,10 +414,17 @@ fn get_line_addresses(
                     .filter(|&(ref k, _)| {
                         !(config.ignore_tests && k.path.starts_with(project.join("tests")))
                     })
-                    .filter(|&(ref k, _)| !(config.exclude_path(&k.path)))
+                    .filter(|&(ref k, _)| {
+                        !config.exclude_path(&k.path)
+                            || analysis.is_included(k.path.as_ref(), &(k.line as usize))
+                    })
+                    .filter(|&(ref k, _)| include_external || k.path.starts_with(&base_dir))
                     .filter(|&(ref k, _)| {
                         !analysis.should_ignore(k.path.as_ref(), &(k.line as usize))
                     })
+                    .filter(|&(_, ref v)| {
+                        !config.function_coverage_only || v.iter().any(|t| t.is_function_entry())
+                    })
                     .collect::<HashMap<SourceLocation, Vec<TracerData>>>();
 
                 let mut tracemap = TraceMap::new();
@@ -288,6 +432,7 @@ fn get_line_addresses(
                     let rpath = config.strip_base_dir(&k.path);
                     let mut address = HashSet::new();
                     let mut fn_name = None;
+                    let mut mangled_name = None;
                     for v in val.iter() {
                         if let Some(a) = v.address {
                             address.insert(a);
@@ -300,6 +445,7 @@ fn get_line_addresses(
                         }
                         if fn_name.is_none() && v.fn_name.is_some() {
                             fn_name = v.fn_name.clone();
+                            mangled_name = v.mangled_name.clone();
                         }
                     }
                     if address.is_empty() {
@@ -317,19 +463,33 @@ fn get_line_addresses(
                             length: 1,
                             stats: CoverageStat::Line(0),
                             fn_name,
+                            mangled_name,
                         },
                     );
+                    if config.include_tests && analysis.is_test_line(&k.path, &(k.line as usize)) {
+                        tracemap.mark_test_line(&k.path, k.line);
+                    }
                 }
                 result.merge(&tracemap);
             }
         }
     }
 
+    // Lines without an address only matter for line coverage, and as no code
+    // was generated for them they're dead code which can't be covered
+    if config.function_coverage_only || config.strip_dead_code {
+        return Ok(result);
+    }
     for (file, ref line_analysis) in analysis.iter() {
-        if config.exclude_path(file) {
+        // Excluded files can still have items forced into coverage
+        let excluded = config.exclude_path(file);
+        if excluded && line_analysis.include.is_empty() {
             continue;
         }
         for line in &line_analysis.cover {
+            if excluded && !line_analysis.is_included(*line) {
+                continue;
+            }
             let line = *line as u64;
             if !result.contains_location(file, line) && !line_analysis.should_ignore(line as usize)
             {
@@ -347,8 +507,12 @@ fn get_line_addresses(
                         length: 0,
                         stats: CoverageStat::Line(0),
                         fn_name: None,
+                        mangled_name: None,
                     },
                 );
+                if config.include_tests && line_analysis.is_test_line(line as usize) {
+                    result.mark_test_line(file, line);
+                }
             }
         }
     }
@@ -372,7 +536,17 @@ pub fn generate_tracemap(
     analysis: &HashMap<PathBuf, LineAnalysis>,
     config: &Config,
 ) -> io::Result<TraceMap> {
-    let manifest = project.root();
+    generate_binary_tracemap(project.root(), test, analysis, config)
+}
+
+/// Generates the tracemap of an executable built from the project in the
+/// given directory, such as a binary a test runs
+pub(crate) fn generate_binary_tracemap(
+    manifest: &Path,
+    test: &Path,
+    analysis: &HashMap<PathBuf, LineAnalysis>,
+    config: &Config,
+) -> io::Result<TraceMap> {
     let file = open_symbols_file(test)?;
     let file = unsafe { MmapOptions::new().map(&file)? };
     if let Ok(obj) = OFile::parse(&*file) {
@@ -396,3 +570,86 @@ pub fn generate_tracemap(
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn sysroot_paths() {
+        let sysroot = Path::new("/opt/aarch64-sysroot");
+        assert_eq!(
+            remap_sysroot(
+                PathBuf::from("/opt/aarch64-sysroot/home/ci/src"),
+                Some(sysroot)
+            ),
+            PathBuf::from("/home/ci/src")
+        );
+        assert_eq!(
+            remap_sysroot(PathBuf::from("/home/ci/src"), Some(sysroot)),
+            PathBuf::from("/home/ci/src")
+        );
+        assert_eq!(
+            remap_sysroot(PathBuf::from("/opt/aarch64-sysroot/src"), None),
+            PathBuf::from("/opt/aarch64-sysroot/src")
+        );
+    }
+
+    #[test]
+    fn source_dir_paths() {
+        let source_dir = env::current_dir().unwrap();
+        let mut unmapped = HashSet::new();
+        let mut remap = SourceRemap {
+            build_dir: Some(PathBuf::from("/build/project")),
+            source_dir: &source_dir,
+            unmapped: &mut unmapped,
+        };
+        assert_eq!(
+            remap.apply(PathBuf::from("/build/project/src")),
+            source_dir.join("src")
+        );
+        assert_eq!(remap.apply(source_dir.join("src")), source_dir.join("src"));
+        assert_eq!(
+            remap.apply(PathBuf::from("/other/src")),
+            PathBuf::from("/other/src")
+        );
+        assert_eq!(
+            remap.apply(PathBuf::from("/rustc/abc/src")),
+            PathBuf::from("/rustc/abc/src")
+        );
+        assert!(unmapped.contains(Path::new("/other/src")));
+        assert_eq!(unmapped.len(), 1);
+
+        assert!(is_dependency_dir(Path::new(
+            "/usr/local/cargo/registry/src/github.com-1ecc6299db9ec823/serde-1.0.104"
+        )));
+        assert!(is_dependency_dir(Path::new(
+            "/root/.cargo/git/checkouts/foo-123/abc"
+        )));
+        assert!(!is_dependency_dir(Path::new("/build/project")));
+    }
+
+    #[test]
+    fn demangle_function_name() {
+        assert_eq!(
+            demangle_name("_ZN8my_crate5utils5my_fn17h0123456789abcdefE"),
+            "my_crate::utils::my_fn"
+        );
+    }
+
+    #[test]
+    fn function_entry_traces() {
+        let trace = |trace_type| TracerData {
+            trace_type,
+            address: Some(0x1000),
+            length: 1,
+            fn_name: Some("foo".to_string()),
+            mangled_name: None,
+        };
+        assert!(trace(LineType::FunctionEntry(0x1000)).is_function_entry());
+        assert!(trace(LineType::TestEntry(0x1000)).is_function_entry());
+        assert!(!trace(LineType::Statement).is_function_entry());
+        assert!(!trace(LineType::Unknown).is_function_entry());
+    }
+}
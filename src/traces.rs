@@ -1,8 +1,9 @@
 use serde::{Deserialize, Serialize};
-use std::cmp::{Ord, Ordering};
+use std::cmp::{max, Ord, Ordering};
 use std::collections::btree_map::Iter;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt::{Display, Formatter, Result};
+use std::mem::discriminant;
 use std::ops::Add;
 use std::path::{Path, PathBuf};
 
@@ -71,7 +72,11 @@ pub struct Trace {
     /// Coverage stats
     pub stats: CoverageStat,
     /// Function name
+    #[serde(rename = "demangled_name", alias = "fn_name")]
     pub fn_name: Option<String>,
+    /// Mangled symbol name of the function
+    #[serde(default)]
+    pub mangled_name: Option<String>,
 }
 
 impl PartialOrd for Trace {
@@ -102,6 +107,21 @@ impl Ord for Trace {
     }
 }
 
+#[cfg(test)]
+impl Trace {
+    /// Trace of a line with no addresses for tests
+    pub(crate) fn new_stub(line: u64, stats: CoverageStat) -> Trace {
+        Trace {
+            line,
+            address: HashSet::new(),
+            length: 1,
+            stats,
+            fn_name: None,
+            mangled_name: None,
+        }
+    }
+}
+
 /// Amount of data coverable in the provided slice traces
 pub fn amount_coverable(traces: &[&Trace]) -> usize {
     let mut result = 0usize;
@@ -130,16 +150,55 @@ pub fn amount_covered(traces: &[&Trace]) -> usize {
     result
 }
 
+fn is_line(stat: &CoverageStat) -> bool {
+    matches!(stat, CoverageStat::Line(_))
+}
+
 pub fn coverage_percentage(traces: &[&Trace]) -> f64 {
     (amount_covered(traces) as f64) / (amount_coverable(traces) as f64)
 }
 
+/// A function and the number of times its entry point was hit
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FunctionHits {
+    /// Name of the function
+    pub name: String,
+    /// Line of the function's entry point
+    pub line: u64,
+    /// Times the function was called
+    pub hits: u64,
+}
+
+/// Finds the functions in the traces, only the trace of a function's entry
+/// point has the function name. Functions with the same name on the same line
+/// such as monomorphised generics are combined.
+pub fn functions(traces: &[&Trace]) -> Vec<FunctionHits> {
+    let mut result: BTreeMap<(u64, &str), u64> = BTreeMap::new();
+    for t in traces {
+        if let (Some(name), CoverageStat::Line(hits)) = (&t.fn_name, &t.stats) {
+            *result.entry((t.line, name.as_str())).or_insert(0) += hits;
+        }
+    }
+    result
+        .into_iter()
+        .map(|((line, name), hits)| FunctionHits {
+            name: name.to_string(),
+            line,
+            hits,
+        })
+        .collect()
+}
+
 /// Stores all the program traces mapped to files and provides an interface to
 /// add, query and change traces.
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct TraceMap {
     /// Traces in the program mapped to the given file
     traces: BTreeMap<PathBuf, Vec<Trace>>,
+    /// Lines of test code in each file, only recorded when test code coverage
+    /// is reported separately
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    test_lines: BTreeMap<PathBuf, BTreeSet<u64>>,
 }
 
 impl TraceMap {
@@ -147,6 +206,7 @@ impl TraceMap {
     pub fn new() -> TraceMap {
         TraceMap {
             traces: BTreeMap::new(),
+            test_lines: BTreeMap::new(),
         }
     }
 
@@ -164,6 +224,7 @@ impl TraceMap {
     /// This adds records which are missing and adds the statistics gathered to
     /// existing records
     pub fn merge(&mut self, other: &TraceMap) {
+        self.merge_test_lines(other);
         for (k, values) in other.iter() {
             if !self.traces.contains_key(k) {
                 self.traces.insert(k.to_path_buf(), values.to_vec());
@@ -187,6 +248,134 @@ impl TraceMap {
         }
     }
 
+    /// Merges the results of another tracemap where each line in both maps
+    /// has a single trace, such as after a call to `dedup`. Where a line is in
+    /// both maps the maximum hit count is kept instead of the sum so code
+    /// shared between test binaries isn't counted multiple times.
+    pub fn merge_max(&mut self, other: &TraceMap) {
+        self.merge_test_lines(other);
+        for (k, values) in other.iter() {
+            let existing = self.traces.entry(k.to_path_buf()).or_insert_with(Vec::new);
+            for v in values.iter() {
+                if let Some(t) = existing.iter_mut().find(|x| x.line == v.line) {
+                    t.stats = match (&t.stats, &v.stats) {
+                        (CoverageStat::Line(l), CoverageStat::Line(r)) => {
+                            CoverageStat::Line(max(*l, *r))
+                        }
+                        _ => t.stats.clone() + v.stats.clone(),
+                    };
+                    t.address.extend(v.address.iter());
+                } else {
+                    existing.push(v.clone());
+                    existing.sort_unstable();
+                }
+            }
+        }
+    }
+
+    /// Merges the results of a tracemap read from a coverage report, where
+    /// traces have no addresses. Traces of the same kind on the same line are
+    /// combined so the hits of each line are summed across the reports.
+    pub fn merge_reports(&mut self, other: &TraceMap) {
+        self.merge_test_lines(other);
+        for (k, values) in other.iter() {
+            let existing = self.traces.entry(k.to_path_buf()).or_insert_with(Vec::new);
+            for v in values.iter() {
+                let same_kind = |x: &&mut Trace| {
+                    x.line == v.line && discriminant(&x.stats) == discriminant(&v.stats)
+                };
+                if let Some(t) = existing.iter_mut().find(same_kind) {
+                    t.stats = t.stats.clone() + v.stats.clone();
+                    if t.fn_name.is_none() {
+                        t.fn_name = v.fn_name.clone();
+                    }
+                } else {
+                    existing.push(v.clone());
+                    existing.sort_unstable();
+                }
+            }
+        }
+    }
+
+    fn merge_test_lines(&mut self, other: &TraceMap) {
+        for (k, lines) in &other.test_lines {
+            self.test_lines
+                .entry(k.to_path_buf())
+                .or_insert_with(BTreeSet::new)
+                .extend(lines);
+        }
+    }
+
+    /// Marks a line of a file as test code
+    pub fn mark_test_line(&mut self, file: &Path, line: u64) {
+        self.test_lines
+            .entry(file.to_path_buf())
+            .or_insert_with(BTreeSet::new)
+            .insert(line);
+    }
+
+    /// Returns true if the line has been marked as test code
+    pub fn is_test_line(&self, file: &Path, line: u64) -> bool {
+        self.test_lines
+            .get(file)
+            .map(|lines| lines.contains(&line))
+            .unwrap_or(false)
+    }
+
+    /// Returns true if any lines have been marked as test code
+    pub fn has_test_lines(&self) -> bool {
+        !self.test_lines.is_empty()
+    }
+
+    /// Gets the traces below a path which are in test code, or outside of
+    /// test code if `test` is false
+    fn get_child_traces_by_kind(&self, root: &Path, test: bool) -> Vec<&Trace> {
+        self.traces
+            .iter()
+            .filter(|&(ref k, _)| k.starts_with(root))
+            .flat_map(|(k, v)| v.iter().map(move |t| (k, t)))
+            .filter(|&(k, t)| self.is_test_line(k, t.line) == test)
+            .map(|(_, t)| t)
+            .collect()
+    }
+
+    /// Coverable points of test code below the given path
+    pub fn test_coverable_in_path(&self, path: &Path) -> usize {
+        amount_coverable(self.get_child_traces_by_kind(path, true).as_slice())
+    }
+
+    /// Covered points of test code below the given path
+    pub fn test_covered_in_path(&self, path: &Path) -> usize {
+        amount_covered(self.get_child_traces_by_kind(path, true).as_slice())
+    }
+
+    /// Coverable points outside of test code below the given path
+    pub fn production_coverable_in_path(&self, path: &Path) -> usize {
+        amount_coverable(self.get_child_traces_by_kind(path, false).as_slice())
+    }
+
+    /// Covered points outside of test code below the given path
+    pub fn production_covered_in_path(&self, path: &Path) -> usize {
+        amount_covered(self.get_child_traces_by_kind(path, false).as_slice())
+    }
+
+    /// Returns the coverage percentage of test code ranging from 0.0-1.0
+    pub fn test_coverage_percentage(&self) -> f64 {
+        coverage_percentage(
+            self.get_child_traces_by_kind(Path::new(""), true)
+                .as_slice(),
+        )
+    }
+
+    /// Returns the coverage percentage of code outside of tests ranging from
+    /// 0.0-1.0
+    pub fn production_coverage_percentage(&self) -> f64 {
+        coverage_percentage(
+            self.get_child_traces_by_kind(Path::new(""), false)
+                .as_slice(),
+        )
+    }
+
     /// This will collapse duplicate Traces into a single trace. Warning this
     /// will lose the addresses of the duplicate traces but increment the results
     /// should be called only if you don't need those addresses from then on
@@ -324,6 +513,11 @@ impl TraceMap {
         amount_covered(self.get_child_traces(path).as_slice())
     }
 
+    /// Functions with their entry point in the given path
+    pub fn functions_in_path(&self, path: &Path) -> Vec<FunctionHits> {
+        functions(self.get_child_traces(path).as_slice())
+    }
+
     /// Give the total amount of coverable points in the code. This will vary
     /// based on the statistics available for line coverage it will be total
     /// lines whereas for condition or decision it will count the number of
@@ -341,6 +535,28 @@ impl TraceMap {
     pub fn coverage_percentage(&self) -> f64 {
         coverage_percentage(self.all_traces().as_slice())
     }
+
+    /// Returns the coverage percentage of only the line traces ranging from
+    /// 0.0-1.0
+    pub fn line_coverage_percentage(&self) -> f64 {
+        let lines = self
+            .all_traces()
+            .into_iter()
+            .filter(|t| is_line(&t.stats))
+            .collect::<Vec<_>>();
+        coverage_percentage(&lines)
+    }
+
+    /// Returns the coverage percentage of only the branch and condition traces
+    /// ranging from 0.0-1.0, this is NaN if there are no branches
+    pub fn branch_coverage_percentage(&self) -> f64 {
+        let branches = self
+            .all_traces()
+            .into_iter()
+            .filter(|t| !is_line(&t.stats))
+            .collect::<Vec<_>>();
+        coverage_percentage(&branches)
+    }
 }
 
 #[cfg(test)]
@@ -400,6 +616,7 @@ mod tests {
             length: 0,
             stats: CoverageStat::Line(1),
             fn_name: Some(String::from("f")),
+            mangled_name: None,
         };
         t1.add_trace(Path::new("file.rs"), trace_1);
 
@@ -409,6 +626,66 @@ mod tests {
         assert_eq!(total_covered, 1);
     }
 
+    #[test]
+    fn function_hits() {
+        let entry = |line, hits, name: Option<&str>| Trace {
+            fn_name: name.map(ToString::to_string),
+            ..Trace::new_stub(line, CoverageStat::Line(hits))
+        };
+        let mut traces = TraceMap::new();
+        let file = Path::new("src/lib.rs");
+        traces.add_trace(file, entry(1, 2, Some("generic")));
+        traces.add_trace(file, entry(2, 2, None));
+        traces.add_trace(file, entry(5, 0, Some("uncalled")));
+        traces.add_trace(Path::new("src/main.rs"), entry(1, 1, Some("main")));
+        // A second monomorphisation of the same function
+        traces.add_trace(file, entry(1, 1, Some("generic")));
+
+        let functions = traces.functions_in_path(file);
+        assert_eq!(
+            functions,
+            vec![
+                FunctionHits {
+                    name: "generic".to_string(),
+                    line: 1,
+                    hits: 3,
+                },
+                FunctionHits {
+                    name: "uncalled".to_string(),
+                    line: 5,
+                    hits: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_code_split() {
+        let line = |line, hits| Trace::new_stub(line, CoverageStat::Line(hits));
+        let file = Path::new("src/lib.rs");
+        let mut traces = TraceMap::new();
+        traces.add_trace(file, line(1, 1));
+        traces.add_trace(file, line(2, 0));
+        traces.add_trace(file, line(10, 1));
+        traces.add_trace(file, line(11, 1));
+        traces.add_trace(file, line(12, 0));
+        assert!(!traces.has_test_lines());
+
+        let mut tests = TraceMap::new();
+        for l in 10..=12 {
+            tests.mark_test_line(file, l);
+        }
+        traces.merge(&tests);
+        assert!(traces.is_test_line(file, 11));
+        assert!(!traces.is_test_line(file, 1));
+        assert_eq!(traces.test_covered_in_path(file), 2);
+        assert_eq!(traces.test_coverable_in_path(file), 3);
+        assert_eq!(traces.production_covered_in_path(file), 1);
+        assert_eq!(traces.production_coverable_in_path(file), 2);
+        assert_eq!(traces.production_coverage_percentage(), 0.5);
+        assert_eq!(traces.total_coverable(), 5);
+    }
+
     #[test]
     fn merge_address_mismatch_and_dedup() {
         let mut t1 = TraceMap::new();
@@ -422,6 +699,7 @@ mod tests {
             length: 0,
             stats: CoverageStat::Line(1),
             fn_name: Some(String::from("f")),
+            mangled_name: None,
         };
         t1.add_trace(Path::new("file.rs"), a_trace.clone());
         t2.add_trace(
@@ -432,6 +710,7 @@ mod tests {
                 length: 0,
                 stats: CoverageStat::Line(2),
                 fn_name: Some(String::from("f")),
+                mangled_name: None,
             },
         );
 
@@ -457,6 +736,7 @@ mod tests {
             length: 0,
             stats: CoverageStat::Line(1),
             fn_name: Some(String::from("f1")),
+            mangled_name: None,
         };
         t1.add_trace(Path::new("file.rs"), a_trace.clone());
         t2.add_trace(
@@ -467,6 +747,7 @@ mod tests {
                 length: 0,
                 stats: CoverageStat::Line(2),
                 fn_name: Some(String::from("f2")),
+                mangled_name: None,
             },
         );
 
@@ -478,6 +759,43 @@ mod tests {
         assert_eq!(all.len(), 2);
     }
 
+    #[test]
+    fn merge_max_shared_function() {
+        let mut t1 = TraceMap::new();
+        let mut t2 = TraceMap::new();
+
+        let mut address = HashSet::new();
+        address.insert(1);
+        t1.add_trace(
+            Path::new("utils.rs"),
+            Trace {
+                address: address.clone(),
+                fn_name: Some(String::from("shared")),
+                ..Trace::new_stub(3, CoverageStat::Line(5))
+            },
+        );
+        t2.add_trace(
+            Path::new("utils.rs"),
+            Trace {
+                address,
+                fn_name: Some(String::from("shared")),
+                ..Trace::new_stub(3, CoverageStat::Line(3))
+            },
+        );
+        t2.add_trace(
+            Path::new("utils.rs"),
+            Trace {
+                fn_name: Some(String::from("shared")),
+                ..Trace::new_stub(4, CoverageStat::Line(1))
+            },
+        );
+        t1.merge_max(&t2);
+        let all = t1.all_traces();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].stats, CoverageStat::Line(5));
+        assert_eq!(all[1].stats, CoverageStat::Line(1));
+    }
+
     #[test]
     fn merge_needed() {
         let mut t1 = TraceMap::new();
@@ -493,6 +811,7 @@ mod tests {
                 length: 0,
                 stats: CoverageStat::Line(5),
                 fn_name: Some(String::from("f")),
+                mangled_name: None,
             },
         );
         t2.add_trace(
@@ -503,6 +822,7 @@ mod tests {
                 length: 0,
                 stats: CoverageStat::Line(2),
                 fn_name: Some(String::from("f")),
+                mangled_name: None,
             },
         );
         t1.merge(&t2);
@@ -515,6 +835,7 @@ mod tests {
                 length: 0,
                 stats: CoverageStat::Line(7),
                 fn_name: Some(String::from("f")),
+                mangled_name: None,
             })
         );
         // Deduplicating should have no effect.
@@ -528,7 +849,51 @@ mod tests {
                 length: 0,
                 stats: CoverageStat::Line(7),
                 fn_name: Some(String::from("f")),
+                mangled_name: None,
             })
         );
     }
+
+    #[test]
+    fn merge_reports_sums_lines() {
+        let taken = LogicState {
+            been_true: true,
+            been_false: false,
+        };
+        let not_taken = LogicState {
+            been_true: false,
+            been_false: true,
+        };
+        let mut t1 = TraceMap::new();
+        t1.add_trace(
+            Path::new("lib.rs"),
+            Trace::new_stub(1, CoverageStat::Line(2)),
+        );
+        t1.add_trace(
+            Path::new("lib.rs"),
+            Trace::new_stub(1, CoverageStat::Branch(taken)),
+        );
+        let mut t2 = TraceMap::new();
+        t2.add_trace(
+            Path::new("lib.rs"),
+            Trace::new_stub(1, CoverageStat::Line(3)),
+        );
+        t2.add_trace(
+            Path::new("lib.rs"),
+            Trace::new_stub(1, CoverageStat::Branch(not_taken)),
+        );
+        t2.add_trace(
+            Path::new("other.rs"),
+            Trace::new_stub(4, CoverageStat::Line(0)),
+        );
+
+        t1.merge_reports(&t2);
+        let lib = t1.get_child_traces(Path::new("lib.rs"));
+        assert_eq!(lib.len(), 2);
+        assert!(lib.iter().any(|t| t.stats == CoverageStat::Line(5)));
+        assert!(lib
+            .iter()
+            .any(|t| t.stats == CoverageStat::Branch(&taken + &not_taken)));
+        assert_eq!(t1.coverable_in_path(Path::new("other.rs")), 1);
+    }
 }
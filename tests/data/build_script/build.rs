@@ -0,0 +1,27 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn table(name: &str, values: &[u64]) -> String {
+    let entries = values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("pub const {}: [u64; {}] = [{}];\n", name, values.len(), entries)
+}
+
+fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let mut generated = String::new();
+    if env::var("CARGO_FEATURE_SQUARES").is_ok() {
+        let squares = (0..10u64).map(|x| x * x).collect::<Vec<_>>();
+        generated.push_str(&table("SQUARES", &squares));
+    } else {
+        generated.push_str(&table("SQUARES", &[]));
+    }
+    let profile = env::var("PROFILE").unwrap_or_default();
+    generated.push_str(&format!("pub const PROFILE: &str = {:?};\n", profile));
+    fs::write(Path::new(&out_dir).join("generated.rs"), generated).unwrap();
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,15 @@
+include!(concat!(env!("OUT_DIR"), "/generated.rs"));
+
+pub fn square(x: usize) -> u64 {
+    SQUARES[x]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_table() {
+        assert_eq!(square(3), 9);
+    }
+}
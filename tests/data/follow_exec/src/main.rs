@@ -0,0 +1,11 @@
+use std::env;
+
+fn greeting(name: &str) -> String {
+    format!("Hello {}", name)
+}
+
+fn main() {
+    if let Some(name) = env::args().nth(1) {
+        println!("{}", greeting(&name));
+    }
+}
@@ -0,0 +1,12 @@
+use std::env;
+use std::process::Command;
+
+#[test]
+fn greets() {
+    // Integration tests are built in target/debug/deps next to the binary's
+    // directory
+    let deps = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let binary = deps.parent().unwrap().join("follow_exec");
+    let output = Command::new(binary).arg("tarpaulin").output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello tarpaulin\n");
+}
@@ -0,0 +1,15 @@
+pub mod proto;
+
+pub fn is_even(x: u32) -> bool {
+    x % 2 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_even() {
+        assert!(is_even(2));
+    }
+}
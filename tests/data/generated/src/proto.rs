@@ -0,0 +1,7 @@
+// This file was @generated by a code generator.
+// DO NOT EDIT
+
+pub fn never_called(x: u32) -> u32 {
+    let y = x * 2;
+    y + 1
+}
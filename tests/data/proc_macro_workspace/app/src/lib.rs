@@ -0,0 +1,15 @@
+macros::answer!();
+
+pub fn double_answer() -> u32 {
+    answer() * 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubled() {
+        assert_eq!(double_answer(), 84);
+    }
+}
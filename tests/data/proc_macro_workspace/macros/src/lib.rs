@@ -0,0 +1,22 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+
+fn constant_fn(name: &str, value: u32) -> String {
+    format!("pub fn {}() -> u32 {{ {} }}", name, value)
+}
+
+#[proc_macro]
+pub fn answer(_item: TokenStream) -> TokenStream {
+    constant_fn("answer", 42).parse().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_function() {
+        assert_eq!(constant_fn("one", 1), "pub fn one() -> u32 { 1 }");
+    }
+}
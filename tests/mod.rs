@@ -23,9 +23,9 @@ pub fn check_percentage_with_cli_args(minimum_coverage: f64, has_lines: bool, ar
              --root -r [DIR] 'directory'"
         ).get_matches_from(args);
 
-    let configs = ConfigWrapper::from(&matches).0;
+    let configs = ConfigWrapper::from(&matches);
     let mut res = TraceMap::new();
-    for config in &configs {
+    for config in configs {
         let (t, _) = launch_tarpaulin(&config).unwrap();
         res.merge(&t);
     }
@@ -90,6 +90,52 @@ fn proc_macro_link() {
     assert!(launch_tarpaulin(&config).is_ok());
 }
 
+#[test]
+fn exclude_generated_files() {
+    let mut config = Config::default();
+    config.exclude_generated = true;
+    config.test_timeout = Duration::from_secs(60);
+    let restore_dir = env::current_dir().unwrap();
+    let test_dir = get_test_path("generated");
+    env::set_current_dir(&test_dir).unwrap();
+    config.manifest = test_dir.join("Cargo.toml");
+
+    let (res, _) = launch_tarpaulin(&config).unwrap();
+    env::set_current_dir(restore_dir).unwrap();
+
+    assert!(res.total_coverable() > 0);
+    assert!(res.files().iter().all(|f| !f.ends_with("proto.rs")));
+    assert_eq!(res.coverage_percentage(), 1.0f64);
+}
+
+#[test]
+fn follow_exec_coverage() {
+    let restore_dir = env::current_dir().unwrap();
+    let test_dir = get_test_path("follow_exec");
+    env::set_current_dir(&test_dir).unwrap();
+    let main = test_dir.join("src/main.rs");
+    let mut config = Config::default();
+    config.test_timeout = Duration::from_secs(60);
+    config.manifest = test_dir.join("Cargo.toml");
+
+    let (res, _) = launch_tarpaulin(&config).unwrap();
+    assert_eq!(res.covered_in_path(&main), 0);
+
+    config.follow_exec = true;
+    let (res, ret) = launch_tarpaulin(&config).unwrap();
+    env::set_current_dir(restore_dir).unwrap();
+    assert_eq!(ret, 0);
+    let covered = res
+        .get_child_traces(&main)
+        .iter()
+        .filter(|t| t.stats != CoverageStat::Line(0))
+        .map(|t| t.line)
+        .collect::<Vec<_>>();
+    // The body of greeting and the print in main
+    assert!(covered.contains(&4));
+    assert!(covered.contains(&9));
+}
+
 #[test]
 fn array_coverage() {
     check_percentage("arrays", 1.0f64, true);
@@ -109,3 +109,53 @@ fn only_doctest_coverage() {
         }
     }
 }
+
+#[test]
+fn only_build_script_coverage() {
+    let mut config = Config::default();
+    config.verbose = true;
+    config.test_timeout = Duration::from_secs(60);
+    config.run_types = vec![RunType::BuildScripts];
+    let restore_dir = env::current_dir().unwrap();
+    let test_dir = get_test_path("build_script");
+    env::set_current_dir(&test_dir).unwrap();
+    config.manifest = test_dir.clone();
+    config.manifest.push("Cargo.toml");
+
+    let (res, ret) = launch_tarpaulin(&config).unwrap();
+    assert_eq!(ret, 0);
+    env::set_current_dir(restore_dir).unwrap();
+
+    let build_rs = test_dir.join("build.rs");
+    assert!(res.covered_in_path(&build_rs) > 0);
+    for f in res.files() {
+        if f.file_name().unwrap() != "build.rs" {
+            assert_eq!(res.covered_in_path(f), 0);
+        }
+    }
+}
+
+#[test]
+fn only_proc_macro_coverage() {
+    let mut config = Config::default();
+    config.verbose = true;
+    config.test_timeout = Duration::from_secs(60);
+    config.run_types = vec![RunType::ProcMacros];
+    let restore_dir = env::current_dir().unwrap();
+    let test_dir = get_test_path("proc_macro_workspace");
+    env::set_current_dir(&test_dir).unwrap();
+    config.manifest = test_dir.clone();
+    config.manifest.push("Cargo.toml");
+
+    let (res, ret) = launch_tarpaulin(&config).unwrap();
+    assert_eq!(ret, 0);
+    env::set_current_dir(restore_dir).unwrap();
+
+    let macros = test_dir.join("macros");
+    assert!(res.covered_in_path(&macros.join("src/lib.rs")) > 0);
+    for f in res.files() {
+        if !f.starts_with(&macros) {
+            assert_eq!(res.covered_in_path(f), 0);
+        }
+    }
+}